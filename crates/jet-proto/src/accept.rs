@@ -1,7 +1,7 @@
 use crate::utils::{RequestHelper, ResponseHelper};
 use crate::{
-    get_uuid_in_path, Error, JET_HEADER_ASSOCIATION, JET_HEADER_HOST, JET_HEADER_INSTANCE, JET_HEADER_METHOD,
-    JET_HEADER_TIMEOUT, JET_HEADER_VERSION,
+    get_bearer_token, get_uuid_in_path, Error, JET_HEADER_ASSOCIATION, JET_HEADER_AUTHORIZATION, JET_HEADER_HOST,
+    JET_HEADER_INSTANCE, JET_HEADER_METHOD, JET_HEADER_TIMEOUT, JET_HEADER_VERSION,
 };
 use http::StatusCode;
 use std::io;
@@ -14,6 +14,8 @@ pub struct JetAcceptReq {
     pub host: String,
     pub association: Uuid,
     pub candidate: Uuid,
+    /// Association token, sent as a bearer token in the `Authorization` header
+    pub token: Option<String>,
 }
 
 impl JetAcceptReq {
@@ -25,6 +27,7 @@ impl JetAcceptReq {
                 stream.write_fmt(format_args!("Connection: Keep-Alive\r\n"))?;
                 stream.write_fmt(format_args!("Jet-Method: {}\r\n", "Accept"))?;
                 stream.write_fmt(format_args!("Jet-Version: {}\r\n", &self.version.to_string()))?;
+                self.write_authorization(&mut stream)?;
                 stream.write_fmt(format_args!("\r\n"))?;
             }
             _ => {
@@ -37,6 +40,7 @@ impl JetAcceptReq {
                 stream.write_fmt(format_args!("Host: {}\r\n", &self.host))?;
                 stream.write_fmt(format_args!("Connection: Keep-Alive\r\n"))?;
                 stream.write_fmt(format_args!("Jet-Version: {}\r\n", &self.version.to_string()))?;
+                self.write_authorization(&mut stream)?;
                 stream.write_fmt(format_args!("\r\n"))?;
             }
         }
@@ -44,6 +48,14 @@ impl JetAcceptReq {
         Ok(())
     }
 
+    fn write_authorization(&self, mut stream: impl io::Write) -> Result<(), Error> {
+        if let Some(token) = &self.token {
+            stream.write_fmt(format_args!("{JET_HEADER_AUTHORIZATION}: Bearer {token}\r\n"))?;
+        }
+
+        Ok(())
+    }
+
     pub fn from_request(request: &httparse::Request) -> Result<Self, Error> {
         if request.is_get_method() {
             let version_opt = request
@@ -62,6 +74,7 @@ impl JetAcceptReq {
                                 host: host.to_string(),
                                 association: association_id,
                                 candidate: candidate_id,
+                                token: get_bearer_token(request),
                             });
                         }
                     } else if path.eq("/") {
@@ -72,6 +85,7 @@ impl JetAcceptReq {
                                     host: host.to_string(),
                                     association: Uuid::nil(),
                                     candidate: Uuid::nil(),
+                                    token: get_bearer_token(request),
                                 });
                             }
                         }
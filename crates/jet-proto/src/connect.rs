@@ -1,7 +1,7 @@
 use crate::utils::{RequestHelper, ResponseHelper};
 use crate::{
-    get_uuid_in_path, Error, JET_HEADER_ASSOCIATION, JET_HEADER_CONNECTION, JET_HEADER_HOST, JET_HEADER_METHOD,
-    JET_HEADER_VERSION,
+    get_bearer_token, get_uuid_in_path, Error, JET_HEADER_ASSOCIATION, JET_HEADER_AUTHORIZATION, JET_HEADER_CONNECTION,
+    JET_HEADER_HOST, JET_HEADER_METHOD, JET_HEADER_VERSION,
 };
 use http::StatusCode;
 use std::io;
@@ -14,6 +14,8 @@ pub struct JetConnectReq {
     pub host: String,
     pub association: Uuid,
     pub candidate: Uuid,
+    /// Association token, sent as a bearer token in the `Authorization` header
+    pub token: Option<String>,
 }
 
 impl JetConnectReq {
@@ -34,6 +36,7 @@ impl JetConnectReq {
                     JET_HEADER_VERSION,
                     &self.version.to_string()
                 ))?;
+                self.write_authorization(&mut stream)?;
                 stream.write_fmt(format_args!("\r\n"))?;
             }
             _ => {
@@ -50,6 +53,7 @@ impl JetConnectReq {
                     JET_HEADER_VERSION,
                     &self.version.to_string()
                 ))?;
+                self.write_authorization(&mut stream)?;
                 stream.write_fmt(format_args!("\r\n"))?;
             }
         }
@@ -57,6 +61,14 @@ impl JetConnectReq {
         Ok(())
     }
 
+    fn write_authorization(&self, mut stream: impl io::Write) -> Result<(), Error> {
+        if let Some(token) = &self.token {
+            stream.write_fmt(format_args!("{JET_HEADER_AUTHORIZATION}: Bearer {token}\r\n"))?;
+        }
+
+        Ok(())
+    }
+
     pub fn from_request(request: &httparse::Request) -> Result<Self, Error> {
         if request.is_get_method() {
             // Version has to be specified
@@ -84,6 +96,7 @@ impl JetConnectReq {
                                 host: host.to_string(),
                                 association: association_id,
                                 candidate: candidate_id,
+                                token: get_bearer_token(request),
                             });
                         }
                     } else if path.eq("/") {
@@ -96,6 +109,7 @@ impl JetConnectReq {
                                             host: host.to_string(),
                                             association,
                                             candidate: Uuid::nil(),
+                                            token: get_bearer_token(request),
                                         });
                                     }
                                 }
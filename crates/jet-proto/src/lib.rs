@@ -29,6 +29,7 @@ const JET_HEADER_TIMEOUT: &str = "Jet-Timeout";
 const JET_HEADER_INSTANCE: &str = "Jet-Instance";
 const JET_HEADER_HOST: &str = "Host";
 const JET_HEADER_CONNECTION: &str = "Connection";
+const JET_HEADER_AUTHORIZATION: &str = "Authorization";
 
 static mut JET_MSG_MASK: u8 = 0x73;
 static JET_MASK_INIT: Once = Once::new();
@@ -184,6 +185,13 @@ impl JetMessage {
     }
 }
 
+fn get_bearer_token(request: &httparse::Request) -> Option<String> {
+    request
+        .get_header_value(JET_HEADER_AUTHORIZATION)
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| token.trim().to_owned())
+}
+
 fn get_uuid_in_path(path: &str, index: usize) -> Option<Uuid> {
     if let Some(raw_uuid) = path.split('/').nth(index + 1) {
         Uuid::parse_str(raw_uuid).ok()
@@ -339,8 +347,25 @@ mod tests {
                     association: Uuid::from_str("300f1c82-d33b-11e9-bb65-2a2ae2dbcce5").unwrap(),
                     candidate: Uuid::from_str("4c8f409a-c1a2-4cae-bda2-84c590fed618").unwrap(),
                     version: 2,
-                    host: "jet101.wayk.net".to_string()
+                    host: "jet101.wayk.net".to_string(),
+                    token: None,
                 })
         );
     }
+
+    #[test]
+    fn connect_request_token_round_trip() {
+        let request = JetMessage::JetConnectReq(JetConnectReq {
+            version: 2,
+            host: "jetsocat".to_owned(),
+            association: Uuid::new_v4(),
+            candidate: Uuid::new_v4(),
+            token: Some("header.payload.signature".to_owned()),
+        });
+
+        let mut buffer = Vec::new();
+        request.write_to(&mut buffer).unwrap();
+
+        assert_eq!(JetMessage::read_request(&mut buffer.as_slice()).unwrap(), request);
+    }
 }
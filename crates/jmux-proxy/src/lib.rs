@@ -15,6 +15,7 @@ use self::id_allocator::IdAllocator;
 use crate::codec::MAXIMUM_PACKET_SIZE_IN_BYTES;
 use anyhow::Context as _;
 use bytes::Bytes;
+use futures_util::future::BoxFuture;
 use futures_util::{SinkExt, StreamExt};
 use jmux_proto::{ChannelData, DistantChannelId, Header, LocalChannelId, Message, ReasonCode};
 use std::collections::HashMap;
@@ -23,7 +24,6 @@ use std::io;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
-use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
 use tokio::net::TcpStream;
 use tokio::sync::{mpsc, oneshot, Notify};
 use tokio::task::JoinHandle;
@@ -35,6 +35,15 @@ pub type ApiResponseReceiver = oneshot::Receiver<JmuxApiResponse>;
 pub type ApiRequestSender = mpsc::Sender<JmuxApiRequest>;
 pub type ApiRequestReceiver = mpsc::Receiver<JmuxApiRequest>;
 
+type ErasedRead = Box<dyn AsyncRead + Unpin + Send>;
+type ErasedWrite = Box<dyn AsyncWrite + Unpin + Send>;
+
+/// Opens the streams to the destinations requested by the JMUX peer, given the host and the port.
+///
+/// The destination is reached directly using `TcpStream::connect` when no connector is provided.
+pub type StreamConnector =
+    Arc<dyn Fn(String, u16) -> BoxFuture<'static, io::Result<(ErasedRead, ErasedWrite)>> + Send + Sync>;
+
 #[derive(Debug)]
 pub enum JmuxApiRequest {
     OpenChannel {
@@ -63,6 +72,7 @@ pub enum JmuxApiResponse {
 pub struct JmuxProxy {
     cfg: JmuxConfig,
     api_request_rx: Option<ApiRequestReceiver>,
+    connector: Option<StreamConnector>,
     jmux_reader: Box<dyn AsyncRead + Unpin + Send>,
     jmux_writer: Box<dyn AsyncWrite + Unpin + Send>,
 }
@@ -75,6 +85,7 @@ impl JmuxProxy {
         Self {
             cfg: JmuxConfig::default(),
             api_request_rx: None,
+            connector: None,
             jmux_reader,
             jmux_writer,
         }
@@ -90,6 +101,11 @@ impl JmuxProxy {
         self
     }
 
+    pub fn with_connector(mut self, connector: StreamConnector) -> Self {
+        self.connector = Some(connector);
+        self
+    }
+
     // TODO: consider using something like ChildTask<T> more widely in Devolutions Gateway
     pub fn spawn(self) -> JoinHandle<anyhow::Result<()>> {
         let fut = self.run();
@@ -106,6 +122,7 @@ async fn run_proxy_impl(proxy: JmuxProxy, span: Span) -> anyhow::Result<()> {
     let JmuxProxy {
         cfg,
         api_request_rx,
+        connector,
         jmux_reader,
         jmux_writer,
     } = proxy;
@@ -125,6 +142,7 @@ async fn run_proxy_impl(proxy: JmuxProxy, span: Span) -> anyhow::Result<()> {
 
     let scheduler_task_handle = JmuxSchedulerTask {
         cfg,
+        connector,
         jmux_stream,
         msg_to_send_tx,
         api_request_rx,
@@ -218,10 +236,15 @@ type DataReceiver = mpsc::UnboundedReceiver<Vec<u8>>;
 type DataSender = mpsc::UnboundedSender<Vec<u8>>;
 type InternalMessageSender = mpsc::UnboundedSender<InternalMessage>;
 
-#[derive(Debug)]
 enum InternalMessage {
-    Eof { id: LocalChannelId },
-    StreamResolved { channel: JmuxChannelCtx, stream: TcpStream },
+    Eof {
+        id: LocalChannelId,
+    },
+    StreamResolved {
+        channel: JmuxChannelCtx,
+        reader: ErasedRead,
+        writer: ErasedWrite,
+    },
 }
 
 // === internal tasks === //
@@ -262,6 +285,7 @@ impl<T: AsyncWrite + Unpin + Send + 'static> JmuxSenderTask<T> {
 
 struct JmuxSchedulerTask<T: AsyncRead + Unpin + Send + 'static> {
     cfg: JmuxConfig,
+    connector: Option<StreamConnector>,
     jmux_stream: FramedRead<T, JmuxCodec>,
     msg_to_send_tx: MessageSender,
     api_request_rx: ApiRequestReceiver,
@@ -280,6 +304,7 @@ impl<T: AsyncRead + Unpin + Send + 'static> JmuxSchedulerTask<T> {
 async fn scheduler_task_impl<T: AsyncRead + Unpin + Send + 'static>(task: JmuxSchedulerTask<T>) -> anyhow::Result<()> {
     let JmuxSchedulerTask {
         cfg,
+        connector,
         mut jmux_stream,
         msg_to_send_tx,
         mut api_request_rx,
@@ -338,14 +363,14 @@ async fn scheduler_task_impl<T: AsyncRead + Unpin + Send + 'static>(task: JmuxSc
                         let (reader, writer) = stream.into_split();
 
                         DataWriterTask {
-                            writer,
+                            writer: Box::new(writer),
                             data_rx,
                         }
                         .spawn(channel.span.clone())
                         .detach();
 
                         DataReaderTask {
-                            reader,
+                            reader: Box::new(reader),
                             local_id: channel.local_id,
                             distant_id: channel.distant_id,
                             window_size_updated: Arc::clone(&channel.window_size_updated),
@@ -392,7 +417,7 @@ async fn scheduler_task_impl<T: AsyncRead + Unpin + Send + 'static>(task: JmuxSc
                         }
                     }
                     InternalMessage::StreamResolved {
-                        channel, stream
+                        channel, reader, writer
                     } => {
                         let local_id = channel.local_id;
                         let distant_id = channel.distant_id;
@@ -418,8 +443,6 @@ async fn scheduler_task_impl<T: AsyncRead + Unpin + Send + 'static>(task: JmuxSc
                             debug!("Channel accepted");
                         });
 
-                        let (reader, writer) = stream.into_split();
-
                         DataWriterTask {
                             writer,
                             data_rx,
@@ -532,6 +555,7 @@ async fn scheduler_task_impl<T: AsyncRead + Unpin + Send + 'static>(task: JmuxSc
                         StreamResolverTask {
                             channel,
                             destination_url: msg.destination_url,
+                            connector: connector.clone(),
                             internal_msg_tx: internal_msg_tx.clone(),
                             msg_to_send_tx: msg_to_send_tx.clone(),
                         }
@@ -701,7 +725,7 @@ async fn scheduler_task_impl<T: AsyncRead + Unpin + Send + 'static>(task: JmuxSc
 // ---------------------- //
 
 struct DataReaderTask {
-    reader: OwnedReadHalf,
+    reader: ErasedRead,
     local_id: LocalChannelId,
     distant_id: DistantChannelId,
     window_size_updated: Arc<Notify>,
@@ -802,7 +826,7 @@ impl DataReaderTask {
 // ---------------------- //
 
 struct DataWriterTask {
-    writer: OwnedWriteHalf,
+    writer: ErasedWrite,
     data_rx: DataReceiver,
 }
 
@@ -834,6 +858,7 @@ impl DataWriterTask {
 struct StreamResolverTask {
     channel: JmuxChannelCtx,
     destination_url: DestinationUrl,
+    connector: Option<StreamConnector>,
     internal_msg_tx: InternalMessageSender,
     msg_to_send_tx: MessageSender,
 }
@@ -858,6 +883,7 @@ impl StreamResolverTask {
         let Self {
             channel,
             destination_url,
+            connector,
             internal_msg_tx,
             msg_to_send_tx,
         } = self;
@@ -866,26 +892,39 @@ impl StreamResolverTask {
         let host = destination_url.host();
         let port = destination_url.port();
 
-        match scheme {
-            "tcp" => match TcpStream::connect((host, port)).await {
-                Ok(stream) => {
-                    internal_msg_tx
-                        .send(InternalMessage::StreamResolved { channel, stream })
-                        .context("could't send back resolved stream through internal mpsc channel")?;
-                }
-                Err(error) => {
-                    debug!(?error, "TcpStream::connect failed");
-                    msg_to_send_tx
-                        .send(Message::open_failure(
-                            channel.distant_id,
-                            ReasonCode::from(error.kind()),
-                            error.to_string(),
-                        ))
-                        .context("couldn’t send OPEN FAILURE message through mpsc channel")?;
-                    anyhow::bail!("couldn’t open TCP stream to {}:{}: {}", host, port, error);
-                }
-            },
-            _ => anyhow::bail!("unsupported scheme: {}", scheme),
+        if scheme != "tcp" {
+            anyhow::bail!("unsupported scheme: {}", scheme);
+        }
+
+        let connect_result = match connector {
+            Some(connector) => connector(host.to_owned(), port).await,
+            None => TcpStream::connect((host, port)).await.map(|stream| {
+                let (reader, writer) = stream.into_split();
+                (Box::new(reader) as ErasedRead, Box::new(writer) as ErasedWrite)
+            }),
+        };
+
+        match connect_result {
+            Ok((reader, writer)) => {
+                internal_msg_tx
+                    .send(InternalMessage::StreamResolved {
+                        channel,
+                        reader,
+                        writer,
+                    })
+                    .map_err(|_| anyhow::anyhow!("could't send back resolved stream through internal mpsc channel"))?;
+            }
+            Err(error) => {
+                debug!(?error, "Connection to destination failed");
+                msg_to_send_tx
+                    .send(Message::open_failure(
+                        channel.distant_id,
+                        ReasonCode::from(error.kind()),
+                        error.to_string(),
+                    ))
+                    .context("couldn’t send OPEN FAILURE message through mpsc channel")?;
+                anyhow::bail!("couldn’t open TCP stream to {}:{}: {}", host, port, error);
+            }
         }
 
         Ok(())
@@ -398,6 +398,11 @@ where
         Ok(ProxyStream { stream, read_leftover })
     }
 
+    /// Gets a reference to the underlying stream
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
     /// Gets underlying stream and leftover bytes
     pub fn into_parts(self) -> (S, Bytes) {
         (self.stream, self.read_leftover)
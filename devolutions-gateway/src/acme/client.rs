@@ -0,0 +1,356 @@
+//! Client side of the ACME protocol (RFC 8555)
+
+use std::time::Duration;
+
+use anyhow::Context as _;
+use picky::hash::HashAlgorithm;
+use picky::jose::jwk::Jwk;
+use picky::key::PrivateKey;
+use picky::signature::SignatureAlgorithm;
+use reqwest::header;
+use serde_json::json;
+use sha2::{Digest as _, Sha256};
+
+use crate::config::AcmeConf;
+
+const REPLAY_NONCE: &str = "replay-nonce";
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const POLL_ATTEMPTS: usize = 30;
+
+pub(crate) struct AcmeClient {
+    http: reqwest::Client,
+    directory: Directory,
+    account_key: PrivateKey,
+    jwk: serde_json::Value,
+    thumbprint: String,
+    account_url: Option<String>,
+    nonce: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Directory {
+    new_nonce: String,
+    new_account: String,
+    new_order: String,
+    #[serde(default)]
+    meta: DirectoryMeta,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DirectoryMeta {
+    terms_of_service: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct Order {
+    pub status: String,
+    pub authorizations: Vec<String>,
+    pub finalize: String,
+    pub certificate: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct Authorization {
+    pub status: String,
+    pub identifier: Identifier,
+    pub challenges: Vec<Challenge>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct Identifier {
+    pub value: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct Challenge {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub url: String,
+    #[serde(default)]
+    pub token: String,
+}
+
+#[derive(Default, Deserialize)]
+struct Problem {
+    #[serde(rename = "type", default)]
+    kind: String,
+    #[serde(default)]
+    detail: String,
+}
+
+impl AcmeClient {
+    /// Fetches the directory of the ACME server.
+    pub(crate) async fn connect(conf: &AcmeConf, account_key: PrivateKey) -> anyhow::Result<Self> {
+        let mut builder =
+            reqwest::Client::builder().user_agent(concat!("devolutions-gateway/", env!("CARGO_PKG_VERSION")));
+
+        for certificate in &conf.trusted_certificates {
+            let certificate = reqwest::Certificate::from_der(&certificate.0).context("invalid CA certificate")?;
+            builder = builder.add_root_certificate(certificate);
+        }
+
+        let http = builder.build().context("build HTTP client")?;
+
+        let directory = http
+            .get(conf.directory_url.clone())
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .context("failed to fetch the ACME directory")?
+            .json::<Directory>()
+            .await
+            .context("invalid ACME directory")?;
+
+        let public_key = account_key.to_public_key().context("invalid account key")?;
+        let jwk = Jwk::from_public_key(&public_key).context("unsupported account key")?;
+        let jwk = serde_json::to_value(jwk).context("failed to serialize JWK")?;
+
+        let (Some(modulus), Some(public_exponent)) = (jwk["n"].as_str(), jwk["e"].as_str()) else {
+            anyhow::bail!("account key is not an RSA key");
+        };
+
+        // RFC 7638: required members only, in lexicographic order and without whitespace.
+        let thumbprint_input = format!(r#"{{"e":"{public_exponent}","kty":"RSA","n":"{modulus}"}}"#);
+        let thumbprint = base64url(&Sha256::digest(thumbprint_input.as_bytes()));
+
+        Ok(Self {
+            http,
+            directory,
+            account_key,
+            jwk: json!({ "e": public_exponent, "kty": "RSA", "n": modulus }),
+            thumbprint,
+            account_url: None,
+            nonce: None,
+        })
+    }
+
+    /// Finds or creates the account associated to the account key.
+    ///
+    /// The terms of service of the ACME server must have been explicitly agreed to by the administrator.
+    pub(crate) async fn register_account(
+        &mut self,
+        contact_email: Option<&str>,
+        agree_to_terms_of_service: bool,
+    ) -> anyhow::Result<()> {
+        if !agree_to_terms_of_service {
+            anyhow::bail!(
+                "the terms of service of the ACME server ({}) must be agreed to by setting Acme.AgreeToTermsOfService",
+                self.directory
+                    .meta
+                    .terms_of_service
+                    .as_deref()
+                    .unwrap_or("unspecified URL")
+            );
+        }
+
+        let mut payload = json!({ "termsOfServiceAgreed": true });
+
+        if let Some(contact_email) = contact_email {
+            payload["contact"] = json!([format!("mailto:{contact_email}")]);
+        }
+
+        let url = self.directory.new_account.clone();
+        let response = self.post(&url, Some(&payload)).await.context("account registration")?;
+
+        self.account_url = Some(location(&response)?);
+
+        Ok(())
+    }
+
+    /// Creates a new order, and returns its URL along with the order itself.
+    pub(crate) async fn new_order(&mut self, domains: &[String]) -> anyhow::Result<(String, Order)> {
+        let identifiers = domains
+            .iter()
+            .map(|domain| json!({ "type": "dns", "value": domain }))
+            .collect::<Vec<_>>();
+
+        let url = self.directory.new_order.clone();
+        let response = self
+            .post(&url, Some(&json!({ "identifiers": identifiers })))
+            .await
+            .context("order creation")?;
+
+        let order_url = location(&response)?;
+        let order = response.json().await.context("invalid order")?;
+
+        Ok((order_url, order))
+    }
+
+    pub(crate) async fn authorization(&mut self, url: &str) -> anyhow::Result<Authorization> {
+        self.post(url, None)
+            .await?
+            .json()
+            .await
+            .context("invalid authorization")
+    }
+
+    /// Waits for the server to validate or invalidate the authorization.
+    pub(crate) async fn poll_authorization(&mut self, url: &str) -> anyhow::Result<Authorization> {
+        for _ in 0..POLL_ATTEMPTS {
+            let authorization = self.authorization(url).await?;
+
+            if authorization.status != "pending" {
+                return Ok(authorization);
+            }
+
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+
+        anyhow::bail!("authorization is still pending")
+    }
+
+    /// Tells the server the challenge is ready to be validated.
+    pub(crate) async fn respond_to_challenge(&mut self, url: &str) -> anyhow::Result<()> {
+        self.post(url, Some(&json!({}))).await.context("challenge response")?;
+        Ok(())
+    }
+
+    pub(crate) async fn finalize(&mut self, url: &str, certificate_signing_request: &[u8]) -> anyhow::Result<()> {
+        let payload = json!({ "csr": base64url(certificate_signing_request) });
+        self.post(url, Some(&payload)).await.context("order finalization")?;
+        Ok(())
+    }
+
+    /// Waits for the server to process the finalized order.
+    pub(crate) async fn poll_order(&mut self, url: &str) -> anyhow::Result<Order> {
+        for _ in 0..POLL_ATTEMPTS {
+            let order = self
+                .post(url, None)
+                .await?
+                .json::<Order>()
+                .await
+                .context("invalid order")?;
+
+            if !matches!(order.status.as_str(), "pending" | "processing") {
+                return Ok(order);
+            }
+
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+
+        anyhow::bail!("order is still processing")
+    }
+
+    /// Downloads the issued certificate chain, in PEM format.
+    pub(crate) async fn certificate(&mut self, url: &str) -> anyhow::Result<String> {
+        self.post(url, None)
+            .await
+            .context("certificate download")?
+            .text()
+            .await
+            .context("invalid certificate chain")
+    }
+
+    /// Key authorization for a challenge token (RFC 8555, section 8.1).
+    pub(crate) fn key_authorization(&self, token: &str) -> String {
+        format!("{token}.{}", self.thumbprint)
+    }
+
+    /// Sends an authenticated request, or a POST-as-GET request when there is no payload.
+    async fn post(&mut self, url: &str, payload: Option<&serde_json::Value>) -> anyhow::Result<reqwest::Response> {
+        let mut retried = false;
+
+        loop {
+            let nonce = self.nonce().await?;
+            let body = self.sign(url, &nonce, payload)?;
+
+            let response = self
+                .http
+                .post(url)
+                .header(header::CONTENT_TYPE, "application/jose+json")
+                .body(body)
+                .send()
+                .await
+                .with_context(|| format!("request to {url} failed"))?;
+
+            self.nonce = replay_nonce(&response);
+
+            let status = response.status();
+
+            if status.is_success() {
+                return Ok(response);
+            }
+
+            let problem = response.json::<Problem>().await.unwrap_or_default();
+
+            // The server may reject a nonce at any time, and the request should then be retried (RFC 8555, section 6.5).
+            if problem.kind == "urn:ietf:params:acme:error:badNonce" && !retried {
+                retried = true;
+                continue;
+            }
+
+            anyhow::bail!(
+                "ACME server responded to {url} with {status}: {} ({})",
+                problem.detail,
+                problem.kind
+            );
+        }
+    }
+
+    async fn nonce(&mut self) -> anyhow::Result<String> {
+        if let Some(nonce) = self.nonce.take() {
+            return Ok(nonce);
+        }
+
+        let response = self
+            .http
+            .head(&self.directory.new_nonce)
+            .send()
+            .await
+            .context("failed to fetch a new nonce")?;
+
+        replay_nonce(&response).context("Replay-Nonce header is missing")
+    }
+
+    /// Produces the flattened JWS JSON serialization of the request.
+    fn sign(&self, url: &str, nonce: &str, payload: Option<&serde_json::Value>) -> anyhow::Result<Vec<u8>> {
+        let mut protected = json!({ "alg": "RS256", "nonce": nonce, "url": url });
+
+        match &self.account_url {
+            Some(account_url) => protected["kid"] = json!(account_url),
+            None => protected["jwk"] = self.jwk.clone(),
+        }
+
+        let protected = base64url(&serde_json::to_vec(&protected)?);
+
+        let payload = match payload {
+            Some(payload) => base64url(&serde_json::to_vec(payload)?),
+            None => String::new(),
+        };
+
+        let signature = SignatureAlgorithm::RsaPkcs1v15(HashAlgorithm::SHA2_256)
+            .sign(format!("{protected}.{payload}").as_bytes(), &self.account_key)
+            .context("failed to sign ACME request")?;
+
+        let jws = json!({
+            "protected": protected,
+            "payload": payload,
+            "signature": base64url(&signature),
+        });
+
+        serde_json::to_vec(&jws).context("failed to serialize JWS")
+    }
+}
+
+fn location(response: &reqwest::Response) -> anyhow::Result<String> {
+    response
+        .headers()
+        .get(header::LOCATION)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned)
+        .context("Location header is missing")
+}
+
+fn replay_nonce(response: &reqwest::Response) -> Option<String> {
+    response
+        .headers()
+        .get(REPLAY_NONCE)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned)
+}
+
+fn base64url(data: &[u8]) -> String {
+    multibase::Base::Base64Url.encode(data)
+}
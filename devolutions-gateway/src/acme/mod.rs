@@ -0,0 +1,688 @@
+//! Automatic certificate management using the ACME protocol (RFC 8555)
+//!
+//! The certificate is obtained and renewed in the background, stored in the data directory, and swapped into the TLS
+//! configuration without restarting. Domain ownership is proven either using the HTTP-01 challenge, served on the
+//! HTTP listeners, or using the TLS-ALPN-01 challenge (RFC 8737), served on the HTTPS listeners.
+
+mod client;
+mod x509;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context as _;
+use async_trait::async_trait;
+use camino::Utf8Path;
+use devolutions_gateway_task::{ShutdownSignal, Task};
+use parking_lot::Mutex;
+use picky::key::{EcCurve, PrivateKey};
+use picky::pem::Pem;
+use sha2::{Digest as _, Sha256};
+use tap::prelude::*;
+use time::OffsetDateTime;
+use tokio_rustls::rustls;
+
+use self::client::AcmeClient;
+use crate::config::{dto, AcmeConf, ConfHandle};
+use crate::tls::CertificateSource;
+
+/// ALPN protocol used by the TLS-ALPN-01 challenge
+pub const TLS_ALPN_01_PROTOCOL: &[u8] = b"acme-tls/1";
+
+const RSA_KEY_BITS: usize = 2048;
+const RENEWAL_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60 * 12);
+const RETRY_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Responses to the challenges currently being validated by the ACME server
+#[derive(Default)]
+pub struct AcmeChallenges {
+    /// Key authorizations, by token (HTTP-01)
+    http_01: Mutex<HashMap<String, String>>,
+    /// Server configurations presenting the validation certificate, by domain (TLS-ALPN-01)
+    tls_alpn_01: Mutex<HashMap<String, Arc<rustls::ServerConfig>>>,
+}
+
+impl AcmeChallenges {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn http_01_key_authorization(&self, token: &str) -> Option<String> {
+        self.http_01.lock().get(token).cloned()
+    }
+
+    pub fn tls_alpn_01_server_config(&self, domain: &str) -> Option<Arc<rustls::ServerConfig>> {
+        self.tls_alpn_01.lock().get(&domain.to_ascii_lowercase()).cloned()
+    }
+
+    async fn publish(
+        &self,
+        challenge_type: dto::AcmeChallengeType,
+        domain: &str,
+        token: &str,
+        key_authorization: String,
+    ) -> anyhow::Result<PublishedChallenge<'_>> {
+        let published = PublishedChallenge {
+            challenges: self,
+            domain: domain.to_ascii_lowercase(),
+            token: token.to_owned(),
+        };
+
+        match challenge_type {
+            dto::AcmeChallengeType::Http01 => {
+                self.http_01.lock().insert(published.token.clone(), key_authorization);
+            }
+            dto::AcmeChallengeType::TlsAlpn01 => {
+                let server_config = tls_alpn_01_server_config(domain, &key_authorization).await?;
+                self.tls_alpn_01
+                    .lock()
+                    .insert(published.domain.clone(), Arc::new(server_config));
+            }
+        }
+
+        Ok(published)
+    }
+}
+
+/// Withdraws the challenge response once dropped
+struct PublishedChallenge<'a> {
+    challenges: &'a AcmeChallenges,
+    domain: String,
+    token: String,
+}
+
+impl Drop for PublishedChallenge<'_> {
+    fn drop(&mut self) {
+        self.challenges.http_01.lock().remove(&self.token);
+        self.challenges.tls_alpn_01.lock().remove(&self.domain);
+    }
+}
+
+/// Self-signed certificate served until a certificate is issued by the ACME server
+///
+/// Generated on each configuration load, which RSA key generation would slow down too much, so a P-256 key is used.
+pub fn placeholder_certificate(domain: &str) -> anyhow::Result<CertificateSource> {
+    let key = PrivateKey::generate_ec(EcCurve::NistP256).context("failed to generate EC key")?;
+    let not_after = OffsetDateTime::now_utc() + time::Duration::days(7);
+    let certificate = x509::self_signed_certificate(&key, domain, not_after, None)?;
+
+    Ok(CertificateSource::External {
+        certificates: vec![rustls::Certificate(certificate)],
+        private_key: rustls::PrivateKey(key.to_pkcs8().context("invalid private key")?),
+    })
+}
+
+pub struct AcmeTask {
+    pub conf_handle: ConfHandle,
+    pub challenges: Arc<AcmeChallenges>,
+}
+
+#[async_trait]
+impl Task for AcmeTask {
+    type Output = anyhow::Result<()>;
+
+    const NAME: &'static str = "ACME";
+
+    async fn run(self, shutdown_signal: ShutdownSignal) -> Self::Output {
+        acme_task(self.conf_handle, self.challenges, shutdown_signal).await;
+        Ok(())
+    }
+}
+
+#[instrument(skip_all)]
+async fn acme_task(conf_handle: ConfHandle, challenges: Arc<AcmeChallenges>, mut shutdown_signal: ShutdownSignal) {
+    debug!("Task started");
+
+    loop {
+        let conf = conf_handle.get_conf();
+
+        let wait = match &conf.acme {
+            Some(acme_conf) => match renewal_date(acme_conf) {
+                Ok(renewal_date) if renewal_date > OffsetDateTime::now_utc() => {
+                    debug!(%renewal_date, "Certificate renewal is not due yet");
+                    RENEWAL_CHECK_INTERVAL
+                }
+                renewal_date => {
+                    match renewal_date {
+                        Ok(_) => info!("Renewing the TLS certificate"),
+                        Err(error) => info!(reason = format!("{error:#}"), "Requesting a new TLS certificate"),
+                    }
+
+                    match obtain_certificate(acme_conf, &challenges).await.and_then(|()| {
+                        conf.tls
+                            .as_ref()
+                            .context("TLS configuration is missing")?
+                            .reload()
+                            .context("failed to reload the TLS certificate")
+                    }) {
+                        Ok(()) => {
+                            info!(domains = ?acme_conf.domains, "TLS certificate issued by the ACME server");
+                            RENEWAL_CHECK_INTERVAL
+                        }
+                        Err(error) => {
+                            error!(error = format!("{error:#}"), "Failed to obtain a TLS certificate");
+                            RETRY_INTERVAL
+                        }
+                    }
+                }
+            },
+            None => RENEWAL_CHECK_INTERVAL,
+        };
+
+        tokio::select! {
+            _ = tokio::time::sleep(wait) => {}
+            _ = conf_handle.change_notified() => {}
+            _ = shutdown_signal.wait() => break,
+        }
+    }
+
+    debug!("Task terminated");
+}
+
+/// Date from which the stored certificate should be renewed
+fn renewal_date(conf: &AcmeConf) -> anyhow::Result<OffsetDateTime> {
+    let certificate_path = conf.certificate_path();
+
+    let pem: Pem = std::fs::read_to_string(&certificate_path)
+        .with_context(|| format!("couldn't read file at {certificate_path}"))?
+        .pipe_deref(str::parse)
+        .context("couldn't parse pem document")?;
+
+    let not_after = x509::certificate_not_after(pem.data()).context("invalid certificate")?;
+
+    Ok(not_after - conf.renew_before)
+}
+
+async fn obtain_certificate(conf: &AcmeConf, challenges: &AcmeChallenges) -> anyhow::Result<()> {
+    let account_key = load_or_generate_account_key(&conf.account_key_path()).await?;
+
+    let mut client = AcmeClient::connect(conf, account_key).await?;
+
+    client
+        .register_account(conf.contact_email.as_deref(), conf.agree_to_terms_of_service)
+        .await?;
+
+    let (order_url, order) = client.new_order(&conf.domains).await?;
+
+    for authorization_url in &order.authorizations {
+        let authorization = client.authorization(authorization_url).await?;
+
+        if authorization.status == "valid" {
+            continue;
+        }
+
+        let domain = authorization.identifier.value;
+
+        let challenge = authorization
+            .challenges
+            .iter()
+            .find(|challenge| challenge.kind == conf.challenge.as_str())
+            .with_context(|| format!("no {} challenge offered for {domain}", conf.challenge.as_str()))?;
+
+        let key_authorization = client.key_authorization(&challenge.token);
+
+        let _published = challenges
+            .publish(conf.challenge, &domain, &challenge.token, key_authorization)
+            .await?;
+
+        client.respond_to_challenge(&challenge.url).await?;
+
+        let authorization = client.poll_authorization(authorization_url).await?;
+
+        anyhow::ensure!(
+            authorization.status == "valid",
+            "authorization for {domain} is {}",
+            authorization.status
+        );
+
+        debug!(%domain, "Domain authorized");
+    }
+
+    let certificate_key = generate_rsa_key().await?;
+    let certificate_signing_request = x509::certificate_signing_request(&certificate_key, &conf.domains)?;
+
+    client.finalize(&order.finalize, &certificate_signing_request).await?;
+
+    let order = client.poll_order(&order_url).await?;
+
+    anyhow::ensure!(order.status == "valid", "order is {}", order.status);
+
+    let certificate_url = order.certificate.context("certificate URL is missing")?;
+    let certificate_chain = client.certificate(&certificate_url).await?;
+
+    // The stored certificate is only replaced by one which can actually be loaded.
+    let certificates =
+        crate::config::read_pem_certificate_chain(certificate_chain.as_bytes()).context("invalid certificate chain")?;
+    let pkcs8 = certificate_key.to_pkcs8().context("failed to encode private key")?;
+    crate::config::ensure_private_key_matches(&certificates[0], &rustls::PrivateKey(pkcs8))?;
+
+    let private_key = certificate_key.to_pem_str().context("failed to encode private key")?;
+
+    // The private key is written first, so that the TLS certificate watcher never pairs the new certificate with the
+    // previous key.
+    write_file_atomically(&conf.private_key_path(), &private_key, true).await?;
+    write_file_atomically(&conf.certificate_path(), &certificate_chain, false).await?;
+
+    Ok(())
+}
+
+async fn load_or_generate_account_key(path: &Utf8Path) -> anyhow::Result<PrivateKey> {
+    match tokio::fs::read_to_string(path).await {
+        Ok(pem) => PrivateKey::from_pem_str(&pem).with_context(|| format!("invalid account key at {path}")),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            info!(%path, "Generating a new ACME account key");
+
+            let key = generate_rsa_key().await?;
+            let pem = key.to_pem_str().context("failed to encode account key")?;
+            write_file_atomically(path, &pem, true).await?;
+
+            Ok(key)
+        }
+        Err(error) => Err(anyhow::Error::new(error).context(format!("couldn't read file at {path}"))),
+    }
+}
+
+async fn generate_rsa_key() -> anyhow::Result<PrivateKey> {
+    tokio::task::spawn_blocking(|| PrivateKey::generate_rsa(RSA_KEY_BITS))
+        .await
+        .context("key generation task failed")?
+        .context("failed to generate RSA key")
+}
+
+/// Replaces the file, making sure it's never observed partially written.
+///
+/// Private files (keys) are only readable by the owner on Unix.
+async fn write_file_atomically(path: &Utf8Path, contents: &str, private: bool) -> anyhow::Result<()> {
+    use tokio::io::AsyncWriteExt as _;
+
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .with_context(|| format!("couldn't create folder at {parent}"))?;
+    }
+
+    let tmp_path = path.with_extension("tmp");
+
+    // A leftover temporary file would keep its permissions.
+    match tokio::fs::remove_file(&tmp_path).await {
+        Ok(()) => {}
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+        Err(error) => return Err(anyhow::Error::new(error).context(format!("couldn't remove file at {tmp_path}"))),
+    }
+
+    let mut options = tokio::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+
+    #[cfg(unix)]
+    if private {
+        options.mode(0o600);
+    }
+
+    #[cfg(not(unix))]
+    let _ = private;
+
+    let mut file = options
+        .open(&tmp_path)
+        .await
+        .with_context(|| format!("couldn't create file at {tmp_path}"))?;
+
+    file.write_all(contents.as_bytes())
+        .await
+        .with_context(|| format!("couldn't write file at {tmp_path}"))?;
+
+    file.sync_all()
+        .await
+        .with_context(|| format!("couldn't flush file at {tmp_path}"))?;
+
+    drop(file);
+
+    tokio::fs::rename(&tmp_path, path)
+        .await
+        .with_context(|| format!("couldn't move {tmp_path} to {path}"))?;
+
+    Ok(())
+}
+
+async fn tls_alpn_01_server_config(domain: &str, key_authorization: &str) -> anyhow::Result<rustls::ServerConfig> {
+    let key = generate_rsa_key().await?;
+
+    let digest = Sha256::digest(key_authorization.as_bytes());
+    let not_after = OffsetDateTime::now_utc() + time::Duration::days(1);
+    let certificate = x509::self_signed_certificate(&key, domain, not_after, Some(digest.as_slice()))?;
+
+    let mut server_config = crate::tls::build_server_config(
+        CertificateSource::External {
+            certificates: vec![rustls::Certificate(certificate)],
+            private_key: rustls::PrivateKey(key.to_pkcs8().context("invalid private key")?),
+        },
+        Vec::new(),
+    )?;
+
+    server_config.alpn_protocols = vec![TLS_ALPN_01_PROTOCOL.to_vec()];
+
+    Ok(server_config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{TempDir, P256_PRIVATE_KEY};
+    use picky::hash::HashAlgorithm;
+    use picky::signature::SignatureAlgorithm;
+    use picky::x509::date::UtcDate;
+    use picky::x509::name::DirectoryName;
+    use picky::x509::{CertificateBuilder, Csr};
+    use serde_json::json;
+    use url::Url;
+
+    const DOMAIN: &str = "gateway.example.test";
+    const TOKEN: &str = "challenge-token";
+
+    /// Certificate chain returned by the stub server once the order is finalized
+    #[derive(Clone, Copy)]
+    enum IssuedChain {
+        /// Certificate for the requested key, followed by the one of the authority
+        Valid,
+        /// Certificate of the authority alone, which doesn't match the requested key
+        Mismatched,
+        /// Not a certificate
+        Garbage,
+    }
+
+    fn issue_certificate_chain(request: &[u8], issued_chain: IssuedChain) -> String {
+        const SIGNATURE_ALGORITHM: SignatureAlgorithm = SignatureAlgorithm::Ecdsa(HashAlgorithm::SHA2_256);
+
+        let authority_key = PrivateKey::from_pem_str(P256_PRIVATE_KEY).unwrap();
+        let not_before = UtcDate::ymd(2020, 1, 1).unwrap();
+        let not_after = UtcDate::ymd(2040, 1, 1).unwrap();
+
+        let authority = CertificateBuilder::new()
+            .validity(not_before.clone(), not_after.clone())
+            .self_signed(DirectoryName::new_common_name("Stub CA"), &authority_key)
+            .ca(true)
+            .signature_hash_type(SIGNATURE_ALGORITHM)
+            .build()
+            .unwrap();
+
+        match issued_chain {
+            IssuedChain::Valid => {
+                let certificate = CertificateBuilder::new()
+                    .validity(not_before, not_after)
+                    .subject_from_csr(Csr::from_der(request).unwrap())
+                    .issuer_cert(&authority, &authority_key)
+                    .signature_hash_type(SIGNATURE_ALGORITHM)
+                    .build()
+                    .unwrap();
+
+                format!("{}\n{}\n", certificate.to_pem().unwrap(), authority.to_pem().unwrap())
+            }
+            IssuedChain::Mismatched => format!("{}\n", authority.to_pem().unwrap()),
+            IssuedChain::Garbage => "-----BEGIN CERTIFICATE-----\nc3R1Yg==\n-----END CERTIFICATE-----\n".to_owned(),
+        }
+    }
+
+    /// Minimal ACME server with a single order, validating the HTTP-01 challenge by looking at the published response.
+    ///
+    /// Signatures are not verified.
+    struct StubServer {
+        base_url: String,
+        challenges: Arc<AcmeChallenges>,
+        nonce: u64,
+        terms_of_service_agreed: Option<bool>,
+        validated: bool,
+        certificate_signing_request: Option<Vec<u8>>,
+        issued_chain: IssuedChain,
+        certificate_chain: Option<String>,
+    }
+
+    impl StubServer {
+        fn handle(&mut self, method: &str, path: &str, body: &[u8]) -> (u16, Option<String>, serde_json::Value) {
+            let payload = serde_json::from_slice::<serde_json::Value>(body)
+                .ok()
+                .and_then(|jws| jws["payload"].as_str().map(str::to_owned))
+                .filter(|payload| !payload.is_empty())
+                .map(|payload| {
+                    let payload = multibase::Base::Base64Url.decode(payload).unwrap();
+                    serde_json::from_slice::<serde_json::Value>(&payload).unwrap()
+                });
+
+            let base_url = &self.base_url;
+
+            match (method, path) {
+                ("GET", "/directory") => (
+                    200,
+                    None,
+                    json!({
+                        "newNonce": format!("{base_url}/new-nonce"),
+                        "newAccount": format!("{base_url}/new-account"),
+                        "newOrder": format!("{base_url}/new-order"),
+                        "meta": { "termsOfService": format!("{base_url}/terms") },
+                    }),
+                ),
+                ("HEAD", "/new-nonce") => (200, None, json!(null)),
+                ("POST", "/new-account") => {
+                    let payload = payload.unwrap();
+                    self.terms_of_service_agreed = payload["termsOfServiceAgreed"].as_bool();
+                    (201, Some(format!("{base_url}/account")), json!({ "status": "valid" }))
+                }
+                ("POST", "/new-order") => (201, Some(format!("{base_url}/order")), self.order()),
+                ("POST", "/order") => (200, None, self.order()),
+                ("POST", "/authorization") => (
+                    200,
+                    None,
+                    json!({
+                        "status": if self.validated { "valid" } else { "pending" },
+                        "identifier": { "type": "dns", "value": DOMAIN },
+                        "challenges": [
+                            { "type": "tls-alpn-01", "url": format!("{base_url}/tls-alpn-challenge"), "token": TOKEN },
+                            { "type": "http-01", "url": format!("{base_url}/http-challenge"), "token": TOKEN },
+                        ],
+                    }),
+                ),
+                ("POST", "/http-challenge") => {
+                    let key_authorization = self.challenges.http_01_key_authorization(TOKEN).unwrap();
+                    assert!(key_authorization.starts_with(&format!("{TOKEN}.")));
+                    self.validated = true;
+                    (200, None, json!({ "status": "processing" }))
+                }
+                ("POST", "/finalize") => {
+                    assert!(self.validated);
+                    let csr = payload.unwrap()["csr"].as_str().unwrap().to_owned();
+                    let csr = multibase::Base::Base64Url.decode(csr).unwrap();
+                    self.certificate_chain = Some(issue_certificate_chain(&csr, self.issued_chain));
+                    self.certificate_signing_request = Some(csr);
+                    (200, None, self.order())
+                }
+                _ => (404, None, json!({ "type": "urn:ietf:params:acme:error:malformed" })),
+            }
+        }
+
+        fn order(&self) -> serde_json::Value {
+            let base_url = &self.base_url;
+
+            let status = match (self.validated, &self.certificate_signing_request) {
+                (_, Some(_)) => "valid",
+                (true, None) => "ready",
+                (false, None) => "pending",
+            };
+
+            json!({
+                "status": status,
+                "authorizations": [format!("{base_url}/authorization")],
+                "finalize": format!("{base_url}/finalize"),
+                "certificate": (status == "valid").then(|| format!("{base_url}/certificate")),
+            })
+        }
+    }
+
+    async fn spawn_stub_server(
+        challenges: Arc<AcmeChallenges>,
+        issued_chain: IssuedChain,
+    ) -> (Url, Arc<Mutex<StubServer>>) {
+        use http_body_util::{BodyExt as _, Full};
+        use hyper::service::service_fn;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+
+        let server = Arc::new(Mutex::new(StubServer {
+            base_url: base_url.clone(),
+            challenges,
+            nonce: 0,
+            terms_of_service_agreed: None,
+            validated: false,
+            certificate_signing_request: None,
+            issued_chain,
+            certificate_chain: None,
+        }));
+
+        tokio::spawn({
+            let server = Arc::clone(&server);
+
+            async move {
+                loop {
+                    let (stream, _) = listener.accept().await.unwrap();
+                    let server = Arc::clone(&server);
+
+                    let service = service_fn(move |request: hyper::Request<hyper::body::Incoming>| {
+                        let server = Arc::clone(&server);
+
+                        async move {
+                            let method = request.method().clone();
+                            let path = request.uri().path().to_owned();
+                            let body = request.into_body().collect().await?.to_bytes();
+
+                            let mut server = server.lock();
+
+                            server.nonce += 1;
+                            let response = hyper::Response::builder().header("replay-nonce", server.nonce);
+
+                            let response = if path == "/certificate" {
+                                response
+                                    .header("content-type", "application/pem-certificate-chain")
+                                    .body(Full::new(bytes::Bytes::from(server.certificate_chain.clone().unwrap())))
+                            } else {
+                                let (status, location, body) = server.handle(method.as_str(), &path, &body);
+
+                                let response = match location {
+                                    Some(location) => response.header("location", location),
+                                    None => response,
+                                };
+
+                                response
+                                    .status(status)
+                                    .header("content-type", "application/json")
+                                    .body(Full::new(bytes::Bytes::from(body.to_string())))
+                            };
+
+                            Ok::<_, hyper::Error>(response.unwrap())
+                        }
+                    });
+
+                    tokio::spawn(
+                        hyper_util::server::conn::auto::Builder::new(hyper_util::rt::TokioExecutor::new())
+                            .serve_connection(hyper_util::rt::TokioIo::new(stream), service)
+                            .into_owned(),
+                    );
+                }
+            }
+        });
+
+        (Url::parse(&format!("{base_url}/directory")).unwrap(), server)
+    }
+
+    fn acme_conf(directory_url: Url, storage_dir: &TempDir, agree_to_terms_of_service: bool) -> AcmeConf {
+        AcmeConf {
+            directory_url,
+            contact_email: None,
+            agree_to_terms_of_service,
+            domains: vec![DOMAIN.to_owned()],
+            challenge: dto::AcmeChallengeType::Http01,
+            renew_before: time::Duration::days(30),
+            trusted_certificates: Vec::new(),
+            storage_path: storage_dir.path().to_owned(),
+        }
+    }
+
+    #[tokio::test]
+    async fn certificate_is_obtained_from_stub_server() {
+        let challenges = Arc::new(AcmeChallenges::new());
+        let (directory_url, server) = spawn_stub_server(Arc::clone(&challenges), IssuedChain::Valid).await;
+        let storage_dir = TempDir::new();
+        let conf = acme_conf(directory_url, &storage_dir, true);
+
+        obtain_certificate(&conf, &challenges).await.unwrap();
+
+        // The challenge response is withdrawn once validated.
+        assert_eq!(challenges.http_01_key_authorization(TOKEN), None);
+
+        let server = server.lock();
+        assert_eq!(server.terms_of_service_agreed, Some(true));
+
+        let request = Csr::from_der(server.certificate_signing_request.as_deref().unwrap()).unwrap();
+        assert!(request.subject_name().to_string().contains(DOMAIN));
+
+        assert_eq!(
+            std::fs::read_to_string(conf.certificate_path()).unwrap(),
+            server.certificate_chain.as_deref().unwrap()
+        );
+
+        let private_key = std::fs::read_to_string(conf.private_key_path()).unwrap();
+        let private_key = PrivateKey::from_pem_str(&private_key).unwrap();
+        assert_eq!(
+            private_key.to_public_key().unwrap().to_der().unwrap(),
+            request.public_key().to_der().unwrap()
+        );
+
+        #[cfg(unix)]
+        for path in [conf.private_key_path(), conf.account_key_path()] {
+            use std::os::unix::fs::PermissionsExt as _;
+            assert_eq!(std::fs::metadata(path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+    }
+
+    #[tokio::test]
+    async fn account_is_not_registered_without_agreeing_to_terms_of_service() {
+        let challenges = Arc::new(AcmeChallenges::new());
+        let (directory_url, server) = spawn_stub_server(Arc::clone(&challenges), IssuedChain::Valid).await;
+        let storage_dir = TempDir::new();
+        let conf = acme_conf(directory_url, &storage_dir, false);
+
+        let error = obtain_certificate(&conf, &challenges).await.unwrap_err();
+
+        assert!(format!("{error:#}").contains("AgreeToTermsOfService"));
+        assert_eq!(server.lock().terms_of_service_agreed, None);
+        assert!(!conf.certificate_path().exists());
+    }
+
+    #[tokio::test]
+    async fn bad_certificate_chain_leaves_stored_files_untouched() {
+        for issued_chain in [IssuedChain::Mismatched, IssuedChain::Garbage] {
+            let challenges = Arc::new(AcmeChallenges::new());
+            let (directory_url, _server) = spawn_stub_server(Arc::clone(&challenges), issued_chain).await;
+            let storage_dir = TempDir::new();
+            let conf = acme_conf(directory_url, &storage_dir, true);
+
+            std::fs::write(conf.private_key_path(), "previous key").unwrap();
+            std::fs::write(conf.certificate_path(), "previous certificate").unwrap();
+
+            obtain_certificate(&conf, &challenges).await.unwrap_err();
+
+            assert_eq!(
+                std::fs::read_to_string(conf.private_key_path()).unwrap(),
+                "previous key"
+            );
+            assert_eq!(
+                std::fs::read_to_string(conf.certificate_path()).unwrap(),
+                "previous certificate"
+            );
+        }
+    }
+
+    #[test]
+    fn placeholder_certificate_is_accepted_by_rustls() {
+        let cert_source = placeholder_certificate(DOMAIN).unwrap();
+        crate::tls::build_server_config(cert_source, Vec::new()).unwrap();
+    }
+}
@@ -0,0 +1,212 @@
+//! X.509 structures involved in ACME
+//!
+//! Everything is signed using SHA-256, with either RSA (PKCS#1 v1.5) or ECDSA depending on the key.
+
+use anyhow::Context as _;
+use oid::ObjectIdentifier;
+use picky::hash::HashAlgorithm;
+use picky::key::{PrivateKey, PrivateKeyKind};
+use picky::signature::SignatureAlgorithm;
+use picky::x509::date::UtcDate;
+use picky::x509::name::DirectoryName;
+use picky::x509::{Cert, CertificateBuilder, Csr};
+use picky_asn1::restricted_string::IA5String;
+use picky_asn1::wrapper::{Asn1SequenceOf, ObjectIdentifierAsn1, OctetStringAsn1, OctetStringAsn1Container};
+use picky_asn1_x509::{Attribute, Extension, GeneralName};
+use time::OffsetDateTime;
+
+// RFC 8737, section 6.1
+const OID_ACME_IDENTIFIER: &str = "1.3.6.1.5.5.7.1.31";
+
+/// Builds a PKCS#10 certificate signing request for the given domains.
+///
+/// The first domain is used as the common name, and all of them are listed in the subject alternative names.
+pub(crate) fn certificate_signing_request(key: &PrivateKey, domains: &[String]) -> anyhow::Result<Vec<u8>> {
+    let common_name = domains.first().context("no domain to certify")?;
+
+    Csr::generate_with_attributes(
+        DirectoryName::new_common_name(common_name.as_str()),
+        key,
+        signature_algorithm(key)?,
+        vec![Attribute::new_extension_request(vec![subject_alt_name(domains)?])],
+    )
+    .context("failed to generate certificate signing request")?
+    .to_der()
+    .context("failed to encode certificate signing request")
+}
+
+/// Builds a self-signed certificate for the domain.
+///
+/// When specified, the SHA-256 digest of a key authorization is embedded in the critical `acmeIdentifier` extension,
+/// as required for the TLS-ALPN-01 challenge.
+pub(crate) fn self_signed_certificate(
+    key: &PrivateKey,
+    domain: &str,
+    not_after: OffsetDateTime,
+    acme_identifier: Option<&[u8]>,
+) -> anyhow::Result<Vec<u8>> {
+    let not_before = OffsetDateTime::now_utc() - time::Duration::hours(1);
+
+    let mut extensions = vec![subject_alt_name(&[domain.to_owned()])?];
+
+    if let Some(digest) = acme_identifier {
+        extensions.push(acme_identifier_extension(digest)?);
+    }
+
+    // The certificate builder has no way to add arbitrary extensions, so they are carried by a request instead.
+    let signature_algorithm = signature_algorithm(key)?;
+
+    let request = Csr::generate_with_attributes(
+        DirectoryName::new_common_name(domain),
+        key,
+        signature_algorithm,
+        vec![Attribute::new_extension_request(extensions)],
+    )
+    .context("failed to generate certificate signing request")?;
+
+    CertificateBuilder::new()
+        .validity(utc_date(not_before)?, utc_date(not_after)?)
+        .self_signed(DirectoryName::new_common_name(domain), key)
+        .subject_from_csr(request)
+        .inherit_extensions_from_csr_attributes(true)
+        .signature_hash_type(signature_algorithm)
+        .build()
+        .context("failed to build certificate")?
+        .to_der()
+        .context("failed to encode certificate")
+}
+
+/// Reads the expiration date of a DER-encoded certificate.
+pub(crate) fn certificate_not_after(certificate: &[u8]) -> anyhow::Result<OffsetDateTime> {
+    let not_after = Cert::from_der(certificate)
+        .context("failed to parse certificate")?
+        .valid_not_after();
+
+    let date = time::Date::from_calendar_date(
+        i32::from(not_after.year()),
+        time::Month::try_from(not_after.month()).context("invalid month")?,
+        not_after.day(),
+    )
+    .context("invalid date")?;
+
+    Ok(date
+        .with_hms(not_after.hour(), not_after.minute(), not_after.second())
+        .context("invalid time")?
+        .assume_utc())
+}
+
+/// Picks the signature algorithm matching the type of the key.
+fn signature_algorithm(key: &PrivateKey) -> anyhow::Result<SignatureAlgorithm> {
+    match key.kind() {
+        PrivateKeyKind::Rsa => Ok(SignatureAlgorithm::RsaPkcs1v15(HashAlgorithm::SHA2_256)),
+        PrivateKeyKind::Ec { .. } => Ok(SignatureAlgorithm::Ecdsa(HashAlgorithm::SHA2_256)),
+        _ => anyhow::bail!("unsupported key type"),
+    }
+}
+
+fn subject_alt_name(domains: &[String]) -> anyhow::Result<Extension> {
+    let names = domains
+        .iter()
+        .map(|domain| {
+            IA5String::from_string(domain.clone())
+                .map(|domain| GeneralName::DnsName(domain.into()))
+                .with_context(|| format!("invalid domain: {domain}"))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    Ok(Extension::new_subject_alt_name(Asn1SequenceOf(names)))
+}
+
+fn acme_identifier_extension(digest: &[u8]) -> anyhow::Result<Extension> {
+    // Unknown extensions are decoded as-is, which is the only way to build one.
+    #[derive(Serialize)]
+    struct RawExtension {
+        extn_id: ObjectIdentifierAsn1,
+        critical: bool,
+        extn_value: OctetStringAsn1Container<OctetStringAsn1>,
+    }
+
+    let extension = RawExtension {
+        extn_id: ObjectIdentifier::try_from(OID_ACME_IDENTIFIER)
+            .expect("valid OID")
+            .into(),
+        critical: true,
+        extn_value: OctetStringAsn1Container(digest.to_vec().into()),
+    };
+
+    let extension = picky_asn1_der::to_vec(&extension).context("failed to encode acmeIdentifier extension")?;
+
+    picky_asn1_der::from_bytes(&extension).context("failed to decode acmeIdentifier extension")
+}
+
+fn utc_date(date: OffsetDateTime) -> anyhow::Result<UtcDate> {
+    let date = date.to_offset(time::UtcOffset::UTC);
+
+    UtcDate::new(
+        u16::try_from(date.year()).context("year out of range")?,
+        u8::from(date.month()),
+        date.day(),
+        date.hour(),
+        date.minute(),
+        date.second(),
+    )
+    .context("invalid date")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dns_names(domains: &[&str]) -> Extension {
+        subject_alt_name(&domains.iter().map(|domain| (*domain).to_owned()).collect::<Vec<_>>()).unwrap()
+    }
+
+    #[test]
+    fn validation_certificate() {
+        let key = PrivateKey::generate_rsa(2048).unwrap();
+        let digest = [0xAB; 32];
+        let not_after = OffsetDateTime::now_utc() + time::Duration::days(1);
+
+        let certificate = self_signed_certificate(&key, "gateway.example.test", not_after, Some(&digest)).unwrap();
+
+        assert_eq!(
+            certificate_not_after(&certificate).unwrap().unix_timestamp(),
+            not_after.unix_timestamp()
+        );
+
+        let certificate = Cert::from_der(&certificate).unwrap();
+        let extensions = certificate.extensions();
+
+        assert!(extensions.contains(&dns_names(&["gateway.example.test"])));
+        assert!(extensions.contains(&acme_identifier_extension(&digest).unwrap()));
+    }
+
+    #[test]
+    fn certificate_signing_request_lists_all_domains() {
+        let key = PrivateKey::generate_rsa(2048).unwrap();
+        let domains = ["a.example.test".to_owned(), "b.example.test".to_owned()];
+
+        let request = certificate_signing_request(&key, &domains).unwrap();
+        let request = Csr::from_der(&request).unwrap();
+
+        assert_eq!(
+            request.public_key().to_der().unwrap(),
+            key.to_public_key().unwrap().to_der().unwrap()
+        );
+
+        // The requested extensions are only exposed once carried over to a certificate.
+        let now = OffsetDateTime::now_utc();
+        let certificate = CertificateBuilder::new()
+            .validity(utc_date(now).unwrap(), utc_date(now + time::Duration::days(1)).unwrap())
+            .self_signed(DirectoryName::new_common_name("issuer"), &key)
+            .subject_from_csr(request)
+            .inherit_extensions_from_csr_attributes(true)
+            .signature_hash_type(signature_algorithm(&key).unwrap())
+            .build()
+            .unwrap();
+
+        assert!(certificate
+            .extensions()
+            .contains(&dns_names(&["a.example.test", "b.example.test"])));
+    }
+}
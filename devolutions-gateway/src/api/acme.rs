@@ -0,0 +1,14 @@
+use axum::extract::{Path, State};
+
+use crate::http::HttpError;
+use crate::DgwState;
+
+/// Serves the key authorization of a pending ACME HTTP-01 challenge
+pub(super) async fn get_http_01_challenge(
+    State(DgwState { acme_challenges, .. }): State<DgwState>,
+    Path(token): Path<String>,
+) -> Result<String, HttpError> {
+    acme_challenges
+        .http_01_key_authorization(&token)
+        .ok_or_else(|| HttpError::not_found().msg("no pending challenge for this token"))
+}
@@ -3,13 +3,16 @@ use crate::extract::ConfigWriteScope;
 use crate::http::HttpError;
 use crate::DgwState;
 use axum::extract::State;
-use axum::routing::patch;
+use axum::routing::{patch, post};
 use axum::{Json, Router};
 use tap::prelude::*;
 use uuid::Uuid;
 
 pub fn make_router<S>(state: DgwState) -> Router<S> {
-    Router::new().route("/", patch(patch_config)).with_state(state)
+    Router::new()
+        .route("/", patch(patch_config))
+        .route("/tls/reload", post(reload_tls_certificate))
+        .with_state(state)
 }
 
 #[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
@@ -101,3 +104,41 @@ async fn patch_config(
 
     Ok(())
 }
+
+/// Reloads the TLS certificate
+///
+/// The certificate and private key files are read again, and used for the new TLS handshakes.
+/// The current certificate is kept in service when the new one is invalid.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    operation_id = "ReloadTlsCertificate",
+    tag = "Config",
+    path = "/jet/config/tls/reload",
+    responses(
+        (status = 200, description = "TLS certificate has been reloaded"),
+        (status = 400, description = "TLS certificate is not read from files"),
+        (status = 401, description = "Invalid or missing authorization token"),
+        (status = 403, description = "Insufficient permissions"),
+        (status = 500, description = "Failed to reload TLS certificate"),
+    ),
+    security(("scope_token" = ["gateway.config.write"])),
+))]
+async fn reload_tls_certificate(
+    _scope: ConfigWriteScope,
+    State(DgwState { conf_handle, .. }): State<DgwState>,
+) -> Result<(), HttpError> {
+    let conf = conf_handle.get_conf();
+
+    let tls = conf
+        .tls
+        .as_ref()
+        .filter(|tls| tls.certificate_files().is_some())
+        .ok_or_else(|| HttpError::bad_request().msg("TLS certificate is not read from files"))?;
+
+    tls.reload()
+        .map_err(HttpError::internal().with_msg("failed to reload TLS certificate").err())?;
+
+    info!("TLS certificate reloaded");
+
+    Ok(())
+}
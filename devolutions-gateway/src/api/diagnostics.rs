@@ -50,6 +50,8 @@ impl From<&Conf> for ConfigDiagnostic {
                             Ok(url) => listeners.push(ListenerUrls {
                                 internal_url: url.clone(),
                                 external_url: url.clone(),
+                                proxy_protocol: None,
+                                unix_socket: None,
                             }),
                             Err(error) => {
                                 warn!(?tcp_tunnel, %error, "invalid URL for Ngrok TCP tunnel");
@@ -63,6 +65,8 @@ impl From<&Conf> for ConfigDiagnostic {
                             Ok(url) => listeners.push(ListenerUrls {
                                 internal_url: url.clone(),
                                 external_url: url.clone(),
+                                proxy_protocol: None,
+                                unix_socket: None,
                             }),
                             Err(error) => {
                                 warn!(?http_tunnel, %error, "invalid URL for Ngrok HTTP tunnel");
@@ -16,6 +16,7 @@ use crate::config::Conf;
 use crate::extract::AssociationToken;
 use crate::http::HttpError;
 use crate::proxy::Proxy;
+use crate::recording::RecordingMessageSender;
 use crate::session::{ConnectionModeDetails, SessionInfo, SessionMessageSender};
 use crate::subscriber::SubscriberSender;
 use crate::token::{ApplicationProtocol, AssociationTokenClaims, ConnectionMode, Protocol};
@@ -33,6 +34,7 @@ async fn fwd_tcp(
         conf_handle,
         sessions,
         subscriber_tx,
+        recordings,
         ..
     }): State<DgwState>,
     AssociationToken(claims): AssociationToken,
@@ -48,7 +50,17 @@ async fn fwd_tcp(
     let span = tracing::Span::current();
 
     let response = ws.on_upgrade(move |ws| {
-        handle_fwd(ws, conf, sessions, subscriber_tx, claims, source_addr, false).instrument(span)
+        handle_fwd(
+            ws,
+            conf,
+            sessions,
+            subscriber_tx,
+            recordings,
+            claims,
+            source_addr,
+            false,
+        )
+        .instrument(span)
     });
 
     Ok(response)
@@ -59,6 +71,7 @@ async fn fwd_tls(
         conf_handle,
         sessions,
         subscriber_tx,
+        recordings,
         ..
     }): State<DgwState>,
     AssociationToken(claims): AssociationToken,
@@ -74,17 +87,19 @@ async fn fwd_tls(
     let span = tracing::Span::current();
 
     let response = ws.on_upgrade(move |ws| {
-        handle_fwd(ws, conf, sessions, subscriber_tx, claims, source_addr, true).instrument(span)
+        handle_fwd(ws, conf, sessions, subscriber_tx, recordings, claims, source_addr, true).instrument(span)
     });
 
     Ok(response)
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn handle_fwd(
     ws: WebSocket,
     conf: Arc<Conf>,
     sessions: SessionMessageSender,
     subscriber_tx: SubscriberSender,
+    recordings: RecordingMessageSender,
     claims: AssociationTokenClaims,
     source_addr: SocketAddr,
     with_tls: bool,
@@ -105,6 +120,7 @@ async fn handle_fwd(
         .claims(claims)
         .sessions(sessions)
         .subscriber_tx(subscriber_tx)
+        .recordings(recordings)
         .with_tls(with_tls)
         .build()
         .run()
@@ -126,6 +142,7 @@ struct Forward<S> {
     client_addr: SocketAddr,
     sessions: SessionMessageSender,
     subscriber_tx: SubscriberSender,
+    recordings: RecordingMessageSender,
     with_tls: bool,
 }
 
@@ -141,10 +158,12 @@ where
             client_addr,
             sessions,
             subscriber_tx,
+            recordings,
             with_tls,
         } = self;
 
-        if claims.jet_rec {
+        // Only the sessions the gateway is able to record by itself can meet the recording policy.
+        if claims.jet_rec && !crate::proxy::is_recordable(&claims.jet_ap) {
             anyhow::bail!("can't meet recording policy");
         }
 
@@ -156,8 +175,8 @@ where
 
         trace!("Select and connect to target");
 
-        let ((server_stream, server_addr), selected_target) =
-            utils::successive_try(&targets, utils::tcp_connect).await?;
+        let ((server_stream, _), selected_target) = utils::tcp_connect(&targets, &conf.target_connection).await?;
+        let server_addr = server_stream.peer_addr()?;
 
         trace!(%selected_target, "Connected");
         span.record("target", selected_target.to_string());
@@ -174,7 +193,9 @@ where
 
             // Establish TLS connection with server
 
-            let server_stream = crate::tls::connect(selected_target.host(), server_stream)
+            let server_stream = conf
+                .target_tls
+                .connect(selected_target.host(), server_stream, &claims.jet_tls_pins)
                 .await
                 .context("TLS connect")?;
 
@@ -200,6 +221,7 @@ where
                 .transport_b(server_stream)
                 .sessions(sessions)
                 .subscriber_tx(subscriber_tx)
+                .recordings(Some(recordings))
                 .buffer_size(buffer_size)
                 .build()
                 .select_dissector_and_forward()
@@ -228,6 +250,7 @@ where
                 .transport_b(server_stream)
                 .sessions(sessions)
                 .subscriber_tx(subscriber_tx)
+                .recordings(Some(recordings))
                 .buffer_size(buffer_size)
                 .build()
                 .select_dissector_and_forward()
@@ -236,3 +259,83 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use nonempty::NonEmpty;
+    use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::target_addr::TargetAddr;
+    use crate::test_utils::mock_state;
+    use crate::token::SessionTtl;
+
+    fn forward<S>(state: &DgwState, claims: AssociationTokenClaims, client_stream: S) -> Forward<S> {
+        Forward::builder()
+            .conf(state.conf_handle.get_conf())
+            .claims(claims)
+            .client_stream(client_stream)
+            .client_addr(SocketAddr::from(([127, 0, 0, 1], 3000)))
+            .sessions(state.sessions.clone())
+            .subscriber_tx(state.subscriber_tx.clone())
+            .recordings(state.recordings.clone())
+            .with_tls(false)
+            .build()
+    }
+
+    fn recorded_session_claims(protocol: Protocol, target: SocketAddr) -> AssociationTokenClaims {
+        AssociationTokenClaims {
+            jet_aid: Uuid::new_v4(),
+            jet_ap: ApplicationProtocol::Known(protocol),
+            jet_cm: ConnectionMode::Fwd {
+                targets: NonEmpty::new(TargetAddr::parse(&format!("tcp://{target}"), None).unwrap()),
+                creds: None,
+            },
+            jet_rec: true,
+            jet_flt: false,
+            jet_ttl: SessionTtl::Unlimited,
+            jet_tls_pins: Vec::new(),
+            exp: 0,
+            jti: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn telnet_session_requiring_recording_is_forwarded() {
+        let (state, _handles) = mock_state(serde_json::json!({}));
+
+        let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let claims = recorded_session_claims(Protocol::Telnet, target.local_addr().unwrap());
+
+        let (mut client, client_stream) = tokio::io::duplex(1024);
+
+        tokio::spawn(forward(&state, claims, client_stream).run());
+
+        // The gateway records Telnet sessions by itself, so the session is not rejected upfront.
+        let (mut server, _) = tokio::time::timeout(Duration::from_secs(5), target.accept())
+            .await
+            .unwrap()
+            .unwrap();
+
+        client.write_all(b"login").await.unwrap();
+        let mut received = [0; 5];
+        server.read_exact(&mut received).await.unwrap();
+        assert_eq!(&received, b"login");
+    }
+
+    #[tokio::test]
+    async fn ssh_session_requiring_recording_is_rejected() {
+        let (state, _handles) = mock_state(serde_json::json!({}));
+
+        let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let claims = recorded_session_claims(Protocol::Ssh, target.local_addr().unwrap());
+
+        let (_client, client_stream) = tokio::io::duplex(1024);
+
+        let error = forward(&state, claims, client_stream).run().await.unwrap_err();
+        assert!(format!("{error:#}").contains("can't meet recording policy"));
+    }
+}
@@ -4,6 +4,7 @@ use axum::response::{IntoResponse, Response};
 use axum::Json;
 use uuid::Uuid;
 
+use crate::recording::RecordingStorageStatus;
 use crate::DgwState;
 
 #[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
@@ -16,6 +17,9 @@ pub(crate) struct Identity {
     /// Gateway service version
     #[serde(skip_serializing_if = "Option::is_none")]
     version: Option<&'static str>,
+    /// State of the recording storage, as of the last periodic check
+    #[serde(skip_serializing_if = "Option::is_none")]
+    recording_storage: Option<RecordingStorageStatus>,
 }
 
 pub(super) enum HealthResponse {
@@ -46,7 +50,11 @@ impl IntoResponse for HealthResponse {
     ),
 ))]
 pub(super) async fn get_health(
-    State(DgwState { conf_handle, .. }): State<DgwState>,
+    State(DgwState {
+        conf_handle,
+        recordings,
+        ..
+    }): State<DgwState>,
     headers: HeaderMap,
 ) -> HealthResponse {
     let conf = conf_handle.get_conf();
@@ -62,6 +70,7 @@ pub(super) async fn get_health(
                 id: conf.id,
                 hostname: conf.hostname.clone(),
                 version: Some(env!("CARGO_PKG_VERSION")),
+                recording_storage: Some(recordings.storage.status()),
             });
         }
     }
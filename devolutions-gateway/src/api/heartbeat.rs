@@ -4,6 +4,7 @@ use uuid::Uuid;
 
 use crate::extract::HeartbeatReadScope;
 use crate::http::HttpError;
+use crate::recording::RecordingStorageStatus;
 use crate::DgwState;
 
 #[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
@@ -17,6 +18,8 @@ pub(crate) struct Heartbeat {
     version: &'static str,
     /// Number of running sessions
     running_session_count: usize,
+    /// State of the recording storage, as of the last periodic check
+    recording_storage: RecordingStorageStatus,
 }
 
 /// Performs a heartbeat check
@@ -35,7 +38,10 @@ pub(crate) struct Heartbeat {
 ))]
 pub(super) async fn get_heartbeat(
     State(DgwState {
-        conf_handle, sessions, ..
+        conf_handle,
+        sessions,
+        recordings,
+        ..
     }): State<DgwState>,
     _scope: HeartbeatReadScope,
 ) -> Result<Json<Heartbeat>, HttpError> {
@@ -51,5 +57,6 @@ pub(super) async fn get_heartbeat(
         hostname: conf.hostname.clone(),
         version: env!("CARGO_PKG_VERSION"),
         running_session_count,
+        recording_storage: recordings.storage.status(),
     }))
 }
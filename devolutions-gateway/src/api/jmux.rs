@@ -1,10 +1,12 @@
 use std::net::SocketAddr;
+use std::sync::Arc;
 
 use axum::extract::ws::WebSocket;
 use axum::extract::{ConnectInfo, State, WebSocketUpgrade};
 use axum::response::Response;
 use tracing::Instrument as _;
 
+use crate::config::Conf;
 use crate::extract::JmuxToken;
 use crate::http::HttpError;
 use crate::session::SessionMessageSender;
@@ -14,6 +16,7 @@ use crate::DgwState;
 
 pub async fn handler(
     State(DgwState {
+        conf_handle,
         sessions,
         subscriber_tx,
         ..
@@ -22,13 +25,16 @@ pub async fn handler(
     ConnectInfo(source_addr): ConnectInfo<SocketAddr>,
     ws: WebSocketUpgrade,
 ) -> Result<Response, HttpError> {
-    let response = ws.on_upgrade(move |ws| handle_socket(ws, sessions, subscriber_tx, claims, source_addr));
+    let conf = conf_handle.get_conf();
+
+    let response = ws.on_upgrade(move |ws| handle_socket(ws, conf, sessions, subscriber_tx, claims, source_addr));
 
     Ok(response)
 }
 
 async fn handle_socket(
     ws: WebSocket,
+    conf: Arc<Conf>,
     sessions: SessionMessageSender,
     subscriber_tx: SubscriberSender,
     claims: JmuxTokenClaims,
@@ -36,7 +42,7 @@ async fn handle_socket(
 ) {
     let stream = crate::ws::websocket_compat(ws);
 
-    let result = crate::jmux::handle(stream, claims, sessions, subscriber_tx)
+    let result = crate::jmux::handle(stream, conf, claims, sessions, subscriber_tx)
         .instrument(info_span!("jmux", client = %source_addr))
         .await;
 
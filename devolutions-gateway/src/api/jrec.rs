@@ -1,28 +1,40 @@
-use std::fs;
 use std::net::SocketAddr;
-use std::path::Path;
+use std::ops::{Bound, RangeInclusive};
 
-use anyhow::Context as _;
 use axum::extract::ws::WebSocket;
 use axum::extract::{self, ConnectInfo, Query, State, WebSocketUpgrade};
-use axum::response::Response;
-use axum::routing::get;
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse as _, Response};
+use axum::routing::{delete, get, post};
 use axum::{Json, Router};
+use axum_extra::headers::{self, HeaderMapExt as _};
 use devolutions_gateway_task::ShutdownSignal;
 use tracing::Instrument as _;
 use uuid::Uuid;
 
-use crate::extract::{JrecToken, RecordingsReadScope};
+use crate::extract::{JrecToken, RecordingsDeleteScope, RecordingsImportScope, RecordingsReadScope};
 use crate::http::HttpError;
-use crate::recording::RecordingMessageSender;
+use crate::recording::{
+    recording_storage, FetchedFiles, RecordingFileReader, RecordingInfo, RecordingIntegrity, RecordingMessageSender,
+    RecordingQuery, RecordingSearchResult, RecordingSortKey, RemoveError, SortOrder,
+};
 use crate::token::{JrecTokenClaims, RecordingFileType, RecordingOperation};
 use crate::DgwState;
 
 pub fn make_router<S>(state: DgwState) -> Router<S> {
     Router::new()
         .route("/push/:id", get(jrec_push))
+        .route("/push/:id/offset", get(get_push_resume_offset))
+        .route("/delete/:id", delete(delete_recording))
+        .route("/delete", delete(delete_many_recordings))
         .route("/list", get(list_recordings))
+        .route("/search", get(search_recordings))
+        .route("/pull/:id/merged", get(pull_merged_recording))
         .route("/pull/:id/:filename", get(pull_recording_file))
+        .route("/shadow/:id", get(shadow_recording))
+        .route("/verify/:id", get(verify_recording))
+        .route("/export/:id", get(export_recording))
+        .route("/import", post(import_recording))
         .route("/play", get(get_player))
         .route("/play/*path", get(get_player))
         .with_state(state)
@@ -32,6 +44,8 @@ pub fn make_router<S>(state: DgwState) -> Router<S> {
 #[serde(rename_all = "camelCase")]
 struct JrecPushQueryParam {
     file_type: RecordingFileType,
+    /// Offset at which the upload of the last recording file is resumed
+    resume_offset: Option<u64>,
 }
 
 async fn jrec_push(
@@ -57,6 +71,7 @@ async fn jrec_push(
             shutdown_signal,
             claims,
             query.file_type,
+            query.resume_offset,
             session_id,
             source_addr,
         )
@@ -65,12 +80,52 @@ async fn jrec_push(
     Ok(response)
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JrecResumeQueryParam {
+    file_type: RecordingFileType,
+}
+
+#[derive(Serialize)]
+struct JrecResumeOffset {
+    /// Size of the last recording file, where the upload should be resumed
+    offset: u64,
+}
+
+/// Retrieves the offset at which a client may resume the upload of the last recording file
+///
+/// The upload is resumed by passing this offset as the `resumeOffset` query parameter of the push endpoint.
+async fn get_push_resume_offset(
+    State(DgwState { recordings, .. }): State<DgwState>,
+    JrecToken(claims): JrecToken,
+    Query(query): Query<JrecResumeQueryParam>,
+    extract::Path(session_id): extract::Path<Uuid>,
+) -> Result<Json<JrecResumeOffset>, HttpError> {
+    if claims.jet_rop != RecordingOperation::Push {
+        return Err(HttpError::forbidden().msg("expected push operation"));
+    }
+
+    if session_id != claims.jet_aid {
+        return Err(HttpError::forbidden().msg("inconsistent session ID"));
+    }
+
+    match recordings.get_resume_offset(session_id, query.file_type).await {
+        Ok(offset) => Ok(Json(JrecResumeOffset { offset })),
+        Err(error) => {
+            debug!(%session_id, error = format!("{error:#}"), "Recording can't be resumed");
+            Err(HttpError::not_found().msg("no resumable recording file"))
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn handle_jrec_push(
     ws: WebSocket,
     recordings: RecordingMessageSender,
     shutdown_signal: ShutdownSignal,
     claims: JrecTokenClaims,
     file_type: RecordingFileType,
+    resume_offset: Option<u64>,
     session_id: Uuid,
     source_addr: SocketAddr,
 ) {
@@ -83,6 +138,7 @@ async fn handle_jrec_push(
         .file_type(file_type)
         .session_id(session_id)
         .shutdown_signal(shutdown_signal)
+        .resume_offset(resume_offset)
         .build()
         .run()
         .instrument(info_span!("jrec", client = %source_addr))
@@ -93,33 +149,192 @@ async fn handle_jrec_push(
     }
 }
 
-fn list_uuid_dirs(dir_path: &Path) -> anyhow::Result<Vec<Uuid>> {
-    let read_dir = fs::read_dir(dir_path).context("couldn’t read directory")?;
+/// Deletes a recording stored on this instance
+#[cfg_attr(feature = "openapi", utoipa::path(
+    delete,
+    operation_id = "DeleteRecording",
+    tag = "Jrec",
+    path = "/jet/jrec/delete/{id}",
+    params(
+        ("id" = Uuid, Path, description = "Recorded session ID"),
+    ),
+    responses(
+        (status = 200, description = "Recording deleted"),
+        (status = 400, description = "Bad request"),
+        (status = 401, description = "Invalid or missing authorization token"),
+        (status = 403, description = "Insufficient permissions"),
+        (status = 404, description = "The specified recording was not found"),
+        (status = 409, description = "The recording is still ongoing and can't be deleted yet"),
+    ),
+    security(("scope_token" = ["gateway.recordings.delete"])),
+))]
+pub(crate) async fn delete_recording(
+    State(DgwState {
+        conf_handle,
+        recordings,
+        ..
+    }): State<DgwState>,
+    extract::Path(id): extract::Path<Uuid>,
+    _scope: RecordingsDeleteScope,
+) -> Result<(), HttpError> {
+    let conf = conf_handle.get_conf();
+
+    debug!(%id, "Delete recording");
 
-    let list = read_dir
-        .filter_map(|entry| {
-            let path = entry.ok()?.path();
-            if path.is_dir() {
-                let file_name = path.file_name()?.to_str()?;
-                let uuid = Uuid::parse_str(file_name).ok()?;
-                Some(uuid)
-            } else {
-                None
-            }
-        })
-        .collect();
+    crate::recording::remove_recording_folder(
+        &conf.recording_path,
+        id,
+        &recordings.active_recordings,
+        &recordings.index,
+        &*recording_storage(&conf),
+    )
+    .await
+    .map_err(remove_error_to_http)
+}
 
-    Ok(list)
+fn remove_error_to_http(e: RemoveError) -> HttpError {
+    match e {
+        RemoveError::NotFound(_) => HttpError::not_found().with_msg("requested recording does not exist").err()(e),
+        RemoveError::Ongoing(_) => HttpError::conflict()
+            .with_msg("attempted to delete a recording for an ongoing session")
+            .err()(e),
+        RemoveError::Other(_) => HttpError::internal().with_msg("failed to delete recording").err()(e),
+    }
 }
 
-/// Lists all recordings stored on this instance
+#[derive(Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub(crate) struct DeleteManyResult {
+    /// Number of recordings found and deleted
+    found_count: usize,
+    /// Number of recordings not found
+    not_found_count: usize,
+}
+
+/// Deletes many recordings stored on this instance
+///
+/// No recording is deleted if any of the specified recordings is still ongoing.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    delete,
+    operation_id = "DeleteManyRecordings",
+    tag = "Jrec",
+    path = "/jet/jrec/delete",
+    request_body(content = Vec<Uuid>, description = "JSON-encoded list of session IDs", content_type = "application/json"),
+    responses(
+        (status = 200, description = "Recordings deleted", body = DeleteManyResult),
+        (status = 400, description = "Bad request"),
+        (status = 401, description = "Invalid or missing authorization token"),
+        (status = 403, description = "Insufficient permissions"),
+        (status = 409, description = "A recording is still ongoing and can't be deleted yet"),
+    ),
+    security(("scope_token" = ["gateway.recordings.delete"])),
+))]
+pub(crate) async fn delete_many_recordings(
+    State(DgwState {
+        conf_handle,
+        recordings,
+        ..
+    }): State<DgwState>,
+    _scope: RecordingsDeleteScope,
+    Json(delete_list): Json<Vec<Uuid>>,
+) -> Result<Json<DeleteManyResult>, HttpError> {
+    let conf = conf_handle.get_conf();
+    let recording_path = conf.recording_path.clone();
+    let storage = recording_storage(&conf);
+
+    if let Some(id) = delete_list
+        .iter()
+        .find(|id| recordings.active_recordings.contains(**id))
+    {
+        debug!(%id, "Refuse to delete an ongoing recording");
+        return Err(HttpError::conflict().msg("attempted to delete a recording for an ongoing session"));
+    }
+
+    let mut found_count = 0;
+    let mut not_found_count = 0;
+
+    for id in delete_list {
+        debug!(%id, "Delete recording");
+
+        match crate::recording::remove_recording_folder(
+            &recording_path,
+            id,
+            &recordings.active_recordings,
+            &recordings.index,
+            &*storage,
+        )
+        .await
+        {
+            Ok(()) => found_count += 1,
+            Err(RemoveError::NotFound(_)) => not_found_count += 1,
+            Err(error) => return Err(remove_error_to_http(error)),
+        }
+    }
+
+    Ok(Json(DeleteManyResult {
+        found_count,
+        not_found_count,
+    }))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ListRecordingsQueryParam {
+    #[serde(default)]
+    detailed: bool,
+    from: Option<i64>,
+    to: Option<i64>,
+    file_type: Option<RecordingFileType>,
+    min_duration: Option<i64>,
+    #[serde(default)]
+    sort: RecordingSortKey,
+    #[serde(default)]
+    order: SortOrder,
+    cursor: Option<String>,
+    limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub(crate) struct RecordingList {
+    /// Recordings of the current page
+    recordings: Vec<RecordingInfo>,
+    /// Cursor to pass in order to retrieve the next page, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_cursor: Option<String>,
+}
+
+#[derive(Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(untagged)]
+pub(crate) enum ListRecordingsResponse {
+    Ids(Vec<Uuid>),
+    Detailed(RecordingList),
+}
+
+/// Lists the recordings stored on this instance
+///
+/// Only the IDs of the recordings are returned, unless `detailed` is set.
+/// Pagination is only available for the detailed listing.
+/// The recordings held by the storage backend are listed as well, as of the last listing of the backend (at startup, then every 5 minutes).
 #[cfg_attr(feature = "openapi", utoipa::path(
     get,
     operation_id = "ListRecordings",
     tag = "Jrec",
     path = "/jet/jrec/list",
+    params(
+        ("detailed" = Option<bool>, Query, description = "Whether to return the recording metadata and paginate the results"),
+        ("from" = Option<i64>, Query, description = "Only recordings started at or after this Unix timestamp"),
+        ("to" = Option<i64>, Query, description = "Only recordings started at or before this Unix timestamp"),
+        ("fileType" = Option<String>, Query, description = "Only recordings containing a file of this type (webm, trp or cast)"),
+        ("minDuration" = Option<i64>, Query, description = "Only recordings lasting at least this number of seconds"),
+        ("sort" = Option<String>, Query, description = "Sort key: startTime (default), duration or totalSize"),
+        ("order" = Option<String>, Query, description = "Sort order: asc or desc (default)"),
+        ("cursor" = Option<String>, Query, description = "Cursor returned along with the previous page"),
+        ("limit" = Option<usize>, Query, description = "Maximum number of recordings per page (default is 100, at most 1000)"),
+    ),
     responses(
-        (status = 200, description = "List of recordings on this Gateway instance", body = [Uuid]),
+        (status = 200, description = "List of recordings on this Gateway instance", body = ListRecordingsResponse),
         (status = 400, description = "Bad request"),
         (status = 401, description = "Invalid or missing authorization token"),
         (status = 403, description = "Insufficient permissions"),
@@ -127,23 +342,54 @@ fn list_uuid_dirs(dir_path: &Path) -> anyhow::Result<Vec<Uuid>> {
     security(("scope_token" = ["gateway.recordings.read"])),
 ))]
 pub(crate) async fn list_recordings(
-    State(DgwState { conf_handle, .. }): State<DgwState>,
+    State(DgwState { recordings, .. }): State<DgwState>,
     _scope: RecordingsReadScope,
-) -> Result<Json<Vec<Uuid>>, HttpError> {
-    let conf = conf_handle.get_conf();
-    let recording_path = conf.recording_path.as_std_path();
+    Query(query): Query<ListRecordingsQueryParam>,
+) -> Result<Json<ListRecordingsResponse>, HttpError> {
+    const DEFAULT_PAGE_SIZE: usize = 100;
+    const MAX_PAGE_SIZE: usize = 1000;
+
+    let (cursor, limit) = if query.detailed {
+        let cursor = query
+            .cursor
+            .as_deref()
+            .map(str::parse)
+            .transpose()
+            .map_err(HttpError::bad_request().with_msg("invalid cursor").err())?;
 
-    let dirs = if recording_path.exists() {
-        list_uuid_dirs(recording_path).map_err(HttpError::internal().with_msg("failed recording listing").err())?
+        let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+
+        (cursor, Some(limit))
     } else {
-        // If the recording directory does not exist, it means that there is no recording yet
-        Vec::new()
+        (None, None)
     };
 
-    Ok(Json(dirs))
+    let (list, next_cursor) = recordings.index.query(&RecordingQuery {
+        from: query.from,
+        to: query.to,
+        file_type: query.file_type,
+        min_duration: query.min_duration,
+        sort: query.sort,
+        order: query.order,
+        cursor,
+        limit,
+    });
+
+    let response = if query.detailed {
+        ListRecordingsResponse::Detailed(RecordingList {
+            recordings: list,
+            next_cursor: next_cursor.map(|cursor| cursor.to_string()),
+        })
+    } else {
+        ListRecordingsResponse::Ids(list.into_iter().map(|info| info.session_id).collect())
+    };
+
+    Ok(Json(response))
 }
 
 /// Retrieves a recording file for a given session
+///
+/// Single byte ranges are supported, so the player can seek in long recordings without downloading everything.
 #[cfg_attr(feature = "openapi", utoipa::path(
     get,
     operation_id = "PullRecordingFile",
@@ -155,24 +401,21 @@ pub(crate) async fn list_recordings(
     ),
     responses(
         (status = 200, description = "Recording file", body = Vec<u8>),
+        (status = 206, description = "Requested range of the recording file", body = Vec<u8>),
         (status = 400, description = "Bad request"),
         (status = 401, description = "Invalid or missing authorization token"),
         (status = 403, description = "Insufficient permissions"),
         (status = 404, description = "File not found"),
+        (status = 416, description = "The requested range can't be satisfied"),
     ),
     security(("jrec_token" = ["pull"])),
 ))]
-pub(crate) async fn pull_recording_file<ReqBody>(
+pub(crate) async fn pull_recording_file(
     State(DgwState { conf_handle, .. }): State<DgwState>,
     extract::Path((id, filename)): extract::Path<(Uuid, String)>,
     JrecToken(claims): JrecToken,
-    request: axum::http::Request<ReqBody>,
-) -> Result<Response<tower_http::services::fs::ServeFileSystemResponseBody>, HttpError>
-where
-    ReqBody: Send + 'static,
-{
-    use tower::ServiceExt as _;
-
+    headers: HeaderMap,
+) -> Result<Response, HttpError> {
     if filename.contains("..") || filename.contains('/') || filename.contains('\\') {
         return Err(HttpError::bad_request().msg("invalid file name"));
     }
@@ -181,24 +424,586 @@ where
         return Err(HttpError::forbidden().msg("not allowed to read this recording"));
     }
 
-    let path = conf_handle
-        .get_conf()
-        .recording_path
-        .join(id.to_string())
-        .join(filename);
+    let conf = conf_handle.get_conf();
+
+    let recording_path = conf.recording_path.join(id.to_string());
+    let path = recording_path.join(&filename);
+
+    // The file may only be available from the storage backend.
+    let mut fetched_files = FetchedFiles::new(recording_storage(&conf), id);
 
-    if !path.exists() || !path.is_file() {
-        return Err(HttpError::not_found().msg("requested file does not exist"));
+    if !path.is_file() {
+        for file_name in ["recording.json", filename.as_str()] {
+            if let Err(error) = fetched_files.fetch(file_name).await {
+                debug!(%id, %file_name, error = format!("{error:#}"), "Recording file not found");
+                return Err(HttpError::not_found().msg("requested file does not exist"));
+            }
+        }
     }
 
-    let response = tower_http::services::ServeFile::new(path)
-        .oneshot(request)
+    // Encrypted recordings are decrypted on the fly.
+    let file = RecordingFileReader::open(&recording_path, &filename, conf.recording.encryption.as_ref())
+        .await
+        .map_err(HttpError::internal().with_msg("failed to open recording file").err())?;
+
+    let content_len = file.content_len();
+    let etag = file.etag().parse::<headers::ETag>().ok();
+    let last_modified = headers::LastModified::from(file.modified());
+
+    let content_type = match path.extension() {
+        Some("webm") => "video/webm",
+        _ => "application/octet-stream",
+    };
+
+    let mut response_headers = HeaderMap::new();
+    response_headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+    response_headers.typed_insert(headers::AcceptRanges::bytes());
+    response_headers.typed_insert(last_modified.clone());
+    if let Some(etag) = etag.clone() {
+        response_headers.typed_insert(etag);
+    }
+
+    // The range is ignored when the file was modified since the client retrieved the validator.
+    let range = headers.typed_get::<headers::Range>().filter(|_| {
+        headers.typed_get::<headers::IfRange>().map_or(true, |if_range| {
+            !if_range.is_modified(etag.as_ref(), Some(&last_modified))
+        })
+    });
+
+    let (status, range) = match range.map(|range| single_byte_range(&range, content_len)) {
+        Some(Ok(Some(range))) => {
+            let content_range = headers::ContentRange::bytes(range.clone(), content_len)
+                .map_err(|_| HttpError::internal().msg("invalid content range"))?;
+            response_headers.typed_insert(content_range);
+
+            (StatusCode::PARTIAL_CONTENT, range)
+        }
+        Some(Err(())) => {
+            response_headers.typed_insert(headers::ContentRange::unsatisfied_bytes(content_len));
+
+            return Ok((StatusCode::RANGE_NOT_SATISFIABLE, response_headers).into_response());
+        }
+        // Multiple ranges are not supported: the whole file is sent instead.
+        Some(Ok(None)) | None => (StatusCode::OK, 0..=content_len.saturating_sub(1)),
+    };
+
+    let body_len = if content_len == 0 {
+        0
+    } else {
+        range.end() - range.start() + 1
+    };
+
+    response_headers.typed_insert(headers::ContentLength(body_len));
+
+    let body = if body_len == 0 {
+        axum::body::Body::empty()
+    } else {
+        let stream = file
+            .read_range(range)
+            .await
+            .map_err(HttpError::internal().with_msg("failed to read recording file").err())?;
+        axum::body::Body::from_stream(fetched_files.attach(stream))
+    };
+
+    Ok((status, response_headers, body).into_response())
+}
+
+/// Returns the requested range when it's a single satisfiable range.
+///
+/// `Ok(None)` is returned for multiple ranges, and `Err(())` when the range can't be satisfied.
+fn single_byte_range(range: &headers::Range, content_len: u64) -> Result<Option<RangeInclusive<u64>>, ()> {
+    if content_len == 0 {
+        return Err(());
+    }
+
+    let mut ranges = range.satisfiable_ranges(content_len);
+
+    let Some((start, end)) = ranges.next() else {
+        return Err(());
+    };
+
+    if ranges.next().is_some() {
+        return Ok(None);
+    }
+
+    let last = content_len - 1;
+
+    let start = match start {
+        Bound::Included(start) => start,
+        Bound::Excluded(start) => start.saturating_add(1),
+        Bound::Unbounded => 0,
+    };
+
+    let end = match end {
+        Bound::Included(end) => end.min(last),
+        Bound::Excluded(end) => end.saturating_sub(1).min(last),
+        Bound::Unbounded => last,
+    };
+
+    if start > end {
+        return Err(());
+    }
+
+    Ok(Some(start..=end))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PullMergedQueryParam {
+    file_type: Option<RecordingFileType>,
+}
+
+/// Retrieves the recording files of a session merged into a single file
+///
+/// A session interrupted by reconnections is recorded as several files. The files of the requested type are
+/// concatenated without transcoding, and their timestamps are rewritten into one continuous stream.
+/// WebM files must have the same tracks, and the merged file holds no seeking information.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    operation_id = "PullMergedRecording",
+    tag = "Jrec",
+    path = "/jet/jrec/pull/{id}/merged",
+    params(
+        ("id" = Uuid, Path, description = "Recorded session ID"),
+        ("fileType" = Option<String>, Query, description = "Type of the files to merge (webm, trp or cast), the type of the first file by default"),
+    ),
+    responses(
+        (status = 200, description = "Merged recording file", body = Vec<u8>),
+        (status = 400, description = "Bad request"),
+        (status = 401, description = "Invalid or missing authorization token"),
+        (status = 403, description = "Insufficient permissions"),
+        (status = 404, description = "The specified recording, or a file of the requested type, was not found"),
+        (status = 409, description = "The recording is still ongoing"),
+    ),
+    security(("jrec_token" = ["pull"])),
+))]
+pub(crate) async fn pull_merged_recording(
+    State(DgwState {
+        conf_handle,
+        recordings,
+        ..
+    }): State<DgwState>,
+    extract::Path(id): extract::Path<Uuid>,
+    JrecToken(claims): JrecToken,
+    Query(query): Query<PullMergedQueryParam>,
+) -> Result<Response, HttpError> {
+    use crate::recording::MergeError;
+
+    if id != claims.jet_aid {
+        return Err(HttpError::forbidden().msg("not allowed to read this recording"));
+    }
+
+    if recordings.active_recordings.contains(id) {
+        return Err(HttpError::conflict().msg("attempted to merge a recording for an ongoing session"));
+    }
+
+    let conf = conf_handle.get_conf();
+
+    let mut fetched_files = FetchedFiles::new(recording_storage(&conf), id);
+
+    fetched_files.fetch("recording.json").await.map_err(
+        HttpError::not_found()
+            .with_msg("requested recording does not exist")
+            .err(),
+    )?;
+
+    let recording_path = conf.recording_path.join(id.to_string());
+
+    let (file_type, stream) = crate::recording::merge_recording(
+        recording_path,
+        query.file_type,
+        fetched_files,
+        conf.recording.encryption.clone(),
+    )
+    .await
+    .map_err(|e| match e {
+        MergeError::NotFound(_) => HttpError::not_found().with_msg("no recording file to merge").err()(e),
+        MergeError::Other(_) => HttpError::internal().with_msg("failed to merge recording files").err()(e),
+    })?;
+
+    let content_type = match file_type {
+        RecordingFileType::WebM => "video/webm",
+        RecordingFileType::TRP => "application/octet-stream",
+        RecordingFileType::Asciicast => "application/x-asciicast",
+    };
+
+    let headers = [
+        (header::CONTENT_TYPE, content_type.to_owned()),
+        (
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{id}.{file_type}\""),
+        ),
+    ];
+
+    Ok((headers, axum::body::Body::from_stream(stream)).into_response())
+}
+
+#[derive(Deserialize)]
+pub(crate) struct SearchRecordingsQueryParam {
+    q: String,
+    limit: Option<usize>,
+}
+
+/// Searches the terminal recordings for a text
+///
+/// The text displayed and typed during the TRP and asciicast recordings is searched, case insensitively.
+/// The position of each hit is returned, so the player can jump to it. Encrypted recordings are not searchable.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    operation_id = "SearchRecordings",
+    tag = "Jrec",
+    path = "/jet/jrec/search",
+    params(
+        ("q" = String, Query, description = "Text to search for"),
+        ("limit" = Option<usize>, Query, description = "Maximum number of recordings returned, most recent first (default is 100, at most 1000)"),
+    ),
+    responses(
+        (status = 200, description = "Recordings matching the search", body = [RecordingSearchResult]),
+        (status = 400, description = "Bad request"),
+        (status = 401, description = "Invalid or missing authorization token"),
+        (status = 403, description = "Insufficient permissions"),
+    ),
+    security(("scope_token" = ["gateway.recordings.read"])),
+))]
+pub(crate) async fn search_recordings(
+    State(DgwState {
+        conf_handle,
+        recordings,
+        ..
+    }): State<DgwState>,
+    _scope: RecordingsReadScope,
+    Query(query): Query<SearchRecordingsQueryParam>,
+) -> Result<Json<Vec<RecordingSearchResult>>, HttpError> {
+    const DEFAULT_LIMIT: usize = 100;
+    const MAX_LIMIT: usize = 1000;
+
+    let text = query.q.trim().to_owned();
+
+    if text.is_empty() {
+        return Err(HttpError::bad_request().msg("empty search text"));
+    }
+
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    let (candidates, _) = recordings.index.query(&RecordingQuery::default());
+
+    let recordings_path = conf_handle.get_conf().recording_path.clone();
+
+    let candidates: Vec<Uuid> = candidates
+        .into_iter()
+        .filter(|info| {
+            info.file_types.iter().any(|file_type| {
+                file_type == RecordingFileType::TRP.as_str() || file_type == RecordingFileType::Asciicast.as_str()
+            })
+        })
+        .map(|info| info.session_id)
+        .collect();
+
+    let results = tokio::task::spawn_blocking(move || {
+        let mut results = Vec::new();
+
+        for id in candidates {
+            match crate::recording::search_recording(&recordings_path.join(id.to_string()), &text) {
+                Ok(Some(result)) => results.push(result),
+                Ok(None) => {}
+                Err(error) => debug!(%id, error = format!("{error:#}"), "Couldn't search recording"),
+            }
+
+            if results.len() >= limit {
+                break;
+            }
+        }
+
+        results
+    })
+    .await
+    .map_err(HttpError::internal().with_msg("search task failed").err())?;
+
+    Ok(Json(results))
+}
+
+/// Shadows an ongoing recording
+///
+/// The recording file being written is streamed over a WebSocket from the start,
+/// then new data is streamed as it is written, until the recording file is complete.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    operation_id = "ShadowRecording",
+    tag = "Jrec",
+    path = "/jet/jrec/shadow/{id}",
+    params(
+        ("id" = Uuid, Path, description = "Recorded session ID"),
+    ),
+    responses(
+        (status = 101, description = "Switching to WebSocket protocol"),
+        (status = 400, description = "Bad request"),
+        (status = 401, description = "Invalid or missing authorization token"),
+        (status = 403, description = "Insufficient permissions"),
+        (status = 404, description = "No ongoing recording for the specified session"),
+    ),
+    security(("scope_token" = ["gateway.recordings.read"])),
+))]
+pub(crate) async fn shadow_recording(
+    State(DgwState {
+        conf_handle,
+        recordings,
+        shutdown_signal,
+        ..
+    }): State<DgwState>,
+    extract::Path(id): extract::Path<Uuid>,
+    _scope: RecordingsReadScope,
+    ws: WebSocketUpgrade,
+) -> Result<Response, HttpError> {
+    if !recordings.active_recordings.contains(id) {
+        return Err(HttpError::not_found().msg("no ongoing recording for this session"));
+    }
+
+    let conf = conf_handle.get_conf();
+
+    let recording_path = conf.recording_path.join(id.to_string());
+
+    let stream = crate::recording::shadow_recording(recordings, &recording_path, conf.recording.encryption.as_ref())
         .await
-        .map_err(HttpError::internal().err())?;
+        .map_err(HttpError::internal().with_msg("failed to open recording").err())?;
+
+    let response =
+        ws.on_upgrade(move |ws| handle_shadow(ws, stream, shutdown_signal).instrument(info_span!("shadow", %id)));
 
     Ok(response)
 }
 
+async fn handle_shadow<S>(mut ws: WebSocket, stream: S, mut shutdown_signal: ShutdownSignal)
+where
+    S: futures::Stream<Item = std::io::Result<bytes::Bytes>>,
+{
+    use axum::extract::ws::Message;
+    use futures::StreamExt as _;
+
+    debug!("Shadowing started");
+
+    let mut stream = std::pin::pin!(stream);
+
+    loop {
+        tokio::select! {
+            item = stream.next() => {
+                match item {
+                    Some(Ok(data)) => {
+                        if let Err(error) = ws.send(Message::Binary(data.into())).await {
+                            debug!(%error, "Failed to send recording data");
+                            break;
+                        }
+                    }
+                    Some(Err(error)) => {
+                        error!(%error, "Failed to read recording");
+                        break;
+                    }
+                    None => break,
+                }
+            }
+            msg = ws.recv() => {
+                // Viewers are not expected to send anything; only watch for the WebSocket to be closed.
+                if matches!(msg, None | Some(Err(_)) | Some(Ok(Message::Close(_)))) {
+                    break;
+                }
+            }
+            _ = shutdown_signal.wait() => {
+                trace!("Received shutdown signal");
+                break;
+            }
+        }
+    }
+
+    let _ = ws.close().await;
+
+    debug!("Shadowing ended");
+}
+
+/// Verifies the integrity of a recording
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    operation_id = "VerifyRecording",
+    tag = "Jrec",
+    path = "/jet/jrec/verify/{id}",
+    params(
+        ("id" = Uuid, Path, description = "Recorded session ID"),
+    ),
+    responses(
+        (status = 200, description = "Integrity report for the recording", body = RecordingIntegrity),
+        (status = 400, description = "Bad request"),
+        (status = 401, description = "Invalid or missing authorization token"),
+        (status = 403, description = "Insufficient permissions"),
+        (status = 404, description = "The specified recording was not found"),
+    ),
+    security(("scope_token" = ["gateway.recordings.read"])),
+))]
+pub(crate) async fn verify_recording(
+    State(DgwState { conf_handle, .. }): State<DgwState>,
+    extract::Path(id): extract::Path<Uuid>,
+    _scope: RecordingsReadScope,
+) -> Result<Json<RecordingIntegrity>, HttpError> {
+    let conf = conf_handle.get_conf();
+
+    let recording_path = conf.recording_path.join(id.to_string());
+
+    if !recording_path.is_dir() {
+        return Err(HttpError::not_found().msg("requested recording does not exist"));
+    }
+
+    let report = crate::recording::verify_recording(recording_path, conf.recording.signing_key.clone())
+        .await
+        .map_err(HttpError::internal().with_msg("failed to verify recording").err())?;
+
+    Ok(Json(report))
+}
+
+/// Exports a recording as a bundle
+///
+/// The bundle is a tar archive holding the folder of the recording, along with the checksums of its files.
+/// The checksums are signed when a recording signing key is configured.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    get,
+    operation_id = "ExportRecording",
+    tag = "Jrec",
+    path = "/jet/jrec/export/{id}",
+    params(
+        ("id" = Uuid, Path, description = "Recorded session ID"),
+    ),
+    responses(
+        (status = 200, description = "Recording bundle", body = Vec<u8>, content_type = "application/x-tar"),
+        (status = 400, description = "Bad request"),
+        (status = 401, description = "Invalid or missing authorization token"),
+        (status = 403, description = "Insufficient permissions"),
+        (status = 404, description = "The specified recording was not found"),
+        (status = 409, description = "The recording is still ongoing and can't be exported yet"),
+    ),
+    security(("scope_token" = ["gateway.recordings.read"])),
+))]
+pub(crate) async fn export_recording(
+    State(DgwState {
+        conf_handle,
+        recordings,
+        ..
+    }): State<DgwState>,
+    extract::Path(id): extract::Path<Uuid>,
+    _scope: RecordingsReadScope,
+) -> Result<Response, HttpError> {
+    let conf = conf_handle.get_conf();
+
+    let recording_path = conf.recording_path.join(id.to_string());
+
+    if !recording_path.is_dir() {
+        return Err(HttpError::not_found().msg("requested recording does not exist"));
+    }
+
+    if recordings.active_recordings.contains(id) {
+        return Err(HttpError::conflict().msg("attempted to export a recording for an ongoing session"));
+    }
+
+    debug!(%id, "Export recording");
+
+    let stream = crate::recording::export_recording(
+        &recording_path,
+        conf.recording.signing_key.as_ref(),
+        FetchedFiles::new(recording_storage(&conf), id),
+    )
+    .await
+    .map_err(HttpError::internal().with_msg("failed to export recording").err())?;
+
+    let headers = [
+        (header::CONTENT_TYPE, "application/x-tar".to_owned()),
+        (
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{id}.tar\""),
+        ),
+    ];
+
+    Ok((headers, axum::body::Body::from_stream(stream)).into_response())
+}
+
+/// Imports a recording bundle produced by the export endpoint
+///
+/// The checksums of the bundle, and their signature when a recording signing key is configured, are verified before
+/// the recording is added to the recordings folder. The bundle must fit in the free space of the recording volume, above the
+/// free space threshold when one is configured.
+#[cfg_attr(feature = "openapi", utoipa::path(
+    post,
+    operation_id = "ImportRecording",
+    tag = "Jrec",
+    path = "/jet/jrec/import",
+    request_body(content = Vec<u8>, description = "Recording bundle", content_type = "application/x-tar"),
+    responses(
+        (status = 200, description = "ID of the imported recording", body = Uuid),
+        (status = 400, description = "Bad request (e.g.: invalid or corrupted bundle)"),
+        (status = 401, description = "Invalid or missing authorization token"),
+        (status = 403, description = "Insufficient permissions"),
+        (status = 409, description = "The recording already exists on this instance, or is ongoing"),
+        (status = 507, description = "Not enough free space on the recording volume"),
+    ),
+    security(("scope_token" = ["gateway.recordings.import"])),
+))]
+pub(crate) async fn import_recording(
+    State(DgwState {
+        conf_handle,
+        recordings,
+        ..
+    }): State<DgwState>,
+    _scope: RecordingsImportScope,
+    headers: HeaderMap,
+    body: axum::body::Body,
+) -> Result<Json<Uuid>, HttpError> {
+    use crate::recording::ImportError;
+    use futures::TryStreamExt as _;
+
+    let conf = conf_handle.get_conf();
+    let recording_path = conf.recording_path.clone();
+
+    // The import must not bring the free space below the threshold at which new recordings are refused.
+    let max_size = match recordings.storage.status().available_space {
+        Some(available_space) => available_space.saturating_sub(conf.recording.free_space_threshold.unwrap_or(0)),
+        None => {
+            warn!("Free space on the recording volume is unknown; the import size is not capped");
+            u64::MAX
+        }
+    };
+
+    let content_length = headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+
+    if content_length.is_some_and(|content_length| content_length > max_size) {
+        return Err(HttpError::insufficient_storage().msg("not enough free space to import the recording"));
+    }
+
+    let reader = tokio_util::io::StreamReader::new(
+        body.into_data_stream()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e)),
+    );
+
+    let id = crate::recording::import_recording(
+        &recording_path,
+        reader,
+        max_size,
+        conf.recording.signing_key.as_ref(),
+        &recordings.active_recordings,
+        &recordings.index,
+    )
+    .await
+    .map_err(|e| match e {
+        ImportError::Invalid(_) => HttpError::bad_request().with_msg("invalid recording bundle").err()(e),
+        ImportError::AlreadyExists(_) => HttpError::conflict().with_msg("recording already exists").err()(e),
+        ImportError::Ongoing(_) => HttpError::conflict().with_msg("the recording is ongoing").err()(e),
+        ImportError::TooLarge(_) => HttpError::insufficient_storage()
+            .with_msg("not enough free space to import the recording")
+            .err()(e),
+        ImportError::Other(_) => HttpError::internal().with_msg("failed to import recording").err()(e),
+    })?;
+
+    info!(%id, "Recording imported");
+
+    Ok(Json(id))
+}
+
 async fn get_player<ReqBody>(
     State(DgwState { conf_handle, .. }): State<DgwState>,
     path: Option<extract::Path<String>>,
@@ -234,3 +1039,93 @@ where
         Err(never) => match never {},
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{self, TempDir};
+
+    fn mock_state(recordings_dir: &TempDir) -> DgwState {
+        let (state, _handles) = test_utils::mock_state(serde_json::json!({
+            "RecordingPath": recordings_dir.path()
+        }));
+
+        state
+    }
+
+    fn create_recording(recordings_dir: &TempDir) -> Uuid {
+        let id = Uuid::new_v4();
+        let recording_path = recordings_dir.path().join(id.to_string());
+        std::fs::create_dir(&recording_path).unwrap();
+        std::fs::write(recording_path.join("recording-0.webm"), "webm data").unwrap();
+        id
+    }
+
+    async fn delete(state: &DgwState, id: Uuid) -> Result<(), HttpError> {
+        delete_recording(State(state.clone()), extract::Path(id), RecordingsDeleteScope).await
+    }
+
+    #[tokio::test]
+    async fn delete_completed_recording() {
+        let recordings_dir = TempDir::new();
+        let state = mock_state(&recordings_dir);
+        let id = create_recording(&recordings_dir);
+
+        delete(&state, id)
+            .await
+            .unwrap_or_else(|error| panic!("failed to delete recording: {error}"));
+
+        assert_eq!(std::fs::read_dir(recordings_dir.path()).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn delete_missing_recording() {
+        let recordings_dir = TempDir::new();
+        let state = mock_state(&recordings_dir);
+
+        let error = delete(&state, Uuid::new_v4()).await.unwrap_err();
+        assert_eq!(error.code, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn delete_ongoing_recording() {
+        let recordings_dir = TempDir::new();
+        let state = mock_state(&recordings_dir);
+        let id = create_recording(&recordings_dir);
+        state.recordings.active_recordings.insert(id);
+
+        let error = delete(&state, id).await.unwrap_err();
+        assert_eq!(error.code, StatusCode::CONFLICT);
+        assert!(recordings_dir.path().join(id.to_string()).is_dir());
+
+        let error = delete_many_recordings(
+            State(state.clone()),
+            RecordingsDeleteScope,
+            Json(vec![Uuid::new_v4(), id]),
+        )
+        .await
+        .err()
+        .unwrap();
+        assert_eq!(error.code, StatusCode::CONFLICT);
+        assert!(recordings_dir.path().join(id.to_string()).is_dir());
+    }
+
+    #[tokio::test]
+    async fn delete_many_counts_missing_recordings() {
+        let recordings_dir = TempDir::new();
+        let state = mock_state(&recordings_dir);
+        let id = create_recording(&recordings_dir);
+
+        let Json(result) = delete_many_recordings(
+            State(state.clone()),
+            RecordingsDeleteScope,
+            Json(vec![id, Uuid::new_v4()]),
+        )
+        .await
+        .unwrap_or_else(|error| panic!("failed to delete recordings: {error}"));
+
+        assert_eq!(result.found_count, 1);
+        assert_eq!(result.not_found_count, 1);
+        assert!(!recordings_dir.path().join(id.to_string()).exists());
+    }
+}
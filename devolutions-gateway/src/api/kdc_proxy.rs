@@ -7,10 +7,11 @@ use axum::routing::post;
 use axum::Router;
 use picky_krb::messages::KdcProxyMessage;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::{TcpStream, UdpSocket};
+use tokio::net::UdpSocket;
 
 use crate::http::{HttpError, HttpErrorBuilder};
 use crate::token::AccessTokenClaims;
+use crate::upstream_proxy::TargetStream;
 use crate::DgwState;
 
 pub fn make_router<S>(state: DgwState) -> Router<S> {
@@ -83,10 +84,12 @@ async fn kdc_proxy(
     trace!("Connecting to KDC server located at {kdc_addr} using protocol {protocol}...");
 
     let kdc_reply_message = if protocol == "tcp" {
-        #[allow(clippy::redundant_closure)] // We get a better caller location for the error by using a closure.
-        let mut connection = TcpStream::connect(kdc_addr.as_addr())
+        let ((mut connection, _), _) = crate::utils::tcp_connect([kdc_addr], &conf.target_connection)
             .await
-            .map_err(|e| unable_to_reach_kdc_server_err(e))?;
+            .map_err(|e| {
+                let kind = crate::utils::io_error_kind(&e);
+                unable_to_reach_kdc_server_err(io::Error::new(kind, format!("{e:#}")))
+            })?;
 
         trace!("Connected! Forwarding KDC message...");
 
@@ -149,7 +152,7 @@ async fn kdc_proxy(
     kdc_reply_message.to_vec().map_err(HttpError::internal().err())
 }
 
-async fn read_kdc_reply_message(connection: &mut TcpStream) -> std::io::Result<Vec<u8>> {
+async fn read_kdc_reply_message(connection: &mut TargetStream) -> std::io::Result<Vec<u8>> {
     let len = connection.read_u32().await?;
     let mut buf = vec![0; (len + 4).try_into().unwrap()];
     buf[0..4].copy_from_slice(&(len.to_be_bytes()));
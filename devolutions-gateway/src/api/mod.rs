@@ -1,3 +1,4 @@
+pub mod acme;
 pub mod config;
 pub mod diagnostics;
 pub mod fwd;
@@ -27,7 +28,11 @@ pub fn make_router<S>(state: crate::DgwState) -> axum::Router<S> {
         .route("/jet/rdp", axum::routing::get(rdp::handler))
         .nest("/jet/fwd", fwd::make_router(state.clone()))
         .nest("/jet/webapp", webapp::make_router(state.clone()))
-        .nest("/jet/net", net::make_router(state.clone()));
+        .nest("/jet/net", net::make_router(state.clone()))
+        .route(
+            "/.well-known/acme-challenge/:token",
+            axum::routing::get(acme::get_http_01_challenge),
+        );
 
     if state.conf_handle.get_conf().web_app.enabled {
         router = router.route(
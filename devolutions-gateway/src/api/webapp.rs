@@ -337,6 +337,7 @@ pub(crate) async fn sign_session_token(
                 jet_rec: false,
                 jet_flt: false,
                 jet_ttl: crate::token::SessionTtl::Unlimited,
+                jet_tls_pins: Vec::new(),
                 exp,
                 jti: Some(jti),
             }
@@ -23,7 +23,13 @@ const CERTIFICATE_LABELS: &[&str] = &["CERTIFICATE", "X509 CERTIFICATE", "TRUSTE
 const PRIVATE_KEY_LABELS: &[&str] = &["PRIVATE KEY", "RSA PRIVATE KEY", "EC PRIVATE KEY"];
 const WEB_APP_TOKEN_DEFAULT_LIFETIME_SECS: u64 = 28800; // 8 hours
 const WEB_APP_DEFAULT_LOGIN_LIMIT_RATE: u8 = 10;
+const RECORDING_RETENTION_DEFAULT_CHECK_INTERVAL_MINS: u64 = 60;
+const RECORDING_POLICY_DEFAULT_GRACE_PERIOD_SECS: u64 = 30;
 const ENV_VAR_DGATEWAY_WEBAPP_PATH: &str = "DGATEWAY_WEBAPP_PATH";
+const ACME_DEFAULT_DIRECTORY_URL: &str = "https://acme-v02.api.letsencrypt.org/directory";
+const ACME_DEFAULT_RENEW_BEFORE_DAYS: u32 = 30;
+const TARGET_CONNECTION_DEFAULT_ATTEMPT_DELAY_MILLIS: u64 = 250;
+const TARGET_CONNECTION_DEFAULT_TIMEOUT_SECS: u64 = 10;
 
 cfg_if! {
     if #[cfg(target_os = "windows")] {
@@ -46,7 +52,11 @@ pub struct TlsPublicKey(pub Vec<u8>);
 
 #[derive(Clone)]
 pub struct Tls {
-    pub acceptor: tokio_rustls::TlsAcceptor,
+    server_config: Arc<parking_lot::RwLock<Arc<rustls::ServerConfig>>>,
+    /// Files the certificate was read from, when it can be reloaded
+    certificate_files: Option<TlsCertificateFiles>,
+    /// Additional certificates, selected by server name indication (SNI)
+    sni_certificates: Vec<SniCertificateFiles>,
 }
 
 impl fmt::Debug for Tls {
@@ -56,12 +66,248 @@ impl fmt::Debug for Tls {
 }
 
 impl Tls {
-    fn init(cert_source: crate::tls::CertificateSource) -> anyhow::Result<Self> {
-        let tls_server_config = crate::tls::build_server_config(cert_source).context("failed build TLS config")?;
+    fn init(
+        cert_source: crate::tls::CertificateSource,
+        certificate_files: Option<TlsCertificateFiles>,
+        sni_certificates: Vec<SniCertificateFiles>,
+    ) -> anyhow::Result<Self> {
+        let tls_server_config = build_server_config(cert_source, &sni_certificates)?;
 
-        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(tls_server_config));
+        Ok(Self {
+            server_config: Arc::new(parking_lot::RwLock::new(Arc::new(tls_server_config))),
+            certificate_files,
+            sni_certificates,
+        })
+    }
+
+    /// Returns the server configuration to use for new TLS handshakes
+    pub fn server_config(&self) -> Arc<rustls::ServerConfig> {
+        Arc::clone(&self.server_config.read())
+    }
+
+    pub fn certificate_files(&self) -> Option<&TlsCertificateFiles> {
+        self.certificate_files.as_ref()
+    }
+
+    /// Paths of all the certificate and private key files, SNI certificates included
+    pub fn certificate_paths(&self) -> impl Iterator<Item = &Utf8Path> {
+        self.certificate_files
+            .iter()
+            .chain(
+                self.sni_certificates
+                    .iter()
+                    .map(|sni_certificate| &sni_certificate.files),
+            )
+            .flat_map(|files| std::iter::once(&files.certificate_path).chain(files.private_key_path.as_ref()))
+            .map(Utf8PathBuf::as_path)
+    }
+
+    /// Reads the certificate files again, and swaps the configuration used by new TLS handshakes.
+    ///
+    /// On error, the current certificate is kept in service.
+    pub fn reload(&self) -> anyhow::Result<()> {
+        let certificate_files = self
+            .certificate_files
+            .as_ref()
+            .context("TLS certificate is not read from files")?;
+
+        let cert_source = certificate_files.read()?;
+
+        let tls_server_config = build_server_config(cert_source, &self.sni_certificates)?;
+
+        *self.server_config.write() = Arc::new(tls_server_config);
+
+        Ok(())
+    }
+}
+
+fn build_server_config(
+    cert_source: crate::tls::CertificateSource,
+    sni_certificates: &[SniCertificateFiles],
+) -> anyhow::Result<rustls::ServerConfig> {
+    let sni_certificates = sni_certificates
+        .iter()
+        .map(|sni_certificate| {
+            let (certificates, private_key) = sni_certificate
+                .files
+                .read_certificate_and_key()
+                .with_context(|| format!("certificate for {:?}", sni_certificate.hostnames))?;
+
+            Ok(crate::tls::SniCertificate {
+                hostnames: sni_certificate.hostnames.clone(),
+                certificates,
+                private_key,
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    crate::tls::build_server_config(cert_source, sni_certificates).context("failed build TLS config")
+}
+
+#[derive(Clone)]
+pub struct SniCertificateFiles {
+    pub hostnames: Vec<String>,
+    pub files: TlsCertificateFiles,
+}
+
+#[derive(Clone)]
+pub struct TlsCertificateFiles {
+    pub certificate_path: Utf8PathBuf,
+    /// Not used for PFX/PKCS12 files which also hold the private key
+    pub private_key_path: Option<Utf8PathBuf>,
+    private_key_password: Option<dto::Password>,
+}
+
+impl TlsCertificateFiles {
+    fn from_dto(
+        certificate_file: &Utf8Path,
+        private_key_file: Option<&Utf8Path>,
+        private_key_password: Option<&dto::Password>,
+    ) -> Self {
+        let data_dir = get_data_dir();
+
+        Self {
+            certificate_path: normalize_data_path(certificate_file, &data_dir),
+            private_key_path: private_key_file.map(|path| normalize_data_path(path, &data_dir)),
+            private_key_password: private_key_password.cloned(),
+        }
+    }
+
+    fn read(&self) -> anyhow::Result<crate::tls::CertificateSource> {
+        let (certificates, private_key) = self.read_certificate_and_key()?;
+
+        Ok(crate::tls::CertificateSource::External {
+            certificates,
+            private_key,
+        })
+    }
+
+    fn read_certificate_and_key(&self) -> anyhow::Result<(Vec<rustls::Certificate>, rustls::PrivateKey)> {
+        let (certificates, private_key) = match self.certificate_path.extension() {
+            Some("pfx" | "p12") => read_pfx_file(&self.certificate_path, self.private_key_password.as_ref())
+                .context("read PFX/PKCS12 file")?,
+            None | Some(_) => {
+                let certificates =
+                    read_rustls_certificate_file(&self.certificate_path).context("read TLS certificate")?;
+
+                let private_key = self
+                    .private_key_path
+                    .as_deref()
+                    .context("TLS private key file is missing")?
+                    .pipe(read_rustls_priv_key_file)
+                    .context("read TLS private key")?;
+
+                (certificates, private_key)
+            }
+        };
+
+        anyhow::ensure!(!certificates.is_empty(), "no TLS certificate found");
+
+        // Not checked by rustls, and a mismatch would make every handshake fail.
+        ensure_private_key_matches(&certificates[0], &private_key)?;
+
+        Ok((certificates, private_key))
+    }
+}
+
+pub(crate) fn ensure_private_key_matches(
+    certificate: &rustls::Certificate,
+    private_key: &rustls::PrivateKey,
+) -> anyhow::Result<()> {
+    use picky::x509::Cert;
+
+    let public_key = PrivateKey::from_pkcs8(&private_key.0)
+        .or_else(|_| PrivateKey::from_rsa_der(&private_key.0))
+        .or_else(|_| PrivateKey::from_ec_der(&private_key.0))
+        .context("unsupported TLS private key")?
+        .to_public_key()
+        .context("invalid TLS private key")?
+        .to_der()
+        .context("failed to encode public key")?;
+
+    let certificate_public_key = Cert::from_der(&certificate.0)
+        .context("failed to parse TLS certificate")?
+        .public_key()
+        .to_der()
+        .context("failed to encode certificate public key")?;
+
+    anyhow::ensure!(
+        certificate_public_key == public_key,
+        "TLS private key does not match the certificate"
+    );
+
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+pub struct AcmeConf {
+    pub directory_url: Url,
+    pub contact_email: Option<String>,
+    /// Whether the terms of service of the ACME server were agreed to
+    pub agree_to_terms_of_service: bool,
+    pub domains: Vec<String>,
+    pub challenge: dto::AcmeChallengeType,
+    /// How long before expiration the certificate is renewed
+    pub renew_before: time::Duration,
+    /// Additional CA certificates to trust when connecting to the ACME server
+    pub trusted_certificates: Vec<rustls::Certificate>,
+    /// Folder holding the account key, the certificate and its private key
+    pub storage_path: Utf8PathBuf,
+}
+
+impl AcmeConf {
+    fn from_dto(value: Option<&dto::AcmeConf>, hostname: &str) -> anyhow::Result<Self> {
+        let default_conf = dto::AcmeConf::default();
+        let value = value.unwrap_or(&default_conf);
+
+        let directory_url = match &value.directory_url {
+            Some(url) => url.clone(),
+            None => Url::parse(ACME_DEFAULT_DIRECTORY_URL).expect("valid URL"),
+        };
+
+        let domains = if value.domains.is_empty() {
+            vec![hostname.to_owned()]
+        } else {
+            value.domains.clone()
+        };
+
+        anyhow::ensure!(
+            domains.iter().all(|domain| !domain.is_empty() && !domain.contains('*')),
+            "wildcard domains can't be validated using HTTP-01 nor TLS-ALPN-01 challenges",
+        );
 
-        Ok(Self { acceptor })
+        let trusted_certificates = value
+            .ca_certificate_file
+            .as_deref()
+            .map(|path| read_rustls_certificate_file(&normalize_data_path(path, &get_data_dir())))
+            .transpose()
+            .context("ACME server CA certificate")?
+            .unwrap_or_default();
+
+        Ok(Self {
+            directory_url,
+            contact_email: value.contact_email.clone(),
+            agree_to_terms_of_service: value.agree_to_terms_of_service,
+            domains,
+            challenge: value.challenge.unwrap_or_default(),
+            renew_before: time::Duration::days(i64::from(
+                value.renew_before_days.unwrap_or(ACME_DEFAULT_RENEW_BEFORE_DAYS),
+            )),
+            trusted_certificates,
+            storage_path: get_data_dir().join("acme"),
+        })
+    }
+
+    pub fn account_key_path(&self) -> Utf8PathBuf {
+        self.storage_path.join("account-key.pem")
+    }
+
+    pub fn certificate_path(&self) -> Utf8PathBuf {
+        self.storage_path.join("certificate.pem")
+    }
+
+    pub fn private_key_path(&self) -> Utf8PathBuf {
+        self.storage_path.join("private-key.pem")
     }
 }
 
@@ -73,12 +319,17 @@ pub struct Conf {
     pub subscriber: Option<dto::Subscriber>,
     pub log_file: Utf8PathBuf,
     pub tls: Option<Tls>,
+    /// Set when the TLS certificate is managed using ACME
+    pub acme: Option<AcmeConf>,
+    pub target_tls: crate::tls::TargetTls,
+    pub target_connection: TargetConnectionConf,
     pub provisioner_public_key: PublicKey,
     pub provisioner_private_key: Option<PrivateKey>,
     pub sub_provisioner_public_key: Option<Subkey>,
     pub delegation_private_key: Option<PrivateKey>,
     pub plugins: Option<Vec<Utf8PathBuf>>,
     pub recording_path: Utf8PathBuf,
+    pub recording: RecordingConf,
     pub sogar: dto::SogarConf,
     pub jrl_file: Utf8PathBuf,
     pub ngrok: Option<dto::NgrokConf>,
@@ -96,6 +347,181 @@ pub struct WebAppConf {
     pub static_root_path: std::path::PathBuf,
 }
 
+#[derive(Debug, Clone)]
+pub struct RecordingConf {
+    pub retention: Option<RecordingRetention>,
+    /// Delay for the recording to start before a session requiring recording is killed
+    pub policy_grace_period: std::time::Duration,
+    /// Key used to sign the recording manifests
+    pub signing_key: Option<PrivateKey>,
+    pub encryption: Option<RecordingEncryption>,
+    /// Free space on the recording volume below which new recordings are refused, in bytes
+    pub free_space_threshold: Option<u64>,
+    /// S3-compatible storage the completed recording files are uploaded to
+    pub s3: Option<S3StorageConf>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TargetConnectionConf {
+    /// Delay before starting the next connection attempt while the previous ones are still pending
+    pub attempt_delay: std::time::Duration,
+    /// Overall time allowed to connect to any of the targets
+    pub timeout: std::time::Duration,
+    /// Rules selecting how each target is reached, by order of precedence
+    pub proxy_rules: Vec<ProxyRule>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProxyRule {
+    pub destinations: Vec<DestinationPattern>,
+    /// Matching targets are reached directly when there is no proxy
+    pub proxy: Option<UpstreamProxy>,
+}
+
+#[derive(Debug, Clone)]
+pub enum DestinationPattern {
+    Any,
+    /// Matches the targets specified using an IP address
+    Network(ipnet::IpNet),
+    /// Matches the targets specified using a host name, possibly with wildcards (e.g.: `*.example.com`)
+    Host(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct UpstreamProxy {
+    pub kind: UpstreamProxyKind,
+    /// Host and port of the proxy
+    pub address: String,
+    pub credentials: Option<(String, dto::Password)>,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum UpstreamProxyKind {
+    Socks5,
+    /// HTTP CONNECT tunneling
+    Http,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProxyProtocolConf {
+    /// Load balancers allowed to announce the address of the client
+    pub trusted_sources: Vec<ipnet::IpNet>,
+}
+
+impl ProxyProtocolConf {
+    fn from_dto(value: &dto::ProxyProtocolConf) -> anyhow::Result<Self> {
+        let trusted_sources = value
+            .trusted_sources
+            .iter()
+            .map(|source| {
+                // A single address is accepted as well.
+                source
+                    .parse::<ipnet::IpNet>()
+                    .or_else(|_| source.parse::<std::net::IpAddr>().map(ipnet::IpNet::from))
+                    .with_context(|| format!("invalid trusted source: {source}"))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        anyhow::ensure!(!trusted_sources.is_empty(), "no trusted source");
+
+        Ok(Self { trusted_sources })
+    }
+
+    pub fn is_trusted(&self, addr: std::net::IpAddr) -> bool {
+        // Dual-stack listeners see IPv4 peers as IPv4-mapped IPv6 addresses.
+        let addr = addr.to_canonical();
+        self.trusted_sources.iter().any(|source| source.contains(&addr))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct UnixSocketConf {
+    /// Permissions applied to the socket file
+    pub mode: u32,
+    /// Users authenticated as administrators (all scopes) by the credentials of their connection
+    pub admin_uids: Vec<u32>,
+}
+
+impl Default for UnixSocketConf {
+    fn default() -> Self {
+        Self {
+            mode: 0o600,
+            admin_uids: Vec::new(),
+        }
+    }
+}
+
+impl UnixSocketConf {
+    fn from_dto(value: &dto::UnixSocketConf) -> anyhow::Result<Self> {
+        let mode = match &value.mode {
+            Some(mode) => u32::from_str_radix(mode, 8).with_context(|| format!("invalid octal mode: {mode}"))?,
+            None => Self::default().mode,
+        };
+
+        anyhow::ensure!(mode <= 0o777, "invalid mode: {mode:o}");
+
+        Ok(Self {
+            mode,
+            admin_uids: value.admin_uids.clone().unwrap_or_default(),
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct S3StorageConf {
+    pub endpoint: Url,
+    pub bucket: String,
+    pub region: String,
+    /// Prefix prepended to the object keys, empty or ending with a slash
+    pub prefix: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    /// Address the bucket in the path (`https://endpoint/bucket/key`) instead of the host name
+    pub force_path_style: bool,
+    /// Keep the recording files on the local volume once uploaded
+    pub keep_local_cache: bool,
+}
+
+impl fmt::Debug for S3StorageConf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("S3StorageConf")
+            .field("endpoint", &self.endpoint)
+            .field("bucket", &self.bucket)
+            .field("region", &self.region)
+            .field("prefix", &self.prefix)
+            .field("access_key_id", &self.access_key_id)
+            .field("force_path_style", &self.force_path_style)
+            .field("keep_local_cache", &self.keep_local_cache)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Clone)]
+pub struct RecordingEncryption {
+    /// Identifier of the master key, recorded in the manifests
+    pub key_id: String,
+    /// Master key used to wrap the per-recording data keys
+    pub master_key: zeroize::Zeroizing<[u8; 32]>,
+}
+
+impl fmt::Debug for RecordingEncryption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RecordingEncryption")
+            .field("key_id", &self.key_id)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct RecordingRetention {
+    pub max_age: Option<std::time::Duration>,
+    /// Maximum total size of the recording folder, in bytes
+    pub max_total_size: Option<u64>,
+    /// Minimum free space to keep on the recording volume, in bytes
+    pub min_free_space: Option<u64>,
+    pub check_interval: std::time::Duration,
+}
+
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub enum WebAppAuth {
     Custom(HashMap<String, WebAppUser>),
@@ -148,41 +574,75 @@ impl Conf {
             .iter()
             .any(|l| matches!(l.internal_url.scheme(), "https" | "wss"));
 
+        let mut acme = None;
+
+        let sni_certificates = conf_file
+            .tls_certificates
+            .iter()
+            .enumerate()
+            .map(|(idx, certificate)| {
+                anyhow::ensure!(
+                    !certificate.hostnames.is_empty(),
+                    "TLS certificate at position {idx} has no hostname"
+                );
+
+                Ok(SniCertificateFiles {
+                    hostnames: certificate.hostnames.clone(),
+                    files: TlsCertificateFiles::from_dto(
+                        &certificate.certificate_file,
+                        certificate.private_key_file.as_deref(),
+                        certificate.private_key_password.as_ref(),
+                    ),
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let mut default_sni_certificates = conf_file
+            .tls_certificates
+            .iter()
+            .filter(|certificate| certificate.default);
+        let default_sni_certificate = default_sni_certificates.next();
+
+        anyhow::ensure!(
+            default_sni_certificates.next().is_none(),
+            "only one TLS certificate can be the default one"
+        );
+
         let tls = match conf_file.tls_certificate_source.unwrap_or_default() {
             _ if !requires_tls => {
                 trace!("Not configured to use HTTPS, ignoring TLS configuration");
                 None
             }
             dto::CertSource::External => {
-                let certificate_path = conf_file
-                    .tls_certificate_file
-                    .as_ref()
-                    .context("TLS usage implied, but TLS certificate file is missing")?;
-
-                let (certificates, private_key) = match certificate_path.extension() {
-                    Some("pfx" | "p12") => read_pfx_file(certificate_path, conf_file.tls_private_key_password.as_ref())
-                        .context("read PFX/PKCS12 file")?,
-                    None | Some(_) => {
-                        let certificates =
-                            read_rustls_certificate_file(certificate_path).context("read TLS certificate")?;
-
-                        let private_key = conf_file
-                            .tls_private_key_file
-                            .as_ref()
-                            .context("TLS private key file is missing")?
-                            .pipe_deref(read_rustls_priv_key_file)
-                            .context("read TLS private key")?;
-
-                        (certificates, private_key)
-                    }
-                };
+                let certificate_files = if let Some(default_certificate) = default_sni_certificate {
+                    anyhow::ensure!(
+                        conf_file.tls_certificate_file.is_none(),
+                        "TLS certificate file can't be specified along with a default certificate in TLS certificates"
+                    );
 
-                let cert_source = crate::tls::CertificateSource::External {
-                    certificates,
-                    private_key,
+                    TlsCertificateFiles::from_dto(
+                        &default_certificate.certificate_file,
+                        default_certificate.private_key_file.as_deref(),
+                        default_certificate.private_key_password.as_ref(),
+                    )
+                } else {
+                    let certificate_path = conf_file
+                        .tls_certificate_file
+                        .as_deref()
+                        .context("TLS usage implied, but TLS certificate file is missing")?;
+
+                    TlsCertificateFiles::from_dto(
+                        certificate_path,
+                        conf_file.tls_private_key_file.as_deref(),
+                        conf_file.tls_private_key_password.as_ref(),
+                    )
                 };
 
-                Tls::init(cert_source).context("failed to init TLS config")?.pipe(Some)
+                let cert_source = certificate_files.read()?;
+
+                Tls::init(cert_source, Some(certificate_files), sni_certificates)
+                    .context("failed to init TLS config")?
+                    .pipe(Some)
             }
             dto::CertSource::System => {
                 let cert_subject_name = conf_file
@@ -208,10 +668,50 @@ impl Conf {
                     store_name,
                 };
 
-                Tls::init(cert_source).context("failed to init TLS config")?.pipe(Some)
+                Tls::init(cert_source, None, sni_certificates)
+                    .context("failed to init TLS config")?
+                    .pipe(Some)
+            }
+            dto::CertSource::Acme => {
+                let acme_conf = AcmeConf::from_dto(conf_file.acme.as_ref(), &hostname).context("ACME config")?;
+
+                let certificate_files = TlsCertificateFiles {
+                    certificate_path: acme_conf.certificate_path(),
+                    private_key_path: Some(acme_conf.private_key_path()),
+                    private_key_password: None,
+                };
+
+                // A self-signed certificate is served until the first certificate is issued.
+                let cert_source = match certificate_files.read() {
+                    Ok(cert_source) => cert_source,
+                    Err(error) => {
+                        debug!(
+                            error = format!("{error:#}"),
+                            "No certificate issued by the ACME server yet"
+                        );
+                        crate::acme::placeholder_certificate(&acme_conf.domains[0])
+                            .context("failed to generate placeholder certificate")?
+                    }
+                };
+
+                acme = Some(acme_conf);
+
+                Tls::init(cert_source, Some(certificate_files), sni_certificates)
+                    .context("failed to init TLS config")?
+                    .pipe(Some)
             }
         };
 
+        if requires_tls && default_sni_certificate.is_some() {
+            anyhow::ensure!(
+                matches!(
+                    conf_file.tls_certificate_source.unwrap_or_default(),
+                    dto::CertSource::External
+                ),
+                "a default certificate in TLS certificates is only supported with the External certificate source"
+            );
+        }
+
         // Sanity check
         if requires_tls && tls.is_none() {
             anyhow::bail!("TLS usage implied but TLS configuration is missing (certificate or/and private key)");
@@ -282,12 +782,34 @@ impl Conf {
             subscriber: conf_file.subscriber.clone(),
             log_file,
             tls,
+            acme,
+            target_tls: conf_file
+                .target_tls
+                .as_ref()
+                .map(target_tls_from_dto)
+                .transpose()
+                .context("target TLS config")?
+                .unwrap_or_else(crate::tls::TargetTls::insecure),
+            target_connection: conf_file
+                .target_connection
+                .as_ref()
+                .map(TargetConnectionConf::from_dto)
+                .transpose()
+                .context("target connection config")?
+                .unwrap_or_default(),
             provisioner_public_key,
             provisioner_private_key,
             sub_provisioner_public_key,
             delegation_private_key,
             plugins: conf_file.plugins.clone(),
             recording_path,
+            recording: conf_file
+                .recording
+                .as_ref()
+                .map(RecordingConf::from_dto)
+                .transpose()
+                .context("recording config")?
+                .unwrap_or_default(),
             sogar: conf_file.sogar.clone().unwrap_or_default(),
             jrl_file,
             ngrok: conf_file.ngrok.clone(),
@@ -303,6 +825,264 @@ impl Conf {
     }
 }
 
+impl Default for RecordingConf {
+    fn default() -> Self {
+        Self {
+            retention: None,
+            policy_grace_period: std::time::Duration::from_secs(RECORDING_POLICY_DEFAULT_GRACE_PERIOD_SECS),
+            signing_key: None,
+            encryption: None,
+            free_space_threshold: None,
+            s3: None,
+        }
+    }
+}
+
+impl Default for TargetConnectionConf {
+    fn default() -> Self {
+        Self {
+            attempt_delay: std::time::Duration::from_millis(TARGET_CONNECTION_DEFAULT_ATTEMPT_DELAY_MILLIS),
+            timeout: std::time::Duration::from_secs(TARGET_CONNECTION_DEFAULT_TIMEOUT_SECS),
+            proxy_rules: Vec::new(),
+        }
+    }
+}
+
+impl TargetConnectionConf {
+    fn from_dto(value: &dto::TargetConnectionConf) -> anyhow::Result<Self> {
+        let timeout = value.timeout.unwrap_or(TARGET_CONNECTION_DEFAULT_TIMEOUT_SECS);
+
+        anyhow::ensure!(timeout > 0, "connection timeout must be greater than zero");
+
+        let proxy_rules = value
+            .proxy_rules
+            .iter()
+            .enumerate()
+            .map(|(idx, rule)| ProxyRule::from_dto(rule).with_context(|| format!("proxy rule #{idx}")))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        Ok(Self {
+            attempt_delay: std::time::Duration::from_millis(
+                value
+                    .attempt_delay
+                    .unwrap_or(TARGET_CONNECTION_DEFAULT_ATTEMPT_DELAY_MILLIS),
+            ),
+            timeout: std::time::Duration::from_secs(timeout),
+            proxy_rules,
+        })
+    }
+
+    /// Returns the proxy to go through in order to reach the target, if any.
+    ///
+    /// The first rule matching the target applies, and targets matching no rule are reached directly.
+    pub fn proxy_for(&self, target: &TargetAddr) -> Option<&UpstreamProxy> {
+        self.proxy_rules
+            .iter()
+            .find(|rule| rule.destinations.iter().any(|destination| destination.matches(target)))
+            .and_then(|rule| rule.proxy.as_ref())
+    }
+}
+
+impl ProxyRule {
+    fn from_dto(value: &dto::ProxyRuleConf) -> anyhow::Result<Self> {
+        anyhow::ensure!(!value.destinations.is_empty(), "no destination");
+
+        let destinations = value
+            .destinations
+            .iter()
+            .map(|destination| DestinationPattern::parse(destination))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let proxy = value
+            .proxy
+            .as_ref()
+            .map(|url| {
+                let kind = match url.scheme() {
+                    "socks5" => UpstreamProxyKind::Socks5,
+                    "http" => UpstreamProxyKind::Http,
+                    scheme => anyhow::bail!("unsupported proxy scheme: {scheme} (expected socks5 or http)"),
+                };
+
+                anyhow::ensure!(
+                    url.username().is_empty() && url.password().is_none(),
+                    "credentials must be provided using ProxyUsername and ProxyPassword, not in the proxy URL"
+                );
+
+                let host = url.host_str().context("proxy host is missing")?;
+                let port = url.port_or_known_default().context("proxy port is missing")?;
+
+                let credentials = match (&value.proxy_username, &value.proxy_password) {
+                    (Some(username), Some(password)) => {
+                        anyhow::ensure!(
+                            kind == UpstreamProxyKind::Socks5,
+                            "authentication is only supported with SOCKS5 proxies"
+                        );
+                        Some((username.clone(), password.clone()))
+                    }
+                    (None, None) => None,
+                    _ => anyhow::bail!("both ProxyUsername and ProxyPassword are required for authentication"),
+                };
+
+                Ok(UpstreamProxy {
+                    kind,
+                    address: format!("{host}:{port}"),
+                    credentials,
+                })
+            })
+            .transpose()?;
+
+        Ok(Self { destinations, proxy })
+    }
+}
+
+impl DestinationPattern {
+    fn parse(value: &str) -> anyhow::Result<Self> {
+        if value == "*" {
+            Ok(Self::Any)
+        } else if let Ok(network) = value.parse::<ipnet::IpNet>() {
+            Ok(Self::Network(network))
+        } else if let Ok(addr) = value.parse::<std::net::IpAddr>() {
+            Ok(Self::Network(ipnet::IpNet::from(addr)))
+        } else {
+            anyhow::ensure!(
+                !value.is_empty() && !value.contains(['/', ':']),
+                "invalid destination: {value}"
+            );
+            Ok(Self::Host(value.to_owned()))
+        }
+    }
+
+    pub fn matches(&self, target: &TargetAddr) -> bool {
+        match self {
+            DestinationPattern::Any => true,
+            DestinationPattern::Network(network) => target
+                .host_ip()
+                .is_some_and(|addr| network.contains(&addr.to_canonical())),
+            DestinationPattern::Host(pattern) => {
+                target.host_ip().is_none() && crate::utils::wildcard_host_match(pattern, target.host())
+            }
+        }
+    }
+}
+
+impl RecordingConf {
+    fn from_dto(value: &dto::RecordingConf) -> anyhow::Result<Self> {
+        const SECONDS_PER_DAY: u64 = 60 * 60 * 24;
+        const BYTES_PER_MEGABYTE: u64 = 1024 * 1024;
+
+        let retention = value
+            .retention
+            .as_ref()
+            .map(|retention| {
+                let check_interval = retention
+                    .check_interval
+                    .unwrap_or(RECORDING_RETENTION_DEFAULT_CHECK_INTERVAL_MINS);
+
+                anyhow::ensure!(check_interval > 0, "retention check interval must be greater than zero");
+
+                Ok(RecordingRetention {
+                    max_age: retention
+                        .max_age
+                        .map(|days| std::time::Duration::from_secs(days.saturating_mul(SECONDS_PER_DAY))),
+                    max_total_size: retention
+                        .max_total_size
+                        .map(|megabytes| megabytes.saturating_mul(BYTES_PER_MEGABYTE)),
+                    min_free_space: retention
+                        .min_free_space
+                        .map(|megabytes| megabytes.saturating_mul(BYTES_PER_MEGABYTE)),
+                    check_interval: std::time::Duration::from_secs(check_interval * 60),
+                })
+            })
+            .transpose()?;
+
+        let signing_key =
+            read_priv_key(value.signing_private_key_file.as_deref(), None).context("recording signing key")?;
+
+        let encryption = value
+            .encryption
+            .as_ref()
+            .map(|encryption| {
+                let master_key_file = normalize_data_path(&encryption.master_key_file, &get_data_dir());
+
+                let master_key = std::fs::read_to_string(&master_key_file)
+                    .with_context(|| format!("couldn't read file at {master_key_file}"))?
+                    .pipe_deref(|hex| decode_hex_key(hex.trim()))
+                    .context("invalid master key (expected 64 hexadecimal characters)")?;
+
+                Ok::<_, anyhow::Error>(RecordingEncryption {
+                    key_id: encryption.key_id.clone(),
+                    master_key,
+                })
+            })
+            .transpose()
+            .context("recording encryption")?;
+
+        let policy_grace_period = std::time::Duration::from_secs(
+            value
+                .policy_grace_period
+                .unwrap_or(RECORDING_POLICY_DEFAULT_GRACE_PERIOD_SECS),
+        );
+
+        let free_space_threshold = value
+            .free_space_threshold
+            .map(|megabytes| megabytes.saturating_mul(BYTES_PER_MEGABYTE));
+
+        let s3 = value
+            .s3
+            .as_ref()
+            .map(S3StorageConf::from_dto)
+            .transpose()
+            .context("recording S3 storage")?;
+
+        Ok(Self {
+            retention,
+            policy_grace_period,
+            signing_key,
+            encryption,
+            free_space_threshold,
+            s3,
+        })
+    }
+}
+
+impl S3StorageConf {
+    fn from_dto(value: &dto::S3StorageConf) -> anyhow::Result<Self> {
+        anyhow::ensure!(!value.bucket.is_empty(), "bucket name is empty");
+        anyhow::ensure!(
+            matches!(value.endpoint.scheme(), "http" | "https") && value.endpoint.host_str().is_some(),
+            "invalid endpoint URL: {}",
+            value.endpoint
+        );
+
+        // Credentials not specified in the configuration file are taken from the usual AWS environment variables.
+        let access_key_id = match &value.access_key_id {
+            Some(access_key_id) => access_key_id.clone(),
+            None => env::var("AWS_ACCESS_KEY_ID").context("access key ID is not specified")?,
+        };
+
+        let secret_access_key = match &value.secret_access_key {
+            Some(secret_access_key) => secret_access_key.clone(),
+            None => env::var("AWS_SECRET_ACCESS_KEY").context("secret access key is not specified")?,
+        };
+
+        let prefix = match value.prefix.as_deref().map(|prefix| prefix.trim_matches('/')) {
+            Some(prefix) if !prefix.is_empty() => format!("{prefix}/"),
+            _ => String::new(),
+        };
+
+        Ok(Self {
+            endpoint: value.endpoint.clone(),
+            bucket: value.bucket.clone(),
+            region: value.region.clone().unwrap_or_else(|| "us-east-1".to_owned()),
+            prefix,
+            access_key_id,
+            secret_access_key,
+            force_path_style: value.force_path_style.unwrap_or(false),
+            keep_local_cache: value.keep_local_cache.unwrap_or(true),
+        })
+    }
+}
+
 impl WebAppConf {
     fn from_dto(value: &dto::WebAppConf) -> anyhow::Result<Self> {
         let authentication = match value.authentication {
@@ -657,6 +1437,64 @@ fn read_pfx_file(
     Ok((certificates, private_key))
 }
 
+fn target_tls_from_dto(value: &dto::TargetTlsConf) -> anyhow::Result<crate::tls::TargetTls> {
+    let roots = match value.verification.unwrap_or_default() {
+        dto::TargetTlsVerification::Insecure => {
+            anyhow::ensure!(
+                value.ca_certificate_file.is_none(),
+                "CA certificate file is only used with the Custom verification"
+            );
+            None
+        }
+        dto::TargetTlsVerification::System => {
+            let certificates = rustls_native_certs::load_native_certs().context("failed to load system roots")?;
+
+            let mut roots = rustls::RootCertStore::empty();
+            let (added, ignored) =
+                roots.add_parsable_certificates(&certificates.iter().map(|cert| cert.to_vec()).collect::<Vec<_>>());
+
+            debug!(added, ignored, "Loaded system root certificates");
+
+            Some(roots)
+        }
+        dto::TargetTlsVerification::Custom => {
+            let path = value
+                .ca_certificate_file
+                .as_deref()
+                .context("CA certificate file is missing for the Custom verification")?
+                .pipe(|path| normalize_data_path(path, &get_data_dir()));
+
+            let mut roots = rustls::RootCertStore::empty();
+
+            for certificate in read_rustls_certificate_file(&path).context("CA certificate")? {
+                roots.add(&certificate).context("invalid CA certificate")?;
+            }
+
+            Some(roots)
+        }
+    };
+
+    let pins = value
+        .pinned_certificates
+        .iter()
+        .map(|pin| {
+            let sha256 = pin
+                .sha256
+                .iter()
+                .map(|hash| crate::tls::parse_spki_pin(hash))
+                .collect::<anyhow::Result<Vec<_>>>()
+                .with_context(|| format!("pinned public key for {}", pin.host))?;
+
+            Ok(crate::tls::SpkiPin {
+                host: pin.host.clone(),
+                sha256,
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    Ok(crate::tls::TargetTls::new(roots, pins))
+}
+
 fn read_rustls_certificate_file(path: &Utf8Path) -> anyhow::Result<Vec<rustls::Certificate>> {
     read_rustls_certificate(Some(path), None).transpose().unwrap()
 }
@@ -665,48 +1503,14 @@ fn read_rustls_certificate(
     path: Option<&Utf8Path>,
     data: Option<&dto::ConfData<dto::CertFormat>>,
 ) -> anyhow::Result<Option<Vec<rustls::Certificate>>> {
-    use picky::pem::{read_pem, PemError};
-
     match (path, data) {
         (Some(path), _) => {
-            let mut x509_chain_file = normalize_data_path(path, &get_data_dir())
+            let x509_chain_file = normalize_data_path(path, &get_data_dir())
                 .pipe_ref(File::open)
                 .with_context(|| format!("couldn't open file at {path}"))?
                 .pipe(std::io::BufReader::new);
 
-            let mut x509_chain = Vec::new();
-
-            loop {
-                match read_pem(&mut x509_chain_file) {
-                    Ok(pem) => {
-                        if CERTIFICATE_LABELS.iter().all(|&label| pem.label() != label) {
-                            anyhow::bail!(
-                                "bad pem label (got {}, expected one of {CERTIFICATE_LABELS:?}) at position {}",
-                                pem.label(),
-                                x509_chain.len(),
-                            );
-                        }
-
-                        x509_chain.push(rustls::Certificate(pem.into_data().into_owned()));
-                    }
-                    Err(e @ PemError::HeaderNotFound) => {
-                        if x509_chain.is_empty() {
-                            return anyhow::Error::new(e)
-                                .context("couldn't parse first pem document")
-                                .pipe(Err);
-                        }
-
-                        break;
-                    }
-                    Err(e) => {
-                        return anyhow::Error::new(e)
-                            .context(format!("couldn't parse pem document at position {}", x509_chain.len()))
-                            .pipe(Err)
-                    }
-                }
-            }
-
-            Ok(Some(x509_chain))
+            read_pem_certificate_chain(x509_chain_file).map(Some)
         }
         (None, Some(data)) => {
             let value = data.decode_value()?;
@@ -719,6 +1523,47 @@ fn read_rustls_certificate(
     }
 }
 
+/// Reads the PEM-encoded certificates of a chain, starting with the leaf.
+pub(crate) fn read_pem_certificate_chain(
+    mut reader: impl std::io::BufRead,
+) -> anyhow::Result<Vec<rustls::Certificate>> {
+    use picky::pem::{read_pem, PemError};
+
+    let mut x509_chain = Vec::new();
+
+    loop {
+        match read_pem(&mut reader) {
+            Ok(pem) => {
+                if CERTIFICATE_LABELS.iter().all(|&label| pem.label() != label) {
+                    anyhow::bail!(
+                        "bad pem label (got {}, expected one of {CERTIFICATE_LABELS:?}) at position {}",
+                        pem.label(),
+                        x509_chain.len(),
+                    );
+                }
+
+                x509_chain.push(rustls::Certificate(pem.into_data().into_owned()));
+            }
+            Err(e @ PemError::HeaderNotFound) => {
+                if x509_chain.is_empty() {
+                    return anyhow::Error::new(e)
+                        .context("couldn't parse first pem document")
+                        .pipe(Err);
+                }
+
+                break;
+            }
+            Err(e) => {
+                return anyhow::Error::new(e)
+                    .context(format!("couldn't parse pem document at position {}", x509_chain.len()))
+                    .pipe(Err)
+            }
+        }
+    }
+
+    Ok(x509_chain)
+}
+
 fn read_pub_key_data(data: &dto::ConfData<dto::PubKeyFormat>) -> anyhow::Result<PublicKey> {
     read_pub_key(None, Some(data)).transpose().unwrap()
 }
@@ -804,6 +1649,19 @@ fn read_priv_key(
     }
 }
 
+fn decode_hex_key(hex: &str) -> anyhow::Result<zeroize::Zeroizing<[u8; 32]>> {
+    let mut key = zeroize::Zeroizing::new([0u8; 32]);
+
+    anyhow::ensure!(hex.len() == key.len() * 2, "bad length");
+
+    for (byte, digits) in key.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        let digits = std::str::from_utf8(digits).context("invalid character")?;
+        *byte = u8::from_str_radix(digits, 16).context("invalid hexadecimal digit")?;
+    }
+
+    Ok(key)
+}
+
 fn to_listener_urls(conf: &dto::ListenerConf, hostname: &str, auto_ipv6: bool) -> anyhow::Result<Vec<ListenerUrls>> {
     fn map_scheme(url: &mut Url) {
         match url.scheme() {
@@ -841,18 +1699,53 @@ fn to_listener_urls(conf: &dto::ListenerConf, hostname: &str, auto_ipv6: bool) -
         external_url.set_host(Some(hostname)).context("external URL hostname")?;
     }
 
+    let proxy_protocol = conf
+        .proxy_protocol
+        .as_ref()
+        .map(ProxyProtocolConf::from_dto)
+        .transpose()
+        .context("PROXY protocol")?;
+
+    let unix_socket = if internal_url.scheme() == "unix" {
+        anyhow::ensure!(
+            proxy_protocol.is_none(),
+            "PROXY protocol is not supported on Unix socket listeners"
+        );
+
+        let unix_socket = conf
+            .unix_socket
+            .as_ref()
+            .map(UnixSocketConf::from_dto)
+            .transpose()
+            .context("Unix socket")?
+            .unwrap_or_default();
+
+        Some(unix_socket)
+    } else {
+        anyhow::ensure!(
+            conf.unix_socket.is_none(),
+            "UnixSocket is only valid for unix:// listeners"
+        );
+
+        None
+    };
+
     let mut out = Vec::new();
 
     if let Some(internal_url_ipv6) = internal_url_ipv6 {
         out.push(ListenerUrls {
             internal_url: internal_url_ipv6,
             external_url: external_url.clone(),
+            proxy_protocol: proxy_protocol.clone(),
+            unix_socket: unix_socket.clone(),
         })
     }
 
     out.push(ListenerUrls {
         internal_url,
         external_url,
+        proxy_protocol,
+        unix_socket,
     });
 
     Ok(out)
@@ -924,6 +1817,22 @@ pub mod dto {
         #[serde(skip_serializing_if = "Option::is_none")]
         pub tls_certificate_store_location: Option<CertStoreLocation>,
 
+        /// Additional certificates, selected by server name indication (SNI)
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub tls_certificates: Vec<TlsCertificateConf>,
+
+        /// Automatic certificate management, when the certificate source is ACME
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub acme: Option<AcmeConf>,
+
+        /// Verification of the certificates presented by the target servers
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub target_tls: Option<TargetTlsConf>,
+
+        /// Connection to the target servers
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub target_connection: Option<TargetConnectionConf>,
+
         /// Listeners to launch at startup
         #[serde(default, skip_serializing_if = "Vec::is_empty")]
         pub listeners: Vec<ListenerConf>,
@@ -936,6 +1845,10 @@ pub mod dto {
         #[serde(skip_serializing_if = "Option::is_none")]
         pub recording_path: Option<Utf8PathBuf>,
 
+        /// Recording configuration
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub recording: Option<RecordingConf>,
+
         /// Ngrok config (closely maps https://ngrok.com/docs/ngrok-agent/config/)
         #[serde(skip_serializing_if = "Option::is_none")]
         pub ngrok: Option<NgrokConf>,
@@ -994,14 +1907,22 @@ pub mod dto {
                 tls_certificate_subject_name: None,
                 tls_certificate_store_name: None,
                 tls_certificate_store_location: None,
+                tls_certificates: Vec::new(),
+                acme: None,
+                target_tls: None,
+                target_connection: None,
                 listeners: vec![
                     ListenerConf {
                         internal_url: "tcp://*:8181".to_owned(),
                         external_url: "tcp://*:8181".to_owned(),
+                        proxy_protocol: None,
+                        unix_socket: None,
                     },
                     ListenerConf {
                         internal_url: "http://*:7171".to_owned(),
                         external_url: "https://*:7171".to_owned(),
+                        proxy_protocol: None,
+                        unix_socket: None,
                     },
                 ],
                 subscriber: None,
@@ -1011,6 +1932,7 @@ pub mod dto {
                 jrl_file: None,
                 plugins: None,
                 recording_path: None,
+                recording: None,
                 web_app: None,
                 sogar: None,
                 debug: None,
@@ -1213,6 +2135,30 @@ pub mod dto {
         pub internal_url: String,
         /// URL to use from external networks
         pub external_url: String,
+        /// PROXY protocol handling, for listeners behind a load balancer
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub proxy_protocol: Option<ProxyProtocolConf>,
+        /// Unix socket options, for unix:// listeners
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub unix_socket: Option<UnixSocketConf>,
+    }
+
+    #[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    pub struct ProxyProtocolConf {
+        /// Addresses of the load balancers, in CIDR notation (e.g.: 10.0.0.0/8)
+        pub trusted_sources: Vec<String>,
+    }
+
+    #[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    pub struct UnixSocketConf {
+        /// Permissions of the socket file, in octal notation (e.g.: 660)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub mode: Option<String>,
+        /// IDs of the users granted all the scopes without a token
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub admin_uids: Option<Vec<u32>>,
     }
 
     /// Subscriber configuration
@@ -1287,6 +2233,8 @@ pub mod dto {
         External,
         /// Provided by Operating System (Windows Certificate Store, etc)
         System,
+        /// Obtained and renewed automatically from an ACME server (Let's Encrypt, etc)
+        Acme,
     }
 
     #[derive(PartialEq, Eq, Debug, Clone, Copy, Default, Serialize, Deserialize)]
@@ -1297,6 +2245,130 @@ pub mod dto {
         LocalMachine,
     }
 
+    #[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    pub struct TlsCertificateConf {
+        /// Host names served with this certificate, possibly with wildcards (e.g.: `*.example.com`)
+        pub hostnames: Vec<String>,
+        pub certificate_file: Utf8PathBuf,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub private_key_file: Option<Utf8PathBuf>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub private_key_password: Option<Password>,
+        /// Serve this certificate when no host name matches, instead of the one from TlsCertificateFile
+        #[serde(default)]
+        pub default: bool,
+    }
+
+    #[derive(PartialEq, Eq, Debug, Clone, Default, Serialize, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    pub struct AcmeConf {
+        /// URL of the ACME directory (default is Let's Encrypt production)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub directory_url: Option<Url>,
+        /// Email address registered with the ACME account
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub contact_email: Option<String>,
+        /// Agree to the terms of service of the ACME server, required to register the account
+        #[serde(default)]
+        pub agree_to_terms_of_service: bool,
+        /// Domains to include in the certificate (default is the hostname)
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub domains: Vec<String>,
+        /// Challenge used to prove the domain ownership (default is HTTP-01)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub challenge: Option<AcmeChallengeType>,
+        /// Number of days before expiration when the certificate is renewed (default is 30)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub renew_before_days: Option<u32>,
+        /// Additional CA certificates to trust when connecting to the ACME server (e.g.: a local test server)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub ca_certificate_file: Option<Utf8PathBuf>,
+    }
+
+    #[derive(PartialEq, Eq, Debug, Clone, Copy, Default, Serialize, Deserialize)]
+    pub enum AcmeChallengeType {
+        /// Served on the HTTP listeners, under `/.well-known/acme-challenge/`
+        #[default]
+        Http01,
+        /// Served on the HTTPS listeners, during the TLS handshake
+        TlsAlpn01,
+    }
+
+    impl AcmeChallengeType {
+        /// Challenge type, as named by the ACME protocol
+        pub fn as_str(self) -> &'static str {
+            match self {
+                AcmeChallengeType::Http01 => "http-01",
+                AcmeChallengeType::TlsAlpn01 => "tls-alpn-01",
+            }
+        }
+    }
+
+    #[derive(PartialEq, Eq, Debug, Clone, Default, Serialize, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    pub struct TargetTlsConf {
+        /// How the certificates of the target servers are verified (default is Insecure)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub verification: Option<TargetTlsVerification>,
+        /// CA certificates to trust, when the verification is Custom
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub ca_certificate_file: Option<Utf8PathBuf>,
+        /// Public keys expected from specific target servers
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub pinned_certificates: Vec<SpkiPinConf>,
+    }
+
+    #[derive(PartialEq, Eq, Debug, Clone, Copy, Default, Serialize, Deserialize)]
+    pub enum TargetTlsVerification {
+        /// Certificates are not verified
+        #[default]
+        Insecure,
+        /// Certificates are verified using the root certificates of the system
+        System,
+        /// Certificates are verified using the CA certificates from CaCertificateFile
+        Custom,
+    }
+
+    #[derive(PartialEq, Eq, Debug, Clone, Default, Serialize, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    pub struct TargetConnectionConf {
+        /// Delay in milliseconds before starting the next attempt while the previous ones are pending (default is 250)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub attempt_delay: Option<u64>,
+        /// Overall connection timeout in seconds (default is 10)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub timeout: Option<u64>,
+        /// Rules selecting the upstream proxy used to reach the targets, by order of precedence
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub proxy_rules: Vec<ProxyRuleConf>,
+    }
+
+    #[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    pub struct ProxyRuleConf {
+        /// Targets matching this rule: `*`, IP ranges (e.g.: `10.0.0.0/8`) or host names (e.g.: `*.example.com`)
+        pub destinations: Vec<String>,
+        /// URL of the proxy (e.g.: `socks5://proxy.example.com:1080`), matching targets are reached directly if omitted
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub proxy: Option<Url>,
+        /// Username for SOCKS5 proxies requiring authentication
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub proxy_username: Option<String>,
+        /// Password for SOCKS5 proxies requiring authentication
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub proxy_password: Option<Password>,
+    }
+
+    #[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    pub struct SpkiPinConf {
+        /// Host name of the target, possibly with a wildcard (e.g.: `*.example.com`)
+        pub host: String,
+        /// Base64-encoded SHA-256 hashes of the SubjectPublicKeyInfo
+        pub sha256: Vec<String>,
+    }
+
     #[derive(PartialEq, Eq, Clone, zeroize::Zeroize)]
     pub struct Password(String);
 
@@ -1369,6 +2441,81 @@ pub mod dto {
         }
     }
 
+    #[derive(PartialEq, Eq, Debug, Clone, Default, Serialize, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    pub struct RecordingConf {
+        /// Retention policy for completed recordings
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub retention: Option<RecordingRetentionConf>,
+        /// Delay for the recording to start when required by the session token, in seconds
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub policy_grace_period: Option<u64>,
+        /// Path to the private key used to sign the recording manifests
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub signing_private_key_file: Option<Utf8PathBuf>,
+        /// Encryption at rest for recordings
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub encryption: Option<RecordingEncryptionConf>,
+        /// Free space on the recording volume below which new recordings are refused, in megabytes
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub free_space_threshold: Option<u64>,
+        /// S3-compatible storage the completed recording files are uploaded to
+        #[serde(rename = "S3", skip_serializing_if = "Option::is_none")]
+        pub s3: Option<S3StorageConf>,
+    }
+
+    #[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    pub struct S3StorageConf {
+        /// URL of the S3-compatible endpoint (e.g.: https://s3.us-east-1.amazonaws.com)
+        pub endpoint: Url,
+        pub bucket: String,
+        /// Region used to sign the requests (default is us-east-1)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub region: Option<String>,
+        /// Prefix of the object keys
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub prefix: Option<String>,
+        /// Access key ID, taken from the AWS_ACCESS_KEY_ID environment variable when not specified
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub access_key_id: Option<String>,
+        /// Secret access key, taken from the AWS_SECRET_ACCESS_KEY environment variable when not specified
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub secret_access_key: Option<String>,
+        /// Use path-style requests instead of virtual-hosted–style requests (required by most self-hosted servers)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub force_path_style: Option<bool>,
+        /// Keep the recording files on the local volume once uploaded (default is true)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub keep_local_cache: Option<bool>,
+    }
+
+    #[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    pub struct RecordingEncryptionConf {
+        /// Identifier of the master key, recorded in the manifests
+        pub key_id: String,
+        /// Path to the file holding the 256-bit master key, hex-encoded
+        pub master_key_file: Utf8PathBuf,
+    }
+
+    #[derive(PartialEq, Eq, Debug, Clone, Default, Serialize, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    pub struct RecordingRetentionConf {
+        /// Maximum age of a completed recording, in days
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub max_age: Option<u64>,
+        /// Maximum total size of the recordings folder, in megabytes
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub max_total_size: Option<u64>,
+        /// Minimum free space to keep on the recording volume, in megabytes
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub min_free_space: Option<u64>,
+        /// Interval between two retention checks, in minutes
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub check_interval: Option<u64>,
+    }
+
     #[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
     #[serde(rename_all = "PascalCase")]
     pub struct WebAppConf {
@@ -9,7 +9,8 @@ use typed_builder::TypedBuilder;
 use crate::config::Conf;
 use crate::proxy::Proxy;
 use crate::rdp_pcb::{extract_association_claims, read_pcb};
-use crate::recording::ActiveRecordings;
+use crate::recording::RecordingMessageSender;
+use crate::rendezvous::RendezvousBroker;
 use crate::session::{ConnectionModeDetails, SessionInfo, SessionMessageSender};
 use crate::subscriber::SubscriberSender;
 use crate::token::{ConnectionMode, CurrentJrl, TokenCache};
@@ -24,7 +25,8 @@ pub struct GenericClient<S> {
     client_stream: S,
     sessions: SessionMessageSender,
     subscriber_tx: SubscriberSender,
-    active_recordings: Arc<ActiveRecordings>,
+    recordings: RecordingMessageSender,
+    rendezvous: Arc<RendezvousBroker>,
 }
 
 impl<S> GenericClient<S>
@@ -45,7 +47,8 @@ where
             mut client_stream,
             sessions,
             subscriber_tx,
-            active_recordings,
+            recordings,
+            rendezvous,
         } = self;
 
         let span = tracing::Span::current();
@@ -63,24 +66,65 @@ where
         };
 
         let source_ip = client_addr.ip();
-        let claims = extract_association_claims(&pdu, source_ip, &conf, &token_cache, &jrl, &active_recordings)?;
+        let claims = extract_association_claims(
+            &pdu,
+            source_ip,
+            &conf,
+            &token_cache,
+            &jrl,
+            &recordings.active_recordings,
+        )?;
 
         span.record("session_id", claims.jet_aid.to_string())
             .record("protocol", claims.jet_ap.to_string());
 
         match claims.jet_cm {
             ConnectionMode::Rdv => {
-                anyhow::bail!("TCP rendezvous not supported");
+                trace!("Join the acceptor");
+
+                let (mut acceptor_stream, acceptor_addr) = rendezvous
+                    .take_acceptor(claims.jet_aid, None)
+                    .await
+                    .with_context(|| format!("no acceptor waiting for association {}", claims.jet_aid))?;
+
+                info!(%acceptor_addr, "TCP rendezvous");
+
+                acceptor_stream
+                    .write_buf(&mut leftover_bytes)
+                    .await
+                    .context("failed to write leftover bytes")?;
+
+                let info = SessionInfo::new(claims.jet_aid, claims.jet_ap, ConnectionModeDetails::Rdv)
+                    .with_ttl(claims.jet_ttl)
+                    .with_recording_policy(claims.jet_rec)
+                    .with_filtering_policy(claims.jet_flt);
+
+                Proxy::builder()
+                    .conf(conf)
+                    .session_info(info)
+                    .address_a(client_addr)
+                    .transport_a(client_stream)
+                    .address_b(acceptor_addr)
+                    .transport_b(acceptor_stream)
+                    .sessions(sessions)
+                    .subscriber_tx(subscriber_tx)
+                    .recordings(Some(recordings))
+                    .build()
+                    .select_dissector_and_forward()
+                    .await
+                    .context("encountered a failure during rendezvous traffic proxying")
             }
             ConnectionMode::Fwd { targets, creds: None } => {
-                if claims.jet_rec {
+                // Only the sessions the gateway is able to record by itself can meet the recording policy.
+                if claims.jet_rec && !crate::proxy::is_recordable(&claims.jet_ap) {
                     anyhow::bail!("can't meet recording policy");
                 }
 
                 trace!("Select and connect to target");
 
-                let ((mut server_stream, server_addr), selected_target) =
-                    utils::successive_try(&targets, utils::tcp_connect).await?;
+                let ((mut server_stream, _), selected_target) =
+                    utils::tcp_connect(&targets, &conf.target_connection).await?;
+                let server_addr = server_stream.peer_addr()?;
 
                 trace!(%selected_target, "Connected");
                 span.record("target", selected_target.to_string());
@@ -112,6 +156,7 @@ where
                     .transport_b(server_stream)
                     .sessions(sessions)
                     .subscriber_tx(subscriber_tx)
+                    .recordings(Some(recordings))
                     .build()
                     .select_dissector_and_forward()
                     .await
@@ -97,11 +97,23 @@ impl HttpError {
         HttpErrorBuilder::new(StatusCode::BAD_REQUEST)
     }
 
+    #[inline]
+    #[track_caller]
+    pub fn conflict() -> HttpErrorBuilder {
+        HttpErrorBuilder::new(StatusCode::CONFLICT)
+    }
+
     #[inline]
     #[track_caller]
     pub fn bad_gateway() -> HttpErrorBuilder {
         HttpErrorBuilder::new(StatusCode::BAD_GATEWAY)
     }
+
+    #[inline]
+    #[track_caller]
+    pub fn insufficient_storage() -> HttpErrorBuilder {
+        HttpErrorBuilder::new(StatusCode::INSUFFICIENT_STORAGE)
+    }
 }
 
 impl fmt::Display for HttpError {
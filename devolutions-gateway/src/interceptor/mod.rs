@@ -7,6 +7,7 @@ use tokio::io::{AsyncRead, AsyncWrite};
 
 pub mod pcap;
 pub mod plugin_recording;
+pub mod terminal_recording;
 // pub mod rdp;
 
 pin_project! {
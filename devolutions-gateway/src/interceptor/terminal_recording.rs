@@ -0,0 +1,296 @@
+//! Server-side terminal recording
+//!
+//! Records the terminal output of a Telnet session as an [asciicast v2] file, stored in the recording
+//! folder of the session like any recording pushed by a client. This allows sessions requiring
+//! recording to be recorded without the cooperation of the client.
+//!
+//! Only the output of the server is recorded, since the keystrokes may contain secrets such as passwords.
+//! Telnet commands are stripped, and the window size negotiated by the client (NAWS) is recorded as resize events.
+//!
+//! [asciicast v2]: https://docs.asciinema.org/manual/asciicast/v2/
+
+use anyhow::Context as _;
+use devolutions_gateway_task::ChildTask;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use uuid::Uuid;
+
+use crate::interceptor::{Inspector, PeerSide};
+use crate::recording::{RecordingMessageSender, RecordingWriter};
+use crate::token::RecordingFileType;
+
+const DEFAULT_WIDTH: u16 = 80;
+const DEFAULT_HEIGHT: u16 = 24;
+
+/// Maximum number of chunks of traffic waiting to be written to the recording.
+///
+/// Inspectors are called while the traffic is forwarded and can't wait for the recording to be written:
+/// when the writer falls behind, the chunks are dropped instead of being buffered without limit.
+const CHANNEL_CAPACITY: usize = 1024;
+
+pub struct TerminalRecordingInspector {
+    side: PeerSide,
+    sender: mpsc::Sender<(PeerSide, Instant, Vec<u8>)>,
+    /// Number of chunks dropped since the last one sent to the writer
+    dropped_count: usize,
+}
+
+impl Inspector for TerminalRecordingInspector {
+    fn inspect_bytes(&mut self, bytes: &[u8]) -> anyhow::Result<()> {
+        match self.sender.try_send((self.side, Instant::now(), bytes.to_vec())) {
+            Ok(()) => {
+                if self.dropped_count > 0 {
+                    warn!(
+                        side = ?self.side,
+                        dropped_count = self.dropped_count,
+                        "Terminal recording caught up; some data is missing from the recording"
+                    );
+                    self.dropped_count = 0;
+                }
+            }
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                if self.dropped_count == 0 {
+                    warn!(side = ?self.side, "Terminal recording is lagging behind; dropping data");
+                }
+                self.dropped_count += 1;
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => anyhow::bail!("terminal recording task is terminated"),
+        }
+
+        Ok(())
+    }
+}
+
+impl TerminalRecordingInspector {
+    /// Returns client side and server side inspector
+    pub fn init(recordings: RecordingMessageSender, session_id: Uuid) -> (Self, Self) {
+        let (sender, receiver) = mpsc::channel(CHANNEL_CAPACITY);
+
+        ChildTask::spawn(writer_task(receiver, recordings, session_id)).detach();
+
+        (
+            Self {
+                side: PeerSide::Client,
+                sender: sender.clone(),
+                dropped_count: 0,
+            },
+            Self {
+                side: PeerSide::Server,
+                sender,
+                dropped_count: 0,
+            },
+        )
+    }
+}
+
+async fn writer_task(
+    receiver: mpsc::Receiver<(PeerSide, Instant, Vec<u8>)>,
+    recordings: RecordingMessageSender,
+    session_id: Uuid,
+) {
+    if let Err(error) = write_recording(receiver, recordings, session_id).await {
+        error!(%session_id, error = format!("{error:#}"), "Terminal recording failure");
+    }
+}
+
+#[derive(Serialize)]
+struct AsciicastHeader {
+    version: u8,
+    width: u16,
+    height: u16,
+    timestamp: i64,
+}
+
+async fn write_recording(
+    mut receiver: mpsc::Receiver<(PeerSide, Instant, Vec<u8>)>,
+    recordings: RecordingMessageSender,
+    session_id: Uuid,
+) -> anyhow::Result<()> {
+    let start = Instant::now();
+
+    let header = AsciicastHeader {
+        version: 2,
+        width: DEFAULT_WIDTH,
+        height: DEFAULT_HEIGHT,
+        timestamp: time::OffsetDateTime::now_utc().unix_timestamp(),
+    };
+
+    let mut writer = RecordingWriter::start(recordings, session_id, RecordingFileType::Asciicast)
+        .await
+        .context("failed to start recording")?;
+
+    let res = async {
+        write_line(&mut writer, &header).await?;
+
+        let mut server_decoder = TelnetDecoder::default();
+        let mut client_decoder = TelnetDecoder::default();
+        let mut output = Vec::new();
+        let mut discarded = Vec::new();
+
+        while let Some((side, instant, bytes)) = receiver.recv().await {
+            let time = instant.duration_since(start).as_secs_f64();
+
+            match side {
+                PeerSide::Server => {
+                    server_decoder.decode(&bytes, &mut output);
+
+                    let text = decode_utf8(&mut output);
+
+                    if !text.is_empty() {
+                        write_line(&mut writer, &(time, "o", text)).await?;
+                    }
+                }
+                PeerSide::Client => {
+                    client_decoder.decode(&bytes, &mut discarded);
+                    discarded.clear();
+
+                    if let Some((width, height)) = client_decoder.window_size.take() {
+                        write_line(&mut writer, &(time, "r", format!("{width}x{height}"))).await?;
+                    }
+                }
+            }
+        }
+
+        anyhow::Ok(())
+    }
+    .await;
+
+    writer.finish().await.context("failed to finish recording")?;
+
+    res
+}
+
+async fn write_line<T: serde::Serialize>(writer: &mut RecordingWriter, value: &T) -> anyhow::Result<()> {
+    let mut line = serde_json::to_vec(value).context("failed to serialize asciicast line")?;
+    line.push(b'\n');
+    writer.write_all(&line).await.context("failed to write recording")
+}
+
+/// Decodes as much UTF-8 text as possible, keeping an incomplete trailing sequence for the next call.
+fn decode_utf8(pending: &mut Vec<u8>) -> String {
+    let complete_len = match std::str::from_utf8(pending) {
+        Err(e) if e.error_len().is_none() => e.valid_up_to(),
+        _ => pending.len(),
+    };
+
+    let rest = pending.split_off(complete_len);
+    let text = String::from_utf8_lossy(pending).into_owned();
+    *pending = rest;
+
+    text
+}
+
+const IAC: u8 = 255;
+const DONT: u8 = 254;
+const WILL: u8 = 251;
+const SB: u8 = 250;
+const SE: u8 = 240;
+const NAWS: u8 = 31;
+
+/// Subnegotiations we care about are short, longer ones are truncated.
+const MAX_SUBNEGOTIATION_SIZE: usize = 64;
+
+#[derive(Debug, Default, Clone, Copy)]
+enum TelnetState {
+    #[default]
+    Data,
+    Command,
+    Option,
+    Subnegotiation,
+    SubnegotiationCommand,
+}
+
+/// Strips Telnet commands (RFC 854) from a stream, keeping track of the window size (RFC 1073).
+#[derive(Debug, Default)]
+struct TelnetDecoder {
+    state: TelnetState,
+    subnegotiation: Vec<u8>,
+    window_size: Option<(u16, u16)>,
+}
+
+impl TelnetDecoder {
+    /// Appends the data bytes from `input` to `data`, handling the commands along the way.
+    fn decode(&mut self, input: &[u8], data: &mut Vec<u8>) {
+        for &byte in input {
+            self.state = match (self.state, byte) {
+                (TelnetState::Data, IAC) => TelnetState::Command,
+                (TelnetState::Data, _) => {
+                    data.push(byte);
+                    TelnetState::Data
+                }
+                // Escaped 0xFF data byte.
+                (TelnetState::Command, IAC) => {
+                    data.push(IAC);
+                    TelnetState::Data
+                }
+                (TelnetState::Command, WILL..=DONT) => TelnetState::Option,
+                (TelnetState::Command, SB) => {
+                    self.subnegotiation.clear();
+                    TelnetState::Subnegotiation
+                }
+                (TelnetState::Command, _) | (TelnetState::Option, _) => TelnetState::Data,
+                (TelnetState::Subnegotiation, IAC) => TelnetState::SubnegotiationCommand,
+                (TelnetState::Subnegotiation, _) | (TelnetState::SubnegotiationCommand, IAC) => {
+                    if self.subnegotiation.len() < MAX_SUBNEGOTIATION_SIZE {
+                        self.subnegotiation.push(byte);
+                    }
+                    TelnetState::Subnegotiation
+                }
+                (TelnetState::SubnegotiationCommand, SE) => {
+                    self.handle_subnegotiation();
+                    TelnetState::Data
+                }
+                (TelnetState::SubnegotiationCommand, _) => TelnetState::Subnegotiation,
+            };
+        }
+    }
+
+    fn handle_subnegotiation(&mut self) {
+        if let [NAWS, width_hi, width_lo, height_hi, height_lo] = self.subnegotiation[..] {
+            let width = u16::from_be_bytes([width_hi, width_lo]);
+            let height = u16::from_be_bytes([height_hi, height_lo]);
+            self.window_size = Some((width, height));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn telnet_commands_are_stripped() {
+        let mut decoder = TelnetDecoder::default();
+        let mut data = Vec::new();
+
+        decoder.decode(b"he\xFF\xFB\x01llo", &mut data);
+        decoder.decode(b"\xFF\xFF\xFF", &mut data);
+        decoder.decode(b"\xF1!", &mut data);
+
+        assert_eq!(data, b"hello\xFF!");
+        assert_eq!(decoder.window_size, None);
+    }
+
+    #[test]
+    fn window_size_is_extracted() {
+        let mut decoder = TelnetDecoder::default();
+        let mut data = Vec::new();
+
+        decoder.decode(b"\xFF\xFA\x1F\x00\x78", &mut data);
+        decoder.decode(b"\x00\x28\xFF\xF0ls", &mut data);
+
+        assert_eq!(data, b"ls");
+        assert_eq!(decoder.window_size, Some((120, 40)));
+    }
+
+    #[test]
+    fn incomplete_utf8_sequence_is_kept() {
+        let mut pending = "é".as_bytes()[..1].to_vec();
+        assert_eq!(decode_utf8(&mut pending), "");
+
+        pending.extend_from_slice(&"é".as_bytes()[1..]);
+        pending.push(b'a');
+        assert_eq!(decode_utf8(&mut pending), "éa");
+        assert!(pending.is_empty());
+    }
+}
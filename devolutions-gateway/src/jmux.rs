@@ -1,19 +1,46 @@
+use std::io;
 use std::sync::Arc;
 
+use crate::config::Conf;
 use crate::session::{ConnectionModeDetails, SessionInfo, SessionMessageSender};
 use crate::subscriber::SubscriberSender;
+use crate::target_addr::TargetAddr;
 use crate::token::JmuxTokenClaims;
+use crate::upstream_proxy::TargetStream;
 
 use anyhow::Context as _;
 use devolutions_gateway_task::ChildTask;
-use jmux_proxy::JmuxProxy;
+use jmux_proxy::{JmuxProxy, StreamConnector};
 use tap::prelude::*;
-use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::io::{AsyncRead, AsyncReadExt as _, AsyncWrite};
 use tokio::sync::Notify;
 use transport::{ErasedRead, ErasedWrite};
 
+const RAW_JMUX_MAGIC: [u8; 4] = *b"JMUX";
+
+/// Reads the preamble sent by the clients of raw TCP listeners, and returns the JMUX token
+///
+/// The preamble is the `JMUX` magic, followed by the length of the token (u16, big endian) and the token itself.
+pub async fn read_token_preamble<S>(stream: &mut S) -> anyhow::Result<String>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut magic = [0; 4];
+    stream.read_exact(&mut magic).await.context("read magic")?;
+    anyhow::ensure!(magic == RAW_JMUX_MAGIC, "invalid JMUX magic");
+
+    let token_len = stream.read_u16().await.context("read token length")?;
+    anyhow::ensure!(token_len > 0, "empty token");
+
+    let mut token = vec![0; usize::from(token_len)];
+    stream.read_exact(&mut token).await.context("read token")?;
+
+    String::from_utf8(token).context("invalid token")
+}
+
 pub async fn handle(
     stream: impl AsyncRead + AsyncWrite + Send + 'static,
+    conf: Arc<Conf>,
     claims: JmuxTokenClaims,
     sessions: SessionMessageSender,
     subscriber_tx: SubscriberSender,
@@ -57,7 +84,35 @@ pub async fn handle(
 
     crate::session::add_session_in_progress(&sessions, &subscriber_tx, info, notify_kill.clone()).await?;
 
-    let proxy_fut = JmuxProxy::new(reader, writer).with_config(config).run();
+    // Channels are opened the same way as the other connections to the targets (upstream proxy rules, etc).
+    let connector: StreamConnector = Arc::new(move |host, port| {
+        let conf = Arc::clone(&conf);
+
+        Box::pin(async move {
+            let target = TargetAddr::from_components("tcp", &host, port)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+
+            let ((stream, _), _) = crate::utils::tcp_connect([&target], &conf.target_connection)
+                .await
+                .map_err(|e| io::Error::new(crate::utils::io_error_kind(&e), format!("{e:#}")))?;
+
+            match stream {
+                TargetStream::Tcp(stream) => {
+                    let (reader, writer) = stream.into_split();
+                    Ok((Box::new(reader) as ErasedRead, Box::new(writer) as ErasedWrite))
+                }
+                TargetStream::HttpTunnel(stream) => {
+                    let (reader, writer) = tokio::io::split(stream);
+                    Ok((Box::new(reader) as ErasedRead, Box::new(writer) as ErasedWrite))
+                }
+            }
+        })
+    });
+
+    let proxy_fut = JmuxProxy::new(reader, writer)
+        .with_config(config)
+        .with_connector(connector)
+        .run();
     let proxy_handle = ChildTask::spawn(proxy_fut);
     let join_fut = proxy_handle.join();
     tokio::pin!(join_fut);
@@ -77,3 +132,24 @@ pub async fn handle(
 
     res
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn token_preamble() {
+        let mut stream: &[u8] = b"JMUX\x00\x05a.b.c\x01\x02";
+
+        let token = read_token_preamble(&mut stream).await.unwrap();
+
+        assert_eq!(token, "a.b.c");
+        assert_eq!(stream, [0x01, 0x02]);
+    }
+
+    #[tokio::test]
+    async fn truncated_token_preamble() {
+        let mut stream: &[u8] = b"JMUX\x00\x05a.b";
+        assert!(read_token_preamble(&mut stream).await.is_err());
+    }
+}
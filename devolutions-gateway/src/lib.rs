@@ -8,6 +8,7 @@ extern crate tracing;
 #[cfg(feature = "openapi")]
 pub mod openapi;
 
+pub mod acme;
 pub mod api;
 pub mod config;
 pub mod extract;
@@ -21,14 +22,19 @@ pub mod middleware;
 pub mod ngrok;
 pub mod plugin_manager;
 pub mod proxy;
+pub mod proxy_protocol;
 pub mod rdp_extension;
 pub mod rdp_pcb;
 pub mod recording;
+pub mod rendezvous;
 pub mod session;
 pub mod subscriber;
 pub mod target_addr;
+#[cfg(test)]
+mod test_utils;
 pub mod tls;
 pub mod token;
+pub mod upstream_proxy;
 pub mod utils;
 pub mod ws;
 
@@ -41,6 +47,8 @@ pub struct DgwState {
     pub subscriber_tx: subscriber::SubscriberSender,
     pub shutdown_signal: devolutions_gateway_task::ShutdownSignal,
     pub recordings: recording::RecordingMessageSender,
+    pub rendezvous: Arc<rendezvous::RendezvousBroker>,
+    pub acme_challenges: Arc<acme::AcmeChallenges>,
 }
 
 #[doc(hidden)]
@@ -70,6 +78,8 @@ impl DgwState {
             subscriber_tx,
             shutdown_signal,
             recordings: recording_manager_handle,
+            rendezvous: Arc::new(rendezvous::RendezvousBroker::new()),
+            acme_challenges: Arc::new(acme::AcmeChallenges::new()),
         };
 
         let handles = MockHandles {
@@ -3,17 +3,22 @@ use async_trait::async_trait;
 use devolutions_gateway_task::{ChildTask, ShutdownSignal, Task};
 use futures::TryFutureExt as _;
 use std::net::SocketAddr;
+use std::sync::Arc;
 use tap::Pipe as _;
-use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt as _};
 use tokio::net::{TcpListener, TcpSocket, TcpStream};
+use tokio_rustls::rustls;
 use tracing::Instrument as _;
 use url::Url;
 
+use crate::config::{ProxyProtocolConf, UnixSocketConf};
 use crate::generic_client::GenericClient;
+use crate::token::AccessTokenClaims;
 use crate::utils::url_to_socket_addr;
 use crate::DgwState;
 
 const HTTP_REQUEST_TIMEOUT: tokio::time::Duration = tokio::time::Duration::from_secs(15);
+const RAW_JMUX_PREAMBLE_TIMEOUT: tokio::time::Duration = tokio::time::Duration::from_secs(10);
 
 #[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 #[derive(Debug, Clone, Serialize)]
@@ -25,6 +30,12 @@ pub struct ListenerUrls {
     /// URL to use from external networks
     #[cfg_attr(feature = "openapi", schema(value_type = String))]
     pub external_url: Url,
+
+    #[serde(skip)]
+    pub proxy_protocol: Option<ProxyProtocolConf>,
+
+    #[serde(skip)]
+    pub unix_socket: Option<UnixSocketConf>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -32,22 +43,53 @@ pub enum ListenerKind {
     Tcp,
     Http,
     Https,
+    Unix,
+}
+
+/// Credentials of the peer connected to a Unix socket listener
+///
+/// Inserted in the extensions of the requests received on a Unix socket.
+#[derive(Debug, Clone, Copy)]
+pub struct UnixPeerCredentials {
+    pub uid: u32,
+    /// Whether the user is allowed to use all the scopes without a token
+    pub is_admin: bool,
+}
+
+enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(tokio::net::UnixListener),
 }
 
 pub struct GatewayListener {
-    addr: SocketAddr,
+    addr: Option<SocketAddr>,
     listener_url: Url,
     kind: ListenerKind,
-    listener: TcpListener,
+    listener: Listener,
+    proxy_protocol: Option<ProxyProtocolConf>,
+    unix_socket: UnixSocketConf,
     state: DgwState,
 }
 
 impl GatewayListener {
-    pub fn init_and_bind(url: impl ToInternalUrl, state: DgwState) -> anyhow::Result<Self> {
+    /// Binds the listener.
+    ///
+    /// The Unix socket configuration is only meaningful for Unix socket listeners, and the default one is used when
+    /// unspecified.
+    pub fn init_and_bind(
+        url: impl ToInternalUrl,
+        unix_socket: Option<UnixSocketConf>,
+        state: DgwState,
+    ) -> anyhow::Result<Self> {
         let url = url.to_internal_url();
 
         info!(%url, "Initiating listener…");
 
+        if url.scheme() == "unix" {
+            return Self::init_and_bind_unix(url, unix_socket.unwrap_or_default(), state);
+        }
+
         let socket_addr = url_to_socket_addr(&url).context("invalid url")?;
 
         let socket = if socket_addr.is_ipv4() {
@@ -71,15 +113,72 @@ impl GatewayListener {
         info!(?kind, addr = %socket_addr, "Listener started successfully");
 
         Ok(Self {
-            addr: socket_addr,
+            addr: Some(socket_addr),
             listener_url: url,
             kind,
-            listener,
+            listener: Listener::Tcp(listener),
+            proxy_protocol: None,
+            unix_socket: UnixSocketConf::default(),
+            state,
+        })
+    }
+
+    #[cfg(unix)]
+    fn init_and_bind_unix(url: Url, unix_socket: UnixSocketConf, state: DgwState) -> anyhow::Result<Self> {
+        use std::os::unix::fs::FileTypeExt as _;
+
+        let path = url.to_file_path().ok().context("invalid Unix socket path")?;
+
+        // A socket file left by a previous run prevents binding, but anything else is not ours to remove.
+        match std::fs::symlink_metadata(&path) {
+            Ok(metadata) if metadata.file_type().is_socket() => {
+                if std::os::unix::net::UnixStream::connect(&path).is_ok() {
+                    anyhow::bail!("{} is already in use", path.display());
+                }
+
+                std::fs::remove_file(&path).context("failed to remove stale Unix socket")?;
+
+                debug!(path = %path.display(), "Removed stale Unix socket");
+            }
+            Ok(_) => anyhow::bail!("{} already exists and is not a Unix socket", path.display()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(anyhow::Error::new(e).context("failed to inspect existing socket file")),
+        }
+
+        let listener = bind_unix_socket_with_mode(&path, unix_socket.mode)?;
+
+        info!(
+            kind = ?ListenerKind::Unix,
+            path = %path.display(),
+            mode = format!("{:o}", unix_socket.mode),
+            "Listener started successfully"
+        );
+
+        Ok(Self {
+            addr: None,
+            listener_url: url,
+            kind: ListenerKind::Unix,
+            listener: Listener::Unix(listener),
+            proxy_protocol: None,
+            unix_socket,
             state,
         })
     }
 
-    pub fn addr(&self) -> SocketAddr {
+    #[cfg(not(unix))]
+    fn init_and_bind_unix(_: Url, _: UnixSocketConf, _: DgwState) -> anyhow::Result<Self> {
+        anyhow::bail!("Unix socket listeners are not supported on this platform")
+    }
+
+    /// Expects a PROXY protocol header on the connections coming from the trusted sources.
+    #[must_use]
+    pub fn with_proxy_protocol(mut self, proxy_protocol: Option<ProxyProtocolConf>) -> Self {
+        self.proxy_protocol = proxy_protocol;
+        self
+    }
+
+    /// Address the listener is bound to, `None` for Unix socket listeners
+    pub fn addr(&self) -> Option<SocketAddr> {
         self.addr
     }
 
@@ -87,16 +186,71 @@ impl GatewayListener {
         self.kind
     }
 
-    #[instrument("listener", skip(self), fields(port = self.listener_url.port().expect("port")))]
+    #[instrument("listener", skip(self), fields(url = %self.listener_url))]
     pub async fn run(self) -> anyhow::Result<()> {
-        match self.kind() {
-            ListenerKind::Tcp => run_tcp_listener(self.listener, self.state).await,
-            ListenerKind::Http => run_http_listener(self.listener, self.state).await,
-            ListenerKind::Https => run_https_listener(self.listener, self.state).await,
+        match (self.kind, self.listener) {
+            (ListenerKind::Tcp, Listener::Tcp(listener)) => {
+                run_tcp_listener(listener, self.proxy_protocol, self.state).await
+            }
+            (ListenerKind::Http, Listener::Tcp(listener)) => {
+                run_http_listener(listener, self.proxy_protocol, self.state).await
+            }
+            (ListenerKind::Https, Listener::Tcp(listener)) => {
+                run_https_listener(listener, self.proxy_protocol, self.state).await
+            }
+            #[cfg(unix)]
+            (ListenerKind::Unix, Listener::Unix(listener)) => {
+                run_unix_listener(listener, self.unix_socket, self.state).await
+            }
+            _ => unreachable!("listener kind and socket are set together"),
         }
     }
 }
 
+/// Binds a Unix socket which is only reachable once it has the requested mode.
+///
+/// The socket is created with the permissions allowed by the umask, so it's bound inside a private directory,
+/// given its final mode, and only then moved to the requested path.
+#[cfg(unix)]
+fn bind_unix_socket_with_mode(path: &std::path::Path, mode: u32) -> anyhow::Result<tokio::net::UnixListener> {
+    use std::os::unix::fs::{DirBuilderExt as _, PermissionsExt as _};
+
+    let parent = path.parent().context("invalid Unix socket path")?;
+
+    // Created next to the socket, so moving the socket is a rename on the same file system.
+    let private_dir = parent.join(format!(".dgw-socket-{}", uuid::Uuid::new_v4()));
+
+    std::fs::DirBuilder::new()
+        .mode(0o700)
+        .create(&private_dir)
+        .with_context(|| format!("failed to create {}", private_dir.display()))?;
+
+    let private_path = private_dir.join("socket");
+
+    let result = (|| {
+        let listener = std::os::unix::net::UnixListener::bind(&private_path).context("failed to bind Unix socket")?;
+
+        std::fs::set_permissions(&private_path, std::fs::Permissions::from_mode(mode))
+            .context("failed to set Unix socket permissions")?;
+
+        std::fs::rename(&private_path, path).context("failed to move Unix socket into place")?;
+
+        anyhow::Ok(listener)
+    })();
+
+    if let Err(error) = std::fs::remove_dir_all(&private_dir) {
+        warn!(path = %private_dir.display(), %error, "Failed to remove private socket directory");
+    }
+
+    let listener = result?;
+
+    listener
+        .set_nonblocking(true)
+        .context("failed to set Unix socket in non-blocking mode")?;
+
+    tokio::net::UnixListener::from_std(listener).context("failed to register Unix socket")
+}
+
 #[async_trait]
 impl Task for GatewayListener {
     type Output = anyhow::Result<()>;
@@ -111,13 +265,28 @@ impl Task for GatewayListener {
     }
 }
 
-async fn run_tcp_listener(listener: TcpListener, state: DgwState) -> anyhow::Result<()> {
+async fn run_tcp_listener(
+    listener: TcpListener,
+    proxy_protocol: Option<ProxyProtocolConf>,
+    state: DgwState,
+) -> anyhow::Result<()> {
     loop {
         match listener.accept().await.context("failed to accept connection") {
-            Ok((stream, peer_addr)) => {
+            Ok((mut stream, peer_addr)) => {
                 let state = state.clone();
+                let proxy_protocol = proxy_protocol.clone();
 
                 ChildTask::spawn(async move {
+                    let peer_addr =
+                        match crate::proxy_protocol::client_addr(&mut stream, peer_addr, proxy_protocol.as_ref()).await
+                        {
+                            Ok(client_addr) => client_addr,
+                            Err(e) => {
+                                warn!(%peer_addr, error = format!("{e:#}"), "Invalid PROXY protocol header");
+                                return;
+                            }
+                        };
+
                     if let Err(e) = handle_tcp_peer(stream, state, peer_addr).await {
                         error!(error = format!("{e:#}"), "Peer failure");
                     }
@@ -143,8 +312,8 @@ async fn handle_tcp_peer(stream: TcpStream, state: DgwState, peer_addr: SocketAd
 
     // Check if first four bytes contains some protocol magic bytes
     match &peeked[..n_read] {
-        [b'J', b'E', b'T', b'\0'] => anyhow::bail!("not yet supported"),
-        [b'J', b'M', b'U', b'X'] => anyhow::bail!("not yet supported"),
+        [b'J', b'E', b'T', b'\0'] => crate::rendezvous::handle_jet_peer(stream, peer_addr, state).await?,
+        [b'J', b'M', b'U', b'X'] => handle_raw_jmux_peer(stream, state, peer_addr).await?,
         _ => {
             GenericClient::builder()
                 .conf(state.conf_handle.get_conf())
@@ -154,7 +323,8 @@ async fn handle_tcp_peer(stream: TcpStream, state: DgwState, peer_addr: SocketAd
                 .jrl(state.jrl)
                 .sessions(state.sessions)
                 .subscriber_tx(state.subscriber_tx)
-                .active_recordings(state.recordings.active_recordings)
+                .recordings(state.recordings)
+                .rendezvous(state.rendezvous)
                 .build()
                 .serve()
                 .await?;
@@ -164,21 +334,64 @@ async fn handle_tcp_peer(stream: TcpStream, state: DgwState, peer_addr: SocketAd
     Ok(())
 }
 
-async fn run_http_listener(listener: TcpListener, state: DgwState) -> anyhow::Result<()> {
+async fn handle_raw_jmux_peer(mut stream: TcpStream, state: DgwState, peer_addr: SocketAddr) -> anyhow::Result<()> {
+    let token = tokio::time::timeout(RAW_JMUX_PREAMBLE_TIMEOUT, crate::jmux::read_token_preamble(&mut stream))
+        .await
+        .context("timed out at JMUX preamble reception")??;
+
+    let conf = state.conf_handle.get_conf();
+
+    let claims = crate::middleware::auth::authenticate(
+        peer_addr,
+        &token,
+        &conf,
+        &state.token_cache,
+        &state.jrl,
+        &state.recordings.active_recordings,
+    )
+    .context("token validation")?;
+
+    let AccessTokenClaims::Jmux(claims) = claims else {
+        anyhow::bail!("unexpected token type (expected JMUX)");
+    };
+
+    crate::jmux::handle(stream, conf, claims, state.sessions, state.subscriber_tx)
+        .instrument(info_span!("jmux"))
+        .await
+}
+
+async fn run_http_listener(
+    listener: TcpListener,
+    proxy_protocol: Option<ProxyProtocolConf>,
+    state: DgwState,
+) -> anyhow::Result<()> {
     loop {
         match listener.accept().await {
-            Ok((stream, peer_addr)) => {
+            Ok((mut stream, peer_addr)) => {
                 let state = state.clone();
+                let proxy_protocol = proxy_protocol.clone();
 
-                let fut = tokio::time::timeout(HTTP_REQUEST_TIMEOUT, async move {
-                    if let Err(e) = handle_http_peer(stream, state, peer_addr).await {
-                        error!(error = format!("{e:#}"), "handle_http_peer failed");
-                    }
+                ChildTask::spawn(async move {
+                    let peer_addr =
+                        match crate::proxy_protocol::client_addr(&mut stream, peer_addr, proxy_protocol.as_ref()).await
+                        {
+                            Ok(client_addr) => client_addr,
+                            Err(e) => {
+                                warn!(%peer_addr, error = format!("{e:#}"), "Invalid PROXY protocol header");
+                                return;
+                            }
+                        };
+
+                    let _ = tokio::time::timeout(HTTP_REQUEST_TIMEOUT, async move {
+                        if let Err(e) = handle_http_peer(stream, state, peer_addr).await {
+                            error!(error = format!("{e:#}"), "handle_http_peer failed");
+                        }
+                    })
+                    .inspect_err(|error| warn!(%error, "Request timed out"))
+                    .instrument(info_span!("http", client = %peer_addr))
+                    .await;
                 })
-                .inspect_err(|error| warn!(%error, "Request timed out"))
-                .instrument(info_span!("http", client = %peer_addr));
-
-                ChildTask::spawn(fut).detach();
+                .detach();
             }
             Err(error) => {
                 error!(%error, "failed to accept connection");
@@ -187,24 +400,90 @@ async fn run_http_listener(listener: TcpListener, state: DgwState) -> anyhow::Re
     }
 }
 
-async fn run_https_listener(listener: TcpListener, state: DgwState) -> anyhow::Result<()> {
-    let conf = state.conf_handle.get_conf();
+async fn run_https_listener(
+    listener: TcpListener,
+    proxy_protocol: Option<ProxyProtocolConf>,
+    state: DgwState,
+) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        state.conf_handle.get_conf().tls.is_some(),
+        "TLS configuration is missing"
+    );
+
+    loop {
+        match listener.accept().await {
+            Ok((mut stream, peer_addr)) => {
+                // Picked for each connection, since the certificate may be reloaded at any time.
+                let Some(server_config) = state.conf_handle.get_conf().tls.as_ref().map(|tls| tls.server_config())
+                else {
+                    error!("TLS configuration is missing");
+                    continue;
+                };
+                let state = state.clone();
+                let proxy_protocol = proxy_protocol.clone();
+
+                ChildTask::spawn(async move {
+                    // The header is sent in clear, before the TLS handshake.
+                    let peer_addr =
+                        match crate::proxy_protocol::client_addr(&mut stream, peer_addr, proxy_protocol.as_ref()).await
+                        {
+                            Ok(client_addr) => client_addr,
+                            Err(e) => {
+                                warn!(%peer_addr, error = format!("{e:#}"), "Invalid PROXY protocol header");
+                                return;
+                            }
+                        };
+
+                    let _ = tokio::time::timeout(HTTP_REQUEST_TIMEOUT, async move {
+                        if let Err(e) = handle_https_peer(stream, server_config, state, peer_addr).await {
+                            error!(error = format!("{e:#}"), "handle_https_peer failed");
+                        }
+                    })
+                    .inspect_err(|error| warn!(%error, "Request timed out"))
+                    .instrument(info_span!("https", client = %peer_addr))
+                    .await;
+                })
+                .detach();
+            }
+            Err(error) => {
+                error!(%error, "failed to accept connection");
+            }
+        }
+    }
+}
 
-    let tls_conf = conf.tls.as_ref().context("TLS configuration is missing")?;
+#[cfg(unix)]
+async fn run_unix_listener(
+    listener: tokio::net::UnixListener,
+    unix_socket: UnixSocketConf,
+    state: DgwState,
+) -> anyhow::Result<()> {
+    // Connections on a Unix socket are local.
+    let peer_addr = SocketAddr::from((std::net::Ipv4Addr::LOCALHOST, 0));
 
     loop {
         match listener.accept().await {
-            Ok((stream, peer_addr)) => {
-                let tls_acceptor = tls_conf.acceptor.clone();
+            Ok((stream, _)) => {
+                let credentials = match stream.peer_cred() {
+                    Ok(cred) => UnixPeerCredentials {
+                        uid: cred.uid(),
+                        is_admin: unix_socket.admin_uids.contains(&cred.uid()),
+                    },
+                    Err(error) => {
+                        warn!(%error, "Failed to retrieve the peer credentials");
+                        continue;
+                    }
+                };
+
                 let state = state.clone();
 
                 let fut = tokio::time::timeout(HTTP_REQUEST_TIMEOUT, async move {
-                    if let Err(e) = handle_https_peer(stream, tls_acceptor, state, peer_addr).await {
-                        error!(error = format!("{e:#}"), "handle_https_peer failed");
+                    if let Err(e) = serve_http_connection(stream, state, peer_addr, Some(credentials)).await {
+                        error!(error = format!("{e:#}"), "handle_unix_peer failed");
                     }
                 })
                 .inspect_err(|error| warn!(%error, "Request timed out"))
-                .instrument(info_span!("https", client = %peer_addr));
+                .instrument(info_span!("unix", uid = credentials.uid));
 
                 ChildTask::spawn(fut).detach();
             }
@@ -217,12 +496,43 @@ async fn run_https_listener(listener: TcpListener, state: DgwState) -> anyhow::R
 
 async fn handle_https_peer(
     stream: TcpStream,
-    tls_acceptor: tokio_rustls::TlsAcceptor,
+    server_config: Arc<rustls::ServerConfig>,
     state: DgwState,
     peer_addr: SocketAddr,
 ) -> anyhow::Result<()> {
-    let tls_stream = tls_acceptor
-        .accept(stream)
+    let start_handshake = tokio_rustls::LazyConfigAcceptor::new(rustls::server::Acceptor::default(), stream)
+        .await
+        .context("TLS handshake failed")?;
+
+    let client_hello = start_handshake.client_hello();
+
+    let is_acme_validation = client_hello
+        .alpn()
+        .is_some_and(|mut protocols| protocols.any(|protocol| protocol == crate::acme::TLS_ALPN_01_PROTOCOL));
+
+    if is_acme_validation {
+        // TLS-ALPN-01 challenge: the validation certificate is presented, and the connection is closed.
+        let domain = client_hello.server_name().context("SNI is missing")?.to_owned();
+
+        let challenge_server_config = state
+            .acme_challenges
+            .tls_alpn_01_server_config(&domain)
+            .with_context(|| format!("no pending TLS-ALPN-01 challenge for {domain}"))?;
+
+        let mut tls_stream = start_handshake
+            .into_stream(challenge_server_config)
+            .await
+            .context("TLS handshake failed")?;
+
+        debug!(%domain, "Served TLS-ALPN-01 challenge");
+
+        let _ = tls_stream.shutdown().await;
+
+        return Ok(());
+    }
+
+    let tls_stream = start_handshake
+        .into_stream(server_config)
         .await
         .context("TLS handshake failed")?
         .pipe(tokio_rustls::TlsStream::Server);
@@ -231,6 +541,18 @@ async fn handle_https_peer(
 }
 
 pub(crate) async fn handle_http_peer<I>(io: I, state: DgwState, peer_addr: SocketAddr) -> anyhow::Result<()>
+where
+    I: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+{
+    serve_http_connection(io, state, peer_addr, None).await
+}
+
+async fn serve_http_connection<I>(
+    io: I,
+    state: DgwState,
+    peer_addr: SocketAddr,
+    unix_peer: Option<UnixPeerCredentials>,
+) -> anyhow::Result<()>
 where
     I: AsyncRead + AsyncWrite + Send + Unpin + 'static,
 {
@@ -238,7 +560,11 @@ where
     use hyper::service::service_fn;
     use tower::Service as _;
 
-    let service = service_fn(move |request: hyper::Request<hyper::body::Incoming>| {
+    let service = service_fn(move |mut request: hyper::Request<hyper::body::Incoming>| {
+        if let Some(unix_peer) = unix_peer {
+            request.extensions_mut().insert(unix_peer);
+        }
+
         // We have to clone `tower_service` because hyper's `Service` uses `&self` whereas
         // tower's `Service` requires `&mut self`.
         //
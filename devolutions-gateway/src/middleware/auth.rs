@@ -2,7 +2,7 @@ use std::net::SocketAddr;
 
 use axum::body::Body;
 use axum::extract::{ConnectInfo, State};
-use axum::http::{Method, Request};
+use axum::http::{header, Method, Request};
 use axum::middleware::Next;
 use axum::response::Response;
 use axum::RequestPartsExt as _;
@@ -12,8 +12,9 @@ use axum_extra::TypedHeader;
 
 use crate::config::Conf;
 use crate::http::HttpError;
+use crate::listener::UnixPeerCredentials;
 use crate::recording::ActiveRecordings;
-use crate::token::{AccessTokenClaims, CurrentJrl, TokenCache, TokenValidator};
+use crate::token::{AccessTokenClaims, CurrentJrl, ScopeTokenClaims, TokenCache, TokenValidator};
 use crate::DgwState;
 
 struct AuthException {
@@ -77,6 +78,12 @@ const AUTH_EXCEPTIONS: &[AuthException] = &[
         path: "/jet/webapp",
         exact_match: true,
     },
+    // -- ACME HTTP-01 challenge -- //
+    AuthException {
+        method: Method::GET,
+        path: "/.well-known/acme-challenge/",
+        exact_match: false,
+    },
     // -- Recording Player -- //
     AuthException {
         method: Method::GET,
@@ -94,7 +101,7 @@ pub async fn auth_middleware(
         ..
     }): State<DgwState>,
     ConnectInfo(source_addr): ConnectInfo<SocketAddr>,
-    request: Request<Body>,
+    mut request: Request<Body>,
     next: Next,
 ) -> Result<Response, HttpError> {
     #[derive(Deserialize)]
@@ -117,9 +124,27 @@ pub async fn auth_middleware(
         }
     });
 
+    // Local administrators connected to a Unix socket listener are authenticated by their credentials.
+    let is_unix_admin = request
+        .extensions()
+        .get::<UnixPeerCredentials>()
+        .is_some_and(|credentials| credentials.is_admin);
+
+    let has_token = request.headers().contains_key(header::AUTHORIZATION)
+        || request
+            .uri()
+            .query()
+            .is_some_and(|query| serde_urlencoded::from_str::<TokenQueryParam>(query).is_ok());
+
     if skip_authentication {
         trace!("unauthenticated route");
         Ok(next.run(request).await)
+    } else if is_unix_admin && !has_token {
+        trace!("authenticated by Unix socket peer credentials");
+        request
+            .extensions_mut()
+            .insert(AccessTokenClaims::Scope(ScopeTokenClaims::wildcard()));
+        Ok(next.run(request).await)
     } else {
         let (mut parts, body) = request.into_parts();
 
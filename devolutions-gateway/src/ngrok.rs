@@ -199,7 +199,8 @@ async fn run_tcp_tunnel(mut tunnel: ngrok::tunnel::TcpTunnel, state: DgwState) {
                         .jrl(state.jrl)
                         .sessions(state.sessions)
                         .subscriber_tx(state.subscriber_tx)
-                        .active_recordings(state.recordings.active_recordings)
+                        .recordings(state.recordings)
+                        .rendezvous(state.rendezvous)
                         .build()
                         .serve()
                         .await
@@ -14,10 +14,19 @@ use uuid::Uuid;
         crate::api::diagnostics::get_configuration,
         crate::api::diagnostics::get_clock,
         crate::api::config::patch_config,
+        crate::api::config::reload_tls_certificate,
         crate::api::jrl::update_jrl,
         crate::api::jrl::get_jrl_info,
+        crate::api::jrec::delete_recording,
+        crate::api::jrec::delete_many_recordings,
         crate::api::jrec::list_recordings,
         crate::api::jrec::pull_recording_file,
+        crate::api::jrec::pull_merged_recording,
+        crate::api::jrec::search_recordings,
+        crate::api::jrec::shadow_recording,
+        crate::api::jrec::verify_recording,
+        crate::api::jrec::export_recording,
+        crate::api::jrec::import_recording,
         crate::api::webapp::sign_app_token,
         crate::api::webapp::sign_session_token,
         // crate::api::net::get_net_config,
@@ -36,6 +45,18 @@ use uuid::Uuid;
         crate::api::config::SubProvisionerKey,
         crate::api::config::ConfigPatch,
         crate::api::jrl::JrlInfo,
+        crate::api::jrec::DeleteManyResult,
+        crate::api::jrec::ListRecordingsResponse,
+        crate::api::jrec::RecordingList,
+        crate::recording::RecordingInfo,
+        crate::recording::RecordingSearchResult,
+        crate::recording::SearchHit,
+        crate::recording::RecordingStorageState,
+        crate::recording::RecordingStorageStatus,
+        crate::recording::RecordingIntegrity,
+        crate::recording::SignatureStatus,
+        crate::recording::FileIntegrity,
+        crate::recording::FileIntegrityStatus,
         crate::token::AccessScope,
         crate::api::webapp::AppTokenSignRequest,
         crate::api::webapp::AppTokenContentType,
@@ -165,7 +186,15 @@ impl Modify for SecurityAddon {
 #[derive(OpenApi)]
 #[openapi(
     paths(post_subscriber_message),
-    components(schemas(SubscriberMessage, SubscriberSessionInfo, SubscriberMessageKind)),
+    components(schemas(
+        SubscriberMessage,
+        SubscriberSessionInfo,
+        SubscriberRecordingInfo,
+        SubscriberStorageInfo,
+        RecordingDeletionReason,
+        SessionKillReason,
+        SubscriberMessageKind
+    )),
     modifiers(&SubscriberSecurityAddon),
 )]
 pub struct SubscriberApiDoc;
@@ -177,6 +206,47 @@ struct SubscriberSessionInfo {
     start_timestamp: OffsetDateTime,
 }
 
+#[derive(utoipa::ToSchema, Serialize)]
+struct SubscriberRecordingInfo {
+    session_id: Uuid,
+    /// Size of the recording folder, in bytes
+    size: u64,
+}
+
+#[derive(utoipa::ToSchema, Serialize)]
+struct SubscriberStorageInfo {
+    /// Space available on the recording volume, in bytes
+    available_space: u64,
+    /// Configured free space threshold, in bytes
+    threshold: u64,
+}
+
+/// Reason why a recording was deleted
+#[allow(unused)]
+#[derive(utoipa::ToSchema, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum RecordingDeletionReason {
+    /// The recording is older than the configured maximum age
+    MaxAge,
+    /// The recording folder exceeded the configured maximum total size
+    MaxTotalSize,
+    /// The free space on the recording volume went below the configured minimum
+    MinFreeSpace,
+}
+
+/// Reason why a session was killed by the Gateway
+#[allow(unused)]
+#[derive(utoipa::ToSchema, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum SessionKillReason {
+    /// Termination was requested using the API
+    Requested,
+    /// The session reached its maximum duration
+    TimeToLive,
+    /// The session must be recorded, but no recording is active
+    RecordingPolicy,
+}
+
 /// Event type for messages
 #[allow(unused)]
 #[derive(utoipa::ToSchema, Serialize)]
@@ -191,6 +261,12 @@ enum SubscriberMessageKind {
     /// Periodic running session listing
     #[serde(rename = "session.list")]
     SessionList,
+    /// A recording was deleted by the retention policy
+    #[serde(rename = "recording.deleted")]
+    RecordingDeleted,
+    /// The free space on the recording volume went below the configured threshold
+    #[serde(rename = "recording.storage_low")]
+    RecordingStorageLow,
 }
 
 /// Message produced on various Gateway events
@@ -205,8 +281,16 @@ struct SubscriberMessage {
     timestamp: OffsetDateTime,
     /// Session information associated to this event
     session: Option<SubscriberSessionInfo>,
+    /// Reason why the session was killed by the Gateway, if it was
+    kill_reason: Option<SessionKillReason>,
     /// Session list associated to this event
     session_list: Option<Vec<SubscriberSessionInfo>>,
+    /// Recording information associated to this event
+    recording: Option<SubscriberRecordingInfo>,
+    /// Reason why the recording was deleted
+    reason: Option<RecordingDeletionReason>,
+    /// Recording storage information associated to this event
+    storage: Option<SubscriberStorageInfo>,
 }
 
 #[allow(unused)]
@@ -1,6 +1,8 @@
 use crate::config::Conf;
 use crate::interceptor::pcap::PcapInspector;
-use crate::interceptor::{Dissector, DummyDissector, Interceptor, WaykDissector};
+use crate::interceptor::terminal_recording::TerminalRecordingInspector;
+use crate::interceptor::{Dissector, DummyDissector, Inspector, Interceptor, WaykDissector};
+use crate::recording::RecordingMessageSender;
 use crate::session::{SessionInfo, SessionMessageSender};
 use crate::subscriber::SubscriberSender;
 use crate::token::{ApplicationProtocol, Protocol};
@@ -24,6 +26,9 @@ pub struct Proxy<A, B> {
     address_b: SocketAddr,
     sessions: SessionMessageSender,
     subscriber_tx: SubscriberSender,
+    /// When set, the gateway records by itself the sessions it's able to record
+    #[builder(default = None)]
+    recordings: Option<RecordingMessageSender>,
     #[builder(default = None)]
     buffer_size: Option<usize>,
 }
@@ -64,6 +69,9 @@ where
     where
         D: Dissector + Send + 'static,
     {
+        let mut client_inspectors: Vec<Box<dyn Inspector + Send>> = Vec::new();
+        let mut server_inspectors: Vec<Box<dyn Inspector + Send>> = Vec::new();
+
         if let Some(capture_path) = self.conf.debug.capture_path.as_ref() {
             let format = time::format_description::parse("[year]-[month]-[day]_[hour]-[minute]-[second]")
                 .expect("valid hardcoded format");
@@ -82,28 +90,51 @@ where
             let (client_inspector, server_inspector) =
                 PcapInspector::init(self.address_a, self.address_b, path, dissector)?;
 
-            let mut a = Interceptor::new(self.transport_a);
-            a.inspectors.push(Box::new(client_inspector));
-
-            let mut b = Interceptor::new(self.transport_b);
-            b.inspectors.push(Box::new(server_inspector));
-
-            Proxy {
-                transport_a: a,
-                transport_b: b,
-                conf: self.conf,
-                session_info: self.session_info,
-                address_a: self.address_a,
-                address_b: self.address_b,
-                sessions: self.sessions,
-                subscriber_tx: self.subscriber_tx,
-                buffer_size: self.buffer_size,
+            client_inspectors.push(Box::new(client_inspector));
+            server_inspectors.push(Box::new(server_inspector));
+        }
+
+        if self.is_terminal_recording_required() {
+            if let Some(recordings) = &self.recordings {
+                trace!("Terminal session will be recorded by the gateway");
+
+                let (client_inspector, server_inspector) =
+                    TerminalRecordingInspector::init(recordings.clone(), self.session_info.id());
+
+                client_inspectors.push(Box::new(client_inspector));
+                server_inspectors.push(Box::new(server_inspector));
             }
-            .forward()
-            .await
-        } else {
-            self.forward().await
         }
+
+        if client_inspectors.is_empty() && server_inspectors.is_empty() {
+            return self.forward().await;
+        }
+
+        let mut a = Interceptor::new(self.transport_a);
+        a.inspectors = client_inspectors;
+
+        let mut b = Interceptor::new(self.transport_b);
+        b.inspectors = server_inspectors;
+
+        Proxy {
+            transport_a: a,
+            transport_b: b,
+            conf: self.conf,
+            session_info: self.session_info,
+            address_a: self.address_a,
+            address_b: self.address_b,
+            sessions: self.sessions,
+            subscriber_tx: self.subscriber_tx,
+            recordings: self.recordings,
+            buffer_size: self.buffer_size,
+        }
+        .forward()
+        .await
+    }
+
+    /// Whether the session must be recorded and the gateway is able to record it by itself.
+    fn is_terminal_recording_required(&self) -> bool {
+        self.session_info.recording_policy && is_recordable(&self.session_info.application_protocol)
     }
 
     pub async fn forward(self) -> anyhow::Result<()> {
@@ -121,8 +152,8 @@ where
         )
         .await?;
 
-        // NOTE(DGW-86): when recording is required, should we wait for it to start before we forward, or simply spawn
-        // a timer to check if the recording is started within a few seconds?
+        // NOTE: when recording is required, the session manager kills the session if the recording is not started
+        // within the configured grace period, or if the recording is interrupted for too long.
 
         let kill_notified = notify_kill.notified();
 
@@ -169,6 +200,14 @@ where
     }
 }
 
+/// Returns whether the gateway is able to record by itself the sessions using this protocol.
+///
+/// Only plaintext terminal protocols can be recorded this way: the gateway doesn't terminate
+/// encrypted protocols such as SSH, so these must still be recorded by the client.
+pub fn is_recordable(application_protocol: &ApplicationProtocol) -> bool {
+    matches!(application_protocol, ApplicationProtocol::Known(Protocol::Telnet))
+}
+
 /// Walks source chain and check for status codes like ECONNRESET or ECONNABORTED that we don’t consider to be actual errors
 fn is_really_an_error(original_error: &io::Error) -> bool {
     use std::error::Error as _;
@@ -0,0 +1,216 @@
+//! PROXY protocol support for listeners behind a load balancer
+//!
+//! Load balancers such as HAProxy or AWS NLB send a header announcing the address of the actual client before
+//! forwarding the connection. Both the human-readable (v1) and binary (v2) formats are supported.
+//!
+//! https://www.haproxy.org/download/2.9/doc/proxy-protocol.txt
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use anyhow::Context as _;
+use tokio::io::{AsyncRead, AsyncReadExt as _};
+
+use crate::config::ProxyProtocolConf;
+
+const HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
+const V1_PREFIX: &[u8] = b"PROXY ";
+/// Including the CRLF
+const V1_MAX_LEN: usize = 107;
+
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+const V2_HEADER_LEN: usize = 16;
+const V2_COMMAND_LOCAL: u8 = 0x0;
+const V2_COMMAND_PROXY: u8 = 0x1;
+const V2_FAMILY_INET: u8 = 0x1;
+const V2_FAMILY_INET6: u8 = 0x2;
+
+/// Returns the address of the actual client.
+///
+/// The PROXY protocol header is required from the trusted sources, and is not looked for on other connections.
+/// The address of the peer is kept when the header doesn't carry an address (e.g.: health checks).
+pub async fn client_addr<S>(
+    stream: &mut S,
+    peer_addr: SocketAddr,
+    conf: Option<&ProxyProtocolConf>,
+) -> anyhow::Result<SocketAddr>
+where
+    S: AsyncRead + Unpin,
+{
+    let Some(conf) = conf else {
+        return Ok(peer_addr);
+    };
+
+    if !conf.is_trusted(peer_addr.ip()) {
+        return Ok(peer_addr);
+    }
+
+    let source_addr = tokio::time::timeout(HEADER_TIMEOUT, read_header(stream))
+        .await
+        .context("timed out waiting for the PROXY protocol header")??;
+
+    match source_addr {
+        Some(source_addr) => {
+            trace!(%peer_addr, %source_addr, "PROXY protocol header received");
+            Ok(source_addr)
+        }
+        None => Ok(peer_addr),
+    }
+}
+
+/// Reads a PROXY protocol header, leaving the stream right after it.
+async fn read_header<S>(stream: &mut S) -> anyhow::Result<Option<SocketAddr>>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut prefix = [0; 6];
+    stream.read_exact(&mut prefix).await.context("read header")?;
+
+    if prefix == V1_PREFIX {
+        // The header is short, and must not be read past its end.
+        let mut line = prefix.to_vec();
+
+        while !line.ends_with(b"\r\n") {
+            anyhow::ensure!(line.len() < V1_MAX_LEN, "PROXY protocol v1 header too long");
+            line.push(stream.read_u8().await.context("read header")?);
+        }
+
+        let line = std::str::from_utf8(&line).context("invalid PROXY protocol v1 header")?;
+
+        parse_v1(line)
+    } else if prefix == V2_SIGNATURE[..6] {
+        let mut header = [0; V2_HEADER_LEN];
+        header[..6].copy_from_slice(&prefix);
+        stream.read_exact(&mut header[6..]).await.context("read header")?;
+
+        anyhow::ensure!(header[..12] == V2_SIGNATURE, "invalid PROXY protocol v2 signature");
+
+        let len = u16::from_be_bytes([header[14], header[15]]);
+        let mut addresses = vec![0; usize::from(len)];
+        stream.read_exact(&mut addresses).await.context("read header")?;
+
+        parse_v2(&header, &addresses)
+    } else {
+        anyhow::bail!("missing PROXY protocol header")
+    }
+}
+
+fn parse_v1(line: &str) -> anyhow::Result<Option<SocketAddr>> {
+    let mut fields = line.trim_end_matches("\r\n").split(' ').skip(1);
+
+    match fields.next() {
+        Some("TCP4" | "TCP6") => {}
+        // The connection was established by the load balancer itself.
+        Some("UNKNOWN") => return Ok(None),
+        _ => anyhow::bail!("unsupported PROXY protocol v1 header"),
+    }
+
+    let source_ip: IpAddr = fields.next().context("missing source address")?.parse()?;
+    let _destination_ip: IpAddr = fields.next().context("missing destination address")?.parse()?;
+    let source_port: u16 = fields.next().context("missing source port")?.parse()?;
+    let _destination_port: u16 = fields.next().context("missing destination port")?.parse()?;
+
+    anyhow::ensure!(fields.next().is_none(), "unexpected field in PROXY protocol v1 header");
+
+    Ok(Some(SocketAddr::new(source_ip, source_port)))
+}
+
+fn parse_v2(header: &[u8; V2_HEADER_LEN], addresses: &[u8]) -> anyhow::Result<Option<SocketAddr>> {
+    let version = header[12] >> 4;
+    let command = header[12] & 0x0F;
+    let family = header[13] >> 4;
+
+    anyhow::ensure!(version == 2, "unsupported PROXY protocol version");
+
+    match command {
+        V2_COMMAND_LOCAL => return Ok(None),
+        V2_COMMAND_PROXY => {}
+        _ => anyhow::bail!("unsupported PROXY protocol v2 command"),
+    }
+
+    // The TLVs following the addresses are ignored.
+    let source_addr = match family {
+        V2_FAMILY_INET => {
+            let addresses: &[u8; 12] = addresses
+                .get(..12)
+                .and_then(|addresses| addresses.try_into().ok())
+                .context("truncated IPv4 addresses")?;
+
+            let ip = Ipv4Addr::from([addresses[0], addresses[1], addresses[2], addresses[3]]);
+            let port = u16::from_be_bytes([addresses[8], addresses[9]]);
+
+            SocketAddr::new(IpAddr::V4(ip), port)
+        }
+        V2_FAMILY_INET6 => {
+            let addresses: &[u8; 36] = addresses
+                .get(..36)
+                .and_then(|addresses| addresses.try_into().ok())
+                .context("truncated IPv6 addresses")?;
+
+            let mut ip = [0; 16];
+            ip.copy_from_slice(&addresses[..16]);
+            let port = u16::from_be_bytes([addresses[32], addresses[33]]);
+
+            SocketAddr::new(IpAddr::V6(Ipv6Addr::from(ip)), port)
+        }
+        // Unix sockets and unspecified families carry no usable address.
+        _ => return Ok(None),
+    };
+
+    Ok(Some(source_addr))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn v1_header() {
+        let mut stream: &[u8] = b"PROXY TCP4 192.0.2.10 198.51.100.1 56324 443\r\nGET /";
+
+        let addr = read_header(&mut stream).await.unwrap();
+
+        assert_eq!(addr, Some("192.0.2.10:56324".parse().unwrap()));
+        assert_eq!(stream, b"GET /");
+    }
+
+    #[tokio::test]
+    async fn v1_unknown_header() {
+        let mut stream: &[u8] = b"PROXY UNKNOWN\r\n";
+        assert_eq!(read_header(&mut stream).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn v2_header() {
+        let mut data = V2_SIGNATURE.to_vec();
+        data.extend_from_slice(&[0x21, 0x21, 0x00, 36 + 3]);
+        data.extend_from_slice(&"2001:db8::10".parse::<Ipv6Addr>().unwrap().octets());
+        data.extend_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        data.extend_from_slice(&56324u16.to_be_bytes());
+        data.extend_from_slice(&443u16.to_be_bytes());
+        // NOOP TLV
+        data.extend_from_slice(&[0x04, 0x00, 0x00]);
+        data.extend_from_slice(b"\x16\x03\x01");
+
+        let mut stream = data.as_slice();
+        let addr = read_header(&mut stream).await.unwrap();
+
+        assert_eq!(addr, Some("[2001:db8::10]:56324".parse().unwrap()));
+        assert_eq!(stream, b"\x16\x03\x01");
+    }
+
+    #[tokio::test]
+    async fn v2_local_header() {
+        let mut data = V2_SIGNATURE.to_vec();
+        data.extend_from_slice(&[0x20, 0x00, 0x00, 0x00]);
+
+        assert_eq!(read_header(&mut data.as_slice()).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn missing_header() {
+        let mut stream: &[u8] = b"GET / HTTP/1.1\r\n";
+        assert!(read_header(&mut stream).await.is_err());
+    }
+}
@@ -152,8 +152,9 @@ enum CleanPathError {
 struct CleanPathResult {
     claims: AssociationTokenClaims,
     destination: TargetAddr,
-    server_addr: SocketAddr,
-    server_stream: tokio_rustls::client::TlsStream<tokio::net::TcpStream>,
+    /// Unknown when the host name of the target is resolved by an upstream proxy
+    server_addr: Option<SocketAddr>,
+    server_stream: tokio_rustls::client::TlsStream<crate::upstream_proxy::TargetStream>,
     x224_rsp: Vec<u8>,
 }
 
@@ -202,7 +203,7 @@ async fn process_cleanpath(
 
     trace!(?targets, "Connecting to destination server");
 
-    let ((mut server_stream, server_addr), selected_target) = utils::successive_try(targets, utils::tcp_connect)
+    let ((mut server_stream, server_addr), selected_target) = utils::tcp_connect(targets, &conf.target_connection)
         .await
         .context("couldn’t connect to RDP server")?;
 
@@ -234,7 +235,9 @@ async fn process_cleanpath(
 
     // Establish TLS connection with server
 
-    let server_stream = crate::tls::connect(selected_target.host(), server_stream)
+    let server_stream = conf
+        .target_tls
+        .connect(selected_target.host(), server_stream, &claims.jet_tls_pins)
         .await
         .map_err(CleanPathError::TlsHandshake)?;
 
@@ -298,7 +301,10 @@ pub async fn handle(
 
     trace!("Sending RDCleanPath response");
 
-    let rdcleanpath_rsp = RDCleanPathPdu::new_response(server_addr.to_string(), x224_rsp, x509_chain)
+    // When the address of the server is unknown, the target is reported as it was specified.
+    let server_addr_repr = server_addr.map_or_else(|| destination.as_addr().to_owned(), |addr| addr.to_string());
+
+    let rdcleanpath_rsp = RDCleanPathPdu::new_response(server_addr_repr, x224_rsp, x509_chain)
         .map_err(|e| anyhow::anyhow!("couldn’t build RDCleanPath response: {e}"))?;
 
     send_clean_path_response(&mut client_stream, &rdcleanpath_rsp).await?;
@@ -312,6 +318,7 @@ pub async fn handle(
             destination_host: destination.clone(),
         },
     )
+    .with_recording_policy(claims.jet_rec)
     .with_ttl(claims.jet_ttl);
 
     info!("RDP-TLS forwarding");
@@ -321,7 +328,7 @@ pub async fn handle(
         .session_info(info)
         .address_a(client_addr)
         .transport_a(client_stream)
-        .address_b(server_addr)
+        .address_b(server_stream.get_ref().0.peer_addr()?)
         .transport_b(server_stream)
         .sessions(sessions)
         .subscriber_tx(subscriber_tx)
@@ -348,13 +355,25 @@ impl From<&CleanPathError> for RDCleanPathPdu {
 }
 
 fn io_to_rdcleanpath_err(err: &io::Error) -> RDCleanPathPdu {
-    if let Some(tokio_rustls::rustls::Error::AlertReceived(tls_alert)) = err
-        .get_ref()
-        .and_then(|e| e.downcast_ref::<tokio_rustls::rustls::Error>())
-    {
-        RDCleanPathPdu::new_tls_error(tls_alert.get_u8())
-    } else {
-        RDCleanPathPdu::new_wsa_error(WsaError::from(err).as_u16())
+    use tokio_rustls::rustls::{AlertDescription, CertificateError, Error as TlsError};
+
+    match err.get_ref().and_then(|e| e.downcast_ref::<TlsError>()) {
+        Some(TlsError::AlertReceived(tls_alert)) => RDCleanPathPdu::new_tls_error(tls_alert.get_u8()),
+        // The alert we would have sent to the server, had the handshake not been aborted locally.
+        Some(TlsError::InvalidCertificate(cert_error)) => {
+            let alert = match cert_error {
+                CertificateError::BadEncoding => AlertDescription::DecodeError,
+                CertificateError::Expired | CertificateError::NotValidYet => AlertDescription::CertificateExpired,
+                CertificateError::Revoked => AlertDescription::CertificateRevoked,
+                CertificateError::UnknownIssuer => AlertDescription::UnknownCA,
+                CertificateError::BadSignature => AlertDescription::DecryptError,
+                CertificateError::ApplicationVerificationFailure => AlertDescription::AccessDenied,
+                _ => AlertDescription::BadCertificate,
+            };
+
+            RDCleanPathPdu::new_tls_error(alert.get_u8())
+        }
+        _ => RDCleanPathPdu::new_wsa_error(WsaError::from(err).as_u16()),
     }
 }
 
@@ -497,3 +516,54 @@ impl From<&io::Error> for WsaError {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_rustls::rustls::{AlertDescription, CertificateError, Error as TlsError};
+
+    fn tls_alert_code(error: TlsError) -> Option<u8> {
+        io_to_rdcleanpath_err(&io::Error::new(io::ErrorKind::InvalidData, error))
+            .error
+            .and_then(|error| error.tls_alert_code)
+    }
+
+    #[test]
+    fn certificate_errors_are_reported_as_tls_alerts() {
+        let cases = [
+            (CertificateError::BadEncoding, AlertDescription::DecodeError),
+            (CertificateError::Expired, AlertDescription::CertificateExpired),
+            (CertificateError::NotValidYet, AlertDescription::CertificateExpired),
+            (CertificateError::Revoked, AlertDescription::CertificateRevoked),
+            (CertificateError::UnknownIssuer, AlertDescription::UnknownCA),
+            (CertificateError::BadSignature, AlertDescription::DecryptError),
+            (
+                CertificateError::ApplicationVerificationFailure,
+                AlertDescription::AccessDenied,
+            ),
+            (CertificateError::NotValidForName, AlertDescription::BadCertificate),
+        ];
+
+        for (certificate_error, alert) in cases {
+            assert_eq!(
+                tls_alert_code(TlsError::InvalidCertificate(certificate_error)),
+                Some(alert.get_u8())
+            );
+        }
+
+        assert_eq!(
+            tls_alert_code(TlsError::AlertReceived(AlertDescription::HandshakeFailure)),
+            Some(AlertDescription::HandshakeFailure.get_u8())
+        );
+    }
+
+    #[test]
+    fn other_errors_are_reported_as_wsa_errors() {
+        let error = io::Error::from(io::ErrorKind::ConnectionRefused);
+
+        let rdcleanpath_err = io_to_rdcleanpath_err(&error).error.unwrap();
+
+        assert_eq!(rdcleanpath_err.tls_alert_code, None);
+        assert_eq!(rdcleanpath_err.wsa_last_error, Some(WsaError::from(&error).as_u16()));
+    }
+}
@@ -20,6 +20,17 @@ pub fn extract_association_claims(
 ) -> anyhow::Result<AssociationTokenClaims> {
     let token = pcb.v2_payload.as_deref().context("V2 payload missing from RDP PCB")?;
 
+    validate_association_token(token, source_ip, conf, token_cache, jrl, active_recordings)
+}
+
+pub fn validate_association_token(
+    token: &str,
+    source_ip: IpAddr,
+    conf: &Conf,
+    token_cache: &TokenCache,
+    jrl: &CurrentJrl,
+    active_recordings: &ActiveRecordings,
+) -> anyhow::Result<AssociationTokenClaims> {
     if conf.debug.dump_tokens {
         debug!(token, "**DEBUG OPTION**");
     }
@@ -0,0 +1,831 @@
+//! Export and import of recordings as self-contained bundles
+//!
+//! A bundle is a tar archive holding the folder of a recording: the manifest, the recording files,
+//! a `SHA256SUMS` file listing the hash of each of them and, when a recording signing key is configured,
+//! a `SHA256SUMS.jws` file holding the signature of the checksums in JWS compact form.
+//!
+//! Encrypted recordings are exported as stored, so importing them requires the same master key.
+//! Likewise, when a signing key is configured, only the bundles whose signature is verified using it are imported.
+
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+use anyhow::Context as _;
+use bytes::{Bytes, BytesMut};
+use camino::{Utf8Path, Utf8PathBuf};
+use futures::stream::BoxStream;
+use futures::{Stream, StreamExt as _};
+use picky::jose::jws::Jws;
+use picky::key::PrivateKey;
+use sha2::{Digest as _, Sha256};
+use tokio::fs;
+use tokio::io::{self, AsyncRead, AsyncReadExt as _, AsyncWriteExt as _, BufWriter};
+use uuid::Uuid;
+
+use super::{integrity, to_hex, ActiveRecordings, FetchedFiles, JrecManifest, RecordingIndex};
+
+const MANIFEST_FILE_NAME: &str = "recording.json";
+const CHECKSUMS_FILE_NAME: &str = "SHA256SUMS";
+const SIGNATURE_FILE_NAME: &str = "SHA256SUMS.jws";
+
+/// Suffix of the folders holding recordings being imported.
+const IMPORTING_RECORDING_SUFFIX: &str = ".importing";
+
+const BLOCK_SIZE: usize = 512;
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+struct ArchiveEntry {
+    header: [u8; BLOCK_SIZE],
+    size: u64,
+    content: EntryContent,
+}
+
+enum EntryContent {
+    Memory(Bytes),
+    File(Utf8PathBuf),
+}
+
+impl ArchiveEntry {
+    fn from_memory(path: &str, content: impl Into<Bytes>, mtime: u64) -> anyhow::Result<Self> {
+        let content = content.into();
+        let size = content.len() as u64;
+
+        Ok(Self {
+            header: tar_header(path, size, mtime)?,
+            size,
+            content: EntryContent::Memory(content),
+        })
+    }
+
+    fn into_stream(self) -> BoxStream<'static, io::Result<Bytes>> {
+        let Self { header, size, content } = self;
+
+        let header = futures::stream::once(async move { Ok(Bytes::copy_from_slice(&header)) });
+
+        let content = match content {
+            EntryContent::Memory(content) => futures::stream::once(async move { Ok(content) }).left_stream(),
+            EntryContent::File(path) => file_stream(path, size).right_stream(),
+        };
+
+        let padding = padding_len(size);
+        let padding = futures::stream::iter((padding > 0).then(|| Ok(Bytes::from(vec![0; padding]))));
+
+        header.chain(content).chain(padding).boxed()
+    }
+}
+
+/// Opens a stream over the bundle of the recording stored in the given folder.
+///
+/// The recording must not be ongoing. Files only held by the storage backend are fetched first,
+/// and released once the stream is dropped.
+pub(crate) async fn export_recording(
+    recording_path: &Utf8Path,
+    signing_key: Option<&PrivateKey>,
+    mut fetched_files: FetchedFiles,
+) -> anyhow::Result<impl Stream<Item = io::Result<Bytes>> + Send> {
+    let manifest_path = recording_path.join(MANIFEST_FILE_NAME);
+
+    let manifest_json = fs::read(&manifest_path)
+        .await
+        .with_context(|| format!("failed to read {manifest_path}"))?;
+    let manifest: JrecManifest = serde_json::from_slice(&manifest_json).context("invalid manifest")?;
+
+    let folder = manifest.session_id.to_string();
+    let now = unix_time(SystemTime::now());
+
+    let mut checksums = format!("{}  {MANIFEST_FILE_NAME}\n", to_hex(&Sha256::digest(&manifest_json)));
+    let mut entries = vec![ArchiveEntry::from_memory(
+        &format!("{folder}/{MANIFEST_FILE_NAME}"),
+        manifest_json,
+        now,
+    )?];
+
+    for file in &manifest.files {
+        let path = recording_path.join(&file.file_name);
+
+        fetched_files
+            .fetch(&file.file_name)
+            .await
+            .with_context(|| format!("failed to fetch {}", file.file_name))?;
+
+        let metadata = fs::metadata(&path)
+            .await
+            .with_context(|| format!("failed to read metadata of {path}"))?;
+
+        let sha256 = integrity::hash_file(path.clone()).await?;
+        checksums.push_str(&format!("{sha256}  {}\n", file.file_name));
+
+        let size = metadata.len();
+        let mtime = metadata.modified().map(unix_time).unwrap_or(now);
+
+        entries.push(ArchiveEntry {
+            header: tar_header(&format!("{folder}/{}", file.file_name), size, mtime)?,
+            size,
+            content: EntryContent::File(path),
+        });
+    }
+
+    if let Some(signing_key) = signing_key {
+        let signature = Jws::new(integrity::signing_alg(signing_key)?, checksums.clone().into_bytes())
+            .encode(signing_key)
+            .context("failed to sign checksums")?;
+
+        entries.push(ArchiveEntry::from_memory(
+            &format!("{folder}/{SIGNATURE_FILE_NAME}"),
+            signature,
+            now,
+        )?);
+    }
+
+    entries.push(ArchiveEntry::from_memory(
+        &format!("{folder}/{CHECKSUMS_FILE_NAME}"),
+        checksums,
+        now,
+    )?);
+
+    // The end of the archive is marked by two zero-filled blocks.
+    let end_of_archive = futures::stream::once(async { Ok(Bytes::from_static(&[0; BLOCK_SIZE * 2])) });
+
+    Ok(fetched_files.attach(
+        futures::stream::iter(entries)
+            .flat_map(ArchiveEntry::into_stream)
+            .chain(end_of_archive),
+    ))
+}
+
+fn file_stream(path: Utf8PathBuf, size: u64) -> impl Stream<Item = io::Result<Bytes>> + Send {
+    futures::stream::try_unfold((None, path, size), |(file, path, remaining)| async move {
+        if remaining == 0 {
+            return Ok(None);
+        }
+
+        let mut file = match file {
+            Some(file) => file,
+            None => fs::File::open(&path).await?,
+        };
+
+        let mut buf = BytesMut::with_capacity(READ_BUFFER_SIZE);
+        let n = (&mut file).take(remaining).read_buf(&mut buf).await?;
+
+        if n == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("{path} was truncated while being exported"),
+            ));
+        }
+
+        Ok(Some((buf.freeze(), (Some(file), path, remaining - n as u64))))
+    })
+}
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum ImportError {
+    #[error("invalid bundle: {0:#}")]
+    Invalid(anyhow::Error),
+    #[error("recording {0} already exists")]
+    AlreadyExists(Uuid),
+    #[error("recording {0} is ongoing")]
+    Ongoing(Uuid),
+    #[error("the bundle is larger than the {0} bytes available for imports")]
+    TooLarge(u64),
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+/// Imports a bundle produced by [`export_recording`] into the recordings folder.
+///
+/// The bundle is extracted in a temporary folder, and moved into place only once all the checksums are verified.
+/// An existing or ongoing recording is never overwritten.
+/// At most `max_size` bytes are read from the bundle.
+pub(crate) async fn import_recording<R>(
+    recordings_path: &Utf8Path,
+    reader: R,
+    max_size: u64,
+    signing_key: Option<&PrivateKey>,
+    active_recordings: &ActiveRecordings,
+    index: &RecordingIndex,
+) -> Result<Uuid, ImportError>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut reader = reader.take(max_size);
+
+    let staging_path = recordings_path.join(format!(".{}{IMPORTING_RECORDING_SUFFIX}", Uuid::new_v4()));
+
+    fs::create_dir_all(&staging_path)
+        .await
+        .with_context(|| format!("failed to create {staging_path}"))
+        .map_err(ImportError::Other)?;
+
+    let result = async {
+        let (id, manifest) = match extract_bundle(&mut reader, &staging_path, signing_key).await {
+            Ok(extracted) => extracted,
+            Err(ImportError::Invalid(_)) if reader.limit() == 0 => return Err(ImportError::TooLarge(max_size)),
+            Err(error) => return Err(error),
+        };
+
+        let recording_path = recordings_path.join(id.to_string());
+
+        active_recordings
+            .with_inactive(id, || move_into_place(id, &staging_path, &recording_path))
+            .unwrap_or(Err(ImportError::Ongoing(id)))?;
+
+        index.update(&recording_path, &manifest);
+
+        Ok::<_, ImportError>(id)
+    }
+    .await;
+
+    // Once moved into place, the files are not in the staging folder anymore.
+    if let Err(error) = fs::remove_dir_all(&staging_path).await {
+        warn!(%error, %staging_path, "Failed to remove the staging folder of an import");
+    }
+
+    result
+}
+
+/// Moves the extracted files into the folder of the recording, which must not exist yet.
+///
+/// Creating the folder fails if it already exists, so a recording is never overwritten, even by concurrent imports.
+fn move_into_place(id: Uuid, staging_path: &Utf8Path, recording_path: &Utf8Path) -> Result<(), ImportError> {
+    match std::fs::create_dir(recording_path) {
+        Ok(()) => {}
+        Err(error) if error.kind() == io::ErrorKind::AlreadyExists => return Err(ImportError::AlreadyExists(id)),
+        Err(error) => {
+            return Err(ImportError::Other(
+                anyhow::Error::new(error).context(format!("failed to create {recording_path}")),
+            ))
+        }
+    }
+
+    let result = move_files(staging_path, recording_path);
+
+    if result.is_err() {
+        if let Err(error) = std::fs::remove_dir_all(recording_path) {
+            warn!(%error, %recording_path, "Failed to remove the folder of a failed import");
+        }
+    }
+
+    result.map_err(ImportError::Other)
+}
+
+fn move_files(from_path: &Utf8Path, to_path: &Utf8Path) -> anyhow::Result<()> {
+    let mut file_names = Vec::new();
+
+    for entry in from_path
+        .read_dir_utf8()
+        .with_context(|| format!("failed to read {from_path}"))?
+    {
+        let entry = entry.with_context(|| format!("failed to read {from_path}"))?;
+        file_names.push(entry.file_name().to_owned());
+    }
+
+    // The manifest goes last, so the folder holds a complete recording as soon as it has a manifest.
+    file_names.sort_by_key(|file_name| file_name == MANIFEST_FILE_NAME);
+
+    for file_name in file_names {
+        let from = from_path.join(&file_name);
+        let to = to_path.join(&file_name);
+        std::fs::rename(&from, &to).with_context(|| format!("failed to rename {from} to {to}"))?;
+    }
+
+    Ok(())
+}
+
+async fn extract_bundle<R>(
+    mut reader: R,
+    staging_path: &Utf8Path,
+    signing_key: Option<&PrivateKey>,
+) -> Result<(Uuid, JrecManifest), ImportError>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut folder: Option<Uuid> = None;
+    let mut hashes = HashMap::new();
+    let mut header = [0; BLOCK_SIZE];
+
+    loop {
+        reader
+            .read_exact(&mut header)
+            .await
+            .context("failed to read entry header")
+            .map_err(ImportError::Invalid)?;
+
+        let Some(entry) = parse_tar_header(&header).map_err(ImportError::Invalid)? else {
+            break;
+        };
+
+        let (entry_folder, file_name) = split_entry_path(&entry.path).map_err(ImportError::Invalid)?;
+
+        match folder {
+            None => folder = Some(entry_folder),
+            Some(folder) if folder == entry_folder => {}
+            Some(_) => {
+                return Err(ImportError::Invalid(anyhow::anyhow!(
+                    "the bundle contains more than one recording"
+                )))
+            }
+        }
+
+        match (entry.kind, file_name) {
+            (EntryKind::Directory, None) => {}
+            (EntryKind::File, Some(file_name)) => {
+                if hashes.contains_key(file_name) {
+                    return Err(ImportError::Invalid(anyhow::anyhow!(
+                        "duplicated entry for {file_name}"
+                    )));
+                }
+
+                let sha256 = extract_file(&mut reader, &staging_path.join(file_name), entry.size).await?;
+                hashes.insert(file_name.to_owned(), sha256);
+            }
+            _ => return Err(ImportError::Invalid(anyhow::anyhow!("unexpected entry {}", entry.path))),
+        }
+
+        let mut padding = vec![0; padding_len(entry.size)];
+        reader
+            .read_exact(&mut padding)
+            .await
+            .context("failed to read entry padding")
+            .map_err(ImportError::Invalid)?;
+    }
+
+    let id = folder.context("empty bundle").map_err(ImportError::Invalid)?;
+
+    let manifest = verify_bundle(id, staging_path, &hashes, signing_key).map_err(ImportError::Invalid)?;
+
+    // The checksums are only meaningful for the bundle, the manifest keeps track of the recording hashes.
+    for file_name in [CHECKSUMS_FILE_NAME, SIGNATURE_FILE_NAME] {
+        if hashes.contains_key(file_name) {
+            let path = staging_path.join(file_name);
+            fs::remove_file(&path)
+                .await
+                .with_context(|| format!("failed to remove {path}"))
+                .map_err(ImportError::Other)?;
+        }
+    }
+
+    Ok((id, manifest))
+}
+
+/// Copies the content of an entry to the given path, and returns its SHA-256 hash.
+async fn extract_file<R>(reader: &mut R, path: &Utf8Path, size: u64) -> Result<String, ImportError>
+where
+    R: AsyncRead + Unpin,
+{
+    let file = fs::File::create(path)
+        .await
+        .with_context(|| format!("failed to create {path}"))
+        .map_err(ImportError::Other)?;
+    let mut file = BufWriter::new(file);
+
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; READ_BUFFER_SIZE];
+    let mut remaining = size;
+
+    while remaining > 0 {
+        let len = usize::try_from(remaining).unwrap_or(usize::MAX).min(buf.len());
+
+        reader
+            .read_exact(&mut buf[..len])
+            .await
+            .with_context(|| format!("failed to read the content of {path}"))
+            .map_err(ImportError::Invalid)?;
+
+        hasher.update(&buf[..len]);
+
+        file.write_all(&buf[..len])
+            .await
+            .with_context(|| format!("failed to write {path}"))
+            .map_err(ImportError::Other)?;
+
+        remaining -= len as u64;
+    }
+
+    file.flush()
+        .await
+        .with_context(|| format!("failed to write {path}"))
+        .map_err(ImportError::Other)?;
+
+    Ok(to_hex(&hasher.finalize()))
+}
+
+/// Checks that the extracted files are exactly the ones listed by the manifest, with matching checksums.
+///
+/// When a signing key is configured, the bundle must be signed, and the signature of the checksums must be valid
+/// for this key. Without a signing key, signed bundles are refused since their signature can't be verified.
+fn verify_bundle(
+    id: Uuid,
+    staging_path: &Utf8Path,
+    hashes: &HashMap<String, String>,
+    signing_key: Option<&PrivateKey>,
+) -> anyhow::Result<JrecManifest> {
+    let checksums = std::fs::read_to_string(staging_path.join(CHECKSUMS_FILE_NAME)).context("missing checksums")?;
+
+    let is_signed = hashes.contains_key(SIGNATURE_FILE_NAME);
+
+    if let Some(signing_key) = signing_key {
+        anyhow::ensure!(is_signed, "the bundle is not signed");
+
+        let public_key = signing_key.to_public_key().context("invalid signing key")?;
+
+        let signature =
+            std::fs::read_to_string(staging_path.join(SIGNATURE_FILE_NAME)).context("failed to read signature")?;
+        let jws = Jws::decode(signature.trim(), &public_key).context("invalid checksums signature")?;
+
+        anyhow::ensure!(
+            jws.payload == checksums.as_bytes(),
+            "the signature doesn't match the checksums"
+        );
+    } else {
+        anyhow::ensure!(
+            !is_signed,
+            "the bundle is signed, but no recording signing key is configured"
+        );
+    }
+
+    let mut listed = 0;
+
+    for line in checksums.lines().filter(|line| !line.is_empty()) {
+        let (expected, file_name) = line.split_once("  ").context("malformed checksums")?;
+
+        let actual = hashes
+            .get(file_name)
+            .with_context(|| format!("{file_name} is missing"))?;
+
+        anyhow::ensure!(*actual == expected, "checksum mismatch for {file_name}");
+
+        listed += 1;
+    }
+
+    let manifest =
+        JrecManifest::read_from_file(staging_path.join(MANIFEST_FILE_NAME)).context("missing or invalid manifest")?;
+
+    anyhow::ensure!(manifest.session_id == id, "the manifest doesn't match the bundle");
+
+    for file_name in hashes.keys() {
+        let is_expected = matches!(
+            file_name.as_str(),
+            MANIFEST_FILE_NAME | CHECKSUMS_FILE_NAME | SIGNATURE_FILE_NAME
+        ) || manifest.files.iter().any(|file| file.file_name == *file_name);
+
+        anyhow::ensure!(is_expected, "unexpected file {file_name}");
+    }
+
+    for file in &manifest.files {
+        anyhow::ensure!(hashes.contains_key(&file.file_name), "{} is missing", file.file_name);
+    }
+
+    let bundle_files = [CHECKSUMS_FILE_NAME, SIGNATURE_FILE_NAME]
+        .iter()
+        .filter(|file_name| hashes.contains_key(**file_name))
+        .count();
+
+    anyhow::ensure!(listed + bundle_files == hashes.len(), "some files are not checksummed");
+
+    Ok(manifest)
+}
+
+/// Splits an entry path into the recording folder and the file name (none for the folder entry itself).
+fn split_entry_path(path: &str) -> anyhow::Result<(Uuid, Option<&str>)> {
+    let path = path.trim_end_matches('/');
+
+    let (folder, file_name) = match path.split_once('/') {
+        Some((folder, file_name)) => (folder, Some(file_name)),
+        None => (path, None),
+    };
+
+    let folder = Uuid::parse_str(folder).with_context(|| format!("unexpected entry {path}"))?;
+
+    if let Some(file_name) = file_name {
+        anyhow::ensure!(
+            !file_name.is_empty() && !file_name.starts_with('.') && !file_name.contains(['/', '\\']),
+            "unexpected entry {path}"
+        );
+    }
+
+    Ok((folder, file_name))
+}
+
+fn padding_len(size: u64) -> usize {
+    let remainder = (size % BLOCK_SIZE as u64) as usize;
+    (BLOCK_SIZE - remainder) % BLOCK_SIZE
+}
+
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+// -- Minimal tar (ustar) support -- //
+
+const NAME_RANGE: std::ops::Range<usize> = 0..100;
+const MODE_RANGE: std::ops::Range<usize> = 100..108;
+const UID_RANGE: std::ops::Range<usize> = 108..116;
+const GID_RANGE: std::ops::Range<usize> = 116..124;
+const SIZE_RANGE: std::ops::Range<usize> = 124..136;
+const MTIME_RANGE: std::ops::Range<usize> = 136..148;
+const CHECKSUM_RANGE: std::ops::Range<usize> = 148..156;
+const TYPEFLAG_OFFSET: usize = 156;
+const MAGIC_RANGE: std::ops::Range<usize> = 257..263;
+const VERSION_RANGE: std::ops::Range<usize> = 263..265;
+const PREFIX_RANGE: std::ops::Range<usize> = 345..500;
+
+const USTAR_MAGIC: &[u8] = b"ustar\0";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EntryKind {
+    File,
+    Directory,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct EntryHeader {
+    path: String,
+    size: u64,
+    kind: EntryKind,
+}
+
+fn tar_header(path: &str, size: u64, mtime: u64) -> anyhow::Result<[u8; BLOCK_SIZE]> {
+    anyhow::ensure!(path.len() <= NAME_RANGE.len(), "path too long: {path}");
+
+    let mut header = [0; BLOCK_SIZE];
+
+    header[NAME_RANGE][..path.len()].copy_from_slice(path.as_bytes());
+    write_octal(&mut header[MODE_RANGE], 0o644);
+    write_octal(&mut header[UID_RANGE], 0);
+    write_octal(&mut header[GID_RANGE], 0);
+    write_number(&mut header[SIZE_RANGE], size);
+    write_number(&mut header[MTIME_RANGE], mtime);
+    header[TYPEFLAG_OFFSET] = b'0';
+    header[MAGIC_RANGE].copy_from_slice(USTAR_MAGIC);
+    header[VERSION_RANGE].copy_from_slice(b"00");
+
+    // The checksum is computed with the checksum field filled with spaces.
+    header[CHECKSUM_RANGE].fill(b' ');
+    let checksum = header_checksum(&header);
+    write_octal(&mut header[CHECKSUM_RANGE][..7], checksum);
+
+    Ok(header)
+}
+
+/// Parses an entry header, returning `None` for the zero-filled block marking the end of the archive.
+fn parse_tar_header(header: &[u8; BLOCK_SIZE]) -> anyhow::Result<Option<EntryHeader>> {
+    if header.iter().all(|byte| *byte == 0) {
+        return Ok(None);
+    }
+
+    anyhow::ensure!(&header[MAGIC_RANGE][..5] == b"ustar", "not a tar archive");
+
+    let expected_checksum = read_number(&header[CHECKSUM_RANGE]).context("invalid checksum field")?;
+    let mut unsigned = *header;
+    unsigned[CHECKSUM_RANGE].fill(b' ');
+    anyhow::ensure!(
+        header_checksum(&unsigned) == expected_checksum,
+        "header checksum mismatch"
+    );
+
+    let name = read_str(&header[NAME_RANGE])?;
+    let prefix = read_str(&header[PREFIX_RANGE])?;
+
+    let path = if prefix.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{prefix}/{name}")
+    };
+
+    let kind = match header[TYPEFLAG_OFFSET] {
+        b'0' | b'\0' => EntryKind::File,
+        b'5' => EntryKind::Directory,
+        other => anyhow::bail!("unsupported type for entry {path}: {}", char::from(other)),
+    };
+
+    let size = read_number(&header[SIZE_RANGE]).context("invalid size field")?;
+
+    Ok(Some(EntryHeader { path, size, kind }))
+}
+
+fn header_checksum(header: &[u8; BLOCK_SIZE]) -> u64 {
+    header.iter().map(|byte| u64::from(*byte)).sum()
+}
+
+/// Writes a NUL-terminated octal number, zero-padded to fill the field.
+fn write_octal(field: &mut [u8], value: u64) {
+    let digits = format!("{value:0width$o}", width = field.len() - 1);
+    field[..digits.len()].copy_from_slice(digits.as_bytes());
+    field[digits.len()] = 0;
+}
+
+/// Writes a number, using the GNU base-256 encoding when it doesn't fit in octal.
+fn write_number(field: &mut [u8], value: u64) {
+    let max_octal_digits = u32::try_from(field.len() - 1).expect("small field");
+
+    if value < 8u64.pow(max_octal_digits) {
+        write_octal(field, value);
+    } else {
+        field.fill(0);
+        let len = field.len();
+        field[len - 8..].copy_from_slice(&value.to_be_bytes());
+        field[0] = 0x80;
+    }
+}
+
+fn read_number(field: &[u8]) -> anyhow::Result<u64> {
+    if field[0] & 0x80 != 0 {
+        let (high, low) = field[1..].split_at(field.len() - 9);
+        anyhow::ensure!(high.iter().all(|byte| *byte == 0), "number too large");
+        return Ok(u64::from_be_bytes(low.try_into().expect("8 bytes")));
+    }
+
+    let digits = read_str(field)?.trim_matches(' ');
+
+    if digits.is_empty() {
+        return Ok(0);
+    }
+
+    u64::from_str_radix(digits, 8).context("invalid octal number")
+}
+
+fn read_str(field: &[u8]) -> anyhow::Result<&str> {
+    let len = field.iter().position(|byte| *byte == 0).unwrap_or(field.len());
+    std::str::from_utf8(&field[..len]).context("invalid UTF-8 string")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::test_utils::{TempDir, P256_PRIVATE_KEY};
+
+    #[test]
+    fn tar_header_roundtrip() {
+        let header = tar_header(
+            "5f7c4f4e-4d6e-4f7b-9d5e-1c2a3b4c5d6e/recording-0.webm",
+            1234,
+            1_700_000_000,
+        )
+        .unwrap();
+
+        let entry = parse_tar_header(&header).unwrap().unwrap();
+
+        assert_eq!(
+            entry,
+            EntryHeader {
+                path: "5f7c4f4e-4d6e-4f7b-9d5e-1c2a3b4c5d6e/recording-0.webm".to_owned(),
+                size: 1234,
+                kind: EntryKind::File,
+            }
+        );
+        assert_eq!(parse_tar_header(&[0; BLOCK_SIZE]).unwrap(), None);
+    }
+
+    #[test]
+    fn large_size_uses_base_256() {
+        let size = 20 * 1024 * 1024 * 1024;
+        let header = tar_header("a", size, 0).unwrap();
+
+        assert_eq!(header[SIZE_RANGE.start], 0x80);
+        assert_eq!(parse_tar_header(&header).unwrap().unwrap().size, size);
+    }
+
+    #[test]
+    fn entry_paths() {
+        let id = Uuid::new_v4();
+
+        assert_eq!(split_entry_path(&format!("{id}/")).unwrap(), (id, None));
+        assert_eq!(
+            split_entry_path(&format!("{id}/recording.json")).unwrap(),
+            (id, Some("recording.json"))
+        );
+        assert!(split_entry_path(&format!("{id}/../recording.json")).is_err());
+        assert!(split_entry_path(&format!("{id}/sub/recording.json")).is_err());
+        assert!(split_entry_path("recording.json").is_err());
+    }
+
+    async fn bundle(key: Option<&PrivateKey>) -> (Uuid, Vec<u8>) {
+        use futures::TryStreamExt as _;
+
+        let recordings_dir = TempDir::new();
+        let id = Uuid::new_v4();
+        let recording_path = recordings_dir.path().join(id.to_string());
+        std::fs::create_dir_all(&recording_path).unwrap();
+
+        std::fs::write(recording_path.join("recording-0.webm"), "webm data").unwrap();
+
+        let manifest = serde_json::json!({
+            "sessionId": id,
+            "startTime": 0,
+            "duration": 10,
+            "files": [{ "fileName": "recording-0.webm", "startTime": 0, "duration": 10 }],
+        });
+        std::fs::write(recording_path.join(MANIFEST_FILE_NAME), manifest.to_string()).unwrap();
+
+        let storage = Arc::new(crate::recording::LocalStorage::new(recordings_dir.path().to_owned()));
+        let chunks: Vec<Bytes> = export_recording(&recording_path, key, FetchedFiles::new(storage, id))
+            .await
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+
+        (id, chunks.concat())
+    }
+
+    async fn import(
+        recordings_dir: &TempDir,
+        bundle: &[u8],
+        signing_key: Option<&PrivateKey>,
+        active_recordings: &ActiveRecordings,
+    ) -> Result<Uuid, ImportError> {
+        import_recording(
+            recordings_dir.path(),
+            bundle,
+            u64::MAX,
+            signing_key,
+            active_recordings,
+            &RecordingIndex::default(),
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn signed_bundle_is_verified_on_import() {
+        let key = PrivateKey::from_pem_str(P256_PRIVATE_KEY).unwrap();
+        let (id, bundle) = bundle(Some(&key)).await;
+
+        let recordings_dir = TempDir::new();
+        let active_recordings = ActiveRecordings::default();
+
+        // The signature can't be verified without a signing key.
+        let result = import(&recordings_dir, &bundle, None, &active_recordings).await;
+        assert!(matches!(result, Err(ImportError::Invalid(_))));
+
+        let result = import_recording(
+            recordings_dir.path(),
+            bundle.as_slice(),
+            1024,
+            Some(&key),
+            &active_recordings,
+            &RecordingIndex::default(),
+        )
+        .await;
+        assert!(matches!(result, Err(ImportError::TooLarge(1024))));
+
+        let imported = import(&recordings_dir, &bundle, Some(&key), &active_recordings)
+            .await
+            .unwrap();
+        assert_eq!(imported, id);
+
+        let recording_path = recordings_dir.path().join(id.to_string());
+        assert!(recording_path.join("recording-0.webm").is_file());
+        assert!(!recording_path.join(SIGNATURE_FILE_NAME).exists());
+
+        // Only the recording folder is left.
+        assert_eq!(std::fs::read_dir(recordings_dir.path()).unwrap().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn unsigned_bundle_is_refused_when_a_signing_key_is_configured() {
+        let key = PrivateKey::from_pem_str(P256_PRIVATE_KEY).unwrap();
+        let (id, bundle) = bundle(None).await;
+
+        let recordings_dir = TempDir::new();
+        let active_recordings = ActiveRecordings::default();
+
+        let result = import(&recordings_dir, &bundle, Some(&key), &active_recordings).await;
+        assert!(matches!(result, Err(ImportError::Invalid(_))));
+
+        let imported = import(&recordings_dir, &bundle, None, &active_recordings).await.unwrap();
+        assert_eq!(imported, id);
+    }
+
+    #[tokio::test]
+    async fn existing_or_ongoing_recording_is_not_overwritten() {
+        let (id, bundle) = bundle(None).await;
+
+        let recordings_dir = TempDir::new();
+        let active_recordings = ActiveRecordings::default();
+        let recording_path = recordings_dir.path().join(id.to_string());
+
+        active_recordings.insert(id);
+        let result = import(&recordings_dir, &bundle, None, &active_recordings).await;
+        assert!(matches!(result, Err(ImportError::Ongoing(ongoing)) if ongoing == id));
+        assert!(!recording_path.exists());
+        active_recordings.remove(id);
+
+        std::fs::create_dir(&recording_path).unwrap();
+        std::fs::write(recording_path.join("recording-0.webm"), "existing data").unwrap();
+
+        let result = import(&recordings_dir, &bundle, None, &active_recordings).await;
+        assert!(matches!(result, Err(ImportError::AlreadyExists(existing)) if existing == id));
+        assert_eq!(
+            std::fs::read_to_string(recording_path.join("recording-0.webm")).unwrap(),
+            "existing data"
+        );
+
+        // The staging folders are removed.
+        assert_eq!(std::fs::read_dir(recordings_dir.path()).unwrap().count(), 1);
+    }
+}
@@ -0,0 +1,456 @@
+//! Encryption at rest for recordings
+//!
+//! Each recording has its own 256-bit data key, wrapped using the configured master key
+//! and stored in the manifest along with the master key ID.
+//!
+//! Recording files are split in chunks of up to `CHUNK_SIZE` bytes, each encrypted using AES-256-GCM
+//! and stored as a 4-byte big-endian length followed by the ciphertext (tag included).
+//! The most significant bit of the length is set on the last chunk of a file, which is always written,
+//! even when empty. This flag is also the associated data of the chunk, so a truncated file is detected.
+//! The nonce of a chunk is the 4-byte big-endian index of the file in the manifest followed by
+//! the 8-byte big-endian index of the chunk in the file, so a nonce is never reused for a given data key.
+
+use aes_gcm::aead::{Aead as _, KeyInit as _, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use anyhow::Context as _;
+use bytes::{Buf as _, Bytes, BytesMut};
+use futures::Stream;
+use rand::RngCore as _;
+use tokio::io::{self, AsyncReadExt as _};
+use uuid::Uuid;
+use zeroize::Zeroizing;
+
+use super::{from_hex, to_hex};
+use crate::config::RecordingEncryption;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+const TAG_SIZE: usize = 16;
+const NONCE_SIZE: usize = 12;
+
+/// Set in the length prefix of the last chunk of a file
+const LAST_CHUNK_FLAG: u32 = 1 << 31;
+
+/// Size of a full chunk once encrypted, length prefix included
+const ENCRYPTED_CHUNK_SIZE: u64 = (4 + CHUNK_SIZE + TAG_SIZE) as u64;
+
+/// Computes the size of the plaintext from the size of an encrypted file.
+pub(super) fn decrypted_len(encrypted_len: u64) -> u64 {
+    let full_chunks = encrypted_len / ENCRYPTED_CHUNK_SIZE;
+    let last_chunk = encrypted_len % ENCRYPTED_CHUNK_SIZE;
+
+    full_chunks * CHUNK_SIZE as u64 + last_chunk.saturating_sub((4 + TAG_SIZE) as u64)
+}
+
+/// Locates the chunk holding the byte at the given plaintext offset.
+///
+/// Returns the index of the chunk, its offset in the encrypted file, and the offset of the byte in the chunk.
+pub(super) fn locate_chunk(offset: u64) -> (u64, u64, u64) {
+    let chunk_index = offset / CHUNK_SIZE as u64;
+    (
+        chunk_index,
+        chunk_index * ENCRYPTED_CHUNK_SIZE,
+        offset % CHUNK_SIZE as u64,
+    )
+}
+
+/// Encryption information stored in the manifest
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct JrecEncryption {
+    /// ID of the master key used to wrap the data key
+    key_id: String,
+    /// Data key wrapped using the master key (nonce followed by the ciphertext), hex-encoded
+    wrapped_key: String,
+}
+
+pub(super) struct DataKey(Zeroizing<[u8; 32]>);
+
+impl DataKey {
+    pub(super) fn generate() -> Self {
+        let mut key = Zeroizing::new([0; 32]);
+        rand::rngs::OsRng.fill_bytes(key.as_mut());
+        Self(key)
+    }
+
+    pub(super) fn wrap(&self, session_id: Uuid, conf: &RecordingEncryption) -> anyhow::Result<JrecEncryption> {
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(conf.master_key.as_ref()));
+
+        let mut nonce = [0; NONCE_SIZE];
+        rand::rngs::OsRng.fill_bytes(&mut nonce);
+
+        let ciphertext = cipher
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: self.0.as_ref(),
+                    aad: session_id.as_bytes(),
+                },
+            )
+            .ok()
+            .context("failed to wrap data key")?;
+
+        let mut wrapped_key = nonce.to_vec();
+        wrapped_key.extend_from_slice(&ciphertext);
+
+        Ok(JrecEncryption {
+            key_id: conf.key_id.clone(),
+            wrapped_key: to_hex(&wrapped_key),
+        })
+    }
+
+    pub(super) fn unwrap(
+        encryption: &JrecEncryption,
+        session_id: Uuid,
+        conf: Option<&RecordingEncryption>,
+    ) -> anyhow::Result<Self> {
+        let conf = conf.context("recording is encrypted, but recording encryption is not configured")?;
+
+        anyhow::ensure!(
+            encryption.key_id == conf.key_id,
+            "recording is encrypted using the master key {}, but the configured master key is {}",
+            encryption.key_id,
+            conf.key_id,
+        );
+
+        let wrapped_key = from_hex(&encryption.wrapped_key).context("invalid wrapped key")?;
+        anyhow::ensure!(wrapped_key.len() > NONCE_SIZE, "wrapped key is too short");
+        let (nonce, ciphertext) = wrapped_key.split_at(NONCE_SIZE);
+
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(conf.master_key.as_ref()));
+
+        let key = cipher
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: session_id.as_bytes(),
+                },
+            )
+            .ok()
+            .context("failed to unwrap data key")
+            .map(Zeroizing::new)?;
+
+        let key = <[u8; 32]>::try_from(key.as_slice())
+            .ok()
+            .context("unexpected data key length")
+            .map(Zeroizing::new)?;
+
+        Ok(Self(key))
+    }
+
+    pub(super) fn encryptor(&self, file_index: usize) -> anyhow::Result<ChunkEncryptor> {
+        Ok(ChunkEncryptor {
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(self.0.as_ref())),
+            file_index: u32::try_from(file_index).context("too many recording files")?,
+            chunk_index: 0,
+            pending: Vec::with_capacity(CHUNK_SIZE),
+        })
+    }
+
+    pub(super) fn decryptor(&self, file_index: usize) -> anyhow::Result<ChunkDecryptor> {
+        Ok(ChunkDecryptor {
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(self.0.as_ref())),
+            file_index: u32::try_from(file_index).context("too many recording files")?,
+            chunk_index: 0,
+            finished: false,
+        })
+    }
+}
+
+/// Parses the length prefix of a chunk, returning the length of the ciphertext and whether it's the last chunk.
+fn chunk_header(header: [u8; 4]) -> io::Result<(usize, bool)> {
+    let header = u32::from_be_bytes(header);
+    let is_last = header & LAST_CHUNK_FLAG != 0;
+    let len = usize::try_from(header & !LAST_CHUNK_FLAG).expect("u32 fits in usize");
+
+    if len > CHUNK_SIZE + TAG_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "encrypted chunk is too large",
+        ));
+    }
+
+    Ok((len, is_last))
+}
+
+fn chunk_aad(is_last: bool) -> [u8; 1] {
+    [u8::from(is_last)]
+}
+
+fn chunk_nonce(file_index: u32, chunk_index: u64) -> [u8; NONCE_SIZE] {
+    let mut nonce = [0; NONCE_SIZE];
+    nonce[..4].copy_from_slice(&file_index.to_be_bytes());
+    nonce[4..].copy_from_slice(&chunk_index.to_be_bytes());
+    nonce
+}
+
+pub(crate) struct ChunkEncryptor {
+    cipher: Aes256Gcm,
+    file_index: u32,
+    chunk_index: u64,
+    pending: Vec<u8>,
+}
+
+impl ChunkEncryptor {
+    /// Buffers the provided data, and appends the chunks ready to be written to `out`.
+    pub(super) fn update(&mut self, data: &[u8], out: &mut Vec<u8>) -> anyhow::Result<()> {
+        self.pending.extend_from_slice(data);
+
+        while self.pending.len() >= CHUNK_SIZE {
+            let rest = self.pending.split_off(CHUNK_SIZE);
+            let chunk = core::mem::replace(&mut self.pending, rest);
+            self.seal(&chunk, false, out)?;
+        }
+
+        Ok(())
+    }
+
+    /// Appends the last chunk to `out`.
+    ///
+    /// The last chunk is written even when there is no pending data, so the file can't be silently truncated.
+    pub(super) fn finish(mut self, out: &mut Vec<u8>) -> anyhow::Result<()> {
+        let chunk = core::mem::take(&mut self.pending);
+        self.seal(&chunk, true, out)
+    }
+
+    fn seal(&mut self, chunk: &[u8], is_last: bool, out: &mut Vec<u8>) -> anyhow::Result<()> {
+        let nonce = chunk_nonce(self.file_index, self.chunk_index);
+
+        let ciphertext = self
+            .cipher
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: chunk,
+                    aad: &chunk_aad(is_last),
+                },
+            )
+            .ok()
+            .context("failed to encrypt chunk")?;
+
+        // Will not overflow since the ciphertext is at most CHUNK_SIZE + TAG_SIZE bytes long.
+        let mut header = u32::try_from(ciphertext.len()).expect("chunk length fits in u32");
+
+        if is_last {
+            header |= LAST_CHUNK_FLAG;
+        }
+
+        out.extend_from_slice(&header.to_be_bytes());
+        out.extend_from_slice(&ciphertext);
+
+        self.chunk_index += 1;
+
+        Ok(())
+    }
+}
+
+pub(crate) struct ChunkDecryptor {
+    cipher: Aes256Gcm,
+    file_index: u32,
+    chunk_index: u64,
+    /// Whether the last chunk of the file was decrypted
+    finished: bool,
+}
+
+impl ChunkDecryptor {
+    /// Moves to the given chunk, for reading a file from the middle.
+    pub(super) fn seek(&mut self, chunk_index: u64) {
+        self.chunk_index = chunk_index;
+    }
+
+    /// Whether the last chunk of the file was decrypted.
+    pub(super) fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Reads and decrypts the next chunk, returning `None` at the end of the file.
+    async fn next_chunk<R>(&mut self, reader: &mut R) -> io::Result<Option<Bytes>>
+    where
+        R: io::AsyncRead + Unpin,
+    {
+        let mut header = [0; 4];
+
+        match reader.read_exact(&mut header).await {
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof && self.finished => return Ok(None),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "encrypted file is truncated",
+                ))
+            }
+            Err(e) => return Err(e),
+        }
+
+        let (len, is_last) = chunk_header(header)?;
+
+        let mut ciphertext = vec![0; len];
+        reader.read_exact(&mut ciphertext).await?;
+
+        self.decrypt(&ciphertext, is_last).map(Some)
+    }
+
+    /// Decrypts the next chunk if it's entirely contained in `buf`, consuming it.
+    pub(super) fn decrypt_buffered(&mut self, buf: &mut BytesMut) -> io::Result<Option<Bytes>> {
+        let Some(header) = buf.get(..4) else {
+            return Ok(None);
+        };
+
+        let (len, is_last) = chunk_header(header.try_into().expect("4-byte slice"))?;
+
+        if buf.len() < 4 + len {
+            return Ok(None);
+        }
+
+        buf.advance(4);
+        let ciphertext = buf.split_to(len);
+
+        self.decrypt(&ciphertext, is_last).map(Some)
+    }
+
+    fn decrypt(&mut self, ciphertext: &[u8], is_last: bool) -> io::Result<Bytes> {
+        if self.finished {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unexpected data after the last chunk",
+            ));
+        }
+
+        let nonce = chunk_nonce(self.file_index, self.chunk_index);
+
+        let plaintext = self
+            .cipher
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: &chunk_aad(is_last),
+                },
+            )
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "failed to decrypt chunk"))?;
+
+        self.chunk_index += 1;
+        self.finished = is_last;
+
+        Ok(Bytes::from(plaintext))
+    }
+
+    /// Turns a reader over an encrypted recording file into a stream of decrypted chunks.
+    pub(crate) fn into_stream<R>(self, reader: R) -> impl Stream<Item = io::Result<Bytes>> + Send
+    where
+        R: io::AsyncRead + Unpin + Send,
+    {
+        futures::stream::try_unfold((self, reader), |(mut decryptor, mut reader)| async move {
+            let chunk = decryptor.next_chunk(&mut reader).await?;
+            Ok(chunk.map(|chunk| (chunk, (decryptor, reader))))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::TryStreamExt as _;
+
+    use super::*;
+
+    fn encrypt(key: &DataKey, data: &[u8]) -> Vec<u8> {
+        let mut encryptor = key.encryptor(0).unwrap();
+        let mut out = Vec::new();
+
+        for part in data.chunks(1000) {
+            encryptor.update(part, &mut out).unwrap();
+        }
+
+        encryptor.finish(&mut out).unwrap();
+
+        out
+    }
+
+    async fn decrypt(key: &DataKey, encrypted: &[u8]) -> io::Result<Vec<u8>> {
+        let chunks: Vec<Bytes> = key.decryptor(0).unwrap().into_stream(encrypted).try_collect().await?;
+        Ok(chunks.concat())
+    }
+
+    fn sample(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[tokio::test]
+    async fn round_trip() {
+        let key = DataKey::generate();
+
+        for len in [0, 1, CHUNK_SIZE - 1, CHUNK_SIZE, 2 * CHUNK_SIZE + 123] {
+            let data = sample(len);
+            let encrypted = encrypt(&key, &data);
+
+            assert_eq!(decrypted_len(encrypted.len() as u64), len as u64);
+            assert_eq!(decrypt(&key, &encrypted).await.unwrap(), data);
+        }
+    }
+
+    #[tokio::test]
+    async fn truncated_file_is_rejected() {
+        let key = DataKey::generate();
+        let encrypted = encrypt(&key, &sample(2 * CHUNK_SIZE + 10));
+
+        // Last chunk dropped, on a chunk boundary.
+        let truncated = &encrypted[..2 * ENCRYPTED_CHUNK_SIZE as usize];
+        assert_eq!(
+            decrypt(&key, truncated).await.unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+
+        // Cut in the middle of the last chunk.
+        let truncated = &encrypted[..encrypted.len() - 5];
+        assert!(decrypt(&key, truncated).await.is_err());
+
+        // A file without any chunk is truncated too.
+        assert!(decrypt(&key, &[]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn last_chunk_flag_is_authenticated() {
+        let key = DataKey::generate();
+        let encrypted = encrypt(&key, &sample(2 * CHUNK_SIZE + 10));
+
+        // Marking an intermediate chunk as the last one, to hide the rest of the file.
+        let mut forged = encrypted[..ENCRYPTED_CHUNK_SIZE as usize].to_vec();
+        forged[0] |= 0x80;
+        assert_eq!(
+            decrypt(&key, &forged).await.unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+
+        // Clearing the flag of the last chunk.
+        let mut forged = encrypted.clone();
+        forged[2 * ENCRYPTED_CHUNK_SIZE as usize] &= 0x7F;
+        assert_eq!(
+            decrypt(&key, &forged).await.unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn buffered_decryption_detects_the_end_of_the_file() {
+        let key = DataKey::generate();
+        let data = sample(CHUNK_SIZE + 10);
+        let encrypted = encrypt(&key, &data);
+
+        let mut decryptor = key.decryptor(0).unwrap();
+        let mut buf = BytesMut::new();
+        let mut decrypted = Vec::new();
+
+        // Fed in small pieces, as when tailing a file being written.
+        for part in encrypted.chunks(4096) {
+            buf.extend_from_slice(part);
+
+            while let Some(chunk) = decryptor.decrypt_buffered(&mut buf).unwrap() {
+                decrypted.extend_from_slice(&chunk);
+            }
+        }
+
+        assert!(decryptor.is_finished());
+        assert!(buf.is_empty());
+        assert_eq!(decrypted, data);
+    }
+}
@@ -0,0 +1,175 @@
+//! Reading of recording files, decrypted on the fly when encrypted at rest
+
+use std::io::SeekFrom;
+use std::ops::RangeInclusive;
+use std::time::SystemTime;
+
+use anyhow::Context as _;
+use bytes::{Bytes, BytesMut};
+use camino::{Utf8Path, Utf8PathBuf};
+use futures::{Stream, StreamExt as _};
+use tokio::fs;
+use tokio::io::{self, AsyncRead, AsyncReadExt as _, AsyncSeekExt as _};
+
+use super::encryption::{self, ChunkDecryptor, DataKey};
+use super::JrecManifest;
+use crate::config::RecordingEncryption;
+
+const READ_BUFFER_SIZE: usize = 16 * 1024;
+
+pub(crate) struct RecordingFileReader {
+    path: Utf8PathBuf,
+    /// Size of the content, once decrypted
+    len: u64,
+    modified: SystemTime,
+    stored_len: u64,
+    sha256: Option<String>,
+    decryptor: Option<ChunkDecryptor>,
+}
+
+impl RecordingFileReader {
+    pub(crate) async fn open(
+        recording_path: &Utf8Path,
+        file_name: &str,
+        encryption_conf: Option<&RecordingEncryption>,
+    ) -> anyhow::Result<Self> {
+        let manifest = JrecManifest::read_from_file(recording_path.join("recording.json")).context("read manifest")?;
+
+        let file_index = manifest.files.iter().position(|file| file.file_name == file_name);
+
+        let decryptor = match (&manifest.encryption, file_index) {
+            (Some(encryption), Some(file_index)) => {
+                let decryptor =
+                    DataKey::unwrap(encryption, manifest.session_id, encryption_conf)?.decryptor(file_index)?;
+                Some(decryptor)
+            }
+            // Files not listed in the manifest, such as the manifest itself, are never encrypted.
+            _ => None,
+        };
+
+        let sha256 = file_index.and_then(|idx| manifest.files[idx].sha256.clone());
+
+        let path = recording_path.join(file_name);
+
+        let metadata = fs::metadata(&path)
+            .await
+            .with_context(|| format!("failed to read metadata of {path}"))?;
+
+        let stored_len = metadata.len();
+
+        let len = if decryptor.is_some() {
+            encryption::decrypted_len(stored_len)
+        } else {
+            stored_len
+        };
+
+        Ok(Self {
+            path,
+            len,
+            modified: metadata.modified().context("file modification time")?,
+            stored_len,
+            sha256,
+            decryptor,
+        })
+    }
+
+    /// Size of the content, once decrypted.
+    pub(crate) fn content_len(&self) -> u64 {
+        self.len
+    }
+
+    pub(crate) fn modified(&self) -> SystemTime {
+        self.modified
+    }
+
+    /// Entity tag for the current content of the file, quotes included.
+    ///
+    /// The hash recorded in the manifest is used once the file is complete.
+    pub(crate) fn etag(&self) -> String {
+        match &self.sha256 {
+            Some(sha256) => format!("\"{sha256}\""),
+            None => {
+                let modified = self
+                    .modified
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_nanos();
+                format!("\"{:x}-{:x}\"", self.stored_len, modified)
+            }
+        }
+    }
+
+    /// Streams the requested range of the content.
+    pub(crate) async fn read_range(
+        self,
+        range: RangeInclusive<u64>,
+    ) -> anyhow::Result<impl Stream<Item = io::Result<Bytes>> + Send> {
+        let (start, end) = range.into_inner();
+        let len = (end + 1).saturating_sub(start);
+
+        let mut file = fs::File::open(&self.path)
+            .await
+            .with_context(|| format!("failed to open {}", self.path))?;
+
+        let stream = match self.decryptor {
+            Some(mut decryptor) => {
+                let (chunk_index, chunk_offset, offset_in_chunk) = encryption::locate_chunk(start);
+
+                file.seek(SeekFrom::Start(chunk_offset)).await.context("seek")?;
+                decryptor.seek(chunk_index);
+
+                slice_stream(decryptor.into_stream(io::BufReader::new(file)), offset_in_chunk, len).left_stream()
+            }
+            None => {
+                file.seek(SeekFrom::Start(start)).await.context("seek")?;
+
+                slice_stream(read_stream(file), 0, len).right_stream()
+            }
+        };
+
+        Ok(stream)
+    }
+}
+
+fn read_stream<R>(reader: R) -> impl Stream<Item = io::Result<Bytes>> + Send
+where
+    R: AsyncRead + Unpin + Send,
+{
+    futures::stream::try_unfold(reader, |mut reader| async move {
+        let mut buf = BytesMut::with_capacity(READ_BUFFER_SIZE);
+        let n = reader.read_buf(&mut buf).await?;
+        Ok((n > 0).then(|| (buf.freeze(), reader)))
+    })
+}
+
+/// Skips the first `skip` bytes of the stream, then yields at most `len` bytes.
+fn slice_stream<S>(stream: S, skip: u64, len: u64) -> impl Stream<Item = io::Result<Bytes>> + Send
+where
+    S: Stream<Item = io::Result<Bytes>> + Send,
+{
+    futures::stream::try_unfold(
+        (Box::pin(stream), skip, len),
+        |(mut stream, mut skip, mut remaining)| async move {
+            while remaining > 0 {
+                let Some(data) = stream.next().await.transpose()? else {
+                    break;
+                };
+
+                let skipped = usize::try_from(skip).unwrap_or(usize::MAX).min(data.len());
+                skip -= skipped as u64;
+                let mut data = data.slice(skipped..);
+
+                if data.is_empty() {
+                    continue;
+                }
+
+                data.truncate(usize::try_from(remaining).unwrap_or(usize::MAX));
+                remaining -= data.len() as u64;
+
+                return Ok(Some((data, (stream, skip, remaining))));
+            }
+
+            Ok(None)
+        },
+    )
+}
@@ -0,0 +1,159 @@
+//! Free space guard for the recording volume
+//!
+//! New recordings are refused while the free space on the recording volume is below the configured threshold,
+//! instead of failing in the middle of a write once the volume is full.
+//!
+//! The free space is checked periodically by the recording manager, and the last status is cached, so that
+//! querying it never hits the disk. When the cached free space is close to the threshold, it's checked again
+//! before starting a recording, since it may have crossed the threshold since the last check.
+
+use anyhow::Context as _;
+use camino::Utf8PathBuf;
+use parking_lot::Mutex;
+
+use crate::subscriber::{self, SubscriberSender, SubscriberStorageInfo};
+
+/// Margin above the threshold within which the cached free space is considered close to the threshold.
+const RECHECK_MARGIN: u64 = 1024 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum RecordingStorageState {
+    /// New recordings are accepted
+    Ok,
+    /// The free space is below the configured threshold, new recordings are refused
+    Low,
+    /// The free space on the recording volume couldn't be determined
+    Unknown,
+}
+
+/// State of the recording storage
+#[derive(Debug, Clone, Copy, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RecordingStorageStatus {
+    pub state: RecordingStorageState,
+    /// Space available on the recording volume, in bytes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub available_space: Option<u64>,
+}
+
+#[derive(Debug)]
+pub struct RecordingStorageMonitor {
+    last_status: Mutex<RecordingStorageStatus>,
+}
+
+impl Default for RecordingStorageMonitor {
+    fn default() -> Self {
+        Self {
+            last_status: Mutex::new(RecordingStorageStatus {
+                state: RecordingStorageState::Unknown,
+                available_space: None,
+            }),
+        }
+    }
+}
+
+impl RecordingStorageMonitor {
+    /// Returns the status found by the last check.
+    pub fn status(&self) -> RecordingStorageStatus {
+        *self.last_status.lock()
+    }
+
+    /// Returns whether the free space found by the last check is close to the threshold, or below it.
+    pub fn is_close_to_threshold(&self, threshold: Option<u64>) -> bool {
+        let available_space = self.status().available_space;
+
+        threshold
+            .zip(available_space)
+            .is_some_and(|(threshold, available_space)| available_space < threshold.saturating_add(RECHECK_MARGIN))
+    }
+
+    /// Checks the free space on the recording volume.
+    ///
+    /// The subscriber is notified when the free space goes below the threshold.
+    pub async fn check(
+        &self,
+        recording_path: Utf8PathBuf,
+        threshold: Option<u64>,
+        subscriber_tx: &SubscriberSender,
+    ) -> RecordingStorageStatus {
+        let available_space = tokio::task::spawn_blocking(move || crate::utils::available_disk_space(&recording_path))
+            .await
+            .context("failed to join the disk space task")
+            .and_then(|result| result);
+
+        let status = match available_space {
+            Ok(available_space) => RecordingStorageStatus {
+                state: if threshold.is_some_and(|threshold| available_space < threshold) {
+                    RecordingStorageState::Low
+                } else {
+                    RecordingStorageState::Ok
+                },
+                available_space: Some(available_space),
+            },
+            Err(error) => {
+                debug!(
+                    error = format!("{error:#}"),
+                    "Couldn't retrieve the free space on the recording volume"
+                );
+
+                RecordingStorageStatus {
+                    state: RecordingStorageState::Unknown,
+                    available_space: None,
+                }
+            }
+        };
+
+        let previous_state = std::mem::replace(&mut *self.last_status.lock(), status).state;
+
+        match (previous_state, status.state, status.available_space.zip(threshold)) {
+            (
+                RecordingStorageState::Ok | RecordingStorageState::Unknown,
+                RecordingStorageState::Low,
+                Some((available_space, threshold)),
+            ) => {
+                warn!(available_space, threshold, "Recording volume is running out of space");
+
+                let message = subscriber::Message::recording_storage_low(SubscriberStorageInfo {
+                    available_space,
+                    threshold,
+                });
+
+                if let Err(error) = subscriber_tx.try_send(message) {
+                    warn!(%error, "Failed to send subscriber message");
+                }
+            }
+            (RecordingStorageState::Low, RecordingStorageState::Ok, _) => {
+                info!("Free space on the recording volume is back above the threshold");
+            }
+            _ => {}
+        }
+
+        status
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor(available_space: Option<u64>) -> RecordingStorageMonitor {
+        let monitor = RecordingStorageMonitor::default();
+        monitor.last_status.lock().available_space = available_space;
+        monitor
+    }
+
+    #[test]
+    fn close_to_threshold() {
+        const THRESHOLD: u64 = 10 * 1024 * 1024 * 1024;
+
+        assert!(monitor(Some(THRESHOLD - 1)).is_close_to_threshold(Some(THRESHOLD)));
+        assert!(monitor(Some(THRESHOLD + RECHECK_MARGIN - 1)).is_close_to_threshold(Some(THRESHOLD)));
+        assert!(!monitor(Some(THRESHOLD + RECHECK_MARGIN)).is_close_to_threshold(Some(THRESHOLD)));
+
+        // Without threshold, or without known free space, there is nothing to check again.
+        assert!(!monitor(Some(0)).is_close_to_threshold(None));
+        assert!(!monitor(None).is_close_to_threshold(Some(THRESHOLD)));
+    }
+}
@@ -0,0 +1,371 @@
+//! Index of the recordings stored on this instance
+//!
+//! The index is rebuilt from the manifests when the recording manager starts, then kept up to date
+//! as recordings are written and deleted, so listing recordings doesn't require reading every manifest.
+
+use std::cmp;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::Context as _;
+use camino::Utf8Path;
+use parking_lot::RwLock;
+use uuid::Uuid;
+
+use super::{JrecManifest, JrecSessionMetadata};
+use crate::token::RecordingFileType;
+
+/// Information about a recording stored on this instance
+#[derive(Debug, Clone, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RecordingInfo {
+    /// Recorded session ID
+    pub session_id: Uuid,
+    /// Unix timestamp at which the recording started
+    pub start_time: i64,
+    /// Duration of the recording, in seconds
+    pub duration: i64,
+    /// Types of the recording files (e.g.: webm, trp, cast)
+    pub file_types: Vec<String>,
+    /// Total size of the recording files, in bytes
+    pub total_size: u64,
+    /// Protocol used during the recorded session, if known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub application_protocol: Option<String>,
+    /// Destination host of the recorded session, if known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub destination_host: Option<String>,
+}
+
+impl RecordingInfo {
+    fn new(recording_path: &Utf8Path, manifest: &JrecManifest) -> Self {
+        let mut file_types = Vec::new();
+        let mut total_size = 0;
+
+        for file in &manifest.files {
+            if let Some(extension) = Utf8Path::new(&file.file_name).extension() {
+                if !file_types.iter().any(|file_type| file_type == extension) {
+                    file_types.push(extension.to_owned());
+                }
+            }
+
+            // The file may not be created yet, or may only be held by the storage backend.
+            match recording_path.join(&file.file_name).metadata() {
+                Ok(metadata) => total_size += metadata.len(),
+                Err(_) => total_size += file.size.unwrap_or(0),
+            }
+        }
+
+        let (application_protocol, destination_host) = match &manifest.session {
+            Some(JrecSessionMetadata {
+                application_protocol,
+                destination_host,
+            }) => (Some(application_protocol.clone()), destination_host.clone()),
+            None => (None, None),
+        };
+
+        Self {
+            session_id: manifest.session_id,
+            start_time: manifest.start_time,
+            duration: manifest.duration,
+            file_types,
+            total_size,
+            application_protocol,
+            destination_host,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RecordingSortKey {
+    #[default]
+    StartTime,
+    Duration,
+    TotalSize,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+/// Position in a listing, pointing right after the last recording returned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordingCursor {
+    key: i128,
+    id: Uuid,
+}
+
+impl fmt::Display for RecordingCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}_{}", self.key, self.id)
+    }
+}
+
+impl FromStr for RecordingCursor {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, id) = s.split_once('_').context("missing separator")?;
+
+        Ok(Self {
+            key: key.parse().context("invalid key")?,
+            id: id.parse().context("invalid ID")?,
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct RecordingQuery {
+    /// Only recordings started at or after this Unix timestamp
+    pub(crate) from: Option<i64>,
+    /// Only recordings started at or before this Unix timestamp
+    pub(crate) to: Option<i64>,
+    pub(crate) file_type: Option<RecordingFileType>,
+    /// Minimum duration, in seconds
+    pub(crate) min_duration: Option<i64>,
+    pub(crate) sort: RecordingSortKey,
+    pub(crate) order: SortOrder,
+    pub(crate) cursor: Option<RecordingCursor>,
+    pub(crate) limit: Option<usize>,
+}
+
+impl RecordingQuery {
+    fn sort_key(&self, info: &RecordingInfo) -> (i128, Uuid) {
+        let key = match self.sort {
+            RecordingSortKey::StartTime => i128::from(info.start_time),
+            RecordingSortKey::Duration => i128::from(info.duration),
+            RecordingSortKey::TotalSize => i128::from(info.total_size),
+        };
+
+        (key, info.session_id)
+    }
+
+    fn matches(&self, info: &RecordingInfo) -> bool {
+        if self.from.is_some_and(|from| info.start_time < from) {
+            return false;
+        }
+
+        if self.to.is_some_and(|to| info.start_time > to) {
+            return false;
+        }
+
+        if self
+            .file_type
+            .is_some_and(|file_type| !info.file_types.iter().any(|t| t == file_type.as_str()))
+        {
+            return false;
+        }
+
+        if self
+            .min_duration
+            .is_some_and(|min_duration| info.duration < min_duration)
+        {
+            return false;
+        }
+
+        if let Some(cursor) = self.cursor {
+            let ordering = self.sort_key(info).cmp(&(cursor.key, cursor.id));
+
+            let after_cursor = match self.order {
+                SortOrder::Asc => ordering == cmp::Ordering::Greater,
+                SortOrder::Desc => ordering == cmp::Ordering::Less,
+            };
+
+            if !after_cursor {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RecordingIndex(RwLock<HashMap<Uuid, RecordingInfo>>);
+
+impl RecordingIndex {
+    /// Rebuilds the whole index from the manifests found in the recordings folder.
+    pub(super) fn rebuild(&self, recordings_path: &Utf8Path) -> anyhow::Result<usize> {
+        let mut recordings = HashMap::new();
+
+        if recordings_path.exists() {
+            let read_dir = recordings_path
+                .read_dir_utf8()
+                .with_context(|| format!("failed to read {recordings_path}"))?;
+
+            for entry in read_dir {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(error) => {
+                        warn!(%error, "Couldn't read entry in recordings folder");
+                        continue;
+                    }
+                };
+
+                let Ok(id) = Uuid::parse_str(entry.file_name()) else {
+                    continue;
+                };
+
+                if !entry.path().is_dir() {
+                    continue;
+                }
+
+                match JrecManifest::read_from_file(entry.path().join("recording.json")) {
+                    Ok(manifest) => {
+                        recordings.insert(id, RecordingInfo::new(entry.path(), &manifest));
+                    }
+                    Err(error) => warn!(error = format!("{error:#}"), %id, "Couldn't read recording manifest"),
+                }
+            }
+        }
+
+        let count = recordings.len();
+
+        *self.0.write() = recordings;
+
+        Ok(count)
+    }
+
+    pub(super) fn update(&self, recording_path: &Utf8Path, manifest: &JrecManifest) {
+        let info = RecordingInfo::new(recording_path, manifest);
+        self.0.write().insert(manifest.session_id, info);
+    }
+
+    pub(super) fn remove(&self, id: Uuid) {
+        self.0.write().remove(&id);
+    }
+
+    pub(super) fn contains(&self, id: Uuid) -> bool {
+        self.0.read().contains_key(&id)
+    }
+
+    /// Returns the recordings matching the query, and the cursor to the next page if there are more.
+    pub(crate) fn query(&self, query: &RecordingQuery) -> (Vec<RecordingInfo>, Option<RecordingCursor>) {
+        let mut recordings: Vec<RecordingInfo> = self
+            .0
+            .read()
+            .values()
+            .filter(|info| query.matches(info))
+            .cloned()
+            .collect();
+
+        recordings.sort_by_key(|info| query.sort_key(info));
+
+        if query.order == SortOrder::Desc {
+            recordings.reverse();
+        }
+
+        let next_cursor = match query.limit {
+            Some(limit) if recordings.len() > limit => {
+                recordings.truncate(limit);
+
+                recordings.last().map(|info| {
+                    let (key, id) = query.sort_key(info);
+                    RecordingCursor { key, id }
+                })
+            }
+            _ => None,
+        };
+
+        (recordings, next_cursor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TempDir;
+
+    fn info(start_time: i64, duration: i64) -> RecordingInfo {
+        RecordingInfo {
+            session_id: Uuid::new_v4(),
+            start_time,
+            duration,
+            file_types: vec!["webm".to_owned()],
+            total_size: 0,
+            application_protocol: None,
+            destination_host: None,
+        }
+    }
+
+    #[test]
+    fn pagination_returns_every_recording_once() {
+        let index = RecordingIndex::default();
+
+        for start_time in [10, 20, 20, 30, 40] {
+            let info = info(start_time, 5);
+            index.0.write().insert(info.session_id, info);
+        }
+
+        let mut query = RecordingQuery {
+            limit: Some(2),
+            ..Default::default()
+        };
+
+        let mut start_times = Vec::new();
+
+        loop {
+            let (page, next_cursor) = index.query(&query);
+            start_times.extend(page.iter().map(|info| info.start_time));
+
+            match next_cursor {
+                Some(cursor) => query.cursor = Some(cursor.to_string().parse().unwrap()),
+                None => break,
+            }
+        }
+
+        assert_eq!(start_times, [40, 30, 20, 20, 10]);
+    }
+
+    #[test]
+    fn filters() {
+        let index = RecordingIndex::default();
+
+        for (start_time, duration) in [(10, 5), (20, 60), (30, 120)] {
+            let info = info(start_time, duration);
+            index.0.write().insert(info.session_id, info);
+        }
+
+        let query = RecordingQuery {
+            to: Some(25),
+            min_duration: Some(30),
+            ..Default::default()
+        };
+        let (recordings, _) = index.query(&query);
+        assert_eq!(recordings.len(), 1);
+        assert_eq!(recordings[0].start_time, 20);
+
+        let query = RecordingQuery {
+            file_type: Some(RecordingFileType::TRP),
+            ..Default::default()
+        };
+        let (recordings, _) = index.query(&query);
+        assert!(recordings.is_empty());
+    }
+
+    #[test]
+    fn total_size_includes_offloaded_files() {
+        let recording_dir = TempDir::new();
+        std::fs::write(recording_dir.path().join("recording-1.webm"), "webm data").unwrap();
+
+        let manifest: JrecManifest = serde_json::from_value(serde_json::json!({
+            "sessionId": Uuid::new_v4(),
+            "startTime": 0,
+            "duration": 20,
+            "files": [
+                { "fileName": "recording-0.webm", "startTime": 0, "duration": 10, "size": 1000 },
+                { "fileName": "recording-1.webm", "startTime": 10, "duration": 10 },
+            ],
+        }))
+        .unwrap();
+
+        let info = RecordingInfo::new(recording_dir.path(), &manifest);
+        assert_eq!(info.total_size, 1000 + "webm data".len() as u64);
+    }
+}
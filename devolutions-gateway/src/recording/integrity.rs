@@ -0,0 +1,319 @@
+//! Tamper-evidence for recordings
+//!
+//! Each recording file is hashed using SHA-256 once the client disconnects.
+//! The file hashes are chained together in the manifest, so that files can't be
+//! removed, swapped or reordered without breaking the chain, and the manifest itself
+//! is signed using the recording signing key when one is configured.
+
+use std::io::Read as _;
+
+use anyhow::Context as _;
+use camino::{Utf8Path, Utf8PathBuf};
+use picky::jose::jws::{Jws, JwsAlg};
+use picky::key::{EcCurve, NamedEcCurve, PrivateKey, PrivateKeyKind};
+use sha2::{Digest as _, Sha256};
+
+use super::{to_hex, JrecManifest};
+
+/// Computes the SHA-256 hash of a file, as a lowercase hexadecimal string.
+pub(crate) async fn hash_file(path: Utf8PathBuf) -> anyhow::Result<String> {
+    tokio::task::spawn_blocking(move || hash_file_blocking(&path))
+        .await
+        .context("failed to join the hashing task")?
+}
+
+fn hash_file_blocking(path: &Utf8Path) -> anyhow::Result<String> {
+    let mut file = std::fs::File::open(path).with_context(|| format!("failed to open {path}"))?;
+
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 64 * 1024];
+
+    loop {
+        let n = file.read(&mut buf).with_context(|| format!("failed to read {path}"))?;
+
+        if n == 0 {
+            break;
+        }
+
+        hasher.update(&buf[..n]);
+    }
+
+    Ok(to_hex(&hasher.finalize()))
+}
+
+impl JrecManifest {
+    /// Recomputes the hash chain from the individual file hashes.
+    ///
+    /// The chain is seeded with the session ID, and each link is computed as
+    /// `SHA-256(previous link || file name || file hash)`.
+    pub(super) fn update_hash_chain(&mut self) {
+        let mut previous = self.session_id.to_string();
+
+        for file in &mut self.files {
+            let link = chain_link(&previous, &file.file_name, file.sha256.as_deref());
+            file.chain_hash = Some(link.clone());
+            previous = link;
+        }
+    }
+
+    /// Signs the manifest, replacing any previous signature.
+    ///
+    /// The manifest is left unsigned on error.
+    pub(super) fn sign(&mut self, key: &PrivateKey) -> anyhow::Result<()> {
+        self.signature = None;
+
+        let alg = signing_alg(key)?;
+        let manifest = serde_json::to_value(&*self).context("failed to serialize manifest")?;
+        let digest = manifest_digest(manifest)?;
+
+        let signature = Jws::new(alg, digest.into_bytes())
+            .encode(key)
+            .context("failed to sign manifest")?;
+
+        self.signature = Some(signature);
+
+        Ok(())
+    }
+}
+
+/// Computes the digest of a manifest, signature excluded.
+///
+/// The digest is computed over the JSON document rather than over `JrecManifest`, so fields unknown to this
+/// version, which would be dropped by deserialization, are still covered.
+fn manifest_digest(mut manifest: serde_json::Value) -> anyhow::Result<String> {
+    if let Some(object) = manifest.as_object_mut() {
+        object.remove("signature");
+    }
+
+    let json = serde_json::to_vec(&manifest).context("failed to serialize manifest")?;
+
+    Ok(to_hex(&Sha256::digest(json)))
+}
+
+/// Picks the JWS algorithm matching the type of the signing key.
+pub(crate) fn signing_alg(key: &PrivateKey) -> anyhow::Result<JwsAlg> {
+    match key.kind() {
+        PrivateKeyKind::Rsa => Ok(JwsAlg::RS256),
+        PrivateKeyKind::Ec { curve, .. } => match curve {
+            NamedEcCurve::Known(EcCurve::NistP256) => Ok(JwsAlg::ES256),
+            NamedEcCurve::Known(EcCurve::NistP384) => Ok(JwsAlg::ES384),
+            _ => anyhow::bail!("unsupported elliptic curve for the signing key"),
+        },
+        _ => anyhow::bail!("unsupported signing key type"),
+    }
+}
+
+fn chain_link(previous: &str, file_name: &str, file_hash: Option<&str>) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(previous.as_bytes());
+    hasher.update(file_name.as_bytes());
+    hasher.update(file_hash.unwrap_or_default().as_bytes());
+    to_hex(&hasher.finalize())
+}
+
+/// Integrity report for a recording
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RecordingIntegrity {
+    /// Whether the recording is intact (all files are matching and the manifest is properly signed)
+    pub valid: bool,
+    /// Status of the manifest signature
+    pub signature: SignatureStatus,
+    /// Status of each recording file, in the order listed by the manifest
+    pub files: Vec<FileIntegrity>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum SignatureStatus {
+    /// The signature matches the manifest
+    Valid,
+    /// The signature does not match the manifest
+    Invalid,
+    /// The manifest is not signed
+    Unsigned,
+    /// The manifest is signed, but no signing key is configured to verify it
+    Unverifiable,
+}
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct FileIntegrity {
+    pub file_name: String,
+    pub status: FileIntegrityStatus,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum FileIntegrityStatus {
+    /// The file matches the hash recorded in the manifest
+    Valid,
+    /// The file or its entry in the hash chain was modified
+    Altered,
+    /// The file is missing from the recording folder
+    Missing,
+    /// No hash was recorded for this file (e.g.: the recording is still ongoing)
+    Unhashed,
+}
+
+/// Verifies the integrity of the recording stored in the given folder.
+pub(crate) async fn verify_recording(
+    recording_path: Utf8PathBuf,
+    signing_key: Option<PrivateKey>,
+) -> anyhow::Result<RecordingIntegrity> {
+    tokio::task::spawn_blocking(move || verify_recording_blocking(&recording_path, signing_key.as_ref()))
+        .await
+        .context("failed to join the verification task")?
+}
+
+fn verify_recording_blocking(
+    recording_path: &Utf8Path,
+    signing_key: Option<&PrivateKey>,
+) -> anyhow::Result<RecordingIntegrity> {
+    let manifest_path = recording_path.join("recording.json");
+    let manifest_json = std::fs::read(&manifest_path).with_context(|| format!("failed to read {manifest_path}"))?;
+    let manifest: JrecManifest = serde_json::from_slice(&manifest_json).context("invalid manifest")?;
+
+    let signature = match (&manifest.signature, signing_key) {
+        (None, _) => SignatureStatus::Unsigned,
+        (Some(_), None) => SignatureStatus::Unverifiable,
+        (Some(signature), Some(key)) => {
+            let public_key = key.to_public_key().context("invalid signing key")?;
+
+            match Jws::decode(signature, &public_key) {
+                Ok(jws) => {
+                    let raw_manifest = serde_json::from_slice(&manifest_json).context("invalid manifest")?;
+
+                    if jws.payload == manifest_digest(raw_manifest)?.into_bytes() {
+                        SignatureStatus::Valid
+                    } else {
+                        SignatureStatus::Invalid
+                    }
+                }
+                Err(error) => {
+                    debug!(%error, "Manifest signature verification failed");
+                    SignatureStatus::Invalid
+                }
+            }
+        }
+    };
+
+    let mut previous = manifest.session_id.to_string();
+    let mut files = Vec::with_capacity(manifest.files.len());
+
+    for file in &manifest.files {
+        let expected_link = chain_link(&previous, &file.file_name, file.sha256.as_deref());
+        let chain_intact = file.chain_hash.as_deref() == Some(expected_link.as_str());
+        previous = file.chain_hash.clone().unwrap_or(expected_link);
+
+        let file_path = recording_path.join(&file.file_name);
+
+        let status = match &file.sha256 {
+            _ if !file_path.is_file() => FileIntegrityStatus::Missing,
+            None => FileIntegrityStatus::Unhashed,
+            Some(_) if !chain_intact => FileIntegrityStatus::Altered,
+            Some(expected_hash) if hash_file_blocking(&file_path)? == *expected_hash => FileIntegrityStatus::Valid,
+            Some(_) => FileIntegrityStatus::Altered,
+        };
+
+        files.push(FileIntegrity {
+            file_name: file.file_name.clone(),
+            status,
+        });
+    }
+
+    let valid =
+        signature == SignatureStatus::Valid && files.iter().all(|file| file.status == FileIntegrityStatus::Valid);
+
+    Ok(RecordingIntegrity {
+        valid,
+        signature,
+        files,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::test_utils::{TempDir, P256_PRIVATE_KEY};
+
+    fn signed_recording(key: &PrivateKey) -> TempDir {
+        let recording_dir = TempDir::new();
+        let recording_path = recording_dir.path();
+
+        let file_path = recording_path.join("recording-0.webm");
+        std::fs::write(&file_path, "webm data").unwrap();
+
+        let mut manifest: JrecManifest = serde_json::from_value(serde_json::json!({
+            "sessionId": Uuid::new_v4(),
+            "startTime": 0,
+            "duration": 10,
+            "files": [{
+                "fileName": "recording-0.webm",
+                "startTime": 0,
+                "duration": 10,
+                "sha256": hash_file_blocking(&file_path).unwrap(),
+            }],
+        }))
+        .unwrap();
+
+        manifest.update_hash_chain();
+        manifest.sign(key).unwrap();
+        manifest.save_to_file(recording_path.join("recording.json")).unwrap();
+
+        recording_dir
+    }
+
+    fn edit_manifest(recording_path: &Utf8Path, edit: impl FnOnce(&mut serde_json::Map<String, serde_json::Value>)) {
+        let manifest_path = recording_path.join("recording.json");
+        let mut manifest: serde_json::Value = serde_json::from_slice(&std::fs::read(&manifest_path).unwrap()).unwrap();
+        edit(manifest.as_object_mut().unwrap());
+        std::fs::write(&manifest_path, serde_json::to_vec_pretty(&manifest).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn algorithm_matches_the_key_type() {
+        let key = PrivateKey::from_pem_str(P256_PRIVATE_KEY).unwrap();
+        assert_eq!(signing_alg(&key).unwrap(), JwsAlg::ES256);
+    }
+
+    #[test]
+    fn signed_manifest_is_verified() {
+        let key = PrivateKey::from_pem_str(P256_PRIVATE_KEY).unwrap();
+        let recording_dir = signed_recording(&key);
+        let recording_path = recording_dir.path();
+
+        let integrity = verify_recording_blocking(recording_path, Some(&key)).unwrap();
+        assert!(integrity.valid);
+        assert_eq!(integrity.signature, SignatureStatus::Valid);
+        assert_eq!(integrity.files[0].status, FileIntegrityStatus::Valid);
+
+        let integrity = verify_recording_blocking(recording_path, None).unwrap();
+        assert!(!integrity.valid);
+        assert_eq!(integrity.signature, SignatureStatus::Unverifiable);
+    }
+
+    #[test]
+    fn tampered_manifest_is_rejected() {
+        let key = PrivateKey::from_pem_str(P256_PRIVATE_KEY).unwrap();
+
+        let recording_dir = signed_recording(&key);
+        edit_manifest(recording_dir.path(), |manifest| {
+            manifest.insert("duration".to_owned(), serde_json::json!(5));
+        });
+        let integrity = verify_recording_blocking(recording_dir.path(), Some(&key)).unwrap();
+        assert_eq!(integrity.signature, SignatureStatus::Invalid);
+
+        // Fields unknown to this version are covered by the signature too.
+        let recording_dir = signed_recording(&key);
+        edit_manifest(recording_dir.path(), |manifest| {
+            manifest.insert("unknownField".to_owned(), serde_json::json!("injected"));
+        });
+        let integrity = verify_recording_blocking(recording_dir.path(), Some(&key)).unwrap();
+        assert_eq!(integrity.signature, SignatureStatus::Invalid);
+    }
+}
@@ -0,0 +1,788 @@
+//! Merging of the recording files of a session into a single file
+//!
+//! A session interrupted by reconnections is recorded as several files. The files are concatenated without
+//! transcoding, and the timestamps are rewritten so that each file starts at its actual position in the session.
+
+use anyhow::Context as _;
+use bytes::Bytes;
+use camino::Utf8PathBuf;
+use devolutions_gateway_task::ChildTask;
+use futures::{Stream, StreamExt as _};
+use tokio::io::{self, AsyncBufRead, AsyncBufReadExt as _, AsyncReadExt as _, AsyncWrite, AsyncWriteExt as _};
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+use super::{FetchedFiles, JrecManifest, RecordingFileReader};
+use crate::config::RecordingEncryption;
+use crate::token::RecordingFileType;
+
+const PIPE_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Elements are held in memory, so bogus sizes must not cause huge allocations.
+const MAX_ELEMENT_SIZE: u64 = 64 * 1024 * 1024;
+
+const TRP_EVENT_HEADER_SIZE: usize = 8;
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum MergeError {
+    #[error("nothing to merge: {0:#}")]
+    NotFound(anyhow::Error),
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+struct MergeInput {
+    reader: Box<dyn AsyncBufRead + Unpin + Send>,
+    /// Position of the file in the session, in milliseconds
+    offset_ms: u64,
+}
+
+/// Merges the files of the given type into a single stream.
+///
+/// When no type is specified, the type of the first file is used.
+/// The files retrieved from the storage backend are released once the stream is dropped.
+pub(crate) async fn merge_recording(
+    recording_path: Utf8PathBuf,
+    file_type: Option<RecordingFileType>,
+    mut fetched_files: FetchedFiles,
+    encryption_conf: Option<RecordingEncryption>,
+) -> Result<(RecordingFileType, impl Stream<Item = io::Result<Bytes>> + Send), MergeError> {
+    let manifest = JrecManifest::read_from_file(recording_path.join("recording.json"))
+        .context("read manifest")
+        .map_err(MergeError::NotFound)?;
+
+    let files: Vec<_> = manifest
+        .files
+        .iter()
+        .filter_map(|file| Some((file, file_type_of(&file.file_name)?)))
+        .collect();
+
+    let file_type = file_type
+        .or_else(|| files.first().map(|(_, file_type)| *file_type))
+        .context("no recording file")
+        .map_err(MergeError::NotFound)?;
+
+    let files: Vec<_> = files
+        .into_iter()
+        .filter(|(_, t)| *t == file_type)
+        .map(|(file, _)| file)
+        .collect();
+
+    let first_start_time = files
+        .first()
+        .map(|file| file.start_time)
+        .with_context(|| format!("no {file_type} file"))
+        .map_err(MergeError::NotFound)?;
+
+    let id = manifest.session_id;
+    let mut inputs = Vec::with_capacity(files.len());
+
+    for file in files {
+        fetched_files
+            .fetch(&file.file_name)
+            .await
+            .map_err(MergeError::NotFound)?;
+
+        let reader = open_reader(&recording_path, &file.file_name, encryption_conf.as_ref())
+            .await
+            .with_context(|| format!("open {}", file.file_name))
+            .map_err(MergeError::Other)?;
+
+        let offset_secs = u64::try_from(file.start_time - first_start_time).unwrap_or(0);
+
+        inputs.push(MergeInput {
+            reader,
+            offset_ms: offset_secs.saturating_mul(1000),
+        });
+    }
+
+    let (mut writer, reader) = io::duplex(PIPE_BUFFER_SIZE);
+    let (result_tx, result_rx) = oneshot::channel();
+
+    ChildTask::spawn(async move {
+        let result = match file_type {
+            RecordingFileType::TRP => merge_trp(inputs, &mut writer).await,
+            RecordingFileType::Asciicast => merge_asciicast(inputs, &mut writer).await,
+            RecordingFileType::WebM => merge_webm(inputs, &mut writer).await,
+        };
+
+        let result = match result {
+            Ok(()) => writer.shutdown().await.context("shutdown"),
+            Err(error) => Err(error),
+        };
+
+        if let Err(error) = &result {
+            warn!(%id, error = format!("{error:#}"), "Failed to merge recording files");
+        }
+
+        let _ = result_tx.send(result);
+    })
+    .detach();
+
+    // The stream ends with an error when the merge fails, instead of looking like a complete file.
+    let failure = futures::stream::once(async move {
+        match result_rx.await {
+            Ok(Ok(())) => None,
+            Ok(Err(error)) => Some(Err(io::Error::other(format!("{error:#}")))),
+            Err(_) => Some(Err(io::Error::other("merge task aborted"))),
+        }
+    })
+    .filter_map(futures::future::ready);
+
+    let stream = fetched_files.attach(tokio_util::io::ReaderStream::new(reader).chain(failure));
+
+    Ok((file_type, stream))
+}
+
+fn file_type_of(file_name: &str) -> Option<RecordingFileType> {
+    match camino::Utf8Path::new(file_name).extension()? {
+        "webm" => Some(RecordingFileType::WebM),
+        "trp" => Some(RecordingFileType::TRP),
+        "cast" => Some(RecordingFileType::Asciicast),
+        _ => None,
+    }
+}
+
+async fn open_reader(
+    recording_path: &camino::Utf8Path,
+    file_name: &str,
+    encryption_conf: Option<&RecordingEncryption>,
+) -> anyhow::Result<Box<dyn AsyncBufRead + Unpin + Send>> {
+    let file = RecordingFileReader::open(recording_path, file_name, encryption_conf).await?;
+
+    let len = file.content_len();
+
+    if len == 0 {
+        return Ok(Box::new(io::empty()));
+    }
+
+    let stream = file.read_range(0..=len - 1).await?;
+
+    Ok(Box::new(tokio_util::io::StreamReader::new(Box::pin(stream))))
+}
+
+/// Concatenates TRP files.
+///
+/// The timestamp of each event is relative to the previous event, so only the first event of each file is updated.
+async fn merge_trp<W>(inputs: Vec<MergeInput>, writer: &mut W) -> anyhow::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let mut elapsed_ms: u64 = 0;
+
+    for MergeInput { mut reader, offset_ms } in inputs {
+        let mut file_elapsed_ms: u64 = 0;
+        let mut is_first_event = true;
+        let mut header = [0; TRP_EVENT_HEADER_SIZE];
+
+        loop {
+            match reader.read_exact(&mut header).await {
+                Ok(_) => {}
+                // The last event of a file cut during a crash may be incomplete.
+                Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(error) => return Err(anyhow::Error::new(error).context("read TRP event")),
+            }
+
+            let delta = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+            let size = u16::from_le_bytes([header[6], header[7]]);
+
+            let mut payload = vec![0; usize::from(size)];
+
+            match reader.read_exact(&mut payload).await {
+                Ok(_) => {}
+                Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(error) => return Err(anyhow::Error::new(error).context("read TRP event")),
+            }
+
+            file_elapsed_ms += u64::from(delta);
+
+            let delta = if is_first_event {
+                is_first_event = false;
+
+                let time_ms = offset_ms + file_elapsed_ms;
+                let delta = time_ms.saturating_sub(elapsed_ms);
+                elapsed_ms = elapsed_ms.max(time_ms);
+
+                u32::try_from(delta).unwrap_or(u32::MAX)
+            } else {
+                elapsed_ms += u64::from(delta);
+                delta
+            };
+
+            header[..4].copy_from_slice(&delta.to_le_bytes());
+
+            writer.write_all(&header).await.context("write")?;
+            writer.write_all(&payload).await.context("write")?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Concatenates asciicast v2 files, keeping the header of the first file.
+async fn merge_asciicast<W>(inputs: Vec<MergeInput>, writer: &mut W) -> anyhow::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let mut last_time = 0.0_f64;
+
+    for (index, MergeInput { reader, offset_ms }) in inputs.into_iter().enumerate() {
+        let offset = std::time::Duration::from_millis(offset_ms).as_secs_f64();
+        let mut lines = reader.lines();
+
+        let Some(header) = lines.next_line().await.context("read asciicast header")? else {
+            continue;
+        };
+
+        if index == 0 {
+            writer.write_all(header.as_bytes()).await.context("write")?;
+            writer.write_all(b"\n").await.context("write")?;
+        }
+
+        while let Some(line) = lines.next_line().await.context("read asciicast event")? {
+            let Ok(mut event) = serde_json::from_str::<Vec<serde_json::Value>>(&line) else {
+                continue;
+            };
+
+            let Some(time) = event.first().and_then(serde_json::Value::as_f64) else {
+                continue;
+            };
+
+            // Events are kept in order, even when the clock went backward between two files.
+            last_time = last_time.max(offset + time);
+            event[0] = serde_json::Value::from(last_time);
+
+            let mut line = serde_json::to_vec(&event).context("serialize asciicast event")?;
+            line.push(b'\n');
+            writer.write_all(&line).await.context("write")?;
+        }
+    }
+
+    Ok(())
+}
+
+mod ebml {
+    //! Minimal reading and writing of the EBML elements used by WebM
+    //!
+    //! https://www.matroska.org/technical/elements.html
+
+    use anyhow::Context as _;
+    use tokio::io::{self, AsyncRead, AsyncReadExt as _};
+
+    use super::MAX_ELEMENT_SIZE;
+
+    pub(super) const EBML_HEADER: u32 = 0x1A45_DFA3;
+    pub(super) const SEGMENT: u32 = 0x1853_8067;
+    pub(super) const SEEK_HEAD: u32 = 0x114D_9B74;
+    pub(super) const INFO: u32 = 0x1549_A966;
+    pub(super) const TIMECODE_SCALE: u32 = 0x2A_D7B1;
+    pub(super) const DURATION: u32 = 0x4489;
+    pub(super) const TRACKS: u32 = 0x1654_AE6B;
+    pub(super) const TRACK_ENTRY: u32 = 0xAE;
+    pub(super) const TRACK_NUMBER: u32 = 0xD7;
+    pub(super) const TRACK_TYPE: u32 = 0x83;
+    pub(super) const CODEC_ID: u32 = 0x86;
+    pub(super) const CLUSTER: u32 = 0x1F43_B675;
+    pub(super) const CLUSTER_TIMECODE: u32 = 0xE7;
+    pub(super) const CLUSTER_POSITION: u32 = 0xA7;
+    pub(super) const CLUSTER_PREV_SIZE: u32 = 0xAB;
+    pub(super) const SIMPLE_BLOCK: u32 = 0xA3;
+    pub(super) const BLOCK_GROUP: u32 = 0xA0;
+    pub(super) const BLOCK: u32 = 0xA1;
+    pub(super) const CUES: u32 = 0x1C53_BB6B;
+    pub(super) const CHAPTERS: u32 = 0x1043_A770;
+    pub(super) const TAGS: u32 = 0x1254_C367;
+    pub(super) const ATTACHMENTS: u32 = 0x1941_A469;
+
+    /// Children of the segment, ending a cluster of unknown size
+    const SEGMENT_CHILDREN: [u32; 8] = [SEEK_HEAD, INFO, TRACKS, CLUSTER, CUES, CHAPTERS, TAGS, ATTACHMENTS];
+
+    /// Size of an element whose size is unknown, as written by live encoders
+    pub(super) const UNKNOWN_SIZE: [u8; 8] = [0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
+
+    #[derive(Debug, Clone, Copy)]
+    pub(super) struct ElementHeader {
+        pub(super) id: u32,
+        /// `None` when the size is unknown
+        pub(super) size: Option<u64>,
+    }
+
+    pub(super) struct EbmlReader<R> {
+        reader: R,
+        peeked: Option<ElementHeader>,
+    }
+
+    impl<R: AsyncRead + Unpin> EbmlReader<R> {
+        pub(super) fn new(reader: R) -> Self {
+            Self { reader, peeked: None }
+        }
+
+        /// Reads the header of the next element, or returns `None` at the end of the stream.
+        pub(super) async fn next_header(&mut self) -> anyhow::Result<Option<ElementHeader>> {
+            if let Some(header) = self.peeked.take() {
+                return Ok(Some(header));
+            }
+
+            let first = match self.reader.read_u8().await {
+                Ok(byte) => byte,
+                Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                Err(error) => return Err(anyhow::Error::new(error).context("read element ID")),
+            };
+
+            let len = first.leading_zeros() + 1;
+            anyhow::ensure!(len <= 4, "invalid element ID");
+
+            let mut id = u32::from(first);
+
+            for _ in 1..len {
+                id = (id << 8) | u32::from(self.reader.read_u8().await.context("read element ID")?);
+            }
+
+            let first = self.reader.read_u8().await.context("read element size")?;
+            let len = first.leading_zeros() + 1;
+            anyhow::ensure!(len <= 8, "invalid element size");
+
+            let mask = 0xFF_u8.checked_shr(len).unwrap_or(0);
+            let mut size = u64::from(first & mask);
+            let mut is_unknown = first & mask == mask;
+
+            for _ in 1..len {
+                let byte = self.reader.read_u8().await.context("read element size")?;
+                is_unknown &= byte == 0xFF;
+                size = (size << 8) | u64::from(byte);
+            }
+
+            Ok(Some(ElementHeader {
+                id,
+                size: (!is_unknown).then_some(size),
+            }))
+        }
+
+        pub(super) async fn read_data(&mut self, header: ElementHeader) -> anyhow::Result<Vec<u8>> {
+            let size = header.size.context("element of unknown size")?;
+            anyhow::ensure!(size <= MAX_ELEMENT_SIZE, "element too large ({size} bytes)");
+
+            let mut data = vec![0; usize::try_from(size)?];
+            self.reader.read_exact(&mut data).await.context("read element")?;
+
+            Ok(data)
+        }
+
+        pub(super) async fn skip(&mut self, header: ElementHeader) -> anyhow::Result<()> {
+            let size = header.size.context("element of unknown size")?;
+
+            let skipped = io::copy(&mut (&mut self.reader).take(size), &mut io::sink())
+                .await
+                .context("skip element")?;
+
+            anyhow::ensure!(skipped == size, "truncated element");
+
+            Ok(())
+        }
+
+        /// Reads the children of an element of unknown size, which ends with the next element of the segment.
+        pub(super) async fn read_unsized_children(&mut self) -> anyhow::Result<Vec<(u32, Vec<u8>)>> {
+            let mut children = Vec::new();
+
+            while let Some(header) = self.next_header().await? {
+                if SEGMENT_CHILDREN.contains(&header.id) {
+                    self.peeked = Some(header);
+                    break;
+                }
+
+                children.push((header.id, self.read_data(header).await?));
+            }
+
+            Ok(children)
+        }
+    }
+
+    fn read_vint(data: &mut &[u8], keep_marker: bool) -> anyhow::Result<u64> {
+        let first = *data.first().context("truncated element")?;
+        let len = usize::try_from(first.leading_zeros() + 1)?;
+        anyhow::ensure!(len <= 8 && data.len() >= len, "invalid variable size integer");
+
+        let first = if keep_marker {
+            first
+        } else {
+            first & 0xFF_u8.checked_shr(u32::try_from(len)?).unwrap_or(0)
+        };
+
+        let value = data[1..len]
+            .iter()
+            .fold(u64::from(first), |value, byte| (value << 8) | u64::from(*byte));
+
+        *data = &data[len..];
+
+        Ok(value)
+    }
+
+    /// Splits the data of a master element into its children.
+    pub(super) fn parse_children(mut data: &[u8]) -> anyhow::Result<Vec<(u32, &[u8])>> {
+        let mut children = Vec::new();
+
+        while !data.is_empty() {
+            let id = u32::try_from(read_vint(&mut data, true)?).context("invalid element ID")?;
+            let size = usize::try_from(read_vint(&mut data, false)?)?;
+            anyhow::ensure!(size <= data.len(), "truncated element");
+
+            children.push((id, &data[..size]));
+            data = &data[size..];
+        }
+
+        Ok(children)
+    }
+
+    pub(super) fn read_uint(data: &[u8]) -> anyhow::Result<u64> {
+        anyhow::ensure!(data.len() <= 8, "invalid unsigned integer");
+        Ok(data.iter().fold(0, |value, byte| (value << 8) | u64::from(*byte)))
+    }
+
+    /// Returns the timecode of a block, relative to the timecode of its cluster.
+    pub(super) fn block_timecode(mut data: &[u8]) -> anyhow::Result<i16> {
+        let _track_number = read_vint(&mut data, false)?;
+        let timecode = data.get(..2).context("truncated block")?;
+        Ok(i16::from_be_bytes([timecode[0], timecode[1]]))
+    }
+
+    pub(super) fn encode_uint(value: u64) -> Vec<u8> {
+        let bytes = value.to_be_bytes();
+        let skip = bytes.iter().take_while(|byte| **byte == 0).count().min(7);
+        bytes[skip..].to_vec()
+    }
+
+    pub(super) fn encode_id(id: u32) -> Vec<u8> {
+        let bytes = id.to_be_bytes();
+        let skip = bytes.iter().take_while(|byte| **byte == 0).count().min(3);
+        bytes[skip..].to_vec()
+    }
+
+    fn encode_size(size: u64) -> Vec<u8> {
+        // A size with all its bits set means “unknown”, hence the 2^(7 × len) - 1 upper bound.
+        let len = (1..=8_u32).find(|len| size < (1 << (7 * len)) - 1).unwrap_or(8);
+        let bytes = (size | (1 << (7 * len))).to_be_bytes();
+        bytes[8 - usize::try_from(len).unwrap_or(8)..].to_vec()
+    }
+
+    pub(super) fn encode_element(id: u32, data: &[u8]) -> Vec<u8> {
+        let mut element = encode_id(id);
+        element.extend_from_slice(&encode_size(data.len() as u64));
+        element.extend_from_slice(data);
+        element
+    }
+}
+
+/// Remuxes WebM files into a single segment.
+///
+/// The header, info and tracks of the first file are kept, and the clusters of every file are appended with their
+/// timecodes shifted. The files must have the same tracks. Seeking information (seek head and cues) is dropped.
+async fn merge_webm<W>(inputs: Vec<MergeInput>, writer: &mut W) -> anyhow::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    use self::ebml::*;
+
+    const DEFAULT_TIMECODE_SCALE: u64 = 1_000_000;
+
+    let mut timecode_scale = None;
+    let mut tracks_summary = None;
+    // End of the last block written, in ticks of the timecode scale
+    let mut end_timecode: u64 = 0;
+
+    for (index, MergeInput { reader, offset_ms }) in inputs.into_iter().enumerate() {
+        let is_first_file = index == 0;
+        let mut reader = EbmlReader::new(reader);
+
+        let header = reader.next_header().await?.context("empty file")?;
+        anyhow::ensure!(header.id == EBML_HEADER, "not a WebM file");
+        let ebml_header = reader.read_data(header).await?;
+
+        let header = reader.next_header().await?.context("missing segment")?;
+        anyhow::ensure!(header.id == SEGMENT, "missing segment");
+
+        if is_first_file {
+            writer.write_all(&encode_element(EBML_HEADER, &ebml_header)).await?;
+            writer.write_all(&encode_id(SEGMENT)).await?;
+            writer.write_all(&UNKNOWN_SIZE).await?;
+        }
+
+        let mut file_timecode_scale = DEFAULT_TIMECODE_SCALE;
+        // Shift applied to the timecodes of the clusters of this file
+        let mut shift = None;
+
+        while let Some(header) = reader.next_header().await? {
+            match header.id {
+                INFO => {
+                    let data = reader.read_data(header).await?;
+                    let children = parse_children(&data)?;
+
+                    file_timecode_scale = match children.iter().find(|(id, _)| *id == TIMECODE_SCALE) {
+                        Some((_, value)) => read_uint(value)?,
+                        None => DEFAULT_TIMECODE_SCALE,
+                    };
+
+                    anyhow::ensure!(file_timecode_scale > 0, "invalid timecode scale");
+
+                    match timecode_scale {
+                        Some(timecode_scale) => anyhow::ensure!(
+                            timecode_scale == file_timecode_scale,
+                            "the recording files have different timecode scales"
+                        ),
+                        None => timecode_scale = Some(file_timecode_scale),
+                    }
+
+                    if is_first_file {
+                        // The duration of the first file is not the duration of the merged file.
+                        let info: Vec<u8> = children
+                            .into_iter()
+                            .filter(|(id, _)| *id != DURATION)
+                            .flat_map(|(id, value)| encode_element(id, value))
+                            .collect();
+
+                        writer.write_all(&encode_element(INFO, &info)).await?;
+                    }
+                }
+                TRACKS => {
+                    let data = reader.read_data(header).await?;
+                    let summary = summarize_tracks(&data)?;
+
+                    match &tracks_summary {
+                        Some(tracks_summary) => {
+                            anyhow::ensure!(*tracks_summary == summary, "the recording files have different tracks")
+                        }
+                        None => tracks_summary = Some(summary),
+                    }
+
+                    if is_first_file {
+                        writer.write_all(&encode_element(TRACKS, &data)).await?;
+                    }
+                }
+                CLUSTER => {
+                    let children = match header.size {
+                        Some(_) => {
+                            let data = reader.read_data(header).await?;
+                            parse_children(&data)?
+                                .into_iter()
+                                .map(|(id, value)| (id, value.to_vec()))
+                                .collect()
+                        }
+                        None => reader.read_unsized_children().await?,
+                    };
+
+                    let timecode = match children.iter().find(|(id, _)| *id == CLUSTER_TIMECODE) {
+                        Some((_, value)) => read_uint(value)?,
+                        None => anyhow::bail!("cluster without timecode"),
+                    };
+
+                    let shift = *shift.get_or_insert_with(|| {
+                        let offset = offset_ms.saturating_mul(1_000_000) / file_timecode_scale;
+                        offset.max(end_timecode.saturating_sub(timecode))
+                    });
+
+                    let timecode = timecode.saturating_add(shift);
+
+                    let mut cluster = encode_element(CLUSTER_TIMECODE, &encode_uint(timecode));
+
+                    for (id, value) in &children {
+                        match *id {
+                            // Positions and sizes of the previous clusters are not valid anymore.
+                            CLUSTER_TIMECODE | CLUSTER_POSITION | CLUSTER_PREV_SIZE => continue,
+                            SIMPLE_BLOCK => end_timecode = end_timecode.max(block_end(timecode, value)?),
+                            BLOCK_GROUP => {
+                                for (child_id, child) in parse_children(value)? {
+                                    if child_id == BLOCK {
+                                        end_timecode = end_timecode.max(block_end(timecode, child)?);
+                                    }
+                                }
+                            }
+                            _ => {}
+                        }
+
+                        cluster.extend_from_slice(&encode_element(*id, value));
+                    }
+
+                    writer.write_all(&encode_element(CLUSTER, &cluster)).await?;
+                }
+                _ => reader.skip(header).await?,
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the timecode right after the given block.
+fn block_end(cluster_timecode: u64, block: &[u8]) -> anyhow::Result<u64> {
+    let relative = ebml::block_timecode(block)?;
+    Ok(cluster_timecode.saturating_add_signed(i64::from(relative)) + 1)
+}
+
+/// Number, type and codec of each track
+fn summarize_tracks(data: &[u8]) -> anyhow::Result<Vec<(u64, u64, Vec<u8>)>> {
+    let mut summary = Vec::new();
+
+    for (id, entry) in ebml::parse_children(data)? {
+        if id != ebml::TRACK_ENTRY {
+            continue;
+        }
+
+        let mut track = (0, 0, Vec::new());
+
+        for (id, value) in ebml::parse_children(entry)? {
+            match id {
+                ebml::TRACK_NUMBER => track.0 = ebml::read_uint(value)?,
+                ebml::TRACK_TYPE => track.1 = ebml::read_uint(value)?,
+                ebml::CODEC_ID => track.2 = value.to_vec(),
+                _ => {}
+            }
+        }
+
+        summary.push(track);
+    }
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ebml::*;
+    use super::*;
+
+    fn input(data: Vec<u8>, offset_ms: u64) -> MergeInput {
+        MergeInput {
+            reader: Box::new(std::io::Cursor::new(data)),
+            offset_ms,
+        }
+    }
+
+    fn webm_file(block_timecodes: &[i16]) -> Vec<u8> {
+        let track = encode_element(
+            TRACK_ENTRY,
+            &[
+                encode_element(TRACK_NUMBER, &[1]),
+                encode_element(TRACK_TYPE, &[1]),
+                encode_element(CODEC_ID, b"V_VP8"),
+            ]
+            .concat(),
+        );
+
+        let mut file = encode_element(EBML_HEADER, &encode_element(0x4282, b"webm"));
+        file.extend_from_slice(&encode_id(SEGMENT));
+        file.extend_from_slice(&UNKNOWN_SIZE);
+        file.extend_from_slice(&encode_element(
+            INFO,
+            &[
+                encode_element(TIMECODE_SCALE, &encode_uint(1_000_000)),
+                encode_element(DURATION, &[0; 8]),
+            ]
+            .concat(),
+        ));
+        file.extend_from_slice(&encode_element(TRACKS, &track));
+
+        // Cluster of unknown size, as written by browsers.
+        file.extend_from_slice(&encode_id(CLUSTER));
+        file.extend_from_slice(&UNKNOWN_SIZE);
+        file.extend_from_slice(&encode_element(CLUSTER_TIMECODE, &encode_uint(0)));
+
+        for timecode in block_timecodes {
+            let mut block = vec![0x81];
+            block.extend_from_slice(&timecode.to_be_bytes());
+            block.extend_from_slice(&[0x80, 0xAA, 0xBB]);
+            file.extend_from_slice(&encode_element(SIMPLE_BLOCK, &block));
+        }
+
+        file.extend_from_slice(&encode_element(CUES, &[]));
+
+        file
+    }
+
+    #[tokio::test]
+    async fn webm_clusters_are_shifted() {
+        let mut merged = Vec::new();
+
+        merge_webm(
+            vec![input(webm_file(&[0, 40]), 0), input(webm_file(&[0, 40]), 5000)],
+            &mut merged,
+        )
+        .await
+        .unwrap();
+
+        let ebml_header = encode_element(EBML_HEADER, &encode_element(0x4282, b"webm"));
+        assert!(merged.starts_with(&ebml_header));
+
+        let segment_header = [encode_id(SEGMENT), UNKNOWN_SIZE.to_vec()].concat();
+        assert!(merged[ebml_header.len()..].starts_with(&segment_header));
+
+        let mut reader = EbmlReader::new(&merged[ebml_header.len() + segment_header.len()..]);
+
+        let mut cluster_timecodes = Vec::new();
+        let mut ids = Vec::new();
+
+        while let Some(header) = reader.next_header().await.unwrap() {
+            let data = reader.read_data(header).await.unwrap();
+            ids.push(header.id);
+
+            match header.id {
+                INFO => assert!(parse_children(&data).unwrap().iter().all(|(id, _)| *id != DURATION)),
+                CLUSTER => {
+                    let children = parse_children(&data).unwrap();
+                    assert_eq!(children[0].0, CLUSTER_TIMECODE);
+                    cluster_timecodes.push(read_uint(children[0].1).unwrap());
+                    assert_eq!(children.iter().filter(|(id, _)| *id == SIMPLE_BLOCK).count(), 2);
+                }
+                _ => {}
+            }
+        }
+
+        assert_eq!(ids, [INFO, TRACKS, CLUSTER, CLUSTER]);
+        assert_eq!(cluster_timecodes, [0, 5000]);
+    }
+
+    #[tokio::test]
+    async fn webm_with_different_tracks_is_refused() {
+        let mut other = webm_file(&[0]);
+        let position = other.windows(5).position(|window| window == b"V_VP8").unwrap();
+        other[position..position + 5].copy_from_slice(b"V_VP9");
+
+        let mut merged = Vec::new();
+        let result = merge_webm(vec![input(webm_file(&[0]), 0), input(other, 1000)], &mut merged).await;
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn trp_timestamps_are_continuous() {
+        fn trp_file(events: &[(u32, &[u8])]) -> Vec<u8> {
+            let mut file = Vec::new();
+
+            for (delta, payload) in events {
+                file.extend_from_slice(&delta.to_le_bytes());
+                file.extend_from_slice(&0u16.to_le_bytes());
+                file.extend_from_slice(&u16::try_from(payload.len()).unwrap().to_le_bytes());
+                file.extend_from_slice(payload);
+            }
+
+            file
+        }
+
+        let mut merged = Vec::new();
+
+        merge_trp(
+            vec![
+                input(trp_file(&[(100, b"a"), (200, b"b")]), 0),
+                input(trp_file(&[(50, b"c"), (10, b"d")]), 2000),
+            ],
+            &mut merged,
+        )
+        .await
+        .unwrap();
+
+        let deltas: Vec<u32> = merged
+            .chunks(TRP_EVENT_HEADER_SIZE + 1)
+            .map(|event| u32::from_le_bytes([event[0], event[1], event[2], event[3]]))
+            .collect();
+
+        // The first event of the second file happens 2050 ms after the start of the session.
+        assert_eq!(deltas, [100, 200, 1750, 10]);
+    }
+}
@@ -0,0 +1,1346 @@
+use core::fmt;
+use std::cmp;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::future::Future;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::Context as _;
+use async_trait::async_trait;
+use camino::{Utf8Path, Utf8PathBuf};
+use devolutions_gateway_task::{ChildTask, ShutdownSignal, Task};
+use parking_lot::Mutex;
+use serde::Serialize;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, BufWriter};
+use tokio::sync::{mpsc, oneshot};
+use tokio::{fs, io};
+use typed_builder::TypedBuilder;
+use uuid::Uuid;
+
+use crate::config::ConfHandle;
+use crate::session::{ConnectionModeDetails, SessionInfo, SessionMessageSender};
+use crate::subscriber::SubscriberSender;
+use crate::token::{JrecTokenClaims, RecordingFileType};
+
+mod archive;
+mod encryption;
+mod file;
+mod free_space;
+mod index;
+mod integrity;
+mod merge;
+mod retention;
+mod search;
+mod shadow;
+mod storage;
+mod stored;
+
+pub(crate) use archive::{export_recording, import_recording, ImportError};
+pub(crate) use file::RecordingFileReader;
+pub use free_space::{RecordingStorageMonitor, RecordingStorageState, RecordingStorageStatus};
+pub(crate) use index::RecordingQuery;
+pub use index::{RecordingCursor, RecordingIndex, RecordingInfo, RecordingSortKey, SortOrder};
+pub(crate) use integrity::verify_recording;
+pub use integrity::{FileIntegrity, FileIntegrityStatus, RecordingIntegrity, SignatureStatus};
+pub(crate) use merge::{merge_recording, MergeError};
+pub use retention::RecordingRetentionTask;
+pub(crate) use search::search_recording;
+pub use search::{RecordingSearchResult, SearchHit};
+pub(crate) use shadow::shadow_recording;
+pub(crate) use storage::{recording_storage, FetchedFiles};
+pub use storage::{LocalStorage, RecordingStorage, S3Storage};
+pub use stored::StoredRecordingIndexTask;
+
+const DISCONNECTED_TTL_SECS: i64 = 10;
+const DISCONNECTED_TTL_DURATION: tokio::time::Duration = tokio::time::Duration::from_secs(DISCONNECTED_TTL_SECS as u64);
+
+/// Interval between two checks of the free space on the recording volume.
+const STORAGE_CHECK_INTERVAL: tokio::time::Duration = tokio::time::Duration::from_secs(60);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JrecFile {
+    file_name: String,
+    start_time: i64,
+    duration: i64,
+    /// SHA-256 hash of the file, computed once the client disconnects
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sha256: Option<String>,
+    /// Link of the hash chain for this file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    chain_hash: Option<String>,
+    /// Size of the file in bytes, recorded once the client disconnects
+    ///
+    /// The file itself may only be held by the storage backend afterwards.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    size: Option<u64>,
+    /// Times the client reconnected and resumed the upload of this file
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    resumes: Vec<JrecFileResume>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JrecFileResume {
+    /// Unix timestamp at which the upload was resumed
+    time: i64,
+    /// Offset in the file at which the upload was resumed, in bytes
+    offset: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JrecManifest {
+    session_id: Uuid,
+    start_time: i64,
+    duration: i64,
+    files: Vec<JrecFile>,
+    /// Information about the recorded session, when known by this instance
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    session: Option<JrecSessionMetadata>,
+    /// Encryption information, for recordings encrypted at rest
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    encryption: Option<encryption::JrecEncryption>,
+    /// Signature of the manifest, in JWS compact form
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    signature: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JrecSessionMetadata {
+    application_protocol: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    destination_host: Option<String>,
+}
+
+impl From<SessionInfo> for JrecSessionMetadata {
+    fn from(info: SessionInfo) -> Self {
+        let destination_host = match info.mode_details {
+            ConnectionModeDetails::Rdv => None,
+            ConnectionModeDetails::Fwd { destination_host } => Some(destination_host.to_string()),
+        };
+
+        Self {
+            application_protocol: info.application_protocol.to_string(),
+            destination_host,
+        }
+    }
+}
+
+impl JrecManifest {
+    fn read_from_file(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let json = std::fs::read(path)?;
+        let manifest = serde_json::from_slice(&json)?;
+        Ok(manifest)
+    }
+
+    fn save_to_file(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let json = serde_json::to_string_pretty(&self)?;
+        std::fs::write(path, json)?;
+        Ok(())
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    use std::fmt::Write as _;
+
+    bytes
+        .iter()
+        .fold(String::with_capacity(bytes.len() * 2), |mut acc, byte| {
+            let _ = write!(acc, "{byte:02x}");
+            acc
+        })
+}
+
+fn from_hex(hex: &str) -> anyhow::Result<Vec<u8>> {
+    anyhow::ensure!(hex.len() % 2 == 0, "odd number of hexadecimal digits");
+
+    hex.as_bytes()
+        .chunks(2)
+        .map(|digits| {
+            let digits = std::str::from_utf8(digits).context("invalid character")?;
+            u8::from_str_radix(digits, 16).context("invalid hexadecimal digit")
+        })
+        .collect()
+}
+
+/// Suffix of the folders holding recordings being removed.
+const REMOVED_RECORDING_SUFFIX: &str = ".removed";
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum RemoveError {
+    #[error("recording {0} not found")]
+    NotFound(Uuid),
+    #[error("recording {0} is ongoing")]
+    Ongoing(Uuid),
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+/// Removes a recording folder from the disk, and the recording from the storage backend.
+///
+/// The folder is renamed while the active recordings are locked, so the recording can't start meanwhile,
+/// and disappears at once from the recordings folder even if the removal itself is interrupted midway.
+/// When the storage backend fails, the folder is put back so the recording stays listed and the removal can be retried.
+/// A recording only held by the storage backend, without a folder on this instance, is removed too.
+pub(crate) async fn remove_recording_folder(
+    recordings_path: &Utf8Path,
+    id: Uuid,
+    active_recordings: &ActiveRecordings,
+    index: &RecordingIndex,
+    storage: &dyn RecordingStorage,
+) -> Result<(), RemoveError> {
+    let recording_path = recordings_path.join(id.to_string());
+    let removed_path = recordings_path.join(format!(".{id}.{}{REMOVED_RECORDING_SUFFIX}", Uuid::new_v4()));
+
+    let has_folder = match active_recordings.rename_if_inactive(id, &recording_path, &removed_path) {
+        Ok(()) => true,
+        Err(RemoveError::NotFound(_)) => false,
+        Err(error) => return Err(error),
+    };
+
+    let is_stored = match storage.delete(id).await {
+        Ok(is_stored) => is_stored,
+        Err(error) => {
+            if has_folder {
+                if let Err(error) = active_recordings.rename_if_inactive(id, &removed_path, &recording_path) {
+                    warn!(%id, %error, "Failed to restore the folder of a recording which couldn't be deleted");
+                }
+            }
+
+            return Err(RemoveError::Other(error.context("delete from the storage backend")));
+        }
+    };
+
+    index.remove(id);
+
+    if !has_folder {
+        return if is_stored {
+            Ok(())
+        } else {
+            Err(RemoveError::NotFound(id))
+        };
+    }
+
+    fs::remove_dir_all(&removed_path)
+        .await
+        .with_context(|| format!("failed to remove {removed_path}"))
+        .map_err(RemoveError::Other)?;
+
+    Ok(())
+}
+
+/// Copies the data from the reader to the writer, encrypting it on the way.
+///
+/// The file is always finalized by writing the last chunk, including when the shutdown signal is received
+/// or when reading fails, so it can be told apart from a truncated file.
+async fn copy_encrypted<R, W>(
+    reader: &mut R,
+    writer: &mut W,
+    mut encryptor: encryption::ChunkEncryptor,
+    shutdown_signal: &mut ShutdownSignal,
+) -> io::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    use tokio::io::AsyncReadExt as _;
+
+    let mut buf = vec![0; 16 * 1024];
+    let mut out = Vec::new();
+
+    let shutdown_signal = shutdown_signal.wait();
+    tokio::pin!(shutdown_signal);
+
+    // Only the reads are interrupted, so a chunk is never partially written.
+    let read_result = loop {
+        let n = tokio::select! {
+            res = reader.read(&mut buf) => match res {
+                Ok(0) => break Ok(()),
+                Ok(n) => n,
+                Err(e) => break Err(e),
+            },
+            _ = &mut shutdown_signal => {
+                trace!("Received shutdown signal");
+                break Ok(());
+            },
+        };
+
+        encryptor
+            .update(&buf[..n], &mut out)
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        writer.write_all(&out).await?;
+        out.clear();
+    };
+
+    encryptor
+        .finish(&mut out)
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    writer.write_all(&out).await?;
+
+    read_result
+}
+
+#[derive(TypedBuilder)]
+pub struct ClientPush<S> {
+    recordings: RecordingMessageSender,
+    claims: JrecTokenClaims,
+    client_stream: S,
+    file_type: RecordingFileType,
+    session_id: Uuid,
+    shutdown_signal: ShutdownSignal,
+    /// Offset at which the upload of the last recording file is resumed, if any
+    #[builder(default)]
+    resume_offset: Option<u64>,
+}
+
+impl<S> ClientPush<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    pub async fn run(self) -> anyhow::Result<()> {
+        let Self {
+            recordings,
+            claims,
+            mut client_stream,
+            file_type,
+            session_id,
+            mut shutdown_signal,
+            resume_offset,
+        } = self;
+
+        if session_id != claims.jet_aid {
+            anyhow::bail!("inconsistent session ID (ID in token: {})", claims.jet_aid);
+        }
+
+        let ConnectedRecordingFile {
+            path: recording_file,
+            encryptor,
+            append,
+        } = match recordings.connect(session_id, file_type, resume_offset).await {
+            Ok(recording_file) => recording_file,
+            Err(e) => {
+                warn!(error = format!("{e:#}"), "Unable to start recording");
+                client_stream.shutdown().await.context("shutdown")?;
+                return Ok(());
+            }
+        };
+
+        debug!(path = %recording_file, "Opening file");
+
+        let res = match fs::OpenOptions::new()
+            .read(false)
+            .write(true)
+            .append(append)
+            .create(true)
+            .open(&recording_file)
+            .await
+        {
+            Ok(file) => {
+                let mut file = BufWriter::new(file);
+
+                let res = match encryptor {
+                    Some(encryptor) => copy_encrypted(&mut client_stream, &mut file, encryptor, &mut shutdown_signal)
+                        .await
+                        .context("JREC streaming to file"),
+                    None => tokio::select! {
+                        res = io::copy(&mut client_stream, &mut file) => {
+                            res.map(|_| ()).context("JREC streaming to file")
+                        },
+                        _ = shutdown_signal.wait() => {
+                            trace!("Received shutdown signal");
+                            client_stream.shutdown().await.context("shutdown")
+                        },
+                    },
+                };
+
+                res.and(file.flush().await.context("flush recording file"))
+            }
+            Err(e) => Err(anyhow::Error::new(e).context(format!("failed to open file at {recording_file}"))),
+        };
+
+        let sha256 = match integrity::hash_file(recording_file.clone()).await {
+            Ok(sha256) => Some(sha256),
+            Err(e) => {
+                warn!(error = format!("{e:#}"), path = %recording_file, "Failed to hash recording file");
+                None
+            }
+        };
+
+        recordings.disconnect(session_id, sha256).await.context("disconnect")?;
+
+        res
+    }
+}
+
+/// Writes a recording file produced by the gateway itself, rather than pushed by a client.
+///
+/// The file is registered with the recording manager exactly like a pushed one, so it's listed
+/// in the manifest, encrypted and hashed the same way, and satisfies the recording policy.
+pub(crate) struct RecordingWriter {
+    recordings: RecordingMessageSender,
+    session_id: Uuid,
+    recording_file: Utf8PathBuf,
+    file: BufWriter<fs::File>,
+    encryptor: Option<encryption::ChunkEncryptor>,
+    out: Vec<u8>,
+}
+
+impl RecordingWriter {
+    pub(crate) async fn start(
+        recordings: RecordingMessageSender,
+        session_id: Uuid,
+        file_type: RecordingFileType,
+    ) -> anyhow::Result<Self> {
+        let ConnectedRecordingFile {
+            path: recording_file,
+            encryptor,
+            ..
+        } = recordings.connect(session_id, file_type, None).await?;
+
+        debug!(path = %recording_file, "Opening file");
+
+        let file = match fs::OpenOptions::new()
+            .read(false)
+            .write(true)
+            .create(true)
+            .open(&recording_file)
+            .await
+        {
+            Ok(file) => file,
+            Err(e) => {
+                let _ = recordings.disconnect(session_id, None).await;
+                return Err(anyhow::Error::new(e).context(format!("failed to open file at {recording_file}")));
+            }
+        };
+
+        Ok(Self {
+            recordings,
+            session_id,
+            recording_file,
+            file: BufWriter::new(file),
+            encryptor,
+            out: Vec::new(),
+        })
+    }
+
+    pub(crate) async fn write_all(&mut self, data: &[u8]) -> anyhow::Result<()> {
+        match &mut self.encryptor {
+            Some(encryptor) => {
+                encryptor.update(data, &mut self.out)?;
+                self.file.write_all(&self.out).await?;
+                self.out.clear();
+            }
+            None => self.file.write_all(data).await?,
+        }
+
+        Ok(())
+    }
+
+    /// Flushes the file and notifies the recording manager that the recording file is complete.
+    pub(crate) async fn finish(mut self) -> anyhow::Result<()> {
+        let res = async {
+            if let Some(encryptor) = self.encryptor.take() {
+                encryptor.finish(&mut self.out)?;
+                self.file.write_all(&self.out).await?;
+            }
+
+            self.file.flush().await.context("flush recording file")
+        }
+        .await;
+
+        let sha256 = match integrity::hash_file(self.recording_file.clone()).await {
+            Ok(sha256) => Some(sha256),
+            Err(e) => {
+                warn!(error = format!("{e:#}"), path = %self.recording_file, "Failed to hash recording file");
+                None
+            }
+        };
+
+        self.recordings
+            .disconnect(self.session_id, sha256)
+            .await
+            .context("disconnect")?;
+
+        res
+    }
+}
+
+/// A set containing IDs of currently active recordings.
+///
+/// The ID is inserted at the initial recording
+///
+/// The purpose of this set is to provide a quick way of checking if a recording
+/// is on-going for a given session ID in non-async context.
+/// If you are looking for the the detailled recording state, you can use the
+/// the `get_state` method provided by `RecordingMessageSender`.
+#[derive(Debug, Default)]
+pub struct ActiveRecordings(Mutex<HashSet<Uuid>>);
+
+impl ActiveRecordings {
+    pub fn contains(&self, id: Uuid) -> bool {
+        self.0.lock().contains(&id)
+    }
+
+    pub(crate) fn insert(&self, id: Uuid) -> usize {
+        let mut guard = self.0.lock();
+        guard.insert(id);
+        guard.len()
+    }
+
+    fn remove(&self, id: Uuid) {
+        self.0.lock().remove(&id);
+    }
+
+    /// Runs an operation on the folder of a recording, unless the recording is active.
+    ///
+    /// The set stays locked during the operation, so the recording can't start in the meantime.
+    fn with_inactive<T>(&self, id: Uuid, operation: impl FnOnce() -> T) -> Option<T> {
+        let guard = self.0.lock();
+
+        if guard.contains(&id) {
+            return None;
+        }
+
+        Some(operation())
+    }
+
+    /// Renames the folder of a recording, unless the recording is active.
+    fn rename_if_inactive(&self, id: Uuid, from: &Utf8Path, to: &Utf8Path) -> Result<(), RemoveError> {
+        self.with_inactive(id, || match std::fs::rename(from, to) {
+            Ok(()) => Ok(()),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Err(RemoveError::NotFound(id)),
+            Err(error) => Err(RemoveError::Other(
+                anyhow::Error::new(error).context(format!("failed to rename {from} to {to}")),
+            )),
+        })
+        .unwrap_or(Err(RemoveError::Ongoing(id)))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum OnGoingRecordingState {
+    Connected,
+    LastSeen { timestamp: i64 },
+}
+
+#[derive(Debug, Clone)]
+struct OnGoingRecording {
+    state: OnGoingRecordingState,
+    manifest: JrecManifest,
+    manifest_path: Utf8PathBuf,
+    /// Files completed so far, indexed and handed to the storage backend once the recording is terminated
+    completed_files: Vec<String>,
+}
+
+enum RecordingManagerMessage {
+    Connect {
+        id: Uuid,
+        file_type: RecordingFileType,
+        resume_offset: Option<u64>,
+        channel: oneshot::Sender<ConnectedRecordingFile>,
+    },
+    Disconnect {
+        id: Uuid,
+        sha256: Option<String>,
+    },
+    GetState {
+        id: Uuid,
+        channel: oneshot::Sender<Option<OnGoingRecordingState>>,
+    },
+    GetCount {
+        channel: oneshot::Sender<usize>,
+    },
+    GetResumeOffset {
+        id: Uuid,
+        file_type: RecordingFileType,
+        channel: oneshot::Sender<anyhow::Result<u64>>,
+    },
+}
+
+/// Recording file to be written by a client connected to the recording manager
+struct ConnectedRecordingFile {
+    path: Utf8PathBuf,
+    encryptor: Option<encryption::ChunkEncryptor>,
+    /// Whether the data should be appended to the existing file
+    append: bool,
+}
+
+impl fmt::Debug for RecordingManagerMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordingManagerMessage::Connect {
+                id,
+                file_type,
+                resume_offset,
+                channel: _,
+            } => f
+                .debug_struct("Connect")
+                .field("id", id)
+                .field("file_type", file_type)
+                .field("resume_offset", resume_offset)
+                .finish_non_exhaustive(),
+            RecordingManagerMessage::Disconnect { id, sha256 } => f
+                .debug_struct("Disconnect")
+                .field("id", id)
+                .field("sha256", sha256)
+                .finish(),
+            RecordingManagerMessage::GetState { id, channel: _ } => {
+                f.debug_struct("GetState").field("id", id).finish_non_exhaustive()
+            }
+            RecordingManagerMessage::GetCount { channel: _ } => f.debug_struct("GetCount").finish_non_exhaustive(),
+            RecordingManagerMessage::GetResumeOffset {
+                id,
+                file_type,
+                channel: _,
+            } => f
+                .debug_struct("GetResumeOffset")
+                .field("id", id)
+                .field("file_type", file_type)
+                .finish_non_exhaustive(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RecordingMessageSender {
+    channel: mpsc::Sender<RecordingManagerMessage>,
+    pub active_recordings: Arc<ActiveRecordings>,
+    pub index: Arc<RecordingIndex>,
+    pub storage: Arc<RecordingStorageMonitor>,
+}
+
+impl RecordingMessageSender {
+    async fn connect(
+        &self,
+        id: Uuid,
+        file_type: RecordingFileType,
+        resume_offset: Option<u64>,
+    ) -> anyhow::Result<ConnectedRecordingFile> {
+        let (tx, rx) = oneshot::channel();
+        self.channel
+            .send(RecordingManagerMessage::Connect {
+                id,
+                file_type,
+                resume_offset,
+                channel: tx,
+            })
+            .await
+            .ok()
+            .context("couldn't send New message")?;
+        rx.await
+            .context("couldn't receive recording file path for this recording")
+    }
+
+    async fn disconnect(&self, id: Uuid, sha256: Option<String>) -> anyhow::Result<()> {
+        self.channel
+            .send(RecordingManagerMessage::Disconnect { id, sha256 })
+            .await
+            .ok()
+            .context("couldn't send Remove message")
+    }
+
+    pub async fn get_state(&self, id: Uuid) -> anyhow::Result<Option<OnGoingRecordingState>> {
+        let (tx, rx) = oneshot::channel();
+        self.channel
+            .send(RecordingManagerMessage::GetState { id, channel: tx })
+            .await
+            .ok()
+            .context("couldn't send GetState message")?;
+        rx.await.context("couldn't receive recording state")
+    }
+
+    pub async fn get_count(&self) -> anyhow::Result<usize> {
+        let (tx, rx) = oneshot::channel();
+        self.channel
+            .send(RecordingManagerMessage::GetCount { channel: tx })
+            .await
+            .ok()
+            .context("couldn't send GetCount message")?;
+        rx.await.context("couldn't receive ongoing recording count")
+    }
+
+    /// Returns the offset at which the upload of the last recording file can be resumed.
+    ///
+    /// Resuming is possible when the client reconnects shortly after being disconnected,
+    /// for unencrypted recordings whose last file is of the same type.
+    pub async fn get_resume_offset(&self, id: Uuid, file_type: RecordingFileType) -> anyhow::Result<u64> {
+        let (tx, rx) = oneshot::channel();
+        self.channel
+            .send(RecordingManagerMessage::GetResumeOffset {
+                id,
+                file_type,
+                channel: tx,
+            })
+            .await
+            .ok()
+            .context("couldn't send GetResumeOffset message")?;
+        rx.await.context("couldn't receive resume offset")?
+    }
+}
+
+pub struct RecordingMessageReceiver {
+    channel: mpsc::Receiver<RecordingManagerMessage>,
+    active_recordings: Arc<ActiveRecordings>,
+    index: Arc<RecordingIndex>,
+    storage: Arc<RecordingStorageMonitor>,
+}
+
+pub fn recording_message_channel() -> (RecordingMessageSender, RecordingMessageReceiver) {
+    let ongoing_recordings = Arc::new(ActiveRecordings::default());
+    let index = Arc::new(RecordingIndex::default());
+    let storage = Arc::new(RecordingStorageMonitor::default());
+
+    let (tx, rx) = mpsc::channel(64);
+
+    let handle = RecordingMessageSender {
+        channel: tx,
+        active_recordings: ongoing_recordings.clone(),
+        index: index.clone(),
+        storage: storage.clone(),
+    };
+
+    let receiver = RecordingMessageReceiver {
+        channel: rx,
+        active_recordings: ongoing_recordings,
+        index,
+        storage,
+    };
+
+    (handle, receiver)
+}
+
+struct DisconnectedTtl {
+    deadline: tokio::time::Instant,
+    id: Uuid,
+}
+
+impl PartialEq for DisconnectedTtl {
+    fn eq(&self, other: &Self) -> bool {
+        self.deadline.eq(&other.deadline) && self.id.eq(&other.id)
+    }
+}
+
+impl Eq for DisconnectedTtl {}
+
+impl PartialOrd for DisconnectedTtl {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for DisconnectedTtl {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        match self.deadline.cmp(&other.deadline) {
+            cmp::Ordering::Less => cmp::Ordering::Greater,
+            cmp::Ordering::Equal => self.id.cmp(&other.id),
+            cmp::Ordering::Greater => cmp::Ordering::Less,
+        }
+    }
+}
+
+pub struct RecordingManagerTask {
+    rx: RecordingMessageReceiver,
+    ongoing_recordings: HashMap<Uuid, OnGoingRecording>,
+    conf_handle: ConfHandle,
+    sessions: SessionMessageSender,
+    subscriber_tx: SubscriberSender,
+}
+
+impl RecordingManagerTask {
+    pub fn new(
+        rx: RecordingMessageReceiver,
+        conf_handle: ConfHandle,
+        sessions: SessionMessageSender,
+        subscriber_tx: SubscriberSender,
+    ) -> Self {
+        Self {
+            rx,
+            ongoing_recordings: HashMap::new(),
+            conf_handle,
+            sessions,
+            subscriber_tx,
+        }
+    }
+
+    async fn check_storage(&self) -> RecordingStorageStatus {
+        let conf = self.conf_handle.get_conf();
+
+        self.rx
+            .storage
+            .check(
+                conf.recording_path.clone(),
+                conf.recording.free_space_threshold,
+                &self.subscriber_tx,
+            )
+            .await
+    }
+
+    async fn handle_connect(
+        &mut self,
+        id: Uuid,
+        file_type: RecordingFileType,
+        resume_offset: Option<u64>,
+    ) -> anyhow::Result<ConnectedRecordingFile> {
+        // The recording is active before its folder is touched, so the folder can't be removed in the meantime.
+        let was_active = self.rx.active_recordings.contains(id);
+        self.rx.active_recordings.insert(id);
+
+        let result = self.connect_recording(id, file_type, resume_offset).await;
+
+        if result.is_err() && !was_active {
+            self.rx.active_recordings.remove(id);
+        }
+
+        result
+    }
+
+    async fn connect_recording(
+        &mut self,
+        id: Uuid,
+        file_type: RecordingFileType,
+        resume_offset: Option<u64>,
+    ) -> anyhow::Result<ConnectedRecordingFile> {
+        const LENGTH_WARNING_THRESHOLD: usize = 1000;
+
+        if let Some(ongoing) = self.ongoing_recordings.get(&id) {
+            if matches!(ongoing.state, OnGoingRecordingState::Connected) {
+                anyhow::bail!("concurrent recording for the same session is not supported");
+            }
+        }
+
+        let conf = self.conf_handle.get_conf();
+
+        // The status is refreshed periodically, so connecting only waits for the disk when close to the threshold.
+        let storage = if self.rx.storage.is_close_to_threshold(conf.recording.free_space_threshold) {
+            self.check_storage().await
+        } else {
+            self.rx.storage.status()
+        };
+
+        if storage.state == RecordingStorageState::Low {
+            anyhow::bail!(
+                "not enough free space on the recording volume ({} bytes available)",
+                storage.available_space.unwrap_or_default()
+            );
+        }
+
+        if let Some(offset) = resume_offset {
+            return self.handle_resume(id, file_type, offset).await;
+        }
+
+        let recording_path = conf.recording_path.join(id.to_string());
+        let manifest_path = recording_path.join("recording.json");
+
+        let session_metadata = self.fetch_session_metadata(id).await;
+
+        let (manifest, recording_file, encryptor) = if recording_path.exists() {
+            debug!(path = %recording_path, "Recording directory already exists");
+
+            let mut existing_manifest =
+                JrecManifest::read_from_file(&manifest_path).context("read manifest from disk")?;
+            let next_file_idx = existing_manifest.files.len();
+
+            // Subsequent files are encrypted using the same data key as the previous ones.
+            let encryptor = existing_manifest
+                .encryption
+                .as_ref()
+                .map(|encryption| {
+                    encryption::DataKey::unwrap(encryption, id, conf.recording.encryption.as_ref())?
+                        .encryptor(next_file_idx)
+                })
+                .transpose()
+                .context("recording encryption")?;
+
+            let start_time = time::OffsetDateTime::now_utc().unix_timestamp();
+
+            let file_name = format!("recording-{next_file_idx}.{file_type}");
+            let recording_file = recording_path.join(&file_name);
+
+            existing_manifest.files.push(JrecFile {
+                start_time,
+                duration: 0,
+                file_name,
+                sha256: None,
+                chain_hash: None,
+                size: None,
+                resumes: Vec::new(),
+            });
+
+            if existing_manifest.session.is_none() {
+                existing_manifest.session = session_metadata;
+            }
+
+            existing_manifest
+                .save_to_file(&manifest_path)
+                .context("override existing manifest")?;
+
+            (existing_manifest, recording_file, encryptor)
+        } else {
+            debug!(path = %recording_path, "Create recording directory");
+
+            fs::create_dir_all(&recording_path)
+                .await
+                .with_context(|| format!("failed to create recording path: {recording_path}"))?;
+
+            let start_time = time::OffsetDateTime::now_utc().unix_timestamp();
+            let file_name = format!("recording-0.{file_type}");
+            let recording_file = recording_path.join(&file_name);
+
+            let first_file = JrecFile {
+                start_time,
+                duration: 0,
+                file_name,
+                sha256: None,
+                chain_hash: None,
+                size: None,
+                resumes: Vec::new(),
+            };
+
+            let (encryption, encryptor) = match &conf.recording.encryption {
+                Some(encryption_conf) => {
+                    let data_key = encryption::DataKey::generate();
+                    let encryption = data_key.wrap(id, encryption_conf)?;
+                    (Some(encryption), Some(data_key.encryptor(0)?))
+                }
+                None => (None, None),
+            };
+
+            let initial_manifest = JrecManifest {
+                session_id: id,
+                start_time,
+                duration: 0,
+                files: vec![first_file],
+                session: session_metadata,
+                encryption,
+                signature: None,
+            };
+
+            initial_manifest
+                .save_to_file(&manifest_path)
+                .context("write initial manifest to disk")?;
+
+            (initial_manifest, recording_file, encryptor)
+        };
+
+        self.rx.index.update(&recording_path, &manifest);
+
+        let completed_files = self
+            .ongoing_recordings
+            .get(&id)
+            .map(|ongoing| ongoing.completed_files.clone())
+            .unwrap_or_default();
+
+        let active_recording_count = self.rx.active_recordings.insert(id);
+
+        self.ongoing_recordings.insert(
+            id,
+            OnGoingRecording {
+                state: OnGoingRecordingState::Connected,
+                manifest,
+                manifest_path,
+                completed_files,
+            },
+        );
+        let ongoing_recording_count = self.ongoing_recordings.len();
+
+        // Sanity check
+        if active_recording_count > LENGTH_WARNING_THRESHOLD || ongoing_recording_count > LENGTH_WARNING_THRESHOLD {
+            warn!(
+                active_recording_count,
+                ongoing_recording_count,
+                "length threshold exceeded (either the load is very high or the list is growing uncontrollably)"
+            );
+        }
+
+        Ok(ConnectedRecordingFile {
+            path: recording_file,
+            encryptor,
+            append: false,
+        })
+    }
+
+    /// Returns the last recording file, if the client can resume its upload.
+    fn resumable_file(&self, id: Uuid, file_type: RecordingFileType) -> anyhow::Result<Utf8PathBuf> {
+        let ongoing = self
+            .ongoing_recordings
+            .get(&id)
+            .context("no recently disconnected recording for this session")?;
+
+        anyhow::ensure!(
+            matches!(ongoing.state, OnGoingRecordingState::LastSeen { .. }),
+            "the recording is still connected"
+        );
+
+        anyhow::ensure!(
+            ongoing.manifest.encryption.is_none(),
+            "resuming an encrypted recording is not supported"
+        );
+
+        let last_file = ongoing.manifest.files.last().context("no recording file")?;
+
+        anyhow::ensure!(
+            Utf8Path::new(&last_file.file_name).extension() == Some(file_type.as_str()),
+            "the last recording file is not of the requested type"
+        );
+
+        let recording_path = ongoing.manifest_path.parent().context("invalid manifest path")?;
+
+        Ok(recording_path.join(&last_file.file_name))
+    }
+
+    async fn get_resume_offset(&self, id: Uuid, file_type: RecordingFileType) -> anyhow::Result<u64> {
+        let recording_file = self.resumable_file(id, file_type)?;
+
+        let metadata = fs::metadata(&recording_file)
+            .await
+            .with_context(|| format!("failed to read metadata of {recording_file}"))?;
+
+        Ok(metadata.len())
+    }
+
+    async fn handle_resume(
+        &mut self,
+        id: Uuid,
+        file_type: RecordingFileType,
+        offset: u64,
+    ) -> anyhow::Result<ConnectedRecordingFile> {
+        let recording_file = self.resumable_file(id, file_type)?;
+
+        // The data sent by the client must directly follow the data already written.
+        let current_offset = self.get_resume_offset(id, file_type).await?;
+        anyhow::ensure!(
+            current_offset == offset,
+            "resume offset mismatch (expected {current_offset}, got {offset})"
+        );
+
+        let ongoing = self
+            .ongoing_recordings
+            .get_mut(&id)
+            .context("no recently disconnected recording for this session")?;
+
+        let last_file = ongoing.manifest.files.last_mut().context("no recording file")?;
+
+        last_file.resumes.push(JrecFileResume {
+            time: time::OffsetDateTime::now_utc().unix_timestamp(),
+            offset,
+        });
+
+        // The file is hashed again once the client disconnects.
+        last_file.sha256 = None;
+        last_file.chain_hash = None;
+        last_file.size = None;
+
+        ongoing.manifest.signature = None;
+
+        ongoing
+            .manifest
+            .save_to_file(&ongoing.manifest_path)
+            .with_context(|| format!("write manifest at {}", ongoing.manifest_path))?;
+
+        if let Some(recording_path) = ongoing.manifest_path.parent() {
+            self.rx.index.update(recording_path, &ongoing.manifest);
+        }
+
+        ongoing.state = OnGoingRecordingState::Connected;
+        self.rx.active_recordings.insert(id);
+
+        debug!(path = %recording_file, offset, "Resume recording");
+
+        Ok(ConnectedRecordingFile {
+            path: recording_file,
+            encryptor: None,
+            append: true,
+        })
+    }
+
+    async fn fetch_session_metadata(&self, id: Uuid) -> Option<JrecSessionMetadata> {
+        match self.sessions.get_running_sessions().await {
+            Ok(mut running_sessions) => running_sessions.remove(&id).map(JrecSessionMetadata::from),
+            Err(error) => {
+                warn!(%id, error = format!("{error:#}"), "Couldn't retrieve session information");
+                None
+            }
+        }
+    }
+
+    async fn handle_disconnect(&mut self, id: Uuid, sha256: Option<String>) -> anyhow::Result<()> {
+        // The session may not have been registered yet when the recording started.
+        let session_metadata = match self.ongoing_recordings.get(&id) {
+            Some(ongoing) if ongoing.manifest.session.is_none() => self.fetch_session_metadata(id).await,
+            _ => None,
+        };
+
+        if let Some(ongoing) = self.ongoing_recordings.get_mut(&id) {
+            if !matches!(ongoing.state, OnGoingRecordingState::Connected) {
+                anyhow::bail!("a recording not connected can’t be disconnected (there is probably a bug)");
+            }
+
+            let end_time = time::OffsetDateTime::now_utc().unix_timestamp();
+
+            ongoing.state = OnGoingRecordingState::LastSeen { timestamp: end_time };
+
+            let current_file = ongoing
+                .manifest
+                .files
+                .last_mut()
+                .context("no recording file (this is a bug)")?;
+            current_file.duration = end_time - current_file.start_time;
+            current_file.sha256 = sha256;
+            let file_name = current_file.file_name.clone();
+
+            if let Some(recording_path) = ongoing.manifest_path.parent() {
+                match fs::metadata(recording_path.join(&file_name)).await {
+                    Ok(metadata) => current_file.size = Some(metadata.len()),
+                    Err(error) => warn!(%id, %file_name, %error, "Couldn't read the size of the recording file"),
+                }
+            }
+
+            ongoing.manifest.duration = end_time - ongoing.manifest.start_time;
+
+            if ongoing.manifest.session.is_none() {
+                ongoing.manifest.session = session_metadata;
+            }
+
+            ongoing.manifest.update_hash_chain();
+
+            // An unsigned manifest is still better than no manifest at all.
+            if let Some(signing_key) = &self.conf_handle.get_conf().recording.signing_key {
+                if let Err(error) = ongoing.manifest.sign(signing_key) {
+                    error!(%id, error = format!("{error:#}"), "Failed to sign the recording manifest");
+                }
+            }
+
+            debug!(path = %ongoing.manifest_path, "Write updated manifest to disk");
+
+            ongoing
+                .manifest
+                .save_to_file(&ongoing.manifest_path)
+                .with_context(|| format!("write manifest at {}", ongoing.manifest_path))?;
+
+            if let Some(recording_path) = ongoing.manifest_path.parent() {
+                self.rx.index.update(recording_path, &ongoing.manifest);
+            }
+
+            // A resumed file is disconnected more than once.
+            if !ongoing.completed_files.contains(&file_name) {
+                ongoing.completed_files.push(file_name);
+            }
+
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("unknown recording for ID {id}"))
+        }
+    }
+
+    /// Indexes the completed files for search, then hands them to the storage backend.
+    ///
+    /// This only happens once the recording can no longer be resumed, since the storage backend may remove the
+    /// local copy of the files.
+    fn finalize_recording(&self, id: Uuid, ongoing: OnGoingRecording) {
+        ChildTask::spawn(self.finalization(id, ongoing)).detach();
+    }
+
+    fn finalization(&self, id: Uuid, ongoing: OnGoingRecording) -> impl Future<Output = ()> + Send + 'static {
+        let storage = recording_storage(&self.conf_handle.get_conf());
+
+        // Encrypted recordings are not searchable.
+        let recording_path = ongoing
+            .manifest_path
+            .parent()
+            .filter(|_| ongoing.manifest.encryption.is_none())
+            .map(Utf8Path::to_owned);
+
+        async move {
+            for file_name in ongoing.completed_files {
+                if let Some(recording_path) = recording_path.clone() {
+                    if search::is_terminal_file(&file_name) {
+                        let file_name = file_name.clone();
+
+                        let result = tokio::task::spawn_blocking(move || {
+                            search::build_search_index(&recording_path, &file_name)
+                        })
+                        .await
+                        .context("failed to join the indexing task")
+                        .and_then(|result| result);
+
+                        if let Err(error) = result {
+                            warn!(%id, error = format!("{error:#}"), "Failed to index terminal recording");
+                        }
+                    }
+                }
+
+                if let Err(error) = storage.store_file(id, &file_name).await {
+                    error!(%id, %file_name, error = format!("{error:#}"), "Failed to store recording file");
+                }
+            }
+        }
+    }
+
+    async fn handle_remove(&mut self, id: Uuid) {
+        if let Some(ongoing) = self.ongoing_recordings.get(&id) {
+            let now = time::OffsetDateTime::now_utc().unix_timestamp();
+
+            match ongoing.state {
+                // NOTE: Comparing with DISCONNECTED_TTL_SECS - 1 just in case the sleep returns faster than expected.
+                // (I don’t know if this can actually happen in practice, but it’s better to be safe than sorry.)
+                OnGoingRecordingState::LastSeen { timestamp } if now >= timestamp + DISCONNECTED_TTL_SECS - 1 => {
+                    debug!(%id, "Mark recording as terminated");
+                    self.rx.active_recordings.remove(id);
+
+                    if let Some(ongoing) = self.ongoing_recordings.remove(&id) {
+                        self.finalize_recording(id, ongoing);
+                    }
+
+                    // The recording is terminated: now is a good timing to kill sessions that _must_ be recorded.
+                    if let Err(error) = self.sessions.enforce_recording_policy(id).await {
+                        warn!(%id, error = format!("{error:#}"), "Failed to enforce recording policy");
+                    }
+                }
+                _ => {
+                    trace!(%id, "Recording should not be removed yet");
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Task for RecordingManagerTask {
+    type Output = anyhow::Result<()>;
+
+    const NAME: &'static str = "recording manager";
+
+    async fn run(self, shutdown_signal: ShutdownSignal) -> Self::Output {
+        recording_manager_task(self, shutdown_signal).await
+    }
+}
+
+#[instrument(skip_all)]
+async fn recording_manager_task(
+    mut manager: RecordingManagerTask,
+    mut shutdown_signal: ShutdownSignal,
+) -> anyhow::Result<()> {
+    debug!("Task started");
+
+    let rebuild_result = tokio::task::spawn_blocking({
+        let index = Arc::clone(&manager.rx.index);
+        let recordings_path = manager.conf_handle.get_conf().recording_path.clone();
+        move || index.rebuild(&recordings_path)
+    })
+    .await
+    .context("failed to join the indexing task")?;
+
+    match rebuild_result {
+        Ok(count) => debug!(count, "Recording index rebuilt"),
+        Err(error) => error!(error = format!("{error:#}"), "Failed to rebuild recording index"),
+    }
+
+    let mut disconnected = BinaryHeap::<DisconnectedTtl>::new();
+
+    let next_remove_sleep = tokio::time::sleep_until(tokio::time::Instant::now());
+    tokio::pin!(next_remove_sleep);
+
+    // Consume initial sleep
+    (&mut next_remove_sleep).await;
+
+    let mut storage_check_interval = tokio::time::interval(STORAGE_CHECK_INTERVAL);
+    storage_check_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            _ = storage_check_interval.tick() => {
+                manager.check_storage().await;
+            }
+            () = &mut next_remove_sleep, if !disconnected.is_empty() => {
+                // Will never panic since we check for non-emptiness before entering this block
+                let to_remove = disconnected.pop().unwrap();
+
+                manager.handle_remove(to_remove.id).await;
+
+                // Re-arm the Sleep instance with the next deadline if required
+                if let Some(next) = disconnected.peek() {
+                    next_remove_sleep.as_mut().reset(next.deadline)
+                }
+            }
+            msg = manager.rx.channel.recv() => {
+                let Some(msg) = msg else {
+                    warn!("All senders are dead");
+                    break;
+                };
+
+                debug!(?msg, "Received message");
+
+                match msg {
+                    RecordingManagerMessage::Connect { id, file_type, resume_offset, channel } => {
+                        match manager.handle_connect(id, file_type, resume_offset).await {
+                            Ok(recording_file) => {
+                                let _ = channel.send(recording_file);
+                            }
+                            Err(e) => error!(error = format!("{e:#}"), "handle_connect"),
+                        }
+                    },
+                    RecordingManagerMessage::Disconnect { id, sha256 } => {
+                        if let Err(e) = manager.handle_disconnect(id, sha256).await {
+                            error!(error = format!("{e:#}"), "handle_disconnect");
+                        }
+
+                        let now = tokio::time::Instant::now();
+                        let deadline = now + DISCONNECTED_TTL_DURATION;
+
+                        disconnected.push(DisconnectedTtl {
+                            deadline,
+                            id,
+                        });
+
+                        // Reset the Sleep instance if the new deadline is sooner or it is already elapsed
+                        if next_remove_sleep.is_elapsed() || deadline < next_remove_sleep.deadline() {
+                            next_remove_sleep.as_mut().reset(deadline);
+                        }
+                    }
+                    RecordingManagerMessage::GetState { id, channel } => {
+                        let response = manager.ongoing_recordings.get(&id).map(|ongoing| ongoing.state.clone());
+                        let _ = channel.send(response);
+                    }
+                    RecordingManagerMessage::GetCount { channel } => {
+                        let _ = channel.send(manager.ongoing_recordings.len());
+                    }
+                    RecordingManagerMessage::GetResumeOffset { id, file_type, channel } => {
+                        let _ = channel.send(manager.get_resume_offset(id, file_type).await);
+                    }
+                }
+            }
+            _ = shutdown_signal.wait() => {
+                break;
+            }
+        }
+    }
+
+    debug!("Task is stopping; wait for disconnect messages");
+
+    while let Some(msg) = manager.rx.channel.recv().await {
+        debug!(?msg, "Received message");
+        if let RecordingManagerMessage::Disconnect { id, sha256 } = msg {
+            if let Err(e) = manager.handle_disconnect(id, sha256).await {
+                error!(error = format!("{e:#}"), "handle_disconnect");
+            }
+        }
+    }
+
+    // The recordings still in their resume window can't be resumed anymore, so they are finalized before exiting.
+    let ongoing_recordings = std::mem::take(&mut manager.ongoing_recordings);
+
+    debug!(count = ongoing_recordings.len(), "Finalize remaining recordings");
+
+    futures::future::join_all(
+        ongoing_recordings
+            .into_iter()
+            .map(|(id, ongoing)| manager.finalization(id, ongoing)),
+    )
+    .await;
+
+    debug!("Task terminated");
+
+    Ok(())
+}
@@ -0,0 +1,411 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context as _;
+use async_trait::async_trait;
+use camino::Utf8Path;
+use devolutions_gateway_task::{ShutdownSignal, Task};
+use tokio::time::sleep;
+use uuid::Uuid;
+
+use super::{
+    recording_storage, remove_recording_folder, ActiveRecordings, JrecManifest, RecordingIndex, RecordingStorage,
+    RemoveError, REMOVED_RECORDING_SUFFIX,
+};
+use crate::config::{ConfHandle, RecordingRetention};
+use crate::subscriber::{self, RecordingDeletionReason, SubscriberRecordingInfo, SubscriberSender};
+
+pub struct RecordingRetentionTask {
+    pub conf_handle: ConfHandle,
+    pub active_recordings: Arc<ActiveRecordings>,
+    pub index: Arc<RecordingIndex>,
+    pub subscriber_tx: SubscriberSender,
+}
+
+#[async_trait]
+impl Task for RecordingRetentionTask {
+    type Output = anyhow::Result<()>;
+
+    const NAME: &'static str = "recording retention";
+
+    async fn run(self, shutdown_signal: ShutdownSignal) -> Self::Output {
+        recording_retention_task(self, shutdown_signal).await
+    }
+}
+
+#[instrument(skip_all)]
+async fn recording_retention_task(
+    task: RecordingRetentionTask,
+    mut shutdown_signal: ShutdownSignal,
+) -> anyhow::Result<()> {
+    // Used when no retention policy is configured, just to pick up configuration changes from time to time.
+    const IDLE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+    debug!("Task started");
+
+    loop {
+        let conf = task.conf_handle.get_conf();
+
+        let interval = if let Some(retention) = &conf.recording.retention {
+            if let Err(error) = apply_retention_policy(
+                &conf.recording_path,
+                retention,
+                &task.active_recordings,
+                &task.index,
+                &*recording_storage(&conf),
+                &task.subscriber_tx,
+            )
+            .await
+            {
+                warn!(
+                    error = format!("{error:#}"),
+                    "Failed to apply recording retention policy"
+                );
+            }
+
+            retention.check_interval
+        } else {
+            IDLE_INTERVAL
+        };
+
+        tokio::select! {
+            _ = sleep(interval) => {}
+            _ = task.conf_handle.change_notified() => {}
+            _ = shutdown_signal.wait() => {
+                break;
+            }
+        }
+    }
+
+    debug!("Task terminated");
+
+    Ok(())
+}
+
+struct RecordingEntry {
+    id: Uuid,
+    /// Unix timestamp at which the recording ended
+    end_time: i64,
+    /// Size of the recording folder, in bytes
+    size: u64,
+}
+
+async fn apply_retention_policy(
+    recordings_path: &Utf8Path,
+    retention: &RecordingRetention,
+    active_recordings: &ActiveRecordings,
+    index: &RecordingIndex,
+    storage: &dyn RecordingStorage,
+    subscriber_tx: &SubscriberSender,
+) -> anyhow::Result<()> {
+    let mut recordings = tokio::task::spawn_blocking({
+        let recordings_path = recordings_path.to_owned();
+        move || scan_recordings(&recordings_path)
+    })
+    .await
+    .context("failed to join the scanning task")??;
+
+    // Oldest recordings are removed first.
+    recordings.sort_by_key(|recording| recording.end_time);
+
+    let now = time::OffsetDateTime::now_utc().unix_timestamp();
+
+    let max_age = retention
+        .max_age
+        .map(|max_age| i64::try_from(max_age.as_secs()).unwrap_or(i64::MAX));
+
+    // Ongoing recordings are never removed, but they are still occupying space.
+    let mut total_size: u64 = recordings.iter().map(|recording| recording.size).sum();
+
+    let mut free_space = match retention.min_free_space {
+        Some(_) => match crate::utils::available_disk_space(recordings_path) {
+            Ok(free_space) => Some(free_space),
+            Err(error) => {
+                warn!(
+                    error = format!("{error:#}"),
+                    "Couldn't retrieve free space on the recording volume"
+                );
+                None
+            }
+        },
+        None => None,
+    };
+
+    trace!(count = recordings.len(), total_size, ?free_space, "Recordings scanned");
+
+    for recording in recordings {
+        let reason = if max_age.is_some_and(|max_age| now.saturating_sub(recording.end_time) > max_age) {
+            RecordingDeletionReason::MaxAge
+        } else if retention.max_total_size.is_some_and(|max| total_size > max) {
+            RecordingDeletionReason::MaxTotalSize
+        } else if retention
+            .min_free_space
+            .zip(free_space)
+            .is_some_and(|(min, free)| free < min)
+        {
+            RecordingDeletionReason::MinFreeSpace
+        } else {
+            // Recordings are sorted from the oldest to the newest: the remaining ones are all to be kept.
+            break;
+        };
+
+        match remove_recording_folder(recordings_path, recording.id, active_recordings, index, storage).await {
+            Ok(()) => {
+                info!(id = %recording.id, size = recording.size, ?reason, "Deleted recording");
+
+                total_size = total_size.saturating_sub(recording.size);
+                free_space = free_space.map(|free_space| free_space.saturating_add(recording.size));
+
+                let message = subscriber::Message::recording_deleted(
+                    SubscriberRecordingInfo {
+                        session_id: recording.id,
+                        size: recording.size,
+                    },
+                    reason,
+                );
+
+                if let Err(error) = subscriber_tx.try_send(message) {
+                    warn!(%error, "Failed to send subscriber message");
+                }
+            }
+            Err(RemoveError::Ongoing(_)) => {
+                trace!(id = %recording.id, "Recording is ongoing; skip it");
+            }
+            Err(error) => {
+                warn!(error = format!("{error:#}"), id = %recording.id, "Couldn't delete recording");
+            }
+        }
+    }
+
+    if retention.max_total_size.is_some_and(|max| total_size > max)
+        || retention
+            .min_free_space
+            .zip(free_space)
+            .is_some_and(|(min, free)| free < min)
+    {
+        warn!(
+            total_size,
+            ?free_space,
+            "Recording retention policy can't be satisfied by deleting completed recordings"
+        );
+    }
+
+    Ok(())
+}
+
+fn scan_recordings(recordings_path: &Utf8Path) -> anyhow::Result<Vec<RecordingEntry>> {
+    let mut recordings = Vec::new();
+
+    if !recordings_path.exists() {
+        return Ok(recordings);
+    }
+
+    let read_dir = recordings_path
+        .read_dir_utf8()
+        .with_context(|| format!("failed to read {recordings_path}"))?;
+
+    for entry in read_dir {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(error) => {
+                warn!(%error, "Couldn't read entry in recordings folder");
+                continue;
+            }
+        };
+
+        let file_name = entry.file_name();
+
+        // Leftovers from a removal which was interrupted midway.
+        if file_name.ends_with(REMOVED_RECORDING_SUFFIX) {
+            debug!(path = %entry.path(), "Remove leftover recording folder");
+            if let Err(error) = std::fs::remove_dir_all(entry.path()) {
+                warn!(%error, path = %entry.path(), "Couldn't remove leftover recording folder");
+            }
+            continue;
+        }
+
+        let Ok(id) = Uuid::parse_str(file_name) else {
+            continue;
+        };
+
+        if !entry.path().is_dir() {
+            continue;
+        }
+
+        match read_recording_entry(id, entry.path()) {
+            Ok(recording) => recordings.push(recording),
+            Err(error) => warn!(error = format!("{error:#}"), %id, "Couldn't inspect recording folder"),
+        }
+    }
+
+    Ok(recordings)
+}
+
+fn read_recording_entry(id: Uuid, path: &Utf8Path) -> anyhow::Result<RecordingEntry> {
+    let mut size = 0;
+
+    for entry in path.read_dir_utf8().context("read recording folder")? {
+        let metadata = entry.and_then(|entry| entry.metadata()).context("file metadata")?;
+        size += metadata.len();
+    }
+
+    let end_time = match JrecManifest::read_from_file(path.join("recording.json")) {
+        Ok(manifest) => manifest.start_time + manifest.duration,
+        Err(error) => {
+            debug!(error = format!("{error:#}"), %id, "Couldn't read manifest; fallback to folder modification time");
+
+            let modified = path
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .context("folder modification time")?;
+
+            time::OffsetDateTime::from(modified).unix_timestamp()
+        }
+    };
+
+    Ok(RecordingEntry { id, end_time, size })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::recording::LocalStorage;
+    use crate::test_utils::TempDir;
+
+    const DAY: u64 = 24 * 60 * 60;
+    const FILE_SIZE: u64 = 10_000;
+
+    /// Creates a completed recording which ended the provided number of days ago.
+    fn create_recording(recordings_dir: &TempDir, days_ago: u64) -> Uuid {
+        let id = Uuid::new_v4();
+        let recording_path = recordings_dir.path().join(id.to_string());
+        std::fs::create_dir(&recording_path).unwrap();
+        std::fs::write(recording_path.join("recording-0.webm"), vec![0; FILE_SIZE as usize]).unwrap();
+
+        JrecManifest {
+            session_id: id,
+            start_time: time::OffsetDateTime::now_utc().unix_timestamp() - (days_ago * DAY) as i64,
+            duration: 0,
+            files: Vec::new(),
+            session: None,
+            encryption: None,
+            signature: None,
+        }
+        .save_to_file(recording_path.join("recording.json"))
+        .unwrap();
+
+        id
+    }
+
+    fn retention(max_age_days: Option<u64>, max_total_size: Option<u64>) -> RecordingRetention {
+        RecordingRetention {
+            max_age: max_age_days.map(|days| Duration::from_secs(days * DAY)),
+            max_total_size,
+            min_free_space: None,
+            check_interval: Duration::from_secs(60),
+        }
+    }
+
+    /// Applies the policy, and returns the remaining recordings along with the number of deletion events.
+    async fn apply(
+        recordings_dir: &TempDir,
+        retention: &RecordingRetention,
+        active_recordings: &ActiveRecordings,
+    ) -> (HashSet<Uuid>, usize) {
+        let (subscriber_tx, mut subscriber_rx) = subscriber::subscriber_channel();
+
+        apply_retention_policy(
+            recordings_dir.path(),
+            retention,
+            active_recordings,
+            &RecordingIndex::default(),
+            &LocalStorage::new(recordings_dir.path().to_owned()),
+            &subscriber_tx,
+        )
+        .await
+        .unwrap();
+
+        let mut deletions = 0;
+        while subscriber_rx.try_recv().is_ok() {
+            deletions += 1;
+        }
+
+        let remaining = recordings_dir
+            .path()
+            .read_dir_utf8()
+            .unwrap()
+            .map(|entry| Uuid::parse_str(entry.unwrap().file_name()).unwrap())
+            .collect();
+
+        (remaining, deletions)
+    }
+
+    #[tokio::test]
+    async fn recordings_older_than_max_age_are_deleted() {
+        let recordings_dir = TempDir::new();
+        create_recording(&recordings_dir, 10);
+        let recent = [
+            create_recording(&recordings_dir, 5),
+            create_recording(&recordings_dir, 1),
+        ];
+
+        let (remaining, deletions) =
+            apply(&recordings_dir, &retention(Some(7), None), &ActiveRecordings::default()).await;
+
+        assert_eq!(remaining, HashSet::from(recent));
+        assert_eq!(deletions, 1);
+    }
+
+    #[tokio::test]
+    async fn oldest_recordings_are_deleted_above_max_total_size() {
+        let recordings_dir = TempDir::new();
+        create_recording(&recordings_dir, 3);
+        let recent = [
+            create_recording(&recordings_dir, 2),
+            create_recording(&recordings_dir, 1),
+        ];
+
+        let retention = retention(None, Some(FILE_SIZE * 5 / 2));
+        let (remaining, deletions) = apply(&recordings_dir, &retention, &ActiveRecordings::default()).await;
+
+        assert_eq!(remaining, HashSet::from(recent));
+        assert_eq!(deletions, 1);
+    }
+
+    #[tokio::test]
+    async fn max_age_and_max_total_size_are_both_enforced() {
+        let recordings_dir = TempDir::new();
+        create_recording(&recordings_dir, 10);
+        create_recording(&recordings_dir, 3);
+        let recent = [
+            create_recording(&recordings_dir, 2),
+            create_recording(&recordings_dir, 1),
+        ];
+
+        let retention = retention(Some(7), Some(FILE_SIZE * 5 / 2));
+        let (remaining, deletions) = apply(&recordings_dir, &retention, &ActiveRecordings::default()).await;
+
+        assert_eq!(remaining, HashSet::from(recent));
+        assert_eq!(deletions, 2);
+    }
+
+    #[tokio::test]
+    async fn active_recordings_are_never_deleted() {
+        let recordings_dir = TempDir::new();
+        let active = create_recording(&recordings_dir, 10);
+        create_recording(&recordings_dir, 5);
+        create_recording(&recordings_dir, 1);
+
+        let active_recordings = ActiveRecordings::default();
+        active_recordings.insert(active);
+
+        // Even with every other recording deleted, the policy is still not satisfied.
+        let retention = retention(Some(7), Some(FILE_SIZE / 2));
+        let (remaining, deletions) = apply(&recordings_dir, &retention, &active_recordings).await;
+
+        assert_eq!(remaining, HashSet::from([active]));
+        assert_eq!(deletions, 2);
+    }
+}
@@ -0,0 +1,413 @@
+//! Full-text search over terminal recordings
+//!
+//! The text of the TRP and asciicast files is extracted once the file is complete, and stored next to it as a list
+//! of lines, each one with the time at which it started. Searching a recording only requires reading these lines.
+//! Encrypted recordings are never indexed, since the index would hold their content in clear.
+
+use std::io::BufRead as _;
+
+use anyhow::Context as _;
+use camino::{Utf8Path, Utf8PathBuf};
+use uuid::Uuid;
+
+use super::JrecManifest;
+use crate::token::RecordingFileType;
+
+const SEARCH_INDEX_VERSION: u8 = 1;
+
+/// Longer lines are split, so a single hit never returns an unbounded amount of text.
+const MAX_LINE_LEN: usize = 1024;
+
+/// Maximum number of hits returned for a single recording
+const MAX_HITS_PER_RECORDING: usize = 100;
+
+const TRP_EVENT_HEADER_SIZE: usize = 8;
+const TRP_TERMINAL_OUTPUT: u16 = 0;
+const TRP_USER_INPUT: u16 = 1;
+
+#[derive(Deserialize)]
+struct SearchIndex {
+    version: u8,
+    lines: Vec<TextLine>,
+}
+
+#[derive(Serialize)]
+struct SearchIndexRef<'a> {
+    version: u8,
+    lines: &'a [TextLine],
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TextLine {
+    /// Time at which the line started, in seconds since the start of the file
+    time: f64,
+    text: String,
+}
+
+/// Recording matching a search
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct RecordingSearchResult {
+    /// Recorded session ID
+    pub session_id: Uuid,
+    /// Unix timestamp at which the recording started
+    pub start_time: i64,
+    /// Occurrences of the searched text, in order of appearance
+    pub hits: Vec<SearchHit>,
+}
+
+/// Occurrence of the searched text in a terminal recording
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SearchHit {
+    /// Name of the recording file
+    pub file_name: String,
+    /// Position of the hit in the recording file, in seconds
+    pub time: f64,
+    /// Line of text holding the searched text
+    pub text: String,
+}
+
+fn terminal_file_type(file_name: &str) -> Option<RecordingFileType> {
+    match Utf8Path::new(file_name).extension()? {
+        "trp" => Some(RecordingFileType::TRP),
+        "cast" => Some(RecordingFileType::Asciicast),
+        _ => None,
+    }
+}
+
+pub(crate) fn is_terminal_file(file_name: &str) -> bool {
+    terminal_file_type(file_name).is_some()
+}
+
+fn search_index_path(recording_path: &Utf8Path, file_name: &str) -> Utf8PathBuf {
+    recording_path.join(format!("{file_name}.search.json"))
+}
+
+/// Extracts the text of a complete terminal recording file, and stores it next to the file.
+pub(crate) fn build_search_index(recording_path: &Utf8Path, file_name: &str) -> anyhow::Result<()> {
+    let lines = extract_lines(recording_path, file_name)?;
+    write_search_index(recording_path, file_name, &lines)
+}
+
+fn extract_lines(recording_path: &Utf8Path, file_name: &str) -> anyhow::Result<Vec<TextLine>> {
+    let file_type = terminal_file_type(file_name).context("not a terminal recording")?;
+
+    let path = recording_path.join(file_name);
+    let file = std::fs::File::open(&path).with_context(|| format!("failed to open {path}"))?;
+    let reader = std::io::BufReader::new(file);
+
+    let mut extractor = TextExtractor::default();
+
+    match file_type {
+        RecordingFileType::TRP => extract_trp(reader, &mut extractor),
+        RecordingFileType::Asciicast => extract_asciicast(reader, &mut extractor),
+        RecordingFileType::WebM => unreachable!(),
+    }
+    .with_context(|| format!("failed to read {path}"))?;
+
+    Ok(extractor.finish())
+}
+
+fn write_search_index(recording_path: &Utf8Path, file_name: &str, lines: &[TextLine]) -> anyhow::Result<()> {
+    let path = search_index_path(recording_path, file_name);
+    let tmp_path = recording_path.join(format!(".{file_name}.search.json.tmp"));
+
+    let index = SearchIndexRef {
+        version: SEARCH_INDEX_VERSION,
+        lines,
+    };
+
+    let json = serde_json::to_vec(&index).context("serialize search index")?;
+    std::fs::write(&tmp_path, json).with_context(|| format!("failed to write {tmp_path}"))?;
+    std::fs::rename(&tmp_path, &path).with_context(|| format!("failed to rename {tmp_path} to {path}"))?;
+
+    Ok(())
+}
+
+/// Reads the search index of a file, building it if it doesn't exist yet (e.g.: recordings made before the upgrade).
+fn load_search_index(recording_path: &Utf8Path, file_name: &str) -> anyhow::Result<Vec<TextLine>> {
+    let path = search_index_path(recording_path, file_name);
+
+    if let Ok(json) = std::fs::read(&path) {
+        match serde_json::from_slice::<SearchIndex>(&json) {
+            Ok(index) if index.version == SEARCH_INDEX_VERSION => return Ok(index.lines),
+            Ok(_) => debug!(%path, "Outdated search index"),
+            Err(error) => debug!(%path, %error, "Invalid search index"),
+        }
+    }
+
+    let lines = extract_lines(recording_path, file_name)?;
+
+    if let Err(error) = write_search_index(recording_path, file_name, &lines) {
+        debug!(%path, error = format!("{error:#}"), "Failed to store search index");
+    }
+
+    Ok(lines)
+}
+
+/// Searches the terminal recordings for the given text, case insensitively.
+///
+/// Recordings without any hit are omitted.
+pub(crate) fn search_recording(
+    recording_path: &Utf8Path,
+    query: &str,
+) -> anyhow::Result<Option<RecordingSearchResult>> {
+    let manifest = JrecManifest::read_from_file(recording_path.join("recording.json")).context("read manifest")?;
+
+    if manifest.encryption.is_some() {
+        return Ok(None);
+    }
+
+    let query = query.to_lowercase();
+    let mut hits = Vec::new();
+
+    for file in &manifest.files {
+        if !is_terminal_file(&file.file_name) {
+            continue;
+        }
+
+        let lines = match load_search_index(recording_path, &file.file_name) {
+            Ok(lines) => lines,
+            Err(error) => {
+                debug!(file_name = %file.file_name, error = format!("{error:#}"), "Terminal recording not searchable");
+                continue;
+            }
+        };
+
+        let matching = lines
+            .into_iter()
+            .filter(|line| line.text.to_lowercase().contains(&query))
+            .map(|line| SearchHit {
+                file_name: file.file_name.clone(),
+                time: line.time,
+                text: line.text,
+            });
+
+        hits.extend(matching);
+
+        if hits.len() >= MAX_HITS_PER_RECORDING {
+            hits.truncate(MAX_HITS_PER_RECORDING);
+            break;
+        }
+    }
+
+    if hits.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(RecordingSearchResult {
+        session_id: manifest.session_id,
+        start_time: manifest.start_time,
+        hits,
+    }))
+}
+
+/// Reads the events of a TRP file.
+///
+/// Each event starts with an 8-byte header: the time elapsed since the previous event in milliseconds (u32),
+/// the type of the event (u16) and the size of the payload (u16), all little-endian.
+fn extract_trp(mut reader: impl std::io::Read, extractor: &mut TextExtractor) -> std::io::Result<()> {
+    let mut time_ms: u64 = 0;
+    let mut header = [0; TRP_EVENT_HEADER_SIZE];
+    let mut payload = Vec::new();
+
+    loop {
+        match reader.read_exact(&mut header) {
+            Ok(()) => {}
+            // The last event of a file cut during a crash may be incomplete.
+            Err(error) if error.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(error) => return Err(error),
+        }
+
+        let delta = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+        let event_type = u16::from_le_bytes([header[4], header[5]]);
+        let size = u16::from_le_bytes([header[6], header[7]]);
+
+        time_ms += u64::from(delta);
+
+        payload.resize(usize::from(size), 0);
+
+        match reader.read_exact(&mut payload) {
+            Ok(()) => {}
+            Err(error) if error.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(error) => return Err(error),
+        }
+
+        if matches!(event_type, TRP_TERMINAL_OUTPUT | TRP_USER_INPUT) {
+            let time = std::time::Duration::from_millis(time_ms).as_secs_f64();
+            extractor.push_bytes(time, &payload);
+        }
+    }
+}
+
+/// Reads the events of an asciicast v2 file: a header line, then one JSON array per event.
+fn extract_asciicast(reader: impl std::io::BufRead, extractor: &mut TextExtractor) -> std::io::Result<()> {
+    for line in reader.lines().skip(1) {
+        let line = line?;
+
+        let Ok((time, code, data)) = serde_json::from_str::<(f64, String, String)>(&line) else {
+            continue;
+        };
+
+        if code == "o" || code == "i" {
+            extractor.push_text(time, &data);
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+enum EscapeState {
+    #[default]
+    Text,
+    Escape,
+    Csi,
+    Osc,
+    OscEscape,
+}
+
+/// Turns a terminal stream into lines of text, stripping the escape sequences and control characters.
+#[derive(Default)]
+struct TextExtractor {
+    state: EscapeState,
+    pending_utf8: Vec<u8>,
+    line: String,
+    line_time: f64,
+    lines: Vec<TextLine>,
+}
+
+impl TextExtractor {
+    fn push_bytes(&mut self, time: f64, bytes: &[u8]) {
+        self.pending_utf8.extend_from_slice(bytes);
+
+        let complete_len = match std::str::from_utf8(&self.pending_utf8) {
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            _ => self.pending_utf8.len(),
+        };
+
+        let rest = self.pending_utf8.split_off(complete_len);
+        let text = String::from_utf8_lossy(&self.pending_utf8).into_owned();
+        self.pending_utf8 = rest;
+
+        self.push_text(time, &text);
+    }
+
+    fn push_text(&mut self, time: f64, text: &str) {
+        for c in text.chars() {
+            self.state = match (self.state, c) {
+                (EscapeState::Text, '\x1b') => EscapeState::Escape,
+                (EscapeState::Text, '\n') => {
+                    self.end_line();
+                    EscapeState::Text
+                }
+                (EscapeState::Text, '\x08') => {
+                    self.line.pop();
+                    EscapeState::Text
+                }
+                (EscapeState::Text, '\t') => {
+                    self.push_char(time, ' ');
+                    EscapeState::Text
+                }
+                (EscapeState::Text, c) if c.is_control() => EscapeState::Text,
+                (EscapeState::Text, c) => {
+                    self.push_char(time, c);
+                    EscapeState::Text
+                }
+                (EscapeState::Escape, '[') => EscapeState::Csi,
+                (EscapeState::Escape, ']') => EscapeState::Osc,
+                (EscapeState::Escape, _) => EscapeState::Text,
+                // Final byte of a control sequence.
+                (EscapeState::Csi, '\x40'..='\x7e') => EscapeState::Text,
+                (EscapeState::Csi, _) => EscapeState::Csi,
+                (EscapeState::Osc, '\x07') => EscapeState::Text,
+                (EscapeState::Osc, '\x1b') => EscapeState::OscEscape,
+                (EscapeState::Osc, _) => EscapeState::Osc,
+                (EscapeState::OscEscape, '\\') => EscapeState::Text,
+                (EscapeState::OscEscape, _) => EscapeState::Osc,
+            };
+        }
+    }
+
+    fn push_char(&mut self, time: f64, c: char) {
+        if self.line.is_empty() {
+            self.line_time = time;
+        }
+
+        self.line.push(c);
+
+        if self.line.len() >= MAX_LINE_LEN {
+            self.end_line();
+        }
+    }
+
+    fn end_line(&mut self) {
+        let text = self.line.trim();
+
+        if !text.is_empty() {
+            self.lines.push(TextLine {
+                time: self.line_time,
+                text: text.to_owned(),
+            });
+        }
+
+        self.line.clear();
+    }
+
+    fn finish(mut self) -> Vec<TextLine> {
+        self.end_line();
+        self.lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_sequences_are_stripped() {
+        let mut extractor = TextExtractor::default();
+
+        extractor.push_text(0.5, "\x1b]0;user@host\x07\x1b[1;32muser@host\x1b[0m:~$ ");
+        extractor.push_text(1.0, "sudo rm -rx\x08f /tmp\r\n");
+        extractor.push_text(2.0, "\x1b[?2004hdone");
+
+        let lines = extractor.finish();
+        let texts: Vec<&str> = lines.iter().map(|line| line.text.as_str()).collect();
+
+        assert_eq!(texts, ["user@host:~$ sudo rm -rf /tmp", "done"]);
+        assert_eq!(lines[0].time, 0.5);
+        assert_eq!(lines[1].time, 2.0);
+    }
+
+    #[test]
+    fn trp_events_are_extracted() {
+        let mut trp = Vec::new();
+
+        for (delta, event_type, payload) in [
+            (100u32, TRP_TERMINAL_OUTPUT, "$ ".as_bytes()),
+            (1500, TRP_USER_INPUT, b"ls\n"),
+            (10, 2, b"\x50\x00\x18\x00"),
+            (250, TRP_TERMINAL_OUTPUT, "caf\u{e9}".as_bytes()),
+        ] {
+            trp.extend_from_slice(&delta.to_le_bytes());
+            trp.extend_from_slice(&event_type.to_le_bytes());
+            trp.extend_from_slice(&u16::try_from(payload.len()).unwrap().to_le_bytes());
+            trp.extend_from_slice(payload);
+        }
+
+        // Incomplete trailing event.
+        trp.extend_from_slice(&[1, 0, 0]);
+
+        let mut extractor = TextExtractor::default();
+        extract_trp(trp.as_slice(), &mut extractor).unwrap();
+        let lines = extractor.finish();
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].text, "$ ls");
+        assert!((lines[0].time - 0.1).abs() < 1e-9);
+        assert_eq!(lines[1].text, "café");
+        assert!((lines[1].time - 1.86).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,142 @@
+//! Live shadowing of ongoing recordings
+//!
+//! The recording file currently being written is read from the start, then tailed
+//! until the recording manager reports that the client is not connected anymore.
+
+use std::time::Duration;
+
+use anyhow::Context as _;
+use bytes::{Bytes, BytesMut};
+use camino::{Utf8Path, Utf8PathBuf};
+use futures::Stream;
+use tokio::io::{self, AsyncReadExt as _};
+use tokio::{fs, time};
+use uuid::Uuid;
+
+use super::encryption::{ChunkDecryptor, DataKey};
+use super::{JrecManifest, OnGoingRecordingState, RecordingMessageSender};
+use crate::config::RecordingEncryption;
+
+/// Delay before checking again for new data once the end of the file is reached.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+const READ_BUFFER_SIZE: usize = 16 * 1024;
+
+/// Opens a stream over the recording file currently being written for the given recording.
+///
+/// The stream yields the content written so far, then the new content as it is written,
+/// and ends once the recording file is complete. Encrypted recordings are decrypted on the fly.
+pub(crate) async fn shadow_recording(
+    recordings: RecordingMessageSender,
+    recording_path: &Utf8Path,
+    encryption_conf: Option<&RecordingEncryption>,
+) -> anyhow::Result<impl Stream<Item = io::Result<Bytes>> + Send> {
+    let manifest = JrecManifest::read_from_file(recording_path.join("recording.json")).context("read manifest")?;
+
+    let file_index = manifest.files.len().checked_sub(1).context("no recording file")?;
+    let file_path = recording_path.join(&manifest.files[file_index].file_name);
+
+    let decryptor = manifest
+        .encryption
+        .as_ref()
+        .map(|encryption| DataKey::unwrap(encryption, manifest.session_id, encryption_conf)?.decryptor(file_index))
+        .transpose()
+        .context("recording encryption")?;
+
+    let tail = Tail {
+        recordings,
+        id: manifest.session_id,
+        file_path,
+        file: None,
+        decryptor,
+        pending: BytesMut::new(),
+        buf: vec![0; READ_BUFFER_SIZE],
+        completed: false,
+        finished: false,
+    };
+
+    Ok(futures::stream::try_unfold(tail, |mut tail| async move {
+        let data = tail.next().await?;
+        Ok(data.map(|data| (data, tail)))
+    }))
+}
+
+struct Tail {
+    recordings: RecordingMessageSender,
+    id: Uuid,
+    file_path: Utf8PathBuf,
+    file: Option<fs::File>,
+    decryptor: Option<ChunkDecryptor>,
+    /// Encrypted data not yet forming a whole chunk
+    pending: BytesMut,
+    buf: Vec<u8>,
+    /// Whether the client is done writing the file
+    completed: bool,
+    /// Whether the whole file was read after completion
+    finished: bool,
+}
+
+impl Tail {
+    async fn next(&mut self) -> io::Result<Option<Bytes>> {
+        loop {
+            if let Some(decryptor) = &mut self.decryptor {
+                if let Some(chunk) = decryptor.decrypt_buffered(&mut self.pending)? {
+                    return Ok(Some(chunk));
+                }
+            }
+
+            if self.finished {
+                if self
+                    .decryptor
+                    .as_ref()
+                    .is_some_and(|decryptor| !decryptor.is_finished())
+                {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "encrypted file is truncated",
+                    ));
+                }
+
+                return Ok(None);
+            }
+
+            let n = self.read().await?;
+
+            if n > 0 {
+                match self.decryptor {
+                    Some(_) => self.pending.extend_from_slice(&self.buf[..n]),
+                    None => return Ok(Some(Bytes::copy_from_slice(&self.buf[..n]))),
+                }
+            } else if self.completed {
+                self.finished = true;
+            } else if self.is_being_written().await {
+                time::sleep(POLL_INTERVAL).await;
+            } else {
+                // The file is flushed before the client is reported as disconnected,
+                // so reading until the end one more time is enough to get everything.
+                self.completed = true;
+            }
+        }
+    }
+
+    async fn read(&mut self) -> io::Result<usize> {
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => match fs::File::open(&self.file_path).await {
+                Ok(file) => self.file.insert(file),
+                // The client may not have created the file yet.
+                Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+                Err(e) => return Err(e),
+            },
+        };
+
+        file.read(&mut self.buf).await
+    }
+
+    async fn is_being_written(&self) -> bool {
+        matches!(
+            self.recordings.get_state(self.id).await,
+            Ok(Some(OnGoingRecordingState::Connected))
+        )
+    }
+}
@@ -0,0 +1,170 @@
+//! Storage backends for the recording files
+//!
+//! Recordings are always written to the recordings folder first. Once a file is complete, it's handed to the
+//! configured backend which may store it off-box. Files missing from the recordings folder are fetched back from
+//! the backend when they are pulled, and released afterwards unless the backend is configured to keep them.
+
+mod s3;
+
+use std::sync::Arc;
+
+use anyhow::Context as _;
+use async_trait::async_trait;
+use camino::{Utf8Path, Utf8PathBuf};
+use devolutions_gateway_task::ChildTask;
+use futures::{Stream, StreamExt as _};
+use uuid::Uuid;
+
+use crate::config::Conf;
+
+pub use s3::S3Storage;
+
+const MANIFEST_FILE_NAME: &str = "recording.json";
+
+#[async_trait]
+pub trait RecordingStorage: Send + Sync {
+    /// Stores a completed recording file, along with the current manifest of the recording.
+    async fn store_file(&self, id: Uuid, file_name: &str) -> anyhow::Result<()>;
+
+    /// Lists the recordings held by this storage.
+    async fn list_recordings(&self) -> anyhow::Result<Vec<Uuid>>;
+
+    /// Makes a recording file available in the recordings folder, retrieving it if necessary.
+    ///
+    /// Returns whether the file was retrieved.
+    async fn fetch_file(&self, id: Uuid, file_name: &str) -> anyhow::Result<bool>;
+
+    /// Removes the local copy of a file retrieved by [`fetch_file`](Self::fetch_file), unless it's meant to be kept.
+    async fn release_file(&self, id: Uuid, file_name: &str) -> anyhow::Result<()>;
+
+    /// Removes all the files of a recording held by this storage.
+    ///
+    /// Returns whether the storage was holding the recording. The recordings folder itself is left untouched.
+    async fn delete(&self, id: Uuid) -> anyhow::Result<bool>;
+}
+
+/// Recording files retrieved from the storage backend in order to serve a request
+///
+/// The local copies are released once dropped, so they only stay in the recordings folder
+/// when the storage backend is configured to keep them.
+pub(crate) struct FetchedFiles {
+    storage: Arc<dyn RecordingStorage>,
+    id: Uuid,
+    file_names: Vec<String>,
+}
+
+impl FetchedFiles {
+    pub(crate) fn new(storage: Arc<dyn RecordingStorage>, id: Uuid) -> Self {
+        Self {
+            storage,
+            id,
+            file_names: Vec::new(),
+        }
+    }
+
+    /// Makes a recording file available in the recordings folder, keeping track of it when it's retrieved.
+    pub(crate) async fn fetch(&mut self, file_name: &str) -> anyhow::Result<()> {
+        if self.storage.fetch_file(self.id, file_name).await? {
+            self.file_names.push(file_name.to_owned());
+        }
+
+        Ok(())
+    }
+
+    /// Keeps the files until the stream is dropped.
+    pub(crate) fn attach<S: Stream>(self, stream: S) -> impl Stream<Item = S::Item> {
+        stream.map(move |item| {
+            let _fetched_files = &self;
+            item
+        })
+    }
+}
+
+impl Drop for FetchedFiles {
+    fn drop(&mut self) {
+        if self.file_names.is_empty() {
+            return;
+        }
+
+        let storage = Arc::clone(&self.storage);
+        let id = self.id;
+        let file_names = std::mem::take(&mut self.file_names);
+
+        ChildTask::spawn(async move {
+            for file_name in file_names {
+                if let Err(error) = storage.release_file(id, &file_name).await {
+                    warn!(%id, %file_name, error = format!("{error:#}"), "Failed to release recording file");
+                }
+            }
+        })
+        .detach();
+    }
+}
+
+/// Returns the storage backend selected by the configuration.
+pub fn recording_storage(conf: &Conf) -> Arc<dyn RecordingStorage> {
+    match &conf.recording.s3 {
+        Some(s3_conf) => Arc::new(S3Storage::new(s3_conf.clone(), conf.recording_path.clone())),
+        None => Arc::new(LocalStorage::new(conf.recording_path.clone())),
+    }
+}
+
+/// Recording files are kept in the recordings folder only
+pub struct LocalStorage {
+    recordings_path: Utf8PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(recordings_path: Utf8PathBuf) -> Self {
+        Self { recordings_path }
+    }
+}
+
+#[async_trait]
+impl RecordingStorage for LocalStorage {
+    async fn store_file(&self, _: Uuid, _: &str) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn list_recordings(&self) -> anyhow::Result<Vec<Uuid>> {
+        list_local_recordings(&self.recordings_path).await
+    }
+
+    async fn fetch_file(&self, id: Uuid, file_name: &str) -> anyhow::Result<bool> {
+        let path = self.recordings_path.join(id.to_string()).join(file_name);
+        anyhow::ensure!(path.is_file(), "{path} not found");
+        Ok(false)
+    }
+
+    async fn release_file(&self, _: Uuid, _: &str) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn delete(&self, _: Uuid) -> anyhow::Result<bool> {
+        Ok(false)
+    }
+}
+
+async fn list_local_recordings(recordings_path: &Utf8Path) -> anyhow::Result<Vec<Uuid>> {
+    let mut recordings = Vec::new();
+
+    if !recordings_path.exists() {
+        return Ok(recordings);
+    }
+
+    let mut read_dir = tokio::fs::read_dir(recordings_path)
+        .await
+        .with_context(|| format!("failed to read {recordings_path}"))?;
+
+    while let Some(entry) = read_dir.next_entry().await.context("read directory entry")? {
+        let Some(id) = entry.file_name().to_str().and_then(|name| Uuid::parse_str(name).ok()) else {
+            continue;
+        };
+
+        if entry.file_type().await.is_ok_and(|file_type| file_type.is_dir()) {
+            recordings.push(id);
+        }
+    }
+
+    Ok(recordings)
+}
@@ -0,0 +1,207 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context as _;
+use async_trait::async_trait;
+use camino::Utf8Path;
+use devolutions_gateway_task::{ShutdownSignal, Task};
+use tokio::time::sleep;
+use uuid::Uuid;
+
+use super::{recording_storage, JrecManifest, RecordingIndex, RecordingStorage};
+use crate::config::ConfHandle;
+
+/// Interval between two listings of the recordings held by the storage backend.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Keeps the recordings only held by the storage backend in the index.
+///
+/// The recordings written by this instance are indexed as they are recorded, so the storage backend is only listed
+/// at startup and from time to time, in order to pick up the recordings offloaded by other instances.
+pub struct StoredRecordingIndexTask {
+    pub conf_handle: ConfHandle,
+    pub index: Arc<RecordingIndex>,
+}
+
+#[async_trait]
+impl Task for StoredRecordingIndexTask {
+    type Output = anyhow::Result<()>;
+
+    const NAME: &'static str = "stored recording index";
+
+    async fn run(self, shutdown_signal: ShutdownSignal) -> Self::Output {
+        stored_recording_index_task(self, shutdown_signal).await
+    }
+}
+
+#[instrument(skip_all)]
+async fn stored_recording_index_task(
+    task: StoredRecordingIndexTask,
+    mut shutdown_signal: ShutdownSignal,
+) -> anyhow::Result<()> {
+    debug!("Task started");
+
+    // Recordings whose manifest couldn't be retrieved are not retried on every refresh.
+    let mut unreadable = HashSet::new();
+
+    loop {
+        let conf = task.conf_handle.get_conf();
+
+        if let Err(error) = index_stored_recordings(
+            &conf.recording_path,
+            &task.index,
+            &*recording_storage(&conf),
+            &mut unreadable,
+        )
+        .await
+        {
+            warn!(
+                error = format!("{error:#}"),
+                "Failed to list recordings from the storage backend"
+            );
+        }
+
+        tokio::select! {
+            _ = sleep(REFRESH_INTERVAL) => {}
+            _ = task.conf_handle.change_notified() => {
+                // The storage backend may have changed.
+                unreadable.clear();
+            }
+            _ = shutdown_signal.wait() => {
+                break;
+            }
+        }
+    }
+
+    debug!("Task terminated");
+
+    Ok(())
+}
+
+/// Adds the recordings only held by the storage backend to the index.
+///
+/// Their manifest is retrieved into the recordings folder, where the manifests of the offloaded recordings are kept.
+/// The recordings whose manifest can't be retrieved are added to `unreadable`, and skipped from then on.
+async fn index_stored_recordings(
+    recordings_path: &Utf8Path,
+    index: &RecordingIndex,
+    storage: &dyn RecordingStorage,
+    unreadable: &mut HashSet<Uuid>,
+) -> anyhow::Result<()> {
+    let stored = storage.list_recordings().await.context("list stored recordings")?;
+
+    let mut count = 0;
+
+    for id in stored
+        .into_iter()
+        .filter(|id| !index.contains(*id) && !unreadable.contains(id))
+    {
+        let recording_path = recordings_path.join(id.to_string());
+
+        let result = match storage.fetch_file(id, "recording.json").await {
+            Ok(_) => JrecManifest::read_from_file(recording_path.join("recording.json")),
+            Err(error) => Err(error),
+        };
+
+        match result {
+            Ok(manifest) => {
+                index.update(&recording_path, &manifest);
+                count += 1;
+            }
+            Err(error) => {
+                warn!(%id, error = format!("{error:#}"), "Couldn't index stored recording");
+                unreadable.insert(id);
+            }
+        }
+    }
+
+    if count > 0 {
+        debug!(count, "Indexed stored recordings");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::test_utils::TempDir;
+
+    /// Storage backend holding the provided recordings, whose manifests are expected in the recordings folder.
+    struct StubStorage {
+        stored: Vec<Uuid>,
+        fetch_count: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl RecordingStorage for StubStorage {
+        async fn store_file(&self, _: Uuid, _: &str) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        async fn list_recordings(&self) -> anyhow::Result<Vec<Uuid>> {
+            Ok(self.stored.clone())
+        }
+
+        async fn fetch_file(&self, _: Uuid, _: &str) -> anyhow::Result<bool> {
+            self.fetch_count.fetch_add(1, Ordering::SeqCst);
+            Ok(false)
+        }
+
+        async fn release_file(&self, _: Uuid, _: &str) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        async fn delete(&self, _: Uuid) -> anyhow::Result<bool> {
+            Ok(false)
+        }
+    }
+
+    #[tokio::test]
+    async fn unreadable_recordings_are_not_retried() {
+        let recordings_dir = TempDir::new();
+
+        let readable = Uuid::new_v4();
+        let recording_path = recordings_dir.path().join(readable.to_string());
+        std::fs::create_dir(&recording_path).unwrap();
+        JrecManifest {
+            session_id: readable,
+            start_time: 0,
+            duration: 0,
+            files: Vec::new(),
+            session: None,
+            encryption: None,
+            signature: None,
+        }
+        .save_to_file(recording_path.join("recording.json"))
+        .unwrap();
+
+        let unreadable_id = Uuid::new_v4();
+
+        let storage = StubStorage {
+            stored: vec![readable, unreadable_id],
+            fetch_count: AtomicUsize::new(0),
+        };
+        let index = RecordingIndex::default();
+        let mut unreadable = HashSet::new();
+
+        index_stored_recordings(recordings_dir.path(), &index, &storage, &mut unreadable)
+            .await
+            .unwrap();
+
+        assert!(index.contains(readable));
+        assert!(!index.contains(unreadable_id));
+        assert_eq!(unreadable, HashSet::from([unreadable_id]));
+        assert_eq!(storage.fetch_count.load(Ordering::SeqCst), 2);
+
+        // Both recordings are skipped from then on.
+        index_stored_recordings(recordings_dir.path(), &index, &storage, &mut unreadable)
+            .await
+            .unwrap();
+
+        assert_eq!(storage.fetch_count.load(Ordering::SeqCst), 2);
+    }
+}
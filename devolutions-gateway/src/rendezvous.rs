@@ -0,0 +1,458 @@
+//! TCP rendezvous broker (Jet protocol)
+//!
+//! An acceptor announces itself for an association using a `JetAcceptReq`, and waits for a connector. Connectors
+//! join the association either using a `JetConnectReq`, or by sending a preconnection blob holding an association
+//! token in rendezvous mode. The two streams are then piped together.
+//!
+//! Jet requests must hold an association token in rendezvous mode for the requested association, sent as a bearer
+//! token in the `Authorization` header. The acceptor and the connector each present their own token.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use anyhow::Context as _;
+use jet_proto::accept::JetAcceptRsp;
+use jet_proto::connect::JetConnectRsp;
+use jet_proto::{JetMessage, StatusCode, JET_MSG_HEADER_SIZE};
+use parking_lot::Mutex;
+use tokio::io::{AsyncRead, AsyncReadExt as _, AsyncWrite, AsyncWriteExt as _};
+use tokio::net::TcpStream;
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+use crate::config::Conf;
+use crate::proxy::Proxy;
+use crate::rdp_pcb::validate_association_token;
+use crate::session::{ConnectionModeDetails, SessionInfo};
+use crate::token::{AssociationTokenClaims, ConnectionMode};
+use crate::DgwState;
+
+const JET_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long an acceptor waits for a connector to join
+const ACCEPTOR_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Maximum number of acceptors waiting for a connector at the same time
+const MAX_PENDING_ACCEPTORS: usize = 1024;
+
+type Handover = oneshot::Sender<(TcpStream, SocketAddr)>;
+
+/// Acceptors waiting for a connector, by association ID
+#[derive(Default)]
+pub struct RendezvousBroker {
+    acceptors: Mutex<HashMap<Uuid, PendingAcceptor>>,
+    next_registration_id: AtomicU64,
+}
+
+/// The stream is kept by the acceptor task until a connector joins, so the task can notice when the acceptor leaves.
+struct PendingAcceptor {
+    registration_id: u64,
+    candidate: Uuid,
+    handover_tx: oneshot::Sender<Handover>,
+}
+
+impl RendezvousBroker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers an acceptor for the association.
+    ///
+    /// Fails with the status code to respond with when an acceptor is already waiting for the association, or when
+    /// too many acceptors are waiting.
+    pub fn register_acceptor(
+        &self,
+        association: Uuid,
+        candidate: Uuid,
+    ) -> Result<AcceptorRegistration<'_>, StatusCode> {
+        let registration_id = self.next_registration_id.fetch_add(1, Ordering::Relaxed);
+        let (handover_tx, handover_rx) = oneshot::channel();
+
+        let mut acceptors = self.acceptors.lock();
+
+        if acceptors.contains_key(&association) {
+            return Err(StatusCode::CONFLICT);
+        }
+
+        if acceptors.len() >= MAX_PENDING_ACCEPTORS {
+            return Err(StatusCode::SERVICE_UNAVAILABLE);
+        }
+
+        acceptors.insert(
+            association,
+            PendingAcceptor {
+                registration_id,
+                candidate,
+                handover_tx,
+            },
+        );
+
+        Ok(AcceptorRegistration {
+            broker: self,
+            association,
+            registration_id,
+            handover_rx,
+        })
+    }
+
+    /// Takes the stream of the acceptor waiting for the association.
+    ///
+    /// When a candidate is specified, it must match the one of the acceptor.
+    pub async fn take_acceptor(&self, association: Uuid, candidate: Option<Uuid>) -> Option<(TcpStream, SocketAddr)> {
+        let handover_tx = {
+            let mut acceptors = self.acceptors.lock();
+
+            let acceptor = acceptors.get(&association)?;
+
+            if candidate.is_some_and(|candidate| candidate != acceptor.candidate) {
+                return None;
+            }
+
+            acceptors.remove(&association)?.handover_tx
+        };
+
+        let (tx, rx) = oneshot::channel();
+
+        // Fails if the acceptor left in the meantime.
+        handover_tx.send(tx).ok()?;
+
+        rx.await.ok()
+    }
+
+    #[cfg(test)]
+    fn pending_acceptors(&self) -> usize {
+        self.acceptors.lock().len()
+    }
+}
+
+/// Acceptor registered for an association, withdrawn on drop unless a connector took its stream
+pub struct AcceptorRegistration<'a> {
+    broker: &'a RendezvousBroker,
+    association: Uuid,
+    registration_id: u64,
+    handover_rx: oneshot::Receiver<Handover>,
+}
+
+impl AcceptorRegistration<'_> {
+    /// Waits for a connector to take the stream of the acceptor.
+    ///
+    /// Fails when the acceptor disconnects or when no connector joins in time.
+    pub async fn wait_for_connector(mut self, stream: TcpStream, addr: SocketAddr) -> anyhow::Result<()> {
+        let association = self.association;
+
+        let handover = tokio::select! {
+            handover = &mut self.handover_rx => handover.context("registration dropped")?,
+            () = wait_for_disconnection(&stream) => anyhow::bail!("acceptor left before a connector joined"),
+            () = tokio::time::sleep(ACCEPTOR_TIMEOUT) => {
+                anyhow::bail!("no connector joined association {association} in time")
+            }
+        };
+
+        let _ = handover.send((stream, addr));
+
+        Ok(())
+    }
+}
+
+impl Drop for AcceptorRegistration<'_> {
+    fn drop(&mut self) {
+        let mut acceptors = self.broker.acceptors.lock();
+
+        // The entry may belong to another acceptor if ours was taken in the meantime.
+        if acceptors
+            .get(&self.association)
+            .is_some_and(|acceptor| acceptor.registration_id == self.registration_id)
+        {
+            acceptors.remove(&self.association);
+        }
+    }
+}
+
+/// Resolves once the acceptor closes the connection, or never if it sends data before a connector joins.
+async fn wait_for_disconnection(stream: &TcpStream) {
+    let mut buf = [0; 1];
+
+    match stream.peek(&mut buf).await {
+        Ok(0) | Err(_) => {}
+        Ok(_) => std::future::pending().await,
+    }
+}
+
+/// Handles a peer speaking the Jet protocol, either as an acceptor or as a connector.
+pub async fn handle_jet_peer(mut stream: TcpStream, peer_addr: SocketAddr, state: DgwState) -> anyhow::Result<()> {
+    let request = tokio::time::timeout(JET_REQUEST_TIMEOUT, read_jet_request(&mut stream))
+        .await
+        .context("timed out at Jet request reception")??;
+
+    let conf = state.conf_handle.get_conf();
+
+    match request {
+        JetMessage::JetAcceptReq(request) => {
+            let association = authorize(request.token.as_deref(), peer_addr, &conf, &state).and_then(|claims| {
+                // Version 1 acceptors don't specify the association, which is then the one of the token.
+                anyhow::ensure!(
+                    request.association.is_nil() || request.association == claims.jet_aid,
+                    "token is not valid for association {}",
+                    request.association
+                );
+
+                Ok(claims.jet_aid)
+            });
+
+            // The acceptor is registered before responding, so a concurrent acceptor can't be accepted as well.
+            let registration = match &association {
+                Ok(association) => state.rendezvous.register_acceptor(*association, request.candidate),
+                Err(_) => Err(StatusCode::UNAUTHORIZED),
+            };
+
+            let status_code = match &registration {
+                Ok(_) => StatusCode::OK,
+                Err(status_code) => *status_code,
+            };
+
+            let response = JetMessage::JetAcceptRsp(JetAcceptRsp {
+                status_code,
+                version: request.version,
+                association: association.as_ref().copied().unwrap_or(request.association),
+                timeout: u32::try_from(ACCEPTOR_TIMEOUT.as_secs()).expect("small timeout"),
+                instance: conf.hostname.clone(),
+            });
+
+            write_jet_response(&mut stream, &response).await?;
+
+            let association = association.context("unauthorized acceptor")?;
+
+            let registration = registration.map_err(|status_code| {
+                anyhow::anyhow!("acceptor for association {association} refused ({status_code})")
+            })?;
+
+            info!(%association, candidate = %request.candidate, "Acceptor waiting for a connector");
+
+            registration.wait_for_connector(stream, peer_addr).await
+        }
+        JetMessage::JetConnectReq(request) => {
+            let claims = authorize(request.token.as_deref(), peer_addr, &conf, &state).and_then(|claims| {
+                anyhow::ensure!(
+                    request.association == claims.jet_aid,
+                    "token is not valid for association {}",
+                    request.association
+                );
+
+                Ok(claims)
+            });
+
+            // Version 1 connectors don't specify the candidate.
+            let candidate = Some(request.candidate).filter(|candidate| !candidate.is_nil());
+
+            let acceptor = match &claims {
+                Ok(_) => state.rendezvous.take_acceptor(request.association, candidate).await,
+                Err(_) => None,
+            };
+
+            let status_code = match (&claims, &acceptor) {
+                (Err(_), _) => StatusCode::UNAUTHORIZED,
+                (Ok(_), None) => StatusCode::NOT_FOUND,
+                (Ok(_), Some(_)) => StatusCode::OK,
+            };
+
+            let response = JetMessage::JetConnectRsp(JetConnectRsp {
+                status_code,
+                version: request.version,
+            });
+
+            write_jet_response(&mut stream, &response).await?;
+
+            let claims = claims.context("unauthorized connector")?;
+
+            let (acceptor_stream, acceptor_addr) =
+                acceptor.with_context(|| format!("no acceptor waiting for association {}", request.association))?;
+
+            let info = SessionInfo::new(claims.jet_aid, claims.jet_ap, ConnectionModeDetails::Rdv)
+                .with_ttl(claims.jet_ttl)
+                .with_recording_policy(claims.jet_rec)
+                .with_filtering_policy(claims.jet_flt);
+
+            info!(association = %request.association, %acceptor_addr, "TCP rendezvous");
+
+            Proxy::builder()
+                .conf(conf)
+                .session_info(info)
+                .address_a(peer_addr)
+                .transport_a(stream)
+                .address_b(acceptor_addr)
+                .transport_b(acceptor_stream)
+                .sessions(state.sessions)
+                .subscriber_tx(state.subscriber_tx)
+                .recordings(Some(state.recordings))
+                .build()
+                .select_dissector_and_forward()
+                .await
+                .context("encountered a failure during rendezvous traffic proxying")
+        }
+        unexpected => anyhow::bail!("unexpected Jet message: {unexpected:?}"),
+    }
+}
+
+fn authorize(
+    token: Option<&str>,
+    peer_addr: SocketAddr,
+    conf: &Conf,
+    state: &DgwState,
+) -> anyhow::Result<AssociationTokenClaims> {
+    let token = token.context("association token is missing")?;
+
+    let claims = validate_association_token(
+        token,
+        peer_addr.ip(),
+        conf,
+        &state.token_cache,
+        &state.jrl,
+        &state.recordings.active_recordings,
+    )?;
+
+    anyhow::ensure!(
+        matches!(claims.jet_cm, ConnectionMode::Rdv),
+        "token is not valid for a rendezvous connection"
+    );
+
+    Ok(claims)
+}
+
+async fn read_jet_request<S>(stream: &mut S) -> anyhow::Result<JetMessage>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut message = vec![0; JET_MSG_HEADER_SIZE as usize];
+    stream.read_exact(&mut message).await.context("read Jet header")?;
+
+    let message_size = usize::from(u16::from_be_bytes([message[4], message[5]]));
+    anyhow::ensure!(message_size >= message.len(), "invalid Jet message size");
+
+    let header_size = message.len();
+    message.resize(message_size, 0);
+    stream
+        .read_exact(&mut message[header_size..])
+        .await
+        .context("read Jet payload")?;
+
+    JetMessage::read_request(&mut message.as_slice()).context("invalid Jet request")
+}
+
+async fn write_jet_response<S>(stream: &mut S, response: &JetMessage) -> anyhow::Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
+    let mut buffer = Vec::new();
+    response.write_to(&mut buffer).context("encode Jet response")?;
+
+    stream.write_all(&buffer).await.context("write Jet response")?;
+    stream.flush().await.context("flush Jet response")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jet_proto::connect::JetConnectReq;
+
+    #[tokio::test]
+    async fn read_connect_request() {
+        let association = Uuid::new_v4();
+        let candidate = Uuid::new_v4();
+
+        let request = JetMessage::JetConnectReq(JetConnectReq {
+            version: 2,
+            host: "jetsocat".to_owned(),
+            association,
+            candidate,
+            token: None,
+        });
+
+        let mut buffer = Vec::new();
+        request.write_to(&mut buffer).unwrap();
+        buffer.extend_from_slice(b"payload");
+
+        let mut stream = buffer.as_slice();
+
+        assert_eq!(read_jet_request(&mut stream).await.unwrap(), request);
+        assert_eq!(stream, b"payload");
+    }
+
+    async fn tcp_pair() -> (TcpStream, TcpStream, SocketAddr) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (server, addr) = listener.accept().await.unwrap();
+        (client, server, addr)
+    }
+
+    #[tokio::test]
+    async fn connector_takes_the_acceptor_stream() {
+        let broker = RendezvousBroker::new();
+        let association = Uuid::new_v4();
+        let candidate = Uuid::new_v4();
+
+        let (mut acceptor, acceptor_server_side, acceptor_addr) = tcp_pair().await;
+
+        let registration = broker.register_acceptor(association, candidate).unwrap();
+        assert_eq!(broker.pending_acceptors(), 1);
+
+        // The association is taken as soon as the acceptor is registered.
+        assert_eq!(
+            broker.register_acceptor(association, Uuid::new_v4()).err(),
+            Some(StatusCode::CONFLICT)
+        );
+
+        let connector = async {
+            assert!(broker.take_acceptor(association, Some(Uuid::new_v4())).await.is_none());
+            broker.take_acceptor(association, Some(candidate)).await.unwrap()
+        };
+
+        let (registered, (mut stream, addr)) = tokio::join!(
+            registration.wait_for_connector(acceptor_server_side, acceptor_addr),
+            connector
+        );
+        registered.unwrap();
+        assert_eq!(addr, acceptor_addr);
+
+        stream.write_all(b"hello").await.unwrap();
+        let mut received = [0; 5];
+        acceptor.read_exact(&mut received).await.unwrap();
+        assert_eq!(&received, b"hello");
+
+        assert_eq!(broker.pending_acceptors(), 0);
+    }
+
+    #[tokio::test]
+    async fn acceptor_is_withdrawn_on_disconnection() {
+        let broker = RendezvousBroker::new();
+        let association = Uuid::new_v4();
+
+        let (acceptor, acceptor_server_side, acceptor_addr) = tcp_pair().await;
+        drop(acceptor);
+
+        let error = broker
+            .register_acceptor(association, Uuid::new_v4())
+            .unwrap()
+            .wait_for_connector(acceptor_server_side, acceptor_addr)
+            .await
+            .unwrap_err();
+
+        assert!(error.to_string().contains("acceptor left"));
+        assert_eq!(broker.pending_acceptors(), 0);
+        assert!(broker.take_acceptor(association, None).await.is_none());
+    }
+
+    #[test]
+    fn dropped_registration_is_withdrawn() {
+        let broker = RendezvousBroker::new();
+        let association = Uuid::new_v4();
+
+        drop(broker.register_acceptor(association, Uuid::new_v4()).unwrap());
+
+        assert_eq!(broker.pending_acceptors(), 0);
+        assert!(broker.register_acceptor(association, Uuid::new_v4()).is_ok());
+    }
+}
@@ -198,6 +198,7 @@ async fn spawn_tasks(conf_handle: ConfHandle) -> anyhow::Result<Tasks> {
     let (session_manager_handle, session_manager_rx) = session_manager_channel();
     let (recording_manager_handle, recording_manager_rx) = recording_message_channel();
     let (subscriber_tx, subscriber_rx) = subscriber_channel();
+    let acme_challenges = Arc::new(devolutions_gateway::acme::AcmeChallenges::new());
     let mut tasks = Tasks::new();
 
     let state = DgwState {
@@ -207,14 +208,21 @@ async fn spawn_tasks(conf_handle: ConfHandle) -> anyhow::Result<Tasks> {
         sessions: session_manager_handle.clone(),
         subscriber_tx: subscriber_tx.clone(),
         shutdown_signal: tasks.shutdown_signal.clone(),
-        recordings: recording_manager_handle,
+        recordings: recording_manager_handle.clone(),
+        rendezvous: Arc::new(devolutions_gateway::rendezvous::RendezvousBroker::new()),
+        acme_challenges: acme_challenges.clone(),
     };
 
     conf.listeners
         .iter()
         .map(|listener| {
-            GatewayListener::init_and_bind(listener.internal_url.clone(), state.clone())
-                .with_context(|| format!("failed to initialize {}", listener.internal_url))
+            GatewayListener::init_and_bind(
+                listener.internal_url.clone(),
+                listener.unix_socket.clone(),
+                state.clone(),
+            )
+            .map(|gateway_listener| gateway_listener.with_proxy_protocol(listener.proxy_protocol.clone()))
+            .with_context(|| format!("failed to initialize {}", listener.internal_url))
         })
         .collect::<anyhow::Result<Vec<GatewayListener>>>()
         .context("failed to bind listener")?
@@ -241,23 +249,48 @@ async fn spawn_tasks(conf_handle: ConfHandle) -> anyhow::Result<Tasks> {
         prefix: conf.log_file.clone(),
     });
 
+    tasks.register(devolutions_gateway::tls::TlsCertificateWatcherTask {
+        conf_handle: conf_handle.clone(),
+    });
+
+    tasks.register(devolutions_gateway::acme::AcmeTask {
+        conf_handle: conf_handle.clone(),
+        challenges: acme_challenges,
+    });
+
+    tasks.register(devolutions_gateway::recording::RecordingRetentionTask {
+        conf_handle: conf_handle.clone(),
+        active_recordings: recording_manager_handle.active_recordings.clone(),
+        index: recording_manager_handle.index.clone(),
+        subscriber_tx: subscriber_tx.clone(),
+    });
+
+    tasks.register(devolutions_gateway::recording::StoredRecordingIndexTask {
+        conf_handle: conf_handle.clone(),
+        index: recording_manager_handle.index.clone(),
+    });
+
     tasks.register(devolutions_gateway::subscriber::SubscriberPollingTask {
-        sessions: session_manager_handle,
-        subscriber: subscriber_tx,
+        sessions: session_manager_handle.clone(),
+        subscriber: subscriber_tx.clone(),
     });
 
     tasks.register(devolutions_gateway::subscriber::SubscriberTask {
-        conf_handle,
+        conf_handle: conf_handle.clone(),
         rx: subscriber_rx,
     });
 
     tasks.register(devolutions_gateway::session::SessionManagerTask::new(
         session_manager_rx,
+        conf_handle.clone(),
+        recording_manager_handle.active_recordings.clone(),
     ));
 
     tasks.register(devolutions_gateway::recording::RecordingManagerTask::new(
         recording_manager_rx,
-        conf.recording_path.clone(),
+        conf_handle,
+        session_manager_handle,
+        subscriber_tx,
     ));
 
     Ok(tasks)
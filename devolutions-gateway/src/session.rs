@@ -1,3 +1,5 @@
+use crate::config::ConfHandle;
+use crate::recording::ActiveRecordings;
 use crate::subscriber;
 use crate::target_addr::TargetAddr;
 use crate::token::{ApplicationProtocol, SessionTtl};
@@ -106,11 +108,14 @@ pub async fn remove_session_in_progress(
         .await
         .context("couldn't remove running session")?;
 
-    if let Some(session) = removed_session {
-        let message = subscriber::Message::session_ended(subscriber::SubscriberSessionInfo {
-            association_id: id,
-            start_timestamp: session.start_timestamp,
-        });
+    if let Some((session, kill_reason)) = removed_session {
+        let message = subscriber::Message::session_ended(
+            subscriber::SubscriberSessionInfo {
+                association_id: id,
+                start_timestamp: session.start_timestamp,
+            },
+            kill_reason,
+        );
 
         if let Err(error) = subscriber_tx.try_send(message) {
             warn!(%error, "Failed to send subscriber message");
@@ -128,6 +133,18 @@ pub enum KillResult {
     NotFound,
 }
 
+/// Reason why a running session was killed by the Gateway
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KillReason {
+    /// Termination was requested using the API
+    Requested,
+    /// The session reached its maximum duration
+    TimeToLive,
+    /// The session must be recorded, but no recording is active
+    RecordingPolicy,
+}
+
 enum SessionManagerMessage {
     New {
         info: SessionInfo,
@@ -135,7 +152,7 @@ enum SessionManagerMessage {
     },
     Remove {
         id: Uuid,
-        channel: oneshot::Sender<Option<SessionInfo>>,
+        channel: oneshot::Sender<Option<(SessionInfo, Option<KillReason>)>>,
     },
     Kill {
         id: Uuid,
@@ -147,6 +164,9 @@ enum SessionManagerMessage {
     GetCount {
         channel: oneshot::Sender<usize>,
     },
+    EnforceRecordingPolicy {
+        id: Uuid,
+    },
 }
 
 impl fmt::Debug for SessionManagerMessage {
@@ -163,6 +183,9 @@ impl fmt::Debug for SessionManagerMessage {
             }
             SessionManagerMessage::GetRunning { channel: _ } => f.debug_struct("GetRunning").finish_non_exhaustive(),
             SessionManagerMessage::GetCount { channel: _ } => f.debug_struct("GetCount").finish_non_exhaustive(),
+            SessionManagerMessage::EnforceRecordingPolicy { id } => {
+                f.debug_struct("EnforceRecordingPolicy").field("id", id).finish()
+            }
         }
     }
 }
@@ -179,7 +202,8 @@ impl SessionMessageSender {
             .context("couldn't send New message")
     }
 
-    pub async fn remove_session(&self, id: Uuid) -> anyhow::Result<Option<SessionInfo>> {
+    /// Removes the session, and returns it along with the reason why it was killed, if it was.
+    pub async fn remove_session(&self, id: Uuid) -> anyhow::Result<Option<(SessionInfo, Option<KillReason>)>> {
         let (tx, rx) = oneshot::channel();
         self.0
             .send(SessionManagerMessage::Remove { id, channel: tx })
@@ -218,6 +242,15 @@ impl SessionMessageSender {
             .context("couldn't send GetRunning message")?;
         rx.await.context("couldn't receive running session count")
     }
+
+    /// Kills the session if it must be recorded, but no recording is active.
+    pub async fn enforce_recording_policy(&self, id: Uuid) -> anyhow::Result<()> {
+        self.0
+            .send(SessionManagerMessage::EnforceRecordingPolicy { id })
+            .await
+            .ok()
+            .context("couldn't send EnforceRecordingPolicy message")
+    }
 }
 
 pub struct SessionMessageReceiver(mpsc::Receiver<SessionManagerMessage>);
@@ -259,14 +292,20 @@ pub struct SessionManagerTask {
     rx: SessionMessageReceiver,
     all_running: RunningSessions,
     all_notify_kill: HashMap<Uuid, Arc<Notify>>,
+    kill_reasons: HashMap<Uuid, KillReason>,
+    conf_handle: ConfHandle,
+    active_recordings: Arc<ActiveRecordings>,
 }
 
 impl SessionManagerTask {
-    pub fn new(rx: SessionMessageReceiver) -> Self {
+    pub fn new(rx: SessionMessageReceiver, conf_handle: ConfHandle, active_recordings: Arc<ActiveRecordings>) -> Self {
         Self {
             rx,
             all_running: HashMap::new(),
             all_notify_kill: HashMap::new(),
+            kill_reasons: HashMap::new(),
+            conf_handle,
+            active_recordings,
         }
     }
 
@@ -276,21 +315,53 @@ impl SessionManagerTask {
         self.all_notify_kill.insert(id, notify_kill);
     }
 
-    fn handle_remove(&mut self, id: Uuid) -> Option<SessionInfo> {
+    fn handle_remove(&mut self, id: Uuid) -> Option<(SessionInfo, Option<KillReason>)> {
         let removed_session = self.all_running.remove(&id);
         let _ = self.all_notify_kill.remove(&id);
-        removed_session
+        let kill_reason = self.kill_reasons.remove(&id);
+        removed_session.map(|session| (session, kill_reason))
     }
 
-    fn handle_kill(&self, id: Uuid) -> KillResult {
+    fn handle_kill(&mut self, id: Uuid, reason: KillReason) -> KillResult {
         match self.all_notify_kill.get(&id) {
             Some(notify_kill) => {
                 notify_kill.notify_waiters();
+                // The session may be killed several times before it actually ends: the first reason is kept.
+                self.kill_reasons.entry(id).or_insert(reason);
                 KillResult::Success
             }
             None => KillResult::NotFound,
         }
     }
+
+    fn handle_enforce_recording_policy(&mut self, id: Uuid) {
+        let Some(session) = self.all_running.get(&id) else {
+            trace!(session.id = %id, "Session already ended");
+            return;
+        };
+
+        if !session.recording_policy {
+            return;
+        }
+
+        if self.active_recordings.contains(id) {
+            trace!(session.id = %id, "Recording policy is met");
+            return;
+        }
+
+        match self.handle_kill(id, KillReason::RecordingPolicy) {
+            KillResult::Success => {
+                warn!(
+                    session.id = %id,
+                    reason = ?KillReason::RecordingPolicy,
+                    "Session killed because it is not recorded"
+                );
+            }
+            KillResult::NotFound => {
+                debug!(session.id = %id, "Session already ended");
+            }
+        }
+    }
 }
 
 #[async_trait]
@@ -312,12 +383,17 @@ async fn session_manager_task(
     debug!("Task started");
 
     let mut with_ttl = BinaryHeap::<WithTtlInfo>::new();
+    let mut with_recording_policy = BinaryHeap::<WithTtlInfo>::new();
 
     let auto_kill_sleep = tokio::time::sleep_until(tokio::time::Instant::now());
     tokio::pin!(auto_kill_sleep);
 
-    // Consume initial sleep
+    let recording_policy_sleep = tokio::time::sleep_until(tokio::time::Instant::now());
+    tokio::pin!(recording_policy_sleep);
+
+    // Consume initial sleeps
     (&mut auto_kill_sleep).await;
+    (&mut recording_policy_sleep).await;
 
     loop {
         tokio::select! {
@@ -325,7 +401,7 @@ async fn session_manager_task(
                 // Will never panic since we check for non-emptiness before entering this block
                 let to_kill = with_ttl.pop().unwrap();
 
-                match manager.handle_kill(to_kill.session_id) {
+                match manager.handle_kill(to_kill.session_id, KillReason::TimeToLive) {
                     KillResult::Success => {
                         info!(session.id = %to_kill.session_id, "Session killed because it reached its max duration");
                     }
@@ -339,6 +415,17 @@ async fn session_manager_task(
                     auto_kill_sleep.as_mut().reset(next.deadline)
                 }
             }
+            () = &mut recording_policy_sleep, if !with_recording_policy.is_empty() => {
+                // Will never panic since we check for non-emptiness before entering this block
+                let to_check = with_recording_policy.pop().unwrap();
+
+                manager.handle_enforce_recording_policy(to_check.session_id);
+
+                // Re-arm the Sleep instance with the next deadline if required
+                if let Some(next) = with_recording_policy.peek() {
+                    recording_policy_sleep.as_mut().reset(next.deadline)
+                }
+            }
             msg = manager.rx.0.recv() => {
                 let Some(msg) = msg else {
                     warn!("All senders are dead");
@@ -366,6 +453,23 @@ async fn session_manager_task(
                             debug!(session.id = %info.id(), minutes = minutes.get(), "Limited TTL session registed");
                         }
 
+                        if info.recording_policy {
+                            let grace_period = manager.conf_handle.get_conf().recording.policy_grace_period;
+                            let now = tokio::time::Instant::now();
+                            let deadline = now + grace_period;
+                            with_recording_policy.push(WithTtlInfo {
+                                deadline,
+                                session_id: info.id(),
+                            });
+
+                            // Reset the Sleep instance if the new deadline is sooner or it is already elapsed
+                            if recording_policy_sleep.is_elapsed() || deadline < recording_policy_sleep.deadline() {
+                                recording_policy_sleep.as_mut().reset(deadline);
+                            }
+
+                            debug!(session.id = %info.id(), ?grace_period, "Session with recording policy registered");
+                        }
+
                         manager.handle_new(info, notify_kill);
                     },
                     SessionManagerMessage::Remove { id, channel } => {
@@ -373,7 +477,7 @@ async fn session_manager_task(
                         let _ = channel.send(removed_session);
                     }
                     SessionManagerMessage::Kill { id, channel } => {
-                        let kill_result = manager.handle_kill(id);
+                        let kill_result = manager.handle_kill(id, KillReason::Requested);
                         let _ = channel.send(kill_result);
                     }
                     SessionManagerMessage::GetRunning { channel } => {
@@ -382,6 +486,9 @@ async fn session_manager_task(
                     SessionManagerMessage::GetCount { channel } => {
                         let _ = channel.send(manager.all_running.len());
                     }
+                    SessionManagerMessage::EnforceRecordingPolicy { id } => {
+                        manager.handle_enforce_recording_policy(id);
+                    }
                 }
             }
             _ = shutdown_signal.wait() => {
@@ -416,3 +523,125 @@ async fn session_manager_task(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::mock_conf_handle;
+    use devolutions_gateway_task::ShutdownHandle;
+    use futures::FutureExt as _;
+
+    fn conf_handle() -> ConfHandle {
+        mock_conf_handle(serde_json::json!({
+            "Recording": {
+                "PolicyGracePeriod": 1
+            }
+        }))
+    }
+
+    #[test]
+    fn enforcing_recording_policy_kills_unrecorded_sessions_only() {
+        let conf_handle = conf_handle();
+        let active_recordings = Arc::new(ActiveRecordings::default());
+        let (_tx, rx) = session_manager_channel();
+
+        let mut manager = SessionManagerTask::new(rx, conf_handle, Arc::clone(&active_recordings));
+
+        let session = |recording_policy| {
+            SessionInfo::new(
+                Uuid::new_v4(),
+                ApplicationProtocol::unknown(),
+                ConnectionModeDetails::Rdv,
+            )
+            .with_recording_policy(recording_policy)
+        };
+
+        let unrecorded = session(true);
+        let recorded = session(true);
+        let unrestricted = session(false);
+
+        active_recordings.insert(recorded.id());
+
+        for (info, should_be_killed) in [(&unrecorded, true), (&recorded, false), (&unrestricted, false)] {
+            let notify_kill = Arc::new(Notify::new());
+            let mut killed = std::pin::pin!(notify_kill.notified());
+            killed.as_mut().enable();
+
+            manager.handle_new(info.clone(), Arc::clone(&notify_kill));
+            manager.handle_enforce_recording_policy(info.id());
+
+            assert_eq!(killed.now_or_never().is_some(), should_be_killed);
+        }
+
+        // A later kill request doesn't hide the reason why the session was killed in the first place.
+        assert!(matches!(
+            manager.handle_kill(unrecorded.id(), KillReason::Requested),
+            KillResult::Success
+        ));
+
+        let (_, kill_reason) = manager.handle_remove(unrecorded.id()).unwrap();
+        assert_eq!(kill_reason, Some(KillReason::RecordingPolicy));
+
+        for id in [recorded.id(), unrestricted.id()] {
+            let (_, kill_reason) = manager.handle_remove(id).unwrap();
+            assert_eq!(kill_reason, None);
+        }
+
+        // The session may end before the recording policy is enforced.
+        manager.handle_enforce_recording_policy(unrecorded.id());
+        assert!(manager.kill_reasons.is_empty());
+    }
+
+    #[tokio::test]
+    async fn unrecorded_session_is_killed_after_grace_period() {
+        let conf_handle = conf_handle();
+        let active_recordings = Arc::new(ActiveRecordings::default());
+        let (tx, rx) = session_manager_channel();
+        let (shutdown_handle, shutdown_signal) = ShutdownHandle::new();
+
+        let manager = SessionManagerTask::new(rx, conf_handle, Arc::clone(&active_recordings));
+        let task = tokio::spawn(session_manager_task(manager, shutdown_signal));
+
+        let session = |recording_policy| {
+            SessionInfo::new(
+                Uuid::new_v4(),
+                ApplicationProtocol::unknown(),
+                ConnectionModeDetails::Rdv,
+            )
+            .with_recording_policy(recording_policy)
+        };
+
+        let unrecorded = session(true);
+        let recorded = session(true);
+        let unrestricted = session(false);
+
+        active_recordings.insert(recorded.id());
+
+        let unrecorded_kill = Arc::new(Notify::new());
+        let killed = unrecorded_kill.notified();
+        tokio::pin!(killed);
+        killed.as_mut().enable();
+
+        for (info, notify_kill) in [
+            (unrecorded.clone(), Arc::clone(&unrecorded_kill)),
+            (recorded.clone(), Arc::new(Notify::new())),
+            (unrestricted.clone(), Arc::new(Notify::new())),
+        ] {
+            tx.new_session(info, notify_kill).await.unwrap();
+        }
+
+        tokio::time::timeout(Duration::from_secs(5), killed).await.unwrap();
+
+        let (_, kill_reason) = tx.remove_session(unrecorded.id()).await.unwrap().unwrap();
+        assert_eq!(kill_reason, Some(KillReason::RecordingPolicy));
+
+        for id in [recorded.id(), unrestricted.id()] {
+            let (_, kill_reason) = tx.remove_session(id).await.unwrap().unwrap();
+            assert_eq!(kill_reason, None);
+        }
+
+        shutdown_handle.signal();
+        drop(tx);
+        task.await.unwrap().unwrap();
+    }
+}
@@ -1,6 +1,6 @@
 use crate::config::dto::Subscriber;
 use crate::config::ConfHandle;
-use crate::session::SessionMessageSender;
+use crate::session::{KillReason, SessionMessageSender};
 use anyhow::Context as _;
 use async_trait::async_trait;
 use devolutions_gateway_task::{ChildTask, ShutdownSignal, Task};
@@ -24,6 +24,32 @@ pub struct SubscriberSessionInfo {
     pub start_timestamp: OffsetDateTime,
 }
 
+#[derive(Debug, Serialize)]
+pub struct SubscriberRecordingInfo {
+    pub session_id: Uuid,
+    /// Size of the recording folder, in bytes
+    pub size: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SubscriberStorageInfo {
+    /// Space available on the recording volume, in bytes
+    pub available_space: u64,
+    /// Configured free space threshold, in bytes
+    pub threshold: u64,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordingDeletionReason {
+    /// The recording is older than the configured maximum age
+    MaxAge,
+    /// The recording folder exceeded the configured maximum total size
+    MaxTotalSize,
+    /// The free space on the recording volume went below the configured minimum
+    MinFreeSpace,
+}
+
 #[derive(Debug, Serialize)]
 #[serde(tag = "kind")]
 #[allow(clippy::enum_variant_names)]
@@ -31,9 +57,20 @@ enum MessageInner {
     #[serde(rename = "session.started")]
     SessionStarted { session: SubscriberSessionInfo },
     #[serde(rename = "session.ended")]
-    SessionEnded { session: SubscriberSessionInfo },
+    SessionEnded {
+        session: SubscriberSessionInfo,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        kill_reason: Option<KillReason>,
+    },
     #[serde(rename = "session.list")]
     SessionList { session_list: Vec<SubscriberSessionInfo> },
+    #[serde(rename = "recording.deleted")]
+    RecordingDeleted {
+        recording: SubscriberRecordingInfo,
+        reason: RecordingDeletionReason,
+    },
+    #[serde(rename = "recording.storage_low")]
+    RecordingStorageLow { storage: SubscriberStorageInfo },
 }
 
 #[derive(Debug, Serialize)]
@@ -52,10 +89,11 @@ impl Message {
         }
     }
 
-    pub fn session_ended(session: SubscriberSessionInfo) -> Self {
+    /// The kill reason is specified when the session was killed by the Gateway.
+    pub fn session_ended(session: SubscriberSessionInfo, kill_reason: Option<KillReason>) -> Self {
         Self {
             timestamp: OffsetDateTime::now_utc(),
-            inner: MessageInner::SessionEnded { session },
+            inner: MessageInner::SessionEnded { session, kill_reason },
         }
     }
 
@@ -65,6 +103,20 @@ impl Message {
             inner: MessageInner::SessionList { session_list },
         }
     }
+
+    pub fn recording_deleted(recording: SubscriberRecordingInfo, reason: RecordingDeletionReason) -> Self {
+        Self {
+            timestamp: OffsetDateTime::now_utc(),
+            inner: MessageInner::RecordingDeleted { recording, reason },
+        }
+    }
+
+    pub fn recording_storage_low(storage: SubscriberStorageInfo) -> Self {
+        Self {
+            timestamp: OffsetDateTime::now_utc(),
+            inner: MessageInner::RecordingStorageLow { storage },
+        }
+    }
 }
 
 #[instrument(skip(subscriber))]
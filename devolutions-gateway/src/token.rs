@@ -262,6 +262,9 @@ pub enum RecordingFileType {
     WebM,
     /// Terminal Playback
     TRP,
+    /// Asciicast v2 terminal recording
+    #[serde(rename = "cast")]
+    Asciicast,
 }
 
 impl RecordingFileType {
@@ -269,6 +272,7 @@ impl RecordingFileType {
         match self {
             RecordingFileType::WebM => "webm",
             RecordingFileType::TRP => "trp",
+            RecordingFileType::Asciicast => "cast",
         }
     }
 }
@@ -367,6 +371,11 @@ pub struct AssociationTokenClaims {
     /// Max session duration
     pub jet_ttl: SessionTtl,
 
+    /// SHA-256 hashes of the SubjectPublicKeyInfo expected from the target server
+    ///
+    /// When set, the target certificate is trusted if its public key matches one of these.
+    pub jet_tls_pins: Vec<[u8; 32]>,
+
     /// JWT expiration time claim.
     ///
     /// We need this to build our token invalidation cache.
@@ -408,6 +417,10 @@ pub enum AccessScope {
     HeartbeatRead,
     #[serde(rename = "gateway.recordings.read")]
     RecordingsRead,
+    #[serde(rename = "gateway.recordings.delete")]
+    RecordingsDelete,
+    #[serde(rename = "gateway.recordings.import")]
+    RecordingsImport,
 }
 
 #[derive(Clone, Deserialize)]
@@ -423,6 +436,17 @@ pub struct ScopeTokenClaims {
     jti: Option<Uuid>,
 }
 
+impl ScopeTokenClaims {
+    /// Claims granting all the scopes, for requests authenticated without a token
+    pub(crate) fn wildcard() -> Self {
+        Self {
+            scope: AccessScope::Wildcard,
+            exp: i64::MAX,
+            jti: None,
+        }
+    }
+}
+
 // ----- bridge claims ----- //
 
 #[derive(Clone, Deserialize)]
@@ -1162,6 +1186,8 @@ mod serde_impl {
         jet_flt: bool,
         #[serde(default)]
         jet_ttl: SessionTtl,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        jet_tls_pins: Vec<String>,
         exp: i64,
         jti: Option<Uuid>, // DVLS up to 2022.1.9 do not generate this claim.
     }
@@ -1232,6 +1258,11 @@ mod serde_impl {
                 jet_rec: self.jet_rec,
                 jet_flt: self.jet_flt,
                 jet_ttl: self.jet_ttl,
+                jet_tls_pins: self
+                    .jet_tls_pins
+                    .iter()
+                    .map(|hash| multibase::Base::Base64Pad.encode(hash))
+                    .collect(),
                 exp: self.exp,
                 jti: self.jti,
             }
@@ -1271,6 +1302,13 @@ mod serde_impl {
                 }
             };
 
+            let jet_tls_pins = claims
+                .jet_tls_pins
+                .iter()
+                .map(|hash| crate::tls::parse_spki_pin(hash))
+                .collect::<anyhow::Result<Vec<_>>>()
+                .map_err(|e| de::Error::custom(format!("invalid jet_tls_pins: {e:#}")))?;
+
             Ok(Self {
                 jet_aid: claims.jet_aid,
                 jet_ap: claims.jet_ap,
@@ -1278,6 +1316,7 @@ mod serde_impl {
                 jet_rec: claims.jet_rec,
                 jet_flt: claims.jet_flt,
                 jet_ttl: claims.jet_ttl,
+                jet_tls_pins,
                 exp: claims.exp,
                 jti: claims.jti,
             })
@@ -0,0 +1,282 @@
+//! Connection to the targets through an upstream proxy
+//!
+//! Some networks require going through a SOCKS5 or HTTP CONNECT proxy in order to reach the targets. The proxy is
+//! selected for each target using the rules from the configuration, and the target host name is resolved by the proxy.
+
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use proxy_http::ProxyStream;
+use proxy_socks::Socks5Stream;
+use proxy_types::DestAddr;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+
+use crate::config::{UpstreamProxy, UpstreamProxyKind};
+use crate::target_addr::TargetAddr;
+
+/// Stream to a target, either direct or tunneled through an upstream proxy
+#[derive(Debug)]
+pub enum TargetStream {
+    Tcp(TcpStream),
+    /// The HTTP proxy may have sent bytes from the target along with its response, so the wrapper is kept.
+    HttpTunnel(ProxyStream<TcpStream>),
+}
+
+impl TargetStream {
+    /// Address of the peer of the underlying TCP connection: either the target, or the upstream proxy.
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        match self {
+            TargetStream::Tcp(stream) => stream.peer_addr(),
+            TargetStream::HttpTunnel(stream) => stream.get_ref().peer_addr(),
+        }
+    }
+}
+
+/// Opens a tunnel to the target over a connection established with the upstream proxy.
+///
+/// The host name of the target, if any, is resolved by the proxy.
+pub async fn open_tunnel(stream: TcpStream, proxy: &UpstreamProxy, target: &TargetAddr) -> io::Result<TargetStream> {
+    let proxy_addr = stream.peer_addr()?;
+
+    let dest = match target.host_ip() {
+        Some(ip) => DestAddr::Ip(SocketAddr::new(ip, target.port())),
+        None => DestAddr::Domain(target.host().to_owned(), target.port()),
+    };
+
+    let stream = match (proxy.kind, &proxy.credentials) {
+        (UpstreamProxyKind::Socks5, None) => Socks5Stream::connect(stream, dest)
+            .await
+            .map(Socks5Stream::into_inner)
+            .map(TargetStream::Tcp)?,
+        (UpstreamProxyKind::Socks5, Some((username, password))) => {
+            Socks5Stream::connect_with_password(stream, dest, username.as_str(), password.get())
+                .await
+                .map(Socks5Stream::into_inner)
+                .map(TargetStream::Tcp)?
+        }
+        (UpstreamProxyKind::Http, _) => ProxyStream::connect(stream, dest).await.map(TargetStream::HttpTunnel)?,
+    };
+
+    debug!(proxy = %proxy_addr, destination = %target, "Tunnel opened through upstream proxy");
+
+    Ok(stream)
+}
+
+impl AsyncRead for TargetStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            TargetStream::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
+            TargetStream::HttpTunnel(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for TargetStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            TargetStream::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
+            TargetStream::HttpTunnel(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            TargetStream::Tcp(stream) => Pin::new(stream).poll_flush(cx),
+            TargetStream::HttpTunnel(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            TargetStream::Tcp(stream) => Pin::new(stream).poll_shutdown(cx),
+            TargetStream::HttpTunnel(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfHandle;
+    use crate::test_utils::mock_conf_handle;
+    use proxy_http::HttpProxyAcceptor;
+    use proxy_socks::Socks5Acceptor;
+    use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+    use tokio::net::TcpListener;
+
+    fn target(addr: &str) -> TargetAddr {
+        TargetAddr::parse(addr, None).unwrap()
+    }
+
+    fn conf_with_proxy_rules(proxy_rules: &str) -> ConfHandle {
+        let proxy_rules: serde_json::Value = serde_json::from_str(proxy_rules).unwrap();
+
+        mock_conf_handle(serde_json::json!({
+            "TargetConnection": {
+                "ProxyRules": proxy_rules
+            }
+        }))
+    }
+
+    #[test]
+    fn destination_patterns() {
+        let conf = conf_with_proxy_rules(
+            r#"[{ "Destinations": ["*", "10.0.0.0/8", "192.168.1.1", "*.example.com", "host.internal"] }]"#,
+        )
+        .get_conf();
+
+        let [any, network, ip, wildcard, host] = conf.target_connection.proxy_rules[0].destinations.as_slice() else {
+            panic!("unexpected destinations");
+        };
+
+        assert!(any.matches(&target("tcp://anything:22")));
+
+        assert!(network.matches(&target("tcp://10.1.2.3:22")));
+        assert!(network.matches(&target("tcp://[::ffff:10.1.2.3]:22")));
+        assert!(!network.matches(&target("tcp://11.1.2.3:22")));
+        assert!(!network.matches(&target("tcp://10.example.com:22")));
+
+        assert!(ip.matches(&target("tcp://192.168.1.1:3389")));
+        assert!(!ip.matches(&target("tcp://192.168.1.2:3389")));
+
+        assert!(wildcard.matches(&target("tcp://server.example.com:22")));
+        assert!(!wildcard.matches(&target("tcp://example.com:22")));
+        assert!(!wildcard.matches(&target("tcp://server.example.org:22")));
+
+        assert!(host.matches(&target("tcp://host.internal:22")));
+        assert!(!host.matches(&target("tcp://other.internal:22")));
+    }
+
+    #[test]
+    fn first_matching_rule_applies() {
+        let conf = conf_with_proxy_rules(
+            r#"[
+                { "Destinations": ["10.10.0.0/16"] },
+                { "Destinations": ["10.0.0.0/8", "*.lab.example.com"], "Proxy": "socks5://socks.example.com:1080" },
+                { "Destinations": ["*"], "Proxy": "http://http.example.com:8080" }
+            ]"#,
+        )
+        .get_conf();
+
+        let proxy_for = |addr: &str| {
+            conf.target_connection
+                .proxy_for(&target(addr))
+                .map(|proxy| (proxy.kind, proxy.address.clone()))
+        };
+
+        assert_eq!(proxy_for("tcp://10.10.1.1:22"), None);
+        assert_eq!(
+            proxy_for("tcp://10.20.1.1:22"),
+            Some((UpstreamProxyKind::Socks5, "socks.example.com:1080".to_owned()))
+        );
+        assert_eq!(
+            proxy_for("tcp://server.lab.example.com:22"),
+            Some((UpstreamProxyKind::Socks5, "socks.example.com:1080".to_owned()))
+        );
+        assert_eq!(
+            proxy_for("tcp://server.example.com:22"),
+            Some((UpstreamProxyKind::Http, "http.example.com:8080".to_owned()))
+        );
+    }
+
+    fn targets() -> [TargetAddr; 2] {
+        [target("tcp://192.168.1.10:3389"), target("tcp://server.example.com:22")]
+    }
+
+    /// Connects to each target through the proxy, checking the tunnel with a ping, and returns the target addresses.
+    async fn connect_through(proxy: &str, proxy_addr: SocketAddr) -> Vec<Option<SocketAddr>> {
+        let conf = conf_with_proxy_rules(&format!(r#"[{{ "Destinations": ["*"], "Proxy": "{proxy}" }}]"#)).get_conf();
+
+        let mut target_addrs = Vec::new();
+
+        for target in targets() {
+            let ((mut stream, target_addr), _) = crate::utils::tcp_connect([&target], &conf.target_connection)
+                .await
+                .unwrap();
+            assert_eq!(stream.peer_addr().unwrap(), proxy_addr);
+            target_addrs.push(target_addr);
+
+            stream.write_all(b"ping").await.unwrap();
+            let mut buf = [0; 4];
+            stream.read_exact(&mut buf).await.unwrap();
+            assert_eq!(&buf, b"ping");
+        }
+
+        target_addrs
+    }
+
+    fn expected_target_addrs() -> [Option<SocketAddr>; 2] {
+        [Some("192.168.1.10:3389".parse().unwrap()), None]
+    }
+
+    fn expected_requests() -> [DestAddr; 2] {
+        [
+            DestAddr::Ip("192.168.1.10:3389".parse().unwrap()),
+            DestAddr::Domain("server.example.com".to_owned(), 22),
+        ]
+    }
+
+    #[tokio::test]
+    async fn socks5_tunnel_returns_target_address() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy_addr = listener.local_addr().unwrap();
+
+        // Stub proxy echoing back the data instead of connecting to the requested destination.
+        let stub = tokio::spawn(async move {
+            let mut requested = Vec::new();
+
+            for _ in 0..2 {
+                let (stream, _) = listener.accept().await.unwrap();
+                let acceptor = Socks5Acceptor::accept(stream).await.unwrap();
+                assert!(acceptor.is_connect_command());
+                requested.push(acceptor.dest_addr().clone());
+
+                let mut stream = acceptor.connected("127.0.0.1:0").await.unwrap();
+                let mut buf = [0; 4];
+                stream.read_exact(&mut buf).await.unwrap();
+                stream.write_all(&buf).await.unwrap();
+            }
+
+            requested
+        });
+
+        let target_addrs = connect_through(&format!("socks5://{proxy_addr}"), proxy_addr).await;
+
+        assert_eq!(target_addrs, expected_target_addrs());
+        assert_eq!(stub.await.unwrap(), expected_requests());
+    }
+
+    #[tokio::test]
+    async fn http_connect_tunnel_returns_target_address() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy_addr = listener.local_addr().unwrap();
+
+        // Stub proxy echoing back the data instead of connecting to the requested destination.
+        let stub = tokio::spawn(async move {
+            let mut requested = Vec::new();
+
+            for _ in 0..2 {
+                let (stream, _) = listener.accept().await.unwrap();
+                let HttpProxyAcceptor::TunnelRequest(request) = HttpProxyAcceptor::accept(stream).await.unwrap() else {
+                    panic!("expected a CONNECT request");
+                };
+                requested.push(request.dest_addr().clone());
+
+                let mut stream = request.success().await.unwrap();
+                let mut buf = [0; 4];
+                stream.read_exact(&mut buf).await.unwrap();
+                stream.write_all(&buf).await.unwrap();
+            }
+
+            requested
+        });
+
+        let target_addrs = connect_through(&format!("http://{proxy_addr}"), proxy_addr).await;
+
+        assert_eq!(target_addrs, expected_target_addrs());
+        assert_eq!(stub.await.unwrap(), expected_requests());
+    }
+}
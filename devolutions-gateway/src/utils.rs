@@ -1,64 +1,225 @@
 use anyhow::Context as _;
-use std::fmt;
+use std::collections::VecDeque;
 use std::net::SocketAddr;
+use std::{fmt, io};
 use tokio::net::{lookup_host, TcpStream};
+use tokio::time::Instant;
 use url::Url;
 
+use crate::config::{TargetConnectionConf, UpstreamProxy};
 use crate::target_addr::TargetAddr;
+use crate::upstream_proxy::TargetStream;
 
-pub async fn tcp_connect(dest: &TargetAddr) -> anyhow::Result<(TcpStream, SocketAddr)> {
-    const CONNECTION_TIMEOUT: tokio::time::Duration = tokio::time::Duration::from_secs(10);
+/// Connects to the first reachable target, racing the connection attempts (RFC 8305, "Happy Eyeballs").
+///
+/// Targets are resolved concurrently, and their addresses are tried in order, IPv6 and IPv4 alternating.
+/// A new attempt is started every `attempt_delay` while the previous ones are still pending, or as soon as
+/// one of them fails. The first established connection wins, and the other attempts are cancelled.
+///
+/// Targets matching a proxy rule are not resolved locally: the addresses of the upstream proxy are raced instead, and
+/// the tunnel is opened through the first connection established. The returned address is the one of the target, which
+/// is unknown when its host name is resolved by the proxy.
+pub async fn tcp_connect<'a>(
+    targets: impl IntoIterator<Item = &'a TargetAddr>,
+    conf: &TargetConnectionConf,
+) -> anyhow::Result<((TargetStream, Option<SocketAddr>), &'a TargetAddr)> {
+    let mut error: Option<anyhow::Error> = None;
+
+    let race = race_connection_attempts(targets, conf, &mut error);
+    let result = tokio::time::timeout(conf.timeout, race).await;
 
-    let fut = async move {
-        let addrs = lookup_host(dest.as_addr())
-            .await
-            .context("failed to lookup destination address")?;
+    match result {
+        Ok(Some(connected)) => Ok(connected),
+        Ok(None) => Err(error.context("empty target list")?),
+        Err(_) => {
+            let timed_out = io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("connection timed out after {:?}", conf.timeout),
+            );
+            Err(match error {
+                Some(error) => error.context(timed_out),
+                None => anyhow::Error::new(timed_out),
+            })
+        }
+    }
+}
 
-        let mut last_err = None;
+/// Kind of the IO error at the origin of a connection failure, for reporting purposes.
+pub fn io_error_kind(error: &anyhow::Error) -> io::ErrorKind {
+    error
+        .downcast_ref::<io::Error>()
+        .or_else(|| error.chain().find_map(|source| source.downcast_ref::<io::Error>()))
+        .map_or(io::ErrorKind::Other, io::Error::kind)
+}
 
-        for addr in addrs {
-            match TcpStream::connect(addr).await {
-                Ok(stream) => return Ok((stream, addr)),
-                Err(error) => {
-                    warn!(%error, resolved = %addr, destination = %dest, "Failed to connect to a resolved address");
-                    last_err = Some(anyhow::Error::new(error).context("TcpStream::connect"))
-                }
-            }
+/// Way to reach a target
+#[derive(Clone, Copy)]
+enum Candidate<'a> {
+    Direct(SocketAddr),
+    /// Through the upstream proxy, at the provided address
+    Proxy(&'a UpstreamProxy, SocketAddr),
+}
+
+impl fmt::Display for Candidate<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Candidate::Direct(addr) => write!(f, "{addr}"),
+            Candidate::Proxy(proxy, addr) => write!(f, "proxy {} ({addr})", proxy.address),
         }
+    }
+}
 
-        Err::<_, anyhow::Error>(last_err.unwrap_or_else(|| anyhow::format_err!("could not resolve to any address")))
+async fn race_connection_attempts<'a>(
+    targets: impl IntoIterator<Item = &'a TargetAddr>,
+    conf: &TargetConnectionConf,
+    error: &mut Option<anyhow::Error>,
+) -> Option<((TargetStream, Option<SocketAddr>), &'a TargetAddr)> {
+    use futures::stream::{FuturesUnordered, StreamExt as _};
+
+    let mut record_error = |e: anyhow::Error| {
+        *error = Some(match error.take() {
+            Some(prev_err) => prev_err.context(e),
+            None => e,
+        });
     };
 
-    let result = tokio::time::timeout(CONNECTION_TIMEOUT, fut).await??;
+    let mut lookups = targets
+        .into_iter()
+        .enumerate()
+        .map(|(idx, target)| async move {
+            let result = match conf.proxy_for(target) {
+                Some(proxy) => lookup_host(proxy.address.as_str()).await.map(|addrs| {
+                    interleave_address_families(addrs)
+                        .into_iter()
+                        .map(|addr| Candidate::Proxy(proxy, addr))
+                        .collect()
+                }),
+                None => lookup_host(target.as_addr()).await.map(|addrs| {
+                    interleave_address_families(addrs)
+                        .into_iter()
+                        .map(Candidate::Direct)
+                        .collect()
+                }),
+            };
+            (idx, target, result)
+        })
+        .collect::<FuturesUnordered<_>>();
 
-    Ok(result)
-}
+    // Candidates of each target, in the order the targets were provided.
+    let mut resolved: Vec<(usize, VecDeque<(Candidate<'_>, &TargetAddr)>)> = Vec::new();
 
-pub async fn successive_try<'a, F, Fut, In, Out>(
-    inputs: impl IntoIterator<Item = &'a In>,
-    func: F,
-) -> anyhow::Result<(Out, &'a In)>
-where
-    In: fmt::Display + 'a,
-    F: Fn(&'a In) -> Fut + 'a,
-    Fut: core::future::Future<Output = anyhow::Result<Out>>,
-{
-    let mut error: Option<anyhow::Error> = None;
+    let mut attempts = FuturesUnordered::new();
+    let mut next_attempt = Instant::now();
 
-    for input in inputs {
-        match func(input).await {
-            Ok(o) => return Ok((o, input)),
-            Err(e) => {
-                let e = e.context(format!("{input} failed"));
-                match error.take() {
-                    Some(prev_err) => error = Some(prev_err.context(e)),
-                    None => error = Some(e),
+    loop {
+        let has_candidate = resolved.iter().any(|(_, candidates)| !candidates.is_empty());
+
+        if !has_candidate && lookups.is_empty() && attempts.is_empty() {
+            return None;
+        }
+
+        // Without any pending attempt, there is no reason to wait before starting the next one.
+        if attempts.is_empty() {
+            next_attempt = Instant::now();
+        }
+
+        tokio::select! {
+            Some((idx, target, result)) = lookups.next(), if !lookups.is_empty() => {
+                match result {
+                    Ok(candidates) => {
+                        let candidates = candidates
+                            .into_iter()
+                            .map(|candidate| (candidate, target))
+                            .collect::<VecDeque<_>>();
+
+                        if candidates.is_empty() {
+                            warn!(destination = %target, "Destination resolved to no address");
+                            record_error(anyhow::anyhow!("{target} failed: could not resolve to any address"));
+                        }
+
+                        let position = resolved.partition_point(|(other_idx, _)| *other_idx < idx);
+                        resolved.insert(position, (idx, candidates));
+                    }
+                    Err(e) => {
+                        warn!(error = %e, destination = %target, "Failed to lookup destination address");
+                        record_error(
+                            anyhow::Error::new(e)
+                                .context("failed to lookup destination address")
+                                .context(format!("{target} failed")),
+                        );
+                    }
                 }
             }
+            Some((candidate, target, elapsed, result)) = attempts.next(), if !attempts.is_empty() => {
+                match result {
+                    Ok(connected) => {
+                        debug!(via = %candidate, destination = %target, ?elapsed, "Connected to destination");
+                        return Some((connected, target));
+                    }
+                    Err(e) => {
+                        warn!(
+                            error = %e,
+                            via = %candidate,
+                            destination = %target,
+                            ?elapsed,
+                            "Failed to connect to destination"
+                        );
+                        record_error(
+                            anyhow::Error::new(e)
+                                .context(format!("connection via {candidate}"))
+                                .context(format!("{target} failed")),
+                        );
+
+                        // No need to wait for the delay to expire when an attempt fails.
+                        next_attempt = Instant::now();
+                    }
+                }
+            }
+            () = tokio::time::sleep_until(next_attempt), if has_candidate => {
+                let (candidate, target) = resolved
+                    .iter_mut()
+                    .find_map(|(_, candidates)| candidates.pop_front())
+                    .expect("a candidate is available");
+
+                trace!(via = %candidate, destination = %target, "Starting connection attempt");
+
+                attempts.push(async move {
+                    let started_at = Instant::now();
+                    let result = match candidate {
+                        Candidate::Direct(addr) => TcpStream::connect(addr)
+                            .await
+                            .map(|stream| (TargetStream::Tcp(stream), Some(addr))),
+                        Candidate::Proxy(proxy, proxy_addr) => async {
+                            let stream = TcpStream::connect(proxy_addr).await?;
+                            let stream = crate::upstream_proxy::open_tunnel(stream, proxy, target).await?;
+                            let target_addr = target.host_ip().map(|ip| SocketAddr::new(ip, target.port()));
+                            Ok::<_, io::Error>((stream, target_addr))
+                        }
+                        .await,
+                    };
+                    (candidate, target, started_at.elapsed(), result)
+                });
+
+                next_attempt = Instant::now() + conf.attempt_delay;
+            }
         }
     }
+}
+
+/// Orders the addresses so that IPv6 and IPv4 alternate, starting with IPv6 (RFC 8305, section 4).
+fn interleave_address_families(addrs: impl IntoIterator<Item = SocketAddr>) -> Vec<SocketAddr> {
+    let (v6, v4): (Vec<_>, Vec<_>) = addrs.into_iter().partition(SocketAddr::is_ipv6);
 
-    Err(error.context("empty input list")?)
+    let mut v6 = v6.into_iter();
+    let mut v4 = v4.into_iter();
+    let mut interleaved = Vec::with_capacity(v6.len() + v4.len());
+
+    loop {
+        match (v6.next(), v4.next()) {
+            (None, None) => return interleaved,
+            (v6_addr, v4_addr) => interleaved.extend(v6_addr.into_iter().chain(v4_addr)),
+        }
+    }
 }
 
 pub fn url_to_socket_addr(url: &Url) -> anyhow::Result<SocketAddr> {
@@ -82,3 +243,79 @@ pub fn wildcard_host_match(wildcard_host: &str, actual_host: &str) -> bool {
         }
     }
 }
+
+/// Returns the space available, in bytes, on the disk holding the provided path.
+///
+/// The disk is found by picking the mount point with the longest match for this path.
+pub fn available_disk_space(path: &camino::Utf8Path) -> anyhow::Result<u64> {
+    let disks = sysinfo::Disks::new_with_refreshed_list();
+
+    let disk = disks
+        .list()
+        .iter()
+        .filter(|disk| path.as_std_path().starts_with(disk.mount_point()))
+        .max_by_key(|disk| disk.mount_point().as_os_str().len())
+        .with_context(|| format!("no disk found for {path}"))?;
+
+    Ok(disk.available_space())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn address_families_are_interleaved() {
+        let addrs = ["10.0.0.1:80", "10.0.0.2:80", "[::1]:80", "10.0.0.3:80", "[::2]:80"]
+            .map(|addr| addr.parse::<SocketAddr>().unwrap());
+
+        let expected = ["[::1]:80", "10.0.0.1:80", "[::2]:80", "10.0.0.2:80", "10.0.0.3:80"]
+            .map(|addr| addr.parse::<SocketAddr>().unwrap());
+
+        assert_eq!(interleave_address_families(addrs), expected);
+    }
+
+    #[tokio::test]
+    async fn unresponsive_target_is_skipped() {
+        use std::time::Duration;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let reachable_addr = listener.local_addr().unwrap();
+
+        // A listener whose accept queue is full leaves the connection attempts unanswered.
+        let socket = tokio::net::TcpSocket::new_v4().unwrap();
+        socket.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let unresponsive_listener = socket.listen(1).unwrap();
+        let unresponsive_addr = unresponsive_listener.local_addr().unwrap();
+
+        let mut queued = Vec::new();
+        while let Ok(Ok(stream)) =
+            tokio::time::timeout(Duration::from_millis(200), TcpStream::connect(unresponsive_addr)).await
+        {
+            queued.push(stream);
+        }
+
+        let targets = [
+            TargetAddr::parse(&format!("tcp://{unresponsive_addr}"), None).unwrap(),
+            TargetAddr::parse(&format!("tcp://{reachable_addr}"), None).unwrap(),
+        ];
+
+        let conf = TargetConnectionConf {
+            attempt_delay: Duration::from_millis(250),
+            ..TargetConnectionConf::default()
+        };
+
+        let started_at = Instant::now();
+
+        let ((_, connected_addr), selected_target) = tcp_connect(&targets, &conf).await.unwrap();
+
+        let elapsed = started_at.elapsed();
+
+        assert_eq!(connected_addr, Some(reachable_addr));
+        assert_eq!(selected_target, &targets[1]);
+
+        // The second target is tried once the delay expires, without waiting for the first attempt to give up.
+        assert!(elapsed >= conf.attempt_delay, "{elapsed:?}");
+        assert!(elapsed < conf.attempt_delay + Duration::from_millis(500), "{elapsed:?}");
+    }
+}
@@ -66,14 +66,22 @@ fn hub_sample() -> Sample {
             tls_certificate_subject_name: None,
             tls_certificate_store_location: None,
             tls_certificate_store_name: None,
+            tls_certificates: vec![],
+            acme: None,
+            target_tls: None,
+            target_connection: None,
             listeners: vec![
                 ListenerConf {
                     internal_url: "tcp://*:8080".to_owned(),
                     external_url: "tcp://*:8080".to_owned(),
+                    proxy_protocol: None,
+                    unix_socket: None,
                 },
                 ListenerConf {
                     internal_url: "ws://*:7171".to_owned(),
                     external_url: "wss://*:443".to_owned(),
+                    proxy_protocol: None,
+                    unix_socket: None,
                 },
             ],
             subscriber: None,
@@ -81,6 +89,7 @@ fn hub_sample() -> Sample {
             jrl_file: None,
             plugins: None,
             recording_path: None,
+            recording: None,
             sogar: None,
             ngrok: None,
             verbosity_profile: Some(VerbosityProfile::Tls),
@@ -117,12 +126,17 @@ fn legacy_sample() -> Sample {
             tls_certificate_subject_name: None,
             tls_certificate_store_location: None,
             tls_certificate_store_name: None,
+            tls_certificates: vec![],
+            acme: None,
+            target_tls: None,
+            target_connection: None,
             listeners: vec![],
             subscriber: None,
             log_file: Some("/path/to/log/file.log".into()),
             jrl_file: None,
             plugins: None,
             recording_path: None,
+            recording: None,
             sogar: None,
             ngrok: None,
             verbosity_profile: None,
@@ -158,12 +172,239 @@ fn system_store_sample() -> Sample {
             tls_certificate_subject_name: Some("localhost".to_owned()),
             tls_certificate_store_location: Some(CertStoreLocation::LocalMachine),
             tls_certificate_store_name: Some("My".to_owned()),
+            tls_certificates: vec![],
+            acme: None,
+            target_tls: None,
+            target_connection: None,
             listeners: vec![],
             subscriber: None,
             log_file: None,
             jrl_file: None,
             plugins: None,
             recording_path: None,
+            recording: None,
+            sogar: None,
+            ngrok: None,
+            verbosity_profile: None,
+            web_app: None,
+            debug: None,
+            rest: Default::default(),
+        },
+    }
+}
+
+fn sni_certificates_sample() -> Sample {
+    Sample {
+        json_repr: r#"{
+            "TlsCertificates": [
+                {
+                    "Hostnames": ["gateway.example.io", "*.gateway.example.io"],
+                    "CertificateFile": "/path/to/gateway.pem",
+                    "PrivateKeyFile": "/path/to/gateway.key",
+                    "Default": true
+                },
+                {
+                    "Hostnames": ["rdp.example.com"],
+                    "CertificateFile": "/path/to/rdp.pfx",
+                    "PrivateKeyPassword": "password"
+                }
+            ]
+        }"#,
+        file_conf: ConfFile {
+            id: None,
+            hostname: None,
+            provisioner_public_key_file: None,
+            provisioner_public_key_data: None,
+            provisioner_private_key_file: None,
+            provisioner_private_key_data: None,
+            sub_provisioner_public_key: None,
+            delegation_private_key_file: None,
+            delegation_private_key_data: None,
+            tls_certificate_source: None,
+            tls_certificate_file: None,
+            tls_private_key_file: None,
+            tls_private_key_password: None,
+            tls_certificate_subject_name: None,
+            tls_certificate_store_location: None,
+            tls_certificate_store_name: None,
+            tls_certificates: vec![
+                TlsCertificateConf {
+                    hostnames: vec!["gateway.example.io".to_owned(), "*.gateway.example.io".to_owned()],
+                    certificate_file: "/path/to/gateway.pem".into(),
+                    private_key_file: Some("/path/to/gateway.key".into()),
+                    private_key_password: None,
+                    default: true,
+                },
+                TlsCertificateConf {
+                    hostnames: vec!["rdp.example.com".to_owned()],
+                    certificate_file: "/path/to/rdp.pfx".into(),
+                    private_key_file: None,
+                    private_key_password: Some("password".into()),
+                    default: false,
+                },
+            ],
+            acme: None,
+            target_tls: None,
+            target_connection: None,
+            listeners: vec![],
+            subscriber: None,
+            log_file: None,
+            jrl_file: None,
+            plugins: None,
+            recording_path: None,
+            recording: None,
+            sogar: None,
+            ngrok: None,
+            verbosity_profile: None,
+            web_app: None,
+            debug: None,
+            rest: Default::default(),
+        },
+    }
+}
+
+fn acme_sample() -> Sample {
+    Sample {
+        json_repr: r#"{
+            "TlsCertificateSource": "Acme",
+            "Acme": {
+                "DirectoryUrl": "https://localhost:14000/dir",
+                "ContactEmail": "admin@example.io",
+                "Domains": ["gateway.example.io"],
+                "Challenge": "TlsAlpn01",
+                "CaCertificateFile": "/path/to/pebble.minica.pem"
+            }
+        }"#,
+        file_conf: ConfFile {
+            id: None,
+            hostname: None,
+            provisioner_public_key_file: None,
+            provisioner_public_key_data: None,
+            provisioner_private_key_file: None,
+            provisioner_private_key_data: None,
+            sub_provisioner_public_key: None,
+            delegation_private_key_file: None,
+            delegation_private_key_data: None,
+            tls_certificate_source: Some(CertSource::Acme),
+            tls_certificate_file: None,
+            tls_private_key_file: None,
+            tls_private_key_password: None,
+            tls_certificate_subject_name: None,
+            tls_certificate_store_location: None,
+            tls_certificate_store_name: None,
+            tls_certificates: vec![],
+            acme: Some(AcmeConf {
+                directory_url: Some("https://localhost:14000/dir".parse().unwrap()),
+                contact_email: Some("admin@example.io".to_owned()),
+                domains: vec!["gateway.example.io".to_owned()],
+                challenge: Some(AcmeChallengeType::TlsAlpn01),
+                renew_before_days: None,
+                ca_certificate_file: Some("/path/to/pebble.minica.pem".into()),
+            }),
+            target_tls: None,
+            target_connection: None,
+            listeners: vec![],
+            subscriber: None,
+            log_file: None,
+            jrl_file: None,
+            plugins: None,
+            recording_path: None,
+            recording: None,
+            sogar: None,
+            ngrok: None,
+            verbosity_profile: None,
+            web_app: None,
+            debug: None,
+            rest: Default::default(),
+        },
+    }
+}
+
+fn target_tls_sample() -> Sample {
+    Sample {
+        json_repr: r#"{
+            "Id": "ab1a2d1c-59d5-4a2f-b8a7-1a4e2b0c6d3f",
+            "Hostname": "hostname.example.io",
+            "ProvisionerPublicKeyFile": "provisioner.pem",
+            "TargetTls": {
+                "Verification": "Custom",
+                "CaCertificateFile": "/path/to/internal-ca.pem",
+                "PinnedCertificates": [
+                    {
+                        "Host": "*.lab.example.io",
+                        "Sha256": ["47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU="]
+                    }
+                ]
+            },
+            "TargetConnection": {
+                "AttemptDelay": 100,
+                "Timeout": 5,
+                "ProxyRules": [
+                    {
+                        "Destinations": ["10.10.0.0/16"]
+                    },
+                    {
+                        "Destinations": ["10.0.0.0/8", "*.lab.example.io"],
+                        "Proxy": "socks5://proxy.example.io:1080",
+                        "ProxyUsername": "gateway",
+                        "ProxyPassword": "hunter2"
+                    }
+                ]
+            },
+            "Listeners": []
+        }"#,
+        file_conf: ConfFile {
+            id: Some(Uuid::from_str("ab1a2d1c-59d5-4a2f-b8a7-1a4e2b0c6d3f").unwrap()),
+            hostname: Some("hostname.example.io".to_owned()),
+            provisioner_public_key_file: Some("provisioner.pem".into()),
+            provisioner_public_key_data: None,
+            provisioner_private_key_file: None,
+            provisioner_private_key_data: None,
+            sub_provisioner_public_key: None,
+            delegation_private_key_file: None,
+            delegation_private_key_data: None,
+            tls_certificate_source: None,
+            tls_certificate_file: None,
+            tls_private_key_file: None,
+            tls_private_key_password: None,
+            tls_certificate_subject_name: None,
+            tls_certificate_store_location: None,
+            tls_certificate_store_name: None,
+            tls_certificates: vec![],
+            acme: None,
+            target_tls: Some(TargetTlsConf {
+                verification: Some(TargetTlsVerification::Custom),
+                ca_certificate_file: Some("/path/to/internal-ca.pem".into()),
+                pinned_certificates: vec![SpkiPinConf {
+                    host: "*.lab.example.io".to_owned(),
+                    sha256: vec!["47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=".to_owned()],
+                }],
+            }),
+            target_connection: Some(TargetConnectionConf {
+                attempt_delay: Some(100),
+                timeout: Some(5),
+                proxy_rules: vec![
+                    ProxyRuleConf {
+                        destinations: vec!["10.10.0.0/16".to_owned()],
+                        proxy: None,
+                        proxy_username: None,
+                        proxy_password: None,
+                    },
+                    ProxyRuleConf {
+                        destinations: vec!["10.0.0.0/8".to_owned(), "*.lab.example.io".to_owned()],
+                        proxy: Some("socks5://proxy.example.io:1080".parse().unwrap()),
+                        proxy_username: Some("gateway".to_owned()),
+                        proxy_password: Some("hunter2".into()),
+                    },
+                ],
+            }),
+            listeners: vec![],
+            subscriber: None,
+            log_file: None,
+            jrl_file: None,
+            plugins: None,
+            recording_path: None,
+            recording: None,
             sogar: None,
             ngrok: None,
             verbosity_profile: None,
@@ -215,14 +456,22 @@ fn standalone_custom_auth_sample() -> Sample {
             tls_certificate_subject_name: None,
             tls_certificate_store_location: None,
             tls_certificate_store_name: None,
+            tls_certificates: vec![],
+            acme: None,
+            target_tls: None,
+            target_connection: None,
             listeners: vec![
                 ListenerConf {
                     internal_url: "tcp://*:8080".to_owned(),
                     external_url: "tcp://*:8080".to_owned(),
+                    proxy_protocol: None,
+                    unix_socket: None,
                 },
                 ListenerConf {
                     internal_url: "http://*:7171".to_owned(),
                     external_url: "https://*:7171".to_owned(),
+                    proxy_protocol: None,
+                    unix_socket: None,
                 },
             ],
             subscriber: None,
@@ -230,6 +479,7 @@ fn standalone_custom_auth_sample() -> Sample {
             jrl_file: None,
             plugins: None,
             recording_path: None,
+            recording: None,
             sogar: None,
             ngrok: None,
             verbosity_profile: None,
@@ -288,14 +538,22 @@ fn standalone_no_auth_sample() -> Sample {
             tls_certificate_subject_name: None,
             tls_certificate_store_location: None,
             tls_certificate_store_name: None,
+            tls_certificates: vec![],
+            acme: None,
+            target_tls: None,
+            target_connection: None,
             listeners: vec![
                 ListenerConf {
                     internal_url: "tcp://*:8080".to_owned(),
                     external_url: "tcp://*:8080".to_owned(),
+                    proxy_protocol: None,
+                    unix_socket: None,
                 },
                 ListenerConf {
                     internal_url: "http://*:7171".to_owned(),
                     external_url: "https://*:7171".to_owned(),
+                    proxy_protocol: None,
+                    unix_socket: None,
                 },
             ],
             subscriber: None,
@@ -303,6 +561,7 @@ fn standalone_no_auth_sample() -> Sample {
             jrl_file: None,
             plugins: None,
             recording_path: None,
+            recording: None,
             sogar: None,
             ngrok: None,
             verbosity_profile: None,
@@ -324,6 +583,9 @@ fn standalone_no_auth_sample() -> Sample {
 #[case(hub_sample())]
 #[case(legacy_sample())]
 #[case(system_store_sample())]
+#[case(acme_sample())]
+#[case(sni_certificates_sample())]
+#[case(target_tls_sample())]
 #[case(standalone_custom_auth_sample())]
 #[case(standalone_no_auth_sample())]
 fn sample_parsing(#[case] sample: Sample) {
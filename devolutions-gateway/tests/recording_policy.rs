@@ -0,0 +1,199 @@
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::Duration;
+
+use devolutions_gateway::generic_client::GenericClient;
+use devolutions_gateway::recording::RecordingManagerTask;
+use devolutions_gateway::session::SessionManagerTask;
+use devolutions_gateway::DgwState;
+use devolutions_gateway_task::Task as _;
+use picky::jose::jws::JwsAlg;
+use picky::jose::jwt::CheckedJwtSig;
+use picky::key::PrivateKey;
+use serde_json::json;
+use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _, DuplexStream};
+use tokio::net::TcpListener;
+use uuid::Uuid;
+
+mod common;
+
+use common::{mock_config, PROVISIONER_PRIVATE_KEY};
+
+fn config(recording_path: &Path) -> String {
+    mock_config(json!({
+        "RecordingPath": recording_path
+    }))
+}
+
+/// Returns an association token requiring the session to be recorded.
+fn recorded_association_token(session_id: Uuid, protocol: &str, target: SocketAddr) -> String {
+    let (_, der) = multibase::decode(PROVISIONER_PRIVATE_KEY).unwrap();
+    let key = PrivateKey::from_pkcs8(&der).unwrap();
+    let now = time::OffsetDateTime::now_utc().unix_timestamp();
+
+    let claims = json!({
+        "jet_aid": session_id,
+        "jet_ap": protocol,
+        "jet_cm": "fwd",
+        "dst_hst": format!("tcp://{target}"),
+        "jet_rec": true,
+        "nbf": now,
+        "exp": now + 60,
+        "jti": Uuid::new_v4(),
+    });
+
+    CheckedJwtSig::new_with_cty(JwsAlg::RS256, "ASSOCIATION", claims)
+        .encode(&key)
+        .unwrap()
+}
+
+/// Encodes a version 2 RDP preconnection blob carrying the token.
+fn preconnection_blob(token: &str) -> Vec<u8> {
+    let payload: Vec<u16> = token.encode_utf16().chain(std::iter::once(0)).collect();
+    let size = 18 + payload.len() * 2;
+
+    let mut pcb = Vec::with_capacity(size);
+    pcb.extend_from_slice(&u32::try_from(size).unwrap().to_le_bytes()); // cbSize
+    pcb.extend_from_slice(&0u32.to_le_bytes()); // Flags
+    pcb.extend_from_slice(&2u32.to_le_bytes()); // Version
+    pcb.extend_from_slice(&0u32.to_le_bytes()); // Id
+    pcb.extend_from_slice(&u16::try_from(payload.len()).unwrap().to_le_bytes()); // cchPCB
+    pcb.extend(payload.iter().flat_map(|unit| unit.to_le_bytes()));
+
+    pcb
+}
+
+fn generic_client(state: &DgwState, client_stream: DuplexStream) -> GenericClient<DuplexStream> {
+    GenericClient::builder()
+        .conf(state.conf_handle.get_conf())
+        .token_cache(state.token_cache.clone())
+        .jrl(state.jrl.clone())
+        .client_addr(SocketAddr::from(([127, 0, 0, 1], 3000)))
+        .client_stream(client_stream)
+        .sessions(state.sessions.clone())
+        .subscriber_tx(state.subscriber_tx.clone())
+        .recordings(state.recordings.clone())
+        .rendezvous(state.rendezvous.clone())
+        .build()
+}
+
+#[tokio::test]
+async fn telnet_session_requiring_recording_is_forwarded() {
+    let recording_dir = tempfile::tempdir().unwrap();
+    let (state, _handles) = DgwState::mock(&config(recording_dir.path())).unwrap();
+
+    let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let token = recorded_association_token(Uuid::new_v4(), "telnet", target.local_addr().unwrap());
+
+    let (mut client, client_stream) = tokio::io::duplex(64 * 1024);
+    client.write_all(&preconnection_blob(&token)).await.unwrap();
+    client.write_all(b"login").await.unwrap();
+
+    tokio::spawn(generic_client(&state, client_stream).serve());
+
+    // The gateway records Telnet sessions by itself, so the session is not rejected upfront.
+    let (mut server, _) = tokio::time::timeout(Duration::from_secs(5), target.accept())
+        .await
+        .unwrap()
+        .unwrap();
+
+    let mut received = [0; 5];
+    server.read_exact(&mut received).await.unwrap();
+    assert_eq!(&received, b"login");
+}
+
+#[tokio::test]
+async fn ssh_session_requiring_recording_is_rejected() {
+    let recording_dir = tempfile::tempdir().unwrap();
+    let (state, _handles) = DgwState::mock(&config(recording_dir.path())).unwrap();
+
+    let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let token = recorded_association_token(Uuid::new_v4(), "ssh", target.local_addr().unwrap());
+
+    let (mut client, client_stream) = tokio::io::duplex(64 * 1024);
+    client.write_all(&preconnection_blob(&token)).await.unwrap();
+
+    let error = generic_client(&state, client_stream).serve().await.unwrap_err();
+    assert!(format!("{error:#}").contains("can't meet recording policy"));
+}
+
+#[tokio::test]
+async fn telnet_session_is_recorded_by_the_gateway() {
+    let recording_dir = tempfile::tempdir().unwrap();
+    let (state, handles) = DgwState::mock(&config(recording_dir.path())).unwrap();
+
+    tokio::spawn(
+        SessionManagerTask::new(
+            handles.session_manager_rx,
+            state.conf_handle.clone(),
+            state.recordings.active_recordings.clone(),
+        )
+        .run(state.shutdown_signal.clone()),
+    );
+
+    tokio::spawn(
+        RecordingManagerTask::new(
+            handles.recording_manager_rx,
+            state.conf_handle.clone(),
+            state.sessions.clone(),
+            state.subscriber_tx.clone(),
+        )
+        .run(state.shutdown_signal.clone()),
+    );
+
+    let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let session_id = Uuid::new_v4();
+    let token = recorded_association_token(session_id, "telnet", target.local_addr().unwrap());
+
+    let (mut client, client_stream) = tokio::io::duplex(64 * 1024);
+    client.write_all(&preconnection_blob(&token)).await.unwrap();
+
+    tokio::spawn(generic_client(&state, client_stream).serve());
+
+    let (mut server, _) = tokio::time::timeout(Duration::from_secs(5), target.accept())
+        .await
+        .unwrap()
+        .unwrap();
+
+    // Window size negotiated by the client (NAWS), then the output of the server.
+    client
+        .write_all(b"\xFF\xFA\x1F\x00\x78\x00\x28\xFF\xF0")
+        .await
+        .unwrap();
+    server.write_all(b"\xFF\xFB\x01hello\r\n").await.unwrap();
+
+    let mut received = [0; 10];
+    client.read_exact(&mut received).await.unwrap();
+    assert_eq!(&received, b"\xFF\xFB\x01hello\r\n");
+
+    let mut received = [0; 9];
+    server.read_exact(&mut received).await.unwrap();
+
+    // The recording file is complete once the session ends.
+    drop(client);
+    drop(server);
+
+    let recording_file = recording_dir
+        .path()
+        .join(session_id.to_string())
+        .join("recording-0.cast");
+
+    let recording = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            match std::fs::read_to_string(&recording_file) {
+                Ok(recording) if recording.contains(r#""o","hello\r\n"]"#) => break recording,
+                _ => tokio::time::sleep(Duration::from_millis(50)).await,
+            }
+        }
+    })
+    .await
+    .unwrap();
+
+    let mut lines = recording.lines();
+    let header: serde_json::Value = serde_json::from_str(lines.next().unwrap()).unwrap();
+    assert_eq!(header["version"], 2);
+    assert!(lines.clone().any(|line| line.ends_with(r#","r","120x40"]"#)));
+    assert!(lines.any(|line| line.ends_with(r#","o","hello\r\n"]"#)));
+
+    handles.shutdown_handle.signal();
+}
@@ -0,0 +1,152 @@
+use std::net::SocketAddr;
+
+use devolutions_gateway::DgwState;
+use jet_proto::accept::JetAcceptReq;
+use jet_proto::connect::JetConnectReq;
+use jet_proto::{JetMessage, StatusCode, JET_MSG_HEADER_SIZE};
+use picky::jose::jws::JwsAlg;
+use picky::jose::jwt::CheckedJwtSig;
+use picky::key::PrivateKey;
+use serde_json::json;
+use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+use tokio::net::{TcpListener, TcpStream};
+use uuid::Uuid;
+
+mod common;
+
+use common::{mock_config, PROVISIONER_PRIVATE_KEY};
+
+fn rendezvous_token(association: Uuid) -> String {
+    let (_, der) = multibase::decode(PROVISIONER_PRIVATE_KEY).unwrap();
+    let key = PrivateKey::from_pkcs8(&der).unwrap();
+    let now = time::OffsetDateTime::now_utc().unix_timestamp();
+
+    let claims = json!({
+        "jet_aid": association,
+        "jet_ap": "unknown",
+        "jet_cm": "rdv",
+        "nbf": now,
+        "exp": now + 60,
+        "jti": Uuid::new_v4(),
+    });
+
+    CheckedJwtSig::new_with_cty(JwsAlg::RS256, "ASSOCIATION", claims)
+        .encode(&key)
+        .unwrap()
+}
+
+async fn spawn_gateway(state: DgwState) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        loop {
+            let (stream, peer_addr) = listener.accept().await.unwrap();
+            let state = state.clone();
+
+            tokio::spawn(async move {
+                let _ = devolutions_gateway::rendezvous::handle_jet_peer(stream, peer_addr, state).await;
+            });
+        }
+    });
+
+    addr
+}
+
+async fn send_request(gateway_addr: SocketAddr, request: JetMessage) -> (TcpStream, Vec<u8>) {
+    let mut stream = TcpStream::connect(gateway_addr).await.unwrap();
+
+    let mut buffer = Vec::new();
+    request.write_to(&mut buffer).unwrap();
+    stream.write_all(&buffer).await.unwrap();
+
+    let mut response = vec![0; JET_MSG_HEADER_SIZE as usize];
+    stream.read_exact(&mut response).await.unwrap();
+    let size = usize::from(u16::from_be_bytes([response[4], response[5]]));
+    response.resize(size, 0);
+    stream
+        .read_exact(&mut response[JET_MSG_HEADER_SIZE as usize..])
+        .await
+        .unwrap();
+
+    (stream, response)
+}
+
+async fn accept(gateway_addr: SocketAddr, association: Uuid, token: Option<String>) -> (TcpStream, StatusCode) {
+    let request = JetMessage::JetAcceptReq(JetAcceptReq {
+        version: 2,
+        host: "test".to_owned(),
+        association,
+        candidate: Uuid::new_v4(),
+        token,
+    });
+
+    let (stream, response) = send_request(gateway_addr, request).await;
+
+    match JetMessage::read_accept_response(&mut response.as_slice()).unwrap() {
+        JetMessage::JetAcceptRsp(response) => (stream, response.status_code),
+        unexpected => panic!("unexpected response: {unexpected:?}"),
+    }
+}
+
+async fn connect(gateway_addr: SocketAddr, association: Uuid, token: Option<String>) -> (TcpStream, StatusCode) {
+    let request = JetMessage::JetConnectReq(JetConnectReq {
+        version: 2,
+        host: "test".to_owned(),
+        association,
+        candidate: Uuid::nil(),
+        token,
+    });
+
+    let (stream, response) = send_request(gateway_addr, request).await;
+
+    match JetMessage::read_connect_response(&mut response.as_slice()).unwrap() {
+        JetMessage::JetConnectRsp(response) => (stream, response.status_code),
+        unexpected => panic!("unexpected response: {unexpected:?}"),
+    }
+}
+
+#[tokio::test]
+async fn acceptor_and_connector_are_piped() {
+    let (state, _handles) = DgwState::mock(&mock_config(json!({}))).unwrap();
+    let gateway_addr = spawn_gateway(state).await;
+    let association = Uuid::new_v4();
+
+    let (mut acceptor, status_code) = accept(gateway_addr, association, Some(rendezvous_token(association))).await;
+    assert_eq!(status_code, StatusCode::OK);
+
+    let (mut connector, status_code) = connect(gateway_addr, association, Some(rendezvous_token(association))).await;
+    assert_eq!(status_code, StatusCode::OK);
+
+    connector.write_all(b"ping").await.unwrap();
+    let mut received = [0; 4];
+    acceptor.read_exact(&mut received).await.unwrap();
+    assert_eq!(&received, b"ping");
+
+    acceptor.write_all(b"pong").await.unwrap();
+    connector.read_exact(&mut received).await.unwrap();
+    assert_eq!(&received, b"pong");
+}
+
+#[tokio::test]
+async fn requests_without_a_valid_token_are_rejected() {
+    let (state, _handles) = DgwState::mock(&mock_config(json!({}))).unwrap();
+    let gateway_addr = spawn_gateway(state).await;
+    let association = Uuid::new_v4();
+
+    let (_, status_code) = accept(gateway_addr, association, None).await;
+    assert_eq!(status_code, StatusCode::UNAUTHORIZED);
+
+    // The token must be for the requested association.
+    let (_, status_code) = accept(gateway_addr, association, Some(rendezvous_token(Uuid::new_v4()))).await;
+    assert_eq!(status_code, StatusCode::UNAUTHORIZED);
+
+    let (_acceptor, status_code) = accept(gateway_addr, association, Some(rendezvous_token(association))).await;
+    assert_eq!(status_code, StatusCode::OK);
+
+    let (_, status_code) = connect(gateway_addr, association, None).await;
+    assert_eq!(status_code, StatusCode::UNAUTHORIZED);
+
+    let (_, status_code) = connect(gateway_addr, association, Some(rendezvous_token(Uuid::new_v4()))).await;
+    assert_eq!(status_code, StatusCode::UNAUTHORIZED);
+}
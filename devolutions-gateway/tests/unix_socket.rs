@@ -0,0 +1,116 @@
+#![cfg(unix)]
+
+use std::os::unix::fs::{MetadataExt as _, PermissionsExt as _};
+use std::path::Path;
+
+use devolutions_gateway::config::UnixSocketConf;
+use devolutions_gateway::listener::GatewayListener;
+use devolutions_gateway::DgwState;
+use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+use tokio::net::UnixStream;
+use url::Url;
+
+mod common;
+
+use common::mock_config;
+
+fn socket_url(path: &Path) -> Url {
+    Url::parse(&format!("unix://{}", path.display())).unwrap()
+}
+
+/// Sends a GET request on a route requiring the `gateway.diagnostics.read` scope, and returns the status line.
+async fn get_configuration(path: &Path) -> String {
+    let mut stream = UnixStream::connect(path).await.unwrap();
+
+    stream
+        .write_all(b"GET /jet/diagnostics/configuration HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+
+    response.lines().next().unwrap_or_default().to_owned()
+}
+
+async fn spawn_listener(path: &Path, unix_socket: UnixSocketConf) -> anyhow::Result<()> {
+    let (state, handles) = DgwState::mock(&mock_config(serde_json::json!({})))?;
+
+    let listener = GatewayListener::init_and_bind(socket_url(path), Some(unix_socket), state)?;
+
+    tokio::spawn(async move {
+        let _handles = handles;
+        let _ = listener.run().await;
+    });
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn administrator_is_authenticated_by_peer_credentials() {
+    let dir = tempfile::tempdir().unwrap();
+    let uid = std::fs::metadata(dir.path()).unwrap().uid();
+
+    let admin_path = dir.path().join("admin.sock");
+    spawn_listener(
+        &admin_path,
+        UnixSocketConf {
+            mode: 0o660,
+            admin_uids: vec![uid],
+        },
+    )
+    .await
+    .unwrap();
+
+    let user_path = dir.path().join("user.sock");
+    spawn_listener(
+        &user_path,
+        UnixSocketConf {
+            mode: 0o600,
+            admin_uids: Vec::new(),
+        },
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+        std::fs::metadata(&admin_path).unwrap().permissions().mode() & 0o777,
+        0o660
+    );
+
+    assert_eq!(get_configuration(&admin_path).await, "HTTP/1.1 200 OK");
+    assert_eq!(get_configuration(&user_path).await, "HTTP/1.1 401 Unauthorized");
+
+    // The private directories in which the sockets are bound are removed.
+    let mut entries = std::fs::read_dir(dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect::<Vec<_>>();
+    entries.sort();
+    assert_eq!(entries, ["admin.sock", "user.sock"]);
+}
+
+#[tokio::test]
+async fn only_stale_sockets_are_replaced() {
+    let dir = tempfile::tempdir().unwrap();
+
+    // Regular files are left untouched.
+    let file_path = dir.path().join("file.sock");
+    std::fs::write(&file_path, "precious").unwrap();
+
+    assert!(spawn_listener(&file_path, UnixSocketConf::default()).await.is_err());
+    assert_eq!(std::fs::read_to_string(&file_path).unwrap(), "precious");
+
+    // A socket still in use is not stolen.
+    let socket_path = dir.path().join("gateway.sock");
+    spawn_listener(&socket_path, UnixSocketConf::default()).await.unwrap();
+
+    assert!(spawn_listener(&socket_path, UnixSocketConf::default()).await.is_err());
+
+    // A socket nobody listens on anymore is replaced.
+    let stale_path = dir.path().join("stale.sock");
+    drop(std::os::unix::net::UnixListener::bind(&stale_path).unwrap());
+
+    spawn_listener(&stale_path, UnixSocketConf::default()).await.unwrap();
+    assert_eq!(get_configuration(&stale_path).await, "HTTP/1.1 401 Unauthorized");
+}
@@ -11,29 +11,32 @@ use tap::prelude::*;
 use tower::{Service as _, ServiceExt as _};
 use tracing_cov_mark::init_cov_mark;
 
-const CONFIG: &str = r#"{
-    "ProvisionerPublicKeyData": {
-        "Value": "mMIIBIjANBgkqhkiG9w0BAQEFAAOCAQ8AMIIBCgKCAQEA4vuqLOkl1pWobt6su1XO9VskgCAwevEGs6kkNjJQBwkGnPKYLmNF1E/af1yCocfVn/OnPf9e4x+lXVyZ6LMDJxFxu+axdgOq3Ld392J1iAEbfvwlyRFnEXFOJNyylqg3bY6LvnWHL/XZczVdMD9xYfq2sO9bg3xjRW4s7r9EEYOFjqVT3VFznH9iWJVtcSEKukmS/3uKoO6lGhacvu0HhjXXdgq0R8zvR4XRJ9Fcnf0f9Ypoc+i6L80NVjrRCeVOH+Ld/2fA9bocpfLarcVqG3RjS+qgOtpyCc0jWVFF4zaGQ7LUDFkEIYILkICeMMn2ll29hmZNzsJzZJ9s6NocgQIDAQAB"
-    },
-    "ProvisionerPrivateKeyData": {
-        "Value": "mMIIEvAIBADANBgkqhkiG9w0BAQEFAASCBKYwggSiAgEAAoIBAQDi+6os6SXWlahu3qy7Vc71WySAIDB68QazqSQ2MlAHCQac8pguY0XUT9p/XIKhx9Wf86c9/17jH6VdXJnoswMnEXG75rF2A6rct3f3YnWIARt+/CXJEWcRcU4k3LKWqDdtjou+dYcv9dlzNV0wP3Fh+raw71uDfGNFbizuv0QRg4WOpVPdUXOcf2JYlW1xIQq6SZL/e4qg7qUaFpy+7QeGNdd2CrRHzO9HhdEn0Vyd/R/1imhz6LovzQ1WOtEJ5U4f4t3/Z8D1uhyl8tqtxWobdGNL6qA62nIJzSNZUUXjNoZDstQMWQQhgguQgJ4wyfaWXb2GZk3OwnNkn2zo2hyBAgMBAAECggEBAKCO0GOQUDmoB0rVrG2fVxPrcrhHDMQKNmljnb/Qexde5RSj7c3yXvS9v5sTvzvc9Vl9qrGKMH6MZhbSZ/RYnERIbKEzoBgQpA4YoX2WYfjgf6ilh7zg2H1YHqSokJNNTlfq2yLQU94zE6wQ9WgpmHRsOkqSJbOuizITqyj+lpGjl8dBAeOCD9HsnOGQiwsQD+joZ3yDRdFKSaBBtbklTYDyAmPvmp2G5A00UIo7KeOcNv59MPHnFBxMj0/z+QPKlqLQMsjL8vQX5DU2t/K4jdFHWGL8NZcz7KsCfh2Aa0vWEnroRzPPhKuBSBtaykbvfTcGrvRioesPq3EUdUqjQSECgYEA52UlMYeRYiTWsGq69lFWSlBjlRKhEMpg0Tp05z7J/A9X+ytB+6dZ37hk5asq84adRp7pnCEHV3SbczGq5ULFQBEqtFWPlD348zB8xxdBpAw3NAkVVDpAXBREhxXOnQm7MMmaXLH6d4Gv4kc6jKTC62w7cUUSlkIhlWSw5pSuVh0CgYEA+x5rJ4MQ6A/OKh058QY3ydRJw/sV54oxIFIIuJDw4I4eMsJ5Ht7MW5Pl1VQj+XuJRgMeqgZMQIIAcf5JNXqcesswVwdXy4awtw3TZV1Hi47Or7qHrFA/DtG4lNeDtyaWNuOtNnGw+LuqEmuu8BsWhB7yTHWJW7z+k6qO90CnArUCgYEA5ew66NwsObkhGmrzG432kCEQ0i+Qm358dWoAf0aErVERuyFgjw3a39H5b7yFETXRUTrWJa0r/lp/nBbeGLAgD2j/ZfEemc56cCrd0XXqY3c/4xSjfO3kxZnd/dxNUP06Y1/vYev3VIgonE7qfpW4mPUSm5pmvac4d5l1rahPEoECgYBUvAToRj+ULpEggNAmVjTI88sYSEcx492DzGqI7M961jm2Ywy/r+pBFHy/KS8iZd8CMtdMA+gC9Fr2HBnT49WdUaa0FxQ25vIGMrIcSAd2Pe/cOBLDwCgm9flUsAwP5wNU7ipqbp6Kr7hJkvBqsJk+Z7rWteptfC5i4XBwWe6A6QJ/Ddv+9vZe89uMdq+PThhELBHK+twZKawpKXYvzKlvPfMVisY+m9m37t7wK8PJexWOI9loVif6+ZIdWpXXntwrz94hYld/6+qK+sSt8EGmcJpAAI3zkp/ZMXhio0fy27sPaTlKlS6GNx/gPXRj6NHg/nu6lMmQ/EpLi1lyExPc8Q"
-    },
-    "Listeners": [
-        {
-            "InternalUrl": "tcp://*:8080",
-            "ExternalUrl": "tcp://*:8080"
+mod common;
+
+use common::{mock_config, PROVISIONER_PRIVATE_KEY};
+
+fn config() -> String {
+    mock_config(json!({
+        "ProvisionerPrivateKeyData": {
+            "Value": PROVISIONER_PRIVATE_KEY
         },
-        {
-            "InternalUrl": "http://*:7171",
-            "ExternalUrl": "https://*:7171"
+        "Listeners": [
+            {
+                "InternalUrl": "tcp://*:8080",
+                "ExternalUrl": "tcp://*:8080"
+            },
+            {
+                "InternalUrl": "http://*:7171",
+                "ExternalUrl": "https://*:7171"
+            }
+        ],
+        "WebApp": {
+            "Enabled": true,
+            "Authentication": "Custom",
+            "AppTokenMaximumLifetime": 28800
         }
-    ],
-    "WebApp": {
-        "Enabled": true,
-        "Authentication": "Custom",
-        "AppTokenMaximumLifetime": 28800
-    }
-}"#;
+    }))
+}
 
 fn initialize_conf() {
     use std::sync::Once;
@@ -53,7 +56,7 @@ fn initialize_conf() {
 async fn custom_authentication_flow() -> anyhow::Result<()> {
     let (cov, _guard) = init_cov_mark();
     initialize_conf();
-    let (state, _handle) = devolutions_gateway::DgwState::mock(CONFIG)?;
+    let (state, _handle) = devolutions_gateway::DgwState::mock(&config())?;
 
     let mut app =
         devolutions_gateway::make_http_service(state).layer(MockConnectInfo(SocketAddr::from(([0, 0, 0, 0], 3000))));
@@ -162,7 +165,7 @@ async fn custom_authentication_flow() -> anyhow::Result<()> {
 async fn sign_app_token_bad_password() -> anyhow::Result<()> {
     let (cov, _guard) = init_cov_mark();
     initialize_conf();
-    let (state, _handle) = devolutions_gateway::DgwState::mock(CONFIG)?;
+    let (state, _handle) = devolutions_gateway::DgwState::mock(&config())?;
 
     let app =
         devolutions_gateway::make_http_service(state).layer(MockConnectInfo(SocketAddr::from(([0, 0, 0, 0], 3000))));
@@ -200,7 +203,7 @@ async fn sign_app_token_bad_password() -> anyhow::Result<()> {
 async fn sign_app_token_username_mismatch() -> anyhow::Result<()> {
     let (cov, _guard) = init_cov_mark();
     initialize_conf();
-    let (state, _handles) = devolutions_gateway::DgwState::mock(CONFIG)?;
+    let (state, _handles) = devolutions_gateway::DgwState::mock(&config())?;
 
     let app =
         devolutions_gateway::make_http_service(state).layer(MockConnectInfo(SocketAddr::from(([0, 0, 0, 0], 3000))));
@@ -7,6 +7,7 @@ pub async fn write_jet_accept_request(
     writer: &mut (dyn AsyncWrite + Send + Unpin),
     association_id: Uuid,
     candidate_id: Uuid,
+    token: Option<String>,
 ) -> Result<()> {
     use jet_proto::accept::JetAcceptReq;
     use jet_proto::JetMessage;
@@ -17,6 +18,7 @@ pub async fn write_jet_accept_request(
         host: "jetsocat".to_owned(),
         association: association_id,
         candidate: candidate_id,
+        token,
     });
 
     let mut buffer: Vec<u8> = Vec::new();
@@ -57,6 +59,7 @@ pub async fn write_jet_connect_request(
     writer: &mut (dyn AsyncWrite + Send + Unpin),
     association_id: Uuid,
     candidate_id: Uuid,
+    token: Option<String>,
 ) -> Result<()> {
     use jet_proto::connect::JetConnectReq;
     use jet_proto::JetMessage;
@@ -67,6 +70,7 @@ pub async fn write_jet_connect_request(
         host: "jetsocat".to_owned(),
         association: association_id,
         candidate: candidate_id,
+        token,
     });
 
     let mut buffer: Vec<u8> = Vec::new();
@@ -21,6 +21,8 @@ public struct AccessScope
     public static AccessScope GatewayConfigWrite = new AccessScope("gateway.config.write");
     public static AccessScope GatewayHeartbeatRead = new AccessScope("gateway.heartbeat.read");
     public static AccessScope GatewayRecordingsRead = new AccessScope("gateway.recordings.read");
+    public static AccessScope GatewayRecordingsDelete = new AccessScope("gateway.recordings.delete");
+    public static AccessScope GatewayRecordingsImport = new AccessScope("gateway.recordings.import");
 
     public override string? ToString()
     {